@@ -0,0 +1,65 @@
+//! Benchmarks of the parsing of the frames into snapshots, see also examples/parse_bench.rs
+//!
+//!     cargo bench --bench parse
+
+use criterion::{
+	black_box,
+	criterion_group,
+	criterion_main,
+	BenchmarkId,
+	Criterion,
+	Throughput,
+};
+
+use forexpros_wss::{
+	data::{
+		snapshots_from_frame,
+		Snapshot,
+	},
+	protocol::peek_pid_and_ts,
+};
+
+/// The BTC/USD frame of the documentation of Snapshot
+const BTC_FRAME: &str = "a[\"{\\\"message\\\":\\\"pid-945629::{\\\\\\\"pid\\\\\\\":\\\\\\\"945629\\\\\\\",\\\\\\\"last_dir\\\\\\\":\\\\\\\"redBg\\\\\\\",\\\\\\\"last_numeric\\\\\\\":18951.2,\\\\\\\"last\\\\\\\":\\\\\\\"18,951.2\\\\\\\",\\\\\\\"bid\\\\\\\":\\\\\\\"18,954.0\\\\\\\",\\\\\\\"ask\\\\\\\":\\\\\\\"18,956.0\\\\\\\",\\\\\\\"high\\\\\\\":\\\\\\\"19,956.0\\\\\\\",\\\\\\\"low\\\\\\\":\\\\\\\"18,279.0\\\\\\\",\\\\\\\"last_close\\\\\\\":\\\\\\\"19,188.0\\\\\\\",\\\\\\\"pc\\\\\\\":\\\\\\\"-236.8\\\\\\\",\\\\\\\"pcp\\\\\\\":\\\\\\\"-1.23%\\\\\\\",\\\\\\\"pc_col\\\\\\\":\\\\\\\"redFont\\\\\\\",\\\\\\\"turnover\\\\\\\":\\\\\\\"21.50K\\\\\\\",\\\\\\\"turnover_numeric\\\\\\\":21503,\\\\\\\"time\\\\\\\":\\\\\\\"19:21:50\\\\\\\",\\\\\\\"timestamp\\\\\\\":1606850510}\\\"}\"]";
+
+/// Number of instruments of the multi-pid frame
+const MULTI_PIDS: usize = 50;
+
+/// Frame carrying the BTC/USD data under MULTI_PIDS pair ids, as for a broad subscription
+fn multi_pid_frame ( ) -> String {
+	let message = &BTC_FRAME [ 2..BTC_FRAME.len ( ) - 1 ];
+	let messages: Vec<_> = ( 0..MULTI_PIDS )
+		.map ( |idx| message.replace ( "945629", &( 945629 + idx ).to_string ( ) ) )
+		.collect ( );
+	format ! ( "a[{}]", messages.join ( "," ) )
+}
+
+fn bench_btc_frame ( c: &mut Criterion ) {
+	let mut group = c.benchmark_group ( "btc_frame" );
+	group.throughput ( Throughput::Bytes ( BTC_FRAME.len ( ) as u64 ) );
+	group.bench_function ( "from_str", |b| b.iter ( || Snapshot::from_str ( black_box ( BTC_FRAME ) ) ) );
+	group.bench_function ( "try_from_str", |b| b.iter ( || Snapshot::try_from_str ( black_box ( BTC_FRAME ) ).unwrap ( ) ) );
+	group.bench_function ( "from_bytes", |b| b.iter ( || Snapshot::from_bytes ( black_box ( BTC_FRAME.as_bytes ( ) ) ).unwrap ( ) ) );
+	group.bench_function ( "from_str_minimal", |b| b.iter ( || Snapshot::from_str_minimal ( black_box ( BTC_FRAME ) ) ) );
+	group.bench_function ( "peek_pid_and_ts", |b| b.iter ( || peek_pid_and_ts ( black_box ( BTC_FRAME ) ).unwrap ( ) ) );
+	group.finish ( );
+}
+
+fn bench_multi_pid_frame ( c: &mut Criterion ) {
+	let frame = multi_pid_frame ( );
+	assert_eq! ( snapshots_from_frame ( &frame ).unwrap ( ).len ( ), MULTI_PIDS );
+
+	let mut group = c.benchmark_group ( "multi_pid_frame" );
+	group.throughput ( Throughput::Elements ( MULTI_PIDS as u64 ) );
+	group.bench_with_input ( BenchmarkId::new ( "snapshots_from_frame", MULTI_PIDS ), &frame, |b, frame| {
+		b.iter ( || snapshots_from_frame ( black_box ( frame ) ).unwrap ( ) )
+	} );
+	// the first instrument only, for the cost of the frame around it
+	group.bench_with_input ( BenchmarkId::new ( "try_from_str", MULTI_PIDS ), &frame, |b, frame| {
+		b.iter ( || Snapshot::try_from_str ( black_box ( frame ) ).unwrap ( ) )
+	} );
+	group.finish ( );
+}
+
+criterion_group! ( benches, bench_btc_frame, bench_multi_pid_frame );
+criterion_main! ( benches );
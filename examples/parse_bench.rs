@@ -0,0 +1,35 @@
+//! Compare Snapshot::from_str(..) with Snapshot::from_str_minimal(..) and Snapshot::from_bytes(..)
+//!
+//! cargo run --release --example parse_bench
+
+use forexpros_wss::data::Snapshot;
+
+use std::time::Instant;
+
+const ITERATIONS: u32 = 200_000;
+
+fn main ( ) {
+	let src = "a[\"{\\\"message\\\":\\\"pid-945629::{\\\\\\\"pid\\\\\\\":\\\\\\\"945629\\\\\\\",\\\\\\\"last_dir\\\\\\\":\\\\\\\"redBg\\\\\\\",\\\\\\\"last_numeric\\\\\\\":18951.2,\\\\\\\"last\\\\\\\":\\\\\\\"18,951.2\\\\\\\",\\\\\\\"bid\\\\\\\":\\\\\\\"18,954.0\\\\\\\",\\\\\\\"ask\\\\\\\":\\\\\\\"18,956.0\\\\\\\",\\\\\\\"high\\\\\\\":\\\\\\\"19,956.0\\\\\\\",\\\\\\\"low\\\\\\\":\\\\\\\"18,279.0\\\\\\\",\\\\\\\"last_close\\\\\\\":\\\\\\\"19,188.0\\\\\\\",\\\\\\\"pc\\\\\\\":\\\\\\\"-236.8\\\\\\\",\\\\\\\"pcp\\\\\\\":\\\\\\\"-1.23%\\\\\\\",\\\\\\\"pc_col\\\\\\\":\\\\\\\"redFont\\\\\\\",\\\\\\\"turnover\\\\\\\":\\\\\\\"21.50K\\\\\\\",\\\\\\\"turnover_numeric\\\\\\\":21503,\\\\\\\"time\\\\\\\":\\\\\\\"19:21:50\\\\\\\",\\\\\\\"timestamp\\\\\\\":1606850510}\\\"}\"]";
+
+	let start = Instant::now ( );
+	for _ in 0..ITERATIONS {
+		std::hint::black_box ( Snapshot::from_str ( std::hint::black_box ( src ) ) );
+	}
+	let full = start.elapsed ( );
+
+	let start = Instant::now ( );
+	for _ in 0..ITERATIONS {
+		std::hint::black_box ( Snapshot::from_str_minimal ( std::hint::black_box ( src ) ) );
+	}
+	let minimal = start.elapsed ( );
+
+	let start = Instant::now ( );
+	for _ in 0..ITERATIONS {
+		std::hint::black_box ( Snapshot::from_bytes ( std::hint::black_box ( src.as_bytes ( ) ) ).unwrap ( ) );
+	}
+	let bytes = start.elapsed ( );
+
+	println ! ( "from_str:         {:?} per snapshot", full / ITERATIONS );
+	println ! ( "from_str_minimal: {:?} per snapshot", minimal / ITERATIONS );
+	println ! ( "from_bytes:       {:?} per snapshot", bytes / ITERATIONS );
+}
@@ -0,0 +1,280 @@
+//! Aggregation of snapshots into OHLC candles of a fixed interval.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::data::{InstrumentClass, Snapshot};
+
+/// Price of a snapshot aggregated into candles, see CandleBuilder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceSource {
+    /// `Snapshot::last_f64()`
+    Last,
+
+    /// Mean of `bid_f64()` and `ask_f64()`, for quotes without trades such as FX rates.
+    Mid,
+}
+
+impl PriceSource {
+    /// Price of the snapshot, None when a field of the source is empty or malformed.
+    pub fn price(self, snapshot: &Snapshot) -> Option<f64> {
+        match self {
+            PriceSource::Last => snapshot.last_f64(),
+            PriceSource::Mid => Some((snapshot.bid_f64()? + snapshot.ask_f64()?) / 2.0),
+        }
+    }
+}
+
+/// Open, high, low and close of the prices of a pid within an interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub pid: String,
+
+    /// Server timestamp of the start of the interval, a multiple of the interval.
+    pub start: u64,
+
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+
+    /// Number of snapshots in the candle, 0 for a candle forward-filled by CandleBuilder.
+    pub ticks: u32,
+
+    /// Whether the interval was still open, for the candles of `CandleBuilder::flush()`
+    pub partial: bool,
+}
+
+impl Candle {
+    fn new(pid: &str, start: u64, price: f64) -> Self {
+        Candle {
+            pid: pid.to_string(),
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            ticks: 1,
+            partial: false,
+        }
+    }
+
+    /// Flat candle at the close of the previous one, without snapshots.
+    fn flat(previous: &Candle, start: u64) -> Self {
+        Candle {
+            ticks: 0,
+            ..Candle::new(&previous.pid, start, previous.close)
+        }
+    }
+
+    fn add(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.ticks += 1;
+    }
+}
+
+/// Builds a candle per pid from the snapshots fed in, by their server timestamps.
+///
+/// A candle closes when the first snapshot of a later interval of its pid arrives, so the
+/// candle of the last interval stays open until then, see `flush()`. Intervals without
+/// snapshots have no candle, unless forward-filled, see `forward_fill(..)`. Snapshots without
+/// price, and snapshots older than the open candle of their pid, are skipped.
+#[derive(Clone, Debug)]
+pub struct CandleBuilder {
+    interval: u64,
+    source: PriceSource,
+    forward_fill: bool,
+    class: InstrumentClass,
+    candles: BTreeMap<String, Candle>,
+}
+
+impl CandleBuilder {
+    /// Builder of candles of the interval, in whole seconds of at least one, from the prices of the source.
+    pub fn new(interval: Duration, source: PriceSource) -> Self {
+        CandleBuilder {
+            interval: interval.as_secs().max(1),
+            source,
+            forward_fill: false,
+            class: InstrumentClass::default(),
+            candles: BTreeMap::new(),
+        }
+    }
+
+    /// Fill the intervals without snapshots between two candles of a pid with flat candles at
+    /// the previous close, without ticks, for a continuous series, e.g. for indicators. The
+    /// intervals before the first snapshot of a pid are not filled. Default is false, keeping
+    /// the gaps. See `update_all(..)`, and StreamBuilder::forward_fill_candles(..) for the
+    /// candles of StreamBuilder::on_candle(..)
+    pub fn forward_fill(mut self, fill: bool) -> Self {
+        self.forward_fill = fill;
+        self
+    }
+
+    /// Trading hours of the instruments: with InstrumentClass::Sessioned, the intervals of a
+    /// session break are not forward-filled, so each session starts afresh with the candle of
+    /// its first snapshot. Default is Continuous, filling every interval.
+    pub fn instrument_class(mut self, class: InstrumentClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// Adds the snapshot. Returns the candle of its pid closed by the snapshot, if any, without
+    /// the forward-filled candles of `update_all(..)`
+    pub fn update(&mut self, snapshot: &Snapshot) -> Option<Candle> {
+        let price = self.source.price(snapshot)?;
+        let start = snapshot.timestamp - snapshot.timestamp % self.interval;
+
+        match self.candles.get_mut(&snapshot.pid) {
+            Some(candle) if start == candle.start => {
+                candle.add(price);
+                None
+            }
+            Some(candle) if start < candle.start => None,
+            _ => self.candles.insert(
+                snapshot.pid.clone(),
+                Candle::new(&snapshot.pid, start, price),
+            ),
+        }
+    }
+
+    /// Same as `update(..)`, followed by the flat candles of the intervals skipped by the
+    /// snapshot with `forward_fill(true)`, in the order of their intervals.
+    pub fn update_all(&mut self, snapshot: &Snapshot) -> Vec<Candle> {
+        let closed = match self.update(snapshot) {
+            Some(closed) => closed,
+            None => return Vec::new(),
+        };
+        let mut candles = Vec::new();
+        let next_start = self.candles[&snapshot.pid].start;
+        let quiet = Duration::from_secs(next_start - closed.start - self.interval);
+        if self.forward_fill && !self.class.is_session_break(quiet) {
+            let mut start = closed.start + self.interval;
+            while start < next_start {
+                candles.push(Candle::flat(&closed, start));
+                start += self.interval;
+            }
+        }
+        candles.insert(0, closed);
+        candles
+    }
+
+    /// Removes and returns the open candles, e.g. the partial candles when the stream ends,
+    /// marked `partial`, in the order of their pids.
+    pub fn flush(&mut self) -> Vec<Candle> {
+        std::mem::take(&mut self.candles)
+            .into_values()
+            .map(|candle| Candle {
+                partial: true,
+                ..candle
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(pid: &str, last: &str, timestamp: u64) -> Snapshot {
+        Snapshot {
+            pid: pid.to_string(),
+            last: last.into(),
+            bid: "1.0".to_string(),
+            ask: "2.0".to_string(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_update() {
+        let mut builder = CandleBuilder::new(Duration::from_secs(60), PriceSource::Last);
+
+        assert_eq!(builder.update(&snapshot("1", "10.0", 120)), None);
+        assert_eq!(builder.update(&snapshot("1", "12.0", 130)), None);
+        assert_eq!(builder.update(&snapshot("2", "5.0", 140)), None);
+        assert_eq!(builder.update(&snapshot("1", "9.0", 179)), None);
+        // older than the open candle
+        assert_eq!(builder.update(&snapshot("1", "1.0", 100)), None);
+
+        assert_eq!(
+            builder.update(&snapshot("1", "11.0", 250)),
+            Some(Candle {
+                pid: "1".to_string(),
+                start: 120,
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 9.0,
+                ticks: 3,
+                partial: false,
+            })
+        );
+
+        let open: Vec<_> = builder
+            .flush()
+            .into_iter()
+            .map(|candle| (candle.pid, candle.start, candle.close, candle.partial))
+            .collect();
+        assert_eq!(
+            open,
+            vec![
+                ("1".to_string(), 240, 11.0, true),
+                ("2".to_string(), 120, 5.0, true)
+            ]
+        );
+        assert!(builder.flush().is_empty());
+    }
+
+    #[test]
+    pub fn test_forward_fill() {
+        let mut builder =
+            CandleBuilder::new(Duration::from_secs(60), PriceSource::Last).forward_fill(true);
+
+        // nothing to fill before the first snapshot
+        assert!(builder.update_all(&snapshot("1", "10.0", 130)).is_empty());
+        assert!(builder.update_all(&snapshot("1", "12.0", 150)).is_empty());
+
+        let candles: Vec<_> = builder
+            .update_all(&snapshot("1", "11.0", 320))
+            .into_iter()
+            .map(|candle| (candle.start, candle.open, candle.close, candle.ticks))
+            .collect();
+        assert_eq!(
+            candles,
+            vec![
+                (120, 10.0, 12.0, 2),
+                (180, 12.0, 12.0, 0),
+                (240, 12.0, 12.0, 0)
+            ]
+        );
+        // the next interval, nothing to fill
+        let candles = builder.update_all(&snapshot("1", "11.0", 400));
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].start, 300);
+
+        // the gaps are kept by default
+        let mut builder = CandleBuilder::new(Duration::from_secs(60), PriceSource::Last);
+        builder.update_all(&snapshot("1", "10.0", 130));
+        assert_eq!(builder.update_all(&snapshot("1", "11.0", 320)).len(), 1);
+
+        // nor filled over a session break
+        let mut builder = CandleBuilder::new(Duration::from_secs(60), PriceSource::Last)
+            .forward_fill(true)
+            .instrument_class(InstrumentClass::Sessioned);
+        builder.update_all(&snapshot("1", "10.0", 130));
+        assert_eq!(builder.update_all(&snapshot("1", "11.0", 320)).len(), 3);
+        let overnight = snapshot("1", "12.0", 320 + 16 * 3600);
+        assert_eq!(builder.update_all(&overnight).len(), 1);
+    }
+
+    #[test]
+    pub fn test_mid() {
+        let mut snapshot = snapshot("1", "10.0", 120);
+        assert_eq!(PriceSource::Mid.price(&snapshot), Some(1.5));
+
+        snapshot.ask = "".to_string();
+        assert_eq!(PriceSource::Mid.price(&snapshot), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Source of the current time of the time-dependent features, such as Stream::is_active(..),
+//! Stream::uptime() and Snapshot::age_with(..), to advance the time explicitly in tests.
+//!
+//! The timers of the tokio runtime, such as the heartbeat and the timeouts, follow the
+//! runtime instead, see `tokio::time::pause()`.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Current time, both monotonic and of the wall clock.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for the durations measured locally.
+    fn now(&self) -> Instant;
+
+    /// Wall clock time, to compare with the server timestamps.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// The real clock, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock standing still until advanced, for deterministic tests.
+///
+/// Both times start at the real time of MockClock::new() and move together.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    system_start: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock {
+            start: Instant::now(),
+            system_start: SystemTime::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the clock forward by the duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.system_start + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_mock_clock() {
+        let clock = MockClock::new();
+        let (now, system_time) = (clock.now(), clock.system_time());
+        assert_eq!(clock.now(), now);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - now, Duration::from_secs(90));
+        assert_eq!(
+            clock.system_time().duration_since(system_time).unwrap(),
+            Duration::from_secs(90)
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! Latest snapshot of each pid between the flushes of StreamBuilder::coalesce(..)
+
+use std::{collections::HashMap, mem, sync::Mutex};
+
+use crate::data::Snapshot;
+
+#[derive(Default)]
+struct Pending {
+    snapshots: Vec<Snapshot>,
+    index: HashMap<String, usize>,
+}
+
+/// Snapshots waiting for the next flush, the newest of each pid in the order the pids first
+/// arrived since the last flush.
+#[derive(Default)]
+pub(crate) struct Coalescer(Mutex<Pending>);
+
+impl Coalescer {
+    /// Keeps the snapshot in place of the pending one of its pid.
+    pub(crate) fn push(&self, snapshot: Snapshot) {
+        let mut pending = self.0.lock().unwrap();
+        let Pending { snapshots, index } = &mut *pending;
+        match index.get(&snapshot.pid) {
+            Some(idx) => snapshots[*idx] = snapshot,
+            None => {
+                index.insert(snapshot.pid.clone(), snapshots.len());
+                snapshots.push(snapshot);
+            }
+        }
+    }
+
+    /// Returns the pending snapshots, leaving none.
+    pub(crate) fn take(&self) -> Vec<Snapshot> {
+        let mut pending = self.0.lock().unwrap();
+        pending.index.clear();
+        mem::take(&mut pending.snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_coalescer() {
+        let snapshot = |pid: &str, timestamp| Snapshot {
+            pid: pid.to_string(),
+            timestamp,
+            ..Default::default()
+        };
+        let coalescer = Coalescer::default();
+        coalescer.push(snapshot("945629", 1));
+        coalescer.push(snapshot("8984", 2));
+        coalescer.push(snapshot("945629", 3));
+
+        let flushed: Vec<_> = coalescer
+            .take()
+            .into_iter()
+            .map(|snapshot| (snapshot.pid, snapshot.timestamp))
+            .collect();
+        assert_eq!(
+            flushed,
+            [("945629".to_string(), 3), ("8984".to_string(), 2)]
+        );
+        assert!(coalescer.take().is_empty());
+    }
+}
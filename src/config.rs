@@ -0,0 +1,287 @@
+//! Options of a stream as plain data, to keep them in a configuration file per deployment,
+//! e.g. JSON with serde_json or TOML with the toml crate, see Stream::from_config(..)
+//!
+//! The durations are in milliseconds. The missing fields keep the defaults of StreamBuilder,
+//! and unknown fields fail the loading, to catch the typos.
+
+use std::time::Duration;
+
+use chrono::FixedOffset;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{InstrumentClass, NumberFormat, TurnoverPolicy},
+    error::Error,
+    push::{Stream, StreamBuilder},
+};
+
+/// Options of StreamBuilder which can be written down, all but the pair ids optional.
+///
+/// ```
+/// use forexpros_wss::config::StreamConfig;
+///
+/// let config: StreamConfig = serde_json::from_str(
+///     r#"{"pids":["945629","8984"],"connect_timeout_ms":5000,"max_connect_attempts":5}"#,
+/// )
+/// .unwrap();
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    /// Pair ids, see Stream::new(..)
+    pub pids: Vec<String>,
+
+    /// See StreamBuilder::url(..)
+    pub url: Option<String>,
+
+    /// See StreamBuilder::domain(..)
+    pub domain: Option<String>,
+
+    /// See StreamBuilder::path_template(..)
+    pub path_template: Option<String>,
+
+    /// See StreamBuilder::preferred_hosts(..)
+    pub preferred_hosts: Vec<String>,
+
+    /// See StreamBuilder::uid(..)
+    pub uid: Option<u64>,
+
+    /// See StreamBuilder::tz_id(..)
+    pub tz_id: Option<u32>,
+
+    /// See StreamBuilder::utc_offset(..), in seconds east of UTC, e.g. -18000 for UTC-5.
+    /// Less than a day either way.
+    pub utc_offset_secs: Option<i32>,
+
+    /// See StreamBuilder::connect_timeout(..)
+    pub connect_timeout_ms: Option<u64>,
+
+    /// See StreamBuilder::max_connect_attempts(..)
+    pub max_connect_attempts: Option<u32>,
+
+    /// See StreamBuilder::retry_delay(..)
+    pub retry_delay_ms: Option<u64>,
+
+    /// See StreamBuilder::retry_jitter(..)
+    pub retry_jitter_ms: Option<u64>,
+
+    /// See StreamBuilder::host_cooldown(..)
+    pub host_cooldown_ms: Option<u64>,
+
+    /// See StreamBuilder::ping_interval(..)
+    pub ping_interval_ms: Option<u64>,
+
+    /// See StreamBuilder::first_data_timeout(..)
+    pub first_data_timeout_ms: Option<u64>,
+
+    /// See StreamBuilder::session_max_age(..)
+    pub session_max_age_ms: Option<u64>,
+
+    /// See StreamBuilder::max_lifetime(..)
+    pub max_lifetime_ms: Option<u64>,
+
+    /// See StreamBuilder::subscribe_batch_size(..)
+    pub subscribe_batch_size: Option<usize>,
+
+    /// See StreamBuilder::subscribe_batch_delay(..)
+    pub subscribe_batch_delay_ms: Option<u64>,
+
+    /// See StreamBuilder::handler_concurrency(..)
+    pub handler_concurrency: Option<usize>,
+
+    /// See StreamBuilder::coalesce(..)
+    pub coalesce_ms: Option<u64>,
+
+    /// See StreamBuilder::active_window(..)
+    pub active_window_ms: Option<u64>,
+
+    /// See StreamBuilder::replay_depth(..)
+    pub replay_depth: Option<usize>,
+
+    /// See StreamBuilder::number_format(..)
+    pub number_format: Option<NumberFormat>,
+
+    /// See StreamBuilder::instrument_class(..)
+    pub instrument_class: Option<InstrumentClass>,
+
+    /// See StreamBuilder::turnover_policy(..)
+    pub turnover_policy: Option<TurnoverPolicy>,
+
+    /// See StreamBuilder::keep_raw(..)
+    pub keep_raw: bool,
+
+    /// See StreamBuilder::field_stats(..)
+    pub field_stats: bool,
+
+    /// See StreamBuilder::lossy_utf8(..)
+    pub lossy_utf8: bool,
+
+    /// See StreamBuilder::numeric_pair_ids(..)
+    pub numeric_pair_ids: bool,
+
+    /// See StreamBuilder::current_thread(..)
+    pub current_thread: bool,
+}
+
+impl StreamConfig {
+    /// Checks the options building the stream would reject: Error::InvalidPairId without pair
+    /// ids, for an empty one or one containing a comma, and for a non-numeric one with
+    /// `numeric_pair_ids`. Error::EmptyDomain for an empty domain. Error::InvalidUtcOffset for
+    /// a `utc_offset_secs` of a day or more.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |pid: &String| {
+            let pid = pid.trim();
+            pid.is_empty()
+                || pid.contains(',')
+                || (self.numeric_pair_ids && !pid.bytes().all(|b| b.is_ascii_digit()))
+        };
+        if self.pids.is_empty() || self.pids.iter().any(invalid) {
+            return Err(Error::InvalidPairId(self.pids.join(",")));
+        }
+        if self.domain.as_deref() == Some("") {
+            return Err(Error::EmptyDomain);
+        }
+        if let Some(secs) = self.utc_offset_secs {
+            if FixedOffset::east_opt(secs).is_none() {
+                return Err(Error::InvalidUtcOffset(secs));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the builder with the options, once validated, to add the options which cannot
+    /// be written down, such as the hooks.
+    pub fn builder(&self) -> Result<StreamBuilder, Error> {
+        self.validate()?;
+        let millis = Duration::from_millis;
+
+        let mut builder = Stream::builder(self.pids.join(","))
+            .keep_raw(self.keep_raw)
+            .field_stats(self.field_stats)
+            .lossy_utf8(self.lossy_utf8)
+            .numeric_pair_ids(self.numeric_pair_ids)
+            .current_thread(self.current_thread)
+            .ping_interval(self.ping_interval_ms.map(millis))
+            .first_data_timeout(self.first_data_timeout_ms.map(millis))
+            .session_max_age(self.session_max_age_ms.map(millis))
+            .max_lifetime(self.max_lifetime_ms.map(millis))
+            .coalesce(self.coalesce_ms.map(millis));
+        if let Some(url) = &self.url {
+            builder = builder.url(url);
+        }
+        if let Some(domain) = &self.domain {
+            builder = builder.domain(domain);
+        }
+        if let Some(template) = &self.path_template {
+            builder = builder.path_template(template);
+        }
+        if !self.preferred_hosts.is_empty() {
+            builder = builder.preferred_hosts(self.preferred_hosts.clone());
+        }
+        if let Some(uid) = self.uid {
+            builder = builder.uid(uid);
+        }
+        if let Some(tz_id) = self.tz_id {
+            builder = builder.tz_id(tz_id);
+        }
+        if let Some(offset) = self.utc_offset_secs.and_then(FixedOffset::east_opt) {
+            builder = builder.utc_offset(offset);
+        }
+        if let Some(timeout) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(millis(timeout));
+        }
+        if let Some(attempts) = self.max_connect_attempts {
+            builder = builder.max_connect_attempts(attempts);
+        }
+        if let Some(delay) = self.retry_delay_ms {
+            builder = builder.retry_delay(millis(delay));
+        }
+        if let Some(jitter) = self.retry_jitter_ms {
+            builder = builder.retry_jitter(millis(jitter));
+        }
+        if let Some(cooldown) = self.host_cooldown_ms {
+            builder = builder.host_cooldown(millis(cooldown));
+        }
+        if let Some(size) = self.subscribe_batch_size {
+            builder = builder.subscribe_batch_size(size);
+        }
+        if let Some(delay) = self.subscribe_batch_delay_ms {
+            builder = builder.subscribe_batch_delay(millis(delay));
+        }
+        if let Some(limit) = self.handler_concurrency {
+            builder = builder.handler_concurrency(limit);
+        }
+        if let Some(window) = self.active_window_ms {
+            builder = builder.active_window(millis(window));
+        }
+        if let Some(depth) = self.replay_depth {
+            builder = builder.replay_depth(depth);
+        }
+        if let Some(number_format) = self.number_format {
+            builder = builder.number_format(number_format);
+        }
+        if let Some(class) = self.instrument_class {
+            builder = builder.instrument_class(class);
+        }
+        if let Some(policy) = self.turnover_policy {
+            builder = builder.turnover_policy(policy);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_config() {
+        let src = r#"{"pids":["945629","8984"],"domain":"investing.com","connect_timeout_ms":5000,
+            "ping_interval_ms":20000,"number_format":"CommaDecimal","numeric_pair_ids":true,
+            "instrument_class":"Sessioned"}"#;
+        let config: StreamConfig = serde_json::from_str(src).unwrap();
+        assert_eq!(config.pids, ["945629", "8984"]);
+        assert_eq!(config.connect_timeout_ms, Some(5000));
+        assert_eq!(config.number_format, Some(NumberFormat::CommaDecimal));
+        assert_eq!(config.instrument_class, Some(InstrumentClass::Sessioned));
+        assert_eq!(config.max_connect_attempts, None);
+        assert!(config.builder().is_ok());
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<StreamConfig>(&json).unwrap(), config);
+
+        // typos fail the loading
+        assert!(serde_json::from_str::<StreamConfig>(r#"{"pid":["945629"]}"#).is_err());
+    }
+
+    #[test]
+    pub fn test_validate() {
+        let mut config = StreamConfig::default();
+        assert!(matches!(config.validate(), Err(Error::InvalidPairId(_))));
+
+        config.pids = vec!["945629".to_string(), " ".to_string()];
+        assert!(matches!(config.validate(), Err(Error::InvalidPairId(_))));
+
+        config.pids = vec!["945629,8984".to_string()];
+        assert!(matches!(config.validate(), Err(Error::InvalidPairId(_))));
+
+        config.pids = vec!["945629".to_string(), "EUR/USD".to_string()];
+        assert!(config.validate().is_ok());
+        config.numeric_pair_ids = true;
+        assert!(matches!(config.builder(), Err(Error::InvalidPairId(_))));
+
+        config.pids = vec!["945629".to_string()];
+        config.domain = Some(String::new());
+        assert!(matches!(config.validate(), Err(Error::EmptyDomain)));
+
+        config.domain = None;
+        config.utc_offset_secs = Some(-18000);
+        assert!(config.validate().is_ok());
+        config.utc_offset_secs = Some(86400);
+        assert!(matches!(
+            config.validate(),
+            Err(Error::InvalidUtcOffset(86400))
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+//! CSV export of snapshots, enabled by the `csv` feature.
+//!
+//! Prices are written as parsed numbers rather than the comma-formatted strings from the
+//! server, so the output can be loaded directly by pandas, Excel and similar tools.
+//! A value which cannot be parsed is written as an empty cell.
+
+use std::io::{self, Write};
+
+use crate::data::Snapshot;
+
+/// Column names in the order written by `Snapshot::write_csv_row`.
+pub const CSV_COLUMNS: [&str; 11] = [
+    "pid",
+    "last",
+    "bid",
+    "ask",
+    "high",
+    "low",
+    "last_close",
+    "pc",
+    "pcp",
+    "turnover",
+    "timestamp",
+];
+
+impl Snapshot {
+    /// Writes the CSV header line matching `write_csv_row`.
+    pub fn write_csv_header<W: Write>(w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", CSV_COLUMNS.join(","))
+    }
+
+    /// Writes this snapshot as a single CSV line.
+    pub fn write_csv_row<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let numbers = [
+            self.last_f64(),
+            self.bid_f64(),
+            self.ask_f64(),
+            self.high_f64(),
+            self.low_f64(),
+            self.last_close_f64(),
+            self.pc_f64(),
+            self.pcp_f64(),
+            Some(self.turnover_numeric as f64),
+        ];
+
+        write!(w, "{}", escape(&self.pid))?;
+        for number in numbers.iter() {
+            match number {
+                Some(number) => write!(w, ",{}", number)?,
+                None => write!(w, ",")?,
+            }
+        }
+        writeln!(w, ",{}", self.timestamp)
+    }
+}
+
+/// Quotes a field when it contains a separator, a quote or a line break.
+fn escape(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_write_csv() {
+        let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.5,"last":"18,951.5","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
+		"last_close":"","pc":"-236.8","pcp":"-1.23%","pc_col":"redFont","turnover":"21.50K",
+		"turnover_numeric":21503,"time":"19:21:50","timestamp":1606850510}"#;
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+
+        let mut out = Vec::new();
+        Snapshot::write_csv_header(&mut out).unwrap();
+        snapshot.write_csv_row(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "pid,last,bid,ask,high,low,last_close,pc,pcp,turnover,timestamp\n\
+             945629,18951.5,18954,18956,19956,18279,,-236.8,-1.23,21503,1606850510\n"
+        );
+    }
+
+    #[test]
+    pub fn test_escape() {
+        assert_eq!(escape("945629"), "945629");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("a\"b"), "\"a\"\"b\"");
+    }
+}
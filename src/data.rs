@@ -1,4 +1,11 @@
-use std::fmt;
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone, Utc};
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fmt, fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use serde::{
     self,
@@ -6,25 +13,56 @@ use serde::{
     Deserialize, Deserializer, Serialize,
 };
 
+use crate::{
+    clock::{Clock, SystemClock},
+    error::Error,
+    protocol::{is_heartbeat_frame, parse_sockjs_messages, parse_uid_frame, FrameMarkers},
+};
+
+/// Descriptive names accepted for the fields of Snapshot, see there. The fields of
+/// SnapshotData besides those of Snapshot, which test_field_aliases_agree checks.
+pub const FIELD_ALIASES: [&str; 7] = [
+    "last_price",
+    "previous_close",
+    "change",
+    "change_percent",
+    "change_color",
+    "volume",
+    "volume_numeric",
+];
+
 /// Snapshot data of instrument from server
 ///
+/// Only `pid` and `last_numeric` are guaranteed present, and `timestamp` nearly so.
+/// Instruments omit some of the other fields, e.g. `bid` and `ask` of indices or `pc_col`:
+/// those are empty, or None for `last_dir`, when missing from the server data.
+///
+/// # Renamed fields
+///
+/// The fields most likely renamed by the provider also accept a descriptive name, e.g.
+/// `change_percent` for `pcp`, so a rename does not silently empty them. Add the new name to
+/// the fields of SnapshotData when a rename shows up, and to FIELD_ALIASES, and keep the old
+/// name as the field name, which is also the serialized one. An object carrying both names
+/// takes the value of the field name.
+///
 /// # Source example:
 ///
 /// "a[\"{\\\"message\\\":\\\"pid-945629::{\\\\\\\"pid\\\\\\\":\\\\\\\"945629\\\\\\\",\\\\\\\"last_dir\\\\\\\":\\\\\\\"redBg\\\\\\\",\\\\\\\"last_numeric\\\\\\\":18951.2,\\\\\\\"last\\\\\\\":\\\\\\\"18,951.2\\\\\\\",\\\\\\\"bid\\\\\\\":\\\\\\\"18,954.0\\\\\\\",\\\\\\\"ask\\\\\\\":\\\\\\\"18,956.0\\\\\\\",\\\\\\\"high\\\\\\\":\\\\\\\"19,956.0\\\\\\\",\\\\\\\"low\\\\\\\":\\\\\\\"18,279.0\\\\\\\",\\\\\\\"last_close\\\\\\\":\\\\\\\"19,188.0\\\\\\\",\\\\\\\"pc\\\\\\\":\\\\\\\"-236.8\\\\\\\",\\\\\\\"pcp\\\\\\\":\\\\\\\"-1.23%\\\\\\\",\\\\\\\"pc_col\\\\\\\":\\\\\\\"redFont\\\\\\\",\\\\\\\"turnover\\\\\\\":\\\\\\\"21.50K\\\\\\\",\\\\\\\"turnover_numeric\\\\\\\":21503,\\\\\\\"time\\\\\\\":\\\\\\\"19:21:50\\\\\\\",\\\\\\\"timestamp\\\\\\\":1606850510}\\\"}\"]"
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(try_from = "SnapshotData")]
 pub struct Snapshot {
     pub pid: String,
 
     //#[serde(skip_deserializing)]
     pub last_dir: Option<Box<str>>,
-    pub last_numeric: f32,
+    /// Last price in full precision, for quotes with 4 or 5 decimals such as EUR/USD.
+    pub last_numeric: f64,
     pub last: Box<str>,
     pub bid: String,
     pub ask: String,
     pub high: String,
     pub low: String,
 
-    #[serde(default)]
     pub last_close: String,
 
     //#[serde(skip_deserializing)]
@@ -37,71 +75,1330 @@ pub struct Snapshot {
     pub pc_col: String,
 
     //#[serde(skip_deserializing)]
-    #[serde(default)]
     pub turnover: String,
 
-    #[serde(
-        deserialize_with = "deserialize_u32_or_string",
-        default = "default_zero"
-    )]
-    pub turnover_numeric: u32,
+    /// Turnover as a number, 0 when missing, empty or malformed. Whether the server sent a
+    /// valid one is told by `turnover_numeric_opt` alone, not by 0.
+    pub turnover_numeric: u64,
+
+    /// `turnover_numeric` when the server sent a valid one, None when it is missing, empty or
+    /// malformed. This is the source of truth on the presence of the turnover, a
+    /// `turnover_numeric` of 0 being either a turnover of 0 or none. Only recorded by the
+    /// policies other than TurnoverPolicy::ZeroOnMissing, and None otherwise, see
+    /// StreamBuilder::turnover_policy(..)
+    #[serde(skip)]
+    pub turnover_numeric_opt: Option<u64>,
 
     //#[serde(skip_deserializing)]
     pub time: String,
+
+    /// Server time in unix seconds, 0 when missing from the server data. The time accessors
+    /// such as `datetime_utc()` return None for 0 rather than a date of 1970.
     pub timestamp: u64,
+
+    /// Local time when the frame carrying this snapshot was read from the socket.
+    /// It is not part of the server data, so None when parsed outside of the stream.
+    #[serde(skip)]
+    pub received_at: Option<SystemTime>,
+
+    /// Number of the snapshot in the order received by the stream, counting from 0 across the
+    /// reconnects, independent of the server timestamp. A gap means snapshots were parsed but
+    /// not delivered, e.g. dropped by the channel or filtered. None when parsed outside of the stream.
+    #[serde(skip)]
+    pub seq: Option<u64>,
+
+    /// Separators of the price fields, used by the numeric accessors such as `last_f64()`.
+    #[serde(skip)]
+    pub number_format: NumberFormat,
+
+    /// Correction of the server clock added to `timestamp` by `datetime_utc()` and `age()`,
+    /// e.g. from estimate_clock_offset(..), see StreamBuilder::clock_offset(..)
+    #[serde(skip)]
+    pub clock_offset: Option<chrono::Duration>,
+
+    /// UTC offset of the time zone `time` is in, i.e. of the tzID subscribed with, see
+    /// StreamBuilder::utc_offset(..) and `local_time()`. None when not given.
+    #[serde(skip)]
+    pub utc_offset: Option<FixedOffset>,
+
+    /// JSON object of the instrument data as sent by the server, to inspect a field which
+    /// looks wrong. Only kept by Snapshot::try_from_str_raw(..), see StreamBuilder::keep_raw(..)
+    #[serde(skip)]
+    pub raw: Option<String>,
+
+    /// SockJS frame the snapshot was parsed from, exactly as received. Only kept by
+    /// StreamBuilder::build_raw(..), which hands it to the handler.
+    #[serde(skip)]
+    pub frame: Option<String>,
 }
 
-fn default_zero() -> u32 {
+/// Instrument data as sent, each field under either of its names, deserialized into Snapshot.
+/// The field name is taken over the descriptive one when both are sent.
+#[derive(Deserialize)]
+struct SnapshotData {
+    pid: String,
+    last_dir: Option<Box<str>>,
+    last_numeric: Option<f64>,
+    last_price: Option<f64>,
+    #[serde(default)]
+    last: Box<str>,
+    #[serde(default)]
+    bid: String,
+    #[serde(default)]
+    ask: String,
+    #[serde(default)]
+    high: String,
+    #[serde(default)]
+    low: String,
+    last_close: Option<String>,
+    previous_close: Option<String>,
+    pc: Option<String>,
+    change: Option<String>,
+    pcp: Option<String>,
+    change_percent: Option<String>,
+    pc_col: Option<String>,
+    change_color: Option<String>,
+    turnover: Option<String>,
+    volume: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some_u64_or_string")]
+    turnover_numeric: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_some_u64_or_string")]
+    volume_numeric: Option<u64>,
+    #[serde(default)]
+    time: String,
+    #[serde(default)]
+    timestamp: u64,
+}
+
+impl TryFrom<SnapshotData> for Snapshot {
+    type Error = &'static str;
+
+    fn try_from(data: SnapshotData) -> Result<Self, Self::Error> {
+        Ok(Snapshot {
+            pid: data.pid,
+            last_dir: data.last_dir,
+            last_numeric: data
+                .last_numeric
+                .or(data.last_price)
+                .ok_or("missing field `last_numeric`")?,
+            last: data.last,
+            bid: data.bid,
+            ask: data.ask,
+            high: data.high,
+            low: data.low,
+            last_close: data.last_close.or(data.previous_close).unwrap_or_default(),
+            pc: data.pc.or(data.change).unwrap_or_default(),
+            pcp: data.pcp.or(data.change_percent).unwrap_or_default(),
+            pc_col: data.pc_col.or(data.change_color).unwrap_or_default(),
+            turnover: data.turnover.or(data.volume).unwrap_or_default(),
+            turnover_numeric: data
+                .turnover_numeric
+                .or(data.volume_numeric)
+                .unwrap_or_else(default_zero),
+            time: data.time,
+            timestamp: data.timestamp,
+            ..Snapshot::default()
+        })
+    }
+}
+
+/// Pid, last price and change percent, e.g. "945629 18,951.2 (-1.23%)"
+///
+/// The last price has the precision if given, e.g. `format!("{:.5}", snapshot)` for FX
+/// quotes, or else the decimals of the server, see `Snapshot::last_decimals()`. The parts
+/// missing from the server data are left out.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pid)?;
+        if let Some(price) = self.last_price() {
+            match f.precision().or_else(|| self.last_decimals()) {
+                Some(precision) => write!(f, " {:.*}", precision, price)?,
+                None => write!(f, " {}", price)?,
+            }
+        }
+        if let Some(percent) = self.change_percent() {
+            write!(f, " ({:+.2})", percent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Server data field of Snapshot, see Snapshot::fields_eq(..)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SnapshotField {
+    Pid,
+    LastDir,
+    LastNumeric,
+    Last,
+    Bid,
+    Ask,
+    High,
+    Low,
+    LastClose,
+    Pc,
+    Pcp,
+    PcCol,
+    Turnover,
+    TurnoverNumeric,
+    Time,
+    Timestamp,
+}
+
+/// Fields compared by Snapshot::content_eq(..): all but `time` and `timestamp`,
+/// which change with every tick even when the prices don't.
+pub const CONTENT_FIELDS: [SnapshotField; 14] = [
+    SnapshotField::Pid,
+    SnapshotField::LastDir,
+    SnapshotField::LastNumeric,
+    SnapshotField::Last,
+    SnapshotField::Bid,
+    SnapshotField::Ask,
+    SnapshotField::High,
+    SnapshotField::Low,
+    SnapshotField::LastClose,
+    SnapshotField::Pc,
+    SnapshotField::Pcp,
+    SnapshotField::PcCol,
+    SnapshotField::Turnover,
+    SnapshotField::TurnoverNumeric,
+];
+
+/// Currency symbols skipped by NumberFormat::parse(..) before the digits
+pub const CURRENCY_SYMBOLS: [char; 4] = ['$', '€', '£', '¥'];
+
+/// Separators of the numbers formatted by the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberFormat {
+    /// Comma thousands separator and dot decimal separator, "18,954.0"
+    #[default]
+    DotDecimal,
+
+    /// Dot thousands separator and comma decimal separator, "18.954,0"
+    CommaDecimal,
+}
+
+impl NumberFormat {
+    /// Parses a number formatted by the server, such as "18,951.2", "+364.0" or "-1.23%".
+    /// A currency symbol of CURRENCY_SYMBOLS before the digits is skipped, e.g. "$1,234.5" or "-€0.5".
+    ///
+    /// Returns None for an empty or malformed value.
+    pub fn parse(self, src: &str) -> Option<f64> {
+        let (thousands, decimal) = self.separators();
+        let src = src.trim();
+        let (sign, digits) = match src.strip_prefix(&['+', '-'][..]) {
+            Some(digits) => (&src[..1], digits),
+            None => ("", src),
+        };
+        let digits = digits.strip_prefix(&CURRENCY_SYMBOLS[..]).unwrap_or(digits);
+        let cleaned: String = sign
+            .chars()
+            .chain(digits.trim_end_matches('%').chars())
+            .filter(|c| *c != thousands)
+            .map(|c| if c == decimal { '.' } else { c })
+            .collect();
+
+        if cleaned.is_empty() {
+            return None;
+        }
+        cleaned.parse().ok()
+    }
+}
+
+impl NumberFormat {
+    /// Thousands and decimal separators.
+    fn separators(self) -> (char, char) {
+        match self {
+            NumberFormat::DotDecimal => (',', '.'),
+            NumberFormat::CommaDecimal => ('.', ','),
+        }
+    }
+
+    /// Number of digits after the decimal separator, 0 without one.
+    fn decimals(self, src: &str) -> usize {
+        let (_, decimal) = self.separators();
+        src.split_once(decimal).map_or(0, |(_, fraction)| {
+            fraction.chars().take_while(char::is_ascii_digit).count()
+        })
+    }
+}
+
+/// What the parsing does with a `turnover_numeric` which is missing, empty or malformed,
+/// e.g. "olia", see Snapshot::try_from_str_with(..)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnoverPolicy {
+    /// `turnover_numeric` is 0, as for a turnover of 0.
+    #[default]
+    ZeroOnMissing,
+
+    /// `turnover_numeric` is 0 and `turnover_numeric_opt` None, which is the valid
+    /// `turnover_numeric` otherwise.
+    NoneOnMissing,
+
+    /// The snapshot fails to parse. Instruments without turnover, such as the FX pairs, fail
+    /// as well.
+    Error,
+}
+
+/// Snapshot with the `turnover_numeric` of the instrument data as sent, taken by this struct
+/// before the snapshot deserializes the other fields, so the policy sees it in the same pass.
+#[derive(Deserialize)]
+struct TurnoverSnapshot {
+    #[serde(default)]
+    turnover_numeric: Option<serde_json::Value>,
+
+    #[serde(default)]
+    volume_numeric: Option<serde_json::Value>,
+
+    #[serde(flatten)]
+    snapshot: Snapshot,
+}
+
+impl TurnoverPolicy {
+    /// Deserializes the instrument data with the policy. The default policy deserializes the
+    /// snapshot alone, the others record the `turnover_numeric` as sent while deserializing it.
+    fn deserialize(self, json: &str) -> Result<Snapshot, serde_json::Error> {
+        if self == TurnoverPolicy::ZeroOnMissing {
+            return serde_json::from_str(json);
+        }
+        let TurnoverSnapshot {
+            turnover_numeric,
+            volume_numeric,
+            mut snapshot,
+        } = serde_json::from_str(json)?;
+        let turnover_numeric = turnover_numeric.or(volume_numeric);
+        let turnover = match &turnover_numeric {
+            Some(serde_json::Value::Number(number)) => number.as_u64(),
+            Some(serde_json::Value::String(turnover)) => turnover.trim().parse().ok(),
+            _ => None,
+        };
+        if let (TurnoverPolicy::Error, None) = (self, turnover) {
+            return Err(de::Error::custom(format!(
+                "missing or malformed turnover_numeric: {}",
+                turnover_numeric.unwrap_or_default()
+            )));
+        }
+        if let Some(value) = &turnover_numeric {
+            snapshot.turnover_numeric = deserialize_u64_or_string(value)?;
+        }
+        snapshot.turnover_numeric_opt = turnover;
+        Ok(snapshot)
+    }
+}
+
+/// Shortest quiet period of an InstrumentClass::Sessioned instrument taken for the break
+/// between two sessions, e.g. overnight or over the lunch break of HK50.
+pub const SESSION_BREAK: Duration = Duration::from_secs(3600);
+
+/// Trading hours of the instruments, a hint for the heuristics telling a quiet market from a
+/// feed problem, see StreamBuilder::instrument_class(..)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstrumentClass {
+    /// Traded around the clock, e.g. BTC/USD: a quiet period is more likely a feed problem.
+    #[default]
+    Continuous,
+
+    /// Traded in sessions, e.g. HK50: quiet overnight and on weekends as expected.
+    Sessioned,
+}
+
+impl InstrumentClass {
+    /// Whether a period without snapshots is the break between two sessions rather than
+    /// missing data: one of at least SESSION_BREAK for Sessioned, never for Continuous.
+    pub fn is_session_break(self, quiet: Duration) -> bool {
+        self == InstrumentClass::Sessioned && quiet >= SESSION_BREAK
+    }
+}
+
+fn default_zero() -> u64 {
     0
 }
 
-fn deserialize_u32_or_string<'de, D>(deserializer: D) -> Result<u32, D::Error>
+/// Lenient turnover: a number, or a string of digits, else 0 rather than failing the snapshot.
+fn deserialize_u64_or_string<'de, D>(deserializer: D) -> Result<u64, D::Error>
 where
     D: Deserializer<'de>,
 {
-    struct U32Visitor;
+    struct U64Visitor;
+
+    impl<'de> Visitor<'de> for U64Visitor {
+        type Value = u64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("u64 or string")
+        }
+        fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(val)
+        }
+
+        fn visit_i64<E>(self, _val: i64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            // only negative integers arrive here
+            Ok(default_zero())
+        }
+
+        fn visit_f64<E>(self, val: f64) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            // saturating, NaN is 0
+            Ok(val as Self::Value)
+        }
+
+        fn visit_str<E>(self, sval: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(sval.trim().parse().unwrap_or_else(|_| default_zero()))
+        }
+    }
+    let visitor = U64Visitor;
+    deserializer.deserialize_any(visitor)
+}
+
+/// Same as deserialize_u64_or_string(..), for a field told apart when missing.
+fn deserialize_some_u64_or_string<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_u64_or_string(deserializer).map(Some)
+}
+
+/// Price of an instrument, see `Snapshot::last_price()`
+///
+/// Displayed with comma thousands separators, e.g. "18,951.2", with the precision if given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Price(pub f64);
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let number = match f.precision() {
+            Some(precision) => format!("{:.*}", precision, self.0.abs()),
+            None => format!("{}", self.0.abs()),
+        };
+        let (integer, fraction) = match number.find('.') {
+            Some(idx) => number.split_at(idx),
+            None => (number.as_str(), ""),
+        };
+
+        if self.0.is_sign_negative() && self.0 != 0.0 {
+            write!(f, "-")?;
+        }
+        for (idx, digit) in integer.chars().enumerate() {
+            if idx > 0 && (integer.len() - idx) % 3 == 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", digit)?;
+        }
+        write!(f, "{}", fraction)
+    }
+}
+
+/// Percentage, e.g. Percent(-1.23) for "-1.23%", see `Snapshot::change_percent()`
+///
+/// Displayed with the precision if given, and with "+" for positive values with the `+` flag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Percent(pub f32);
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.sign_plus() && self.0 >= 0.0 {
+            write!(f, "+")?;
+        }
+        match f.precision() {
+            Some(precision) => write!(f, "{:.*}%", precision, self.0),
+            None => write!(f, "{}%", self.0),
+        }
+    }
+}
+
+/// Direction of the last price of `last_dir`, see `Snapshot::direction()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// "greenBg", the price went up.
+    Up,
+
+    /// "redBg", the price went down.
+    Down,
+
+    /// Any other value, e.g. a mangled "$reenBg" seen in the feed.
+    Unknown,
+}
+
+impl Direction {
+    /// Classifies the CSS class sent by the server. Only exact known values are Up or Down.
+    pub fn parse(src: &str) -> Self {
+        match src {
+            "greenBg" | "greenFont" => Direction::Up,
+            "redBg" | "redFont" => Direction::Down,
+            _ => Direction::Unknown,
+        }
+    }
+}
+
+/// Direction of the session of an instrument, reconciled from its fields, see `Snapshot::sentiment()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sentiment {
+    /// Up from the previous close.
+    Bullish,
+
+    /// Down from the previous close.
+    Bearish,
+
+    /// Unchanged from the previous close.
+    Flat,
+
+    /// No field tells the direction.
+    Unknown,
+}
+
+impl From<Direction> for Sentiment {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => Sentiment::Bullish,
+            Direction::Down => Sentiment::Bearish,
+            Direction::Unknown => Sentiment::Unknown,
+        }
+    }
+}
+
+/// Health of the quote of a snapshot at a glance, see Snapshot::quote_quality()
+///
+/// The numbers are None when a field they need is empty or malformed, e.g. the bid and ask
+/// of indices.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuoteQuality {
+    /// See Snapshot::spread()
+    pub spread: Option<f64>,
+
+    /// See Snapshot::mid()
+    pub mid: Option<f64>,
+
+    /// Whether the bid is above the ask. False without both.
+    pub crossed: bool,
+
+    /// See Snapshot::age()
+    pub age: Option<Duration>,
+}
+
+/// Number of snapshots missing each field of the server data, see StreamBuilder::field_stats(..)
+///
+/// A price field counts as missing when absent, empty or unparseable by its accessor, e.g. a
+/// `last` of "-" behind a valid `last_numeric`, the other string fields when absent or empty,
+/// `last_dir` when None, `timestamp` when 0, and `turnover_numeric` when `turnover_numeric_opt`
+/// is None and it fell back to 0, which with the default TurnoverPolicy::ZeroOnMissing includes
+/// a turnover of 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FieldStats {
+    /// Snapshots counted, those missing each field among them.
+    pub snapshots: u64,
+    /// Frames of the pid which failed to parse, not counted in `snapshots`.
+    pub parse_errors: u64,
+    pub last_dir: u64,
+    pub last: u64,
+    pub bid: u64,
+    pub ask: u64,
+    pub high: u64,
+    pub low: u64,
+    pub last_close: u64,
+    pub pc: u64,
+    pub pcp: u64,
+    pub pc_col: u64,
+    pub turnover: u64,
+    pub turnover_numeric: u64,
+    pub time: u64,
+    pub timestamp: u64,
+}
+
+impl FieldStats {
+    /// Counts the snapshot, and each of its fields missing, the prices parsed with its
+    /// `number_format`.
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        let count = |counter: &mut u64, missing: bool| *counter += missing as u64;
+        let price = |counter: &mut u64, price: &str| {
+            count(counter, snapshot.number_format.parse(price).is_none())
+        };
+        self.snapshots += 1;
+        count(&mut self.last_dir, snapshot.last_dir.is_none());
+        price(&mut self.last, &snapshot.last);
+        price(&mut self.bid, &snapshot.bid);
+        price(&mut self.ask, &snapshot.ask);
+        price(&mut self.high, &snapshot.high);
+        price(&mut self.low, &snapshot.low);
+        price(&mut self.last_close, &snapshot.last_close);
+        price(&mut self.pc, &snapshot.pc);
+        price(&mut self.pcp, &snapshot.pcp);
+        count(&mut self.pc_col, snapshot.pc_col.is_empty());
+        count(&mut self.turnover, snapshot.turnover_parsed().is_none());
+        count(
+            &mut self.turnover_numeric,
+            snapshot.turnover_numeric == 0 && snapshot.turnover_numeric_opt.is_none(),
+        );
+        count(&mut self.time, snapshot.time.is_empty());
+        count(&mut self.timestamp, snapshot.timestamp == 0);
+    }
+
+    /// Returns the counts of both, e.g. the total of several pids.
+    pub fn merge(self, other: &FieldStats) -> Self {
+        FieldStats {
+            snapshots: self.snapshots + other.snapshots,
+            parse_errors: self.parse_errors + other.parse_errors,
+            last_dir: self.last_dir + other.last_dir,
+            last: self.last + other.last,
+            bid: self.bid + other.bid,
+            ask: self.ask + other.ask,
+            high: self.high + other.high,
+            low: self.low + other.low,
+            last_close: self.last_close + other.last_close,
+            pc: self.pc + other.pc,
+            pcp: self.pcp + other.pcp,
+            pc_col: self.pc_col + other.pc_col,
+            turnover: self.turnover + other.turnover,
+            turnover_numeric: self.turnover_numeric + other.turnover_numeric,
+            time: self.time + other.time,
+            timestamp: self.timestamp + other.timestamp,
+        }
+    }
+}
+
+/// Fields of Snapshot commonly used alone, see Snapshot::from_str_minimal(..)
+///
+/// The other fields of the server data are skipped without allocating.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SnapshotMinimal {
+    #[serde(default)]
+    pub pid: String,
+
+    #[serde(default)]
+    pub last_numeric: f64,
+
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+/// Returns the JSON object of the instrument data in the frame from the server, following the
+/// "::" of the first message such as `{"message":"pid-945629::{..}"}`, see parse_sockjs_messages(..),
+/// with the strategy which decoded it.
+fn extract_json(src: &str, markers: &FrameMarkers) -> Option<(String, ParseStrategy)> {
+    let json = parse_sockjs_messages(src)
+        .ok()
+        .and_then(|messages| message_json(&messages, markers));
+    ParseStrategy::decode(json, || {
+        extract_json_lenient(src, MAX_ESCAPING_DEPTH, markers)
+    })
+}
+
+/// Layers of escaping or wrapping decoded by extract_json_lenient(..)
+const MAX_ESCAPING_DEPTH: usize = 8;
+
+/// Instrument data of a frame escaped more or less than on the wire, depending on how it was
+/// captured: a decoded message, a frame logged as a JSON string with or without the enclosing
+/// quotes, and so on. Decodes a layer at a time until a message or the instrument data is found.
+fn extract_json_lenient(src: &str, depth: usize, markers: &FrameMarkers) -> Option<String> {
+    if depth == 0 {
+        return None;
+    }
+    let src = src.trim();
+    let found = match src.chars().next()? {
+        'a' => parse_sockjs_messages(src).ok().and_then(|messages| {
+            messages
+                .iter()
+                .find_map(|message| extract_json_lenient(message, depth - 1, markers))
+        }),
+        '"' => serde_json::from_str::<String>(src)
+            .ok()
+            .and_then(|decoded| extract_json_lenient(&decoded, depth - 1, markers)),
+        '{' => serde_json::from_str::<serde_json::Value>(src)
+            .ok()
+            .and_then(|value| match value.get("pid") {
+                Some(_) => Some(src.to_string()),
+                None => markers.snapshot_json(src),
+            }),
+        _ => None,
+    };
+    found.or_else(|| {
+        // escaped once more, without the enclosing quotes
+        let decoded: String = serde_json::from_str(&format!("\"{}\"", src)).ok()?;
+        extract_json_lenient(&decoded, depth - 1, markers)
+    })
+}
+
+/// Message of the websockets of a HAR export of the browser, e.g.
+/// `{"type":"receive","time":1606850510.1,"opcode":1,"data":"a[..]"}`
+#[derive(Deserialize)]
+struct DevtoolsMessage {
+    data: String,
+}
+
+/// Frame in the copy of the browser devtools: a message of a HAR export, a string of the
+/// console in quotes, or a row of the messages table after its arrow.
+fn unwrap_devtools(src: &str) -> String {
+    let src = src
+        .trim()
+        .trim_start_matches(&['↓', '↑', '⬇', '⬆', '▼', '▲'][..])
+        .trim();
+    if let Ok(message) = serde_json::from_str::<DevtoolsMessage>(src) {
+        return message.data;
+    }
+    for quote in ['\'', '`'].iter() {
+        if src.len() >= 2 && src.starts_with(*quote) && src.ends_with(*quote) {
+            return src[1..src.len() - 1].to_string();
+        }
+    }
+    src.to_string()
+}
+
+/// Same as extract_json(..) on the bytes of a SockJS array frame, such as a binary message.
+fn extract_json_bytes(src: &[u8], markers: &FrameMarkers) -> Option<(String, ParseStrategy)> {
+    let messages = match src.split_first()? {
+        (b'a', array) => serde_json::from_slice::<Vec<String>>(array).ok(),
+        _ => None,
+    };
+    let json = messages.and_then(|messages| message_json(&messages, markers));
+    ParseStrategy::decode(json, || {
+        extract_json_lenient(std::str::from_utf8(src).ok()?, MAX_ESCAPING_DEPTH, markers)
+    })
+}
+
+/// Instrument data of the first message carrying one, see FrameMarkers::snapshot_json(..)
+fn message_json(messages: &[String], markers: &FrameMarkers) -> Option<String> {
+    messages
+        .iter()
+        .find_map(|message| markers.snapshot_json(message))
+}
+
+/// How the instrument data of a frame was decoded, see Snapshot::try_from_str_strategy(..)
+///
+/// The strategy is chosen on the shape of the data rather than by trying each in turn, so the
+/// data is deserialized once, whichever strategy applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParseStrategy {
+    /// As extracted from the frame, the usual case.
+    Raw,
+    /// Decoded once more as a JSON string, for data escaped a level too many, i.e. with the
+    /// first key in `\"` quotes.
+    UnescapeOnce,
+    /// With the triple-escaped quotes `\\\"` stripped to plain quotes.
+    TripleStrip,
+    /// Decoded a layer at a time, for a frame which is not a SockJS array frame of the server,
+    /// e.g. one captured as a JSON string.
+    Lenient,
+}
+
+impl ParseStrategy {
+    /// The strategies in the order they are tried.
+    pub const ALL: [ParseStrategy; 4] = [
+        ParseStrategy::Raw,
+        ParseStrategy::UnescapeOnce,
+        ParseStrategy::TripleStrip,
+        ParseStrategy::Lenient,
+    ];
+
+    /// Decodes the instrument data extracted from a SockJS frame with the first strategy which
+    /// applies, the lenient extraction of the frame when there was none.
+    fn decode(
+        json: Option<String>,
+        lenient: impl FnOnce() -> Option<String>,
+    ) -> Option<(String, ParseStrategy)> {
+        let json = match json {
+            Some(json) => json,
+            None => return lenient().map(|json| (json, ParseStrategy::Lenient)),
+        };
+        let strategy = ParseStrategy::ALL
+            .iter()
+            .copied()
+            .find(|strategy| strategy.applies(&json))?;
+        let decoded = match strategy {
+            ParseStrategy::UnescapeOnce => serde_json::from_str(&format!("\"{}\"", json)).ok()?,
+            ParseStrategy::TripleStrip => json.replace(r#"\\\""#, "\""),
+            _ => json,
+        };
+        if strategy != ParseStrategy::Raw {
+            log::debug!("Decoded the instrument data with {:?}", strategy);
+        }
+        Some((decoded, strategy))
+    }
+
+    /// Whether the strategy applies to the data extracted from a SockJS frame, judging by the
+    /// quotes of its first key.
+    fn applies(self, json: &str) -> bool {
+        let json = json.trim_start();
+        match self {
+            ParseStrategy::Raw => !json.starts_with(r#"{\"#),
+            ParseStrategy::UnescapeOnce => json.starts_with(r#"{\""#),
+            ParseStrategy::TripleStrip => json.starts_with(r#"{\\\""#),
+            ParseStrategy::Lenient => false,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Given original data from forexpros wss server, returns the Snapshot with extracted data.
+    ///
+    /// The data escaped a level off is decoded as well, see ParseStrategy.
+    pub fn from_str<'a>(src: &'a str) -> Self {
+        Snapshot::try_from_str_strategy(src)
+            .expect("Expect the instrument data")
+            .0
+    }
+
+    /// Same as Snapshot::from_str(..), but returns the error instead of panicking on a malformed frame.
+    pub fn try_from_str(src: &str) -> Result<Self, serde_json::Error> {
+        Snapshot::try_from_str_strategy(src).map(|(snapshot, _)| snapshot)
+    }
+
+    /// Same as Snapshot::try_from_str(..), and returns the strategy which decoded the data, e.g.
+    /// to count the frames escaped a level off.
+    pub fn try_from_str_strategy(src: &str) -> Result<(Self, ParseStrategy), serde_json::Error> {
+        let (json, strategy) = extract_json(src, &FrameMarkers::default())
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        Ok((serde_json::from_str(&json)?, strategy))
+    }
+
+    /// Same as Snapshot::try_from_str(..), with the policy for a missing, empty or malformed
+    /// `turnover_numeric`, which the default TurnoverPolicy::ZeroOnMissing takes as 0.
+    pub fn try_from_str_with(src: &str, policy: TurnoverPolicy) -> Result<Self, serde_json::Error> {
+        let (json, _) = extract_json(src, &FrameMarkers::default())
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        policy.deserialize(&json)
+    }
+
+    /// Same as Snapshot::try_from_str(..) on the bytes of the frame, e.g. of a binary message,
+    /// without converting them to a &str first. The messages of the SockJS array are still
+    /// decoded into strings, and so checked for UTF-8, by serde_json: it spares a pass over the
+    /// frame, not the decoding of its messages.
+    ///
+    /// Run `cargo run --release --example parse_bench` to compare the two.
+    pub fn from_bytes(src: &[u8]) -> Result<Self, serde_json::Error> {
+        let (json, _) = extract_json_bytes(src, &FrameMarkers::default())
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        serde_json::from_str(&json)
+    }
+
+    /// Same as Snapshot::try_from_str(..), and keeps the JSON object of the instrument data in `raw`.
+    pub fn try_from_str_raw(src: &str) -> Result<Self, serde_json::Error> {
+        let (json, _) = extract_json(src, &FrameMarkers::default())
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        let mut snapshot: Snapshot = serde_json::from_str(&json)?;
+        snapshot.raw = Some(json);
+        Ok(snapshot)
+    }
+
+    /// Parses the frame of a stream with its markers and applies the policy, keeping the JSON
+    /// object of the instrument data in `raw` with keep_raw, see StreamBuilder::frame_markers(..)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn parse_frame(
+        src: &str,
+        markers: &FrameMarkers,
+        keep_raw: bool,
+        policy: TurnoverPolicy,
+    ) -> Result<Self, serde_json::Error> {
+        let (json, _) = extract_json(src, markers)
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        Snapshot::parse_data(json, keep_raw, policy)
+    }
+
+    /// Same as Snapshot::parse_frame(..) on the instrument data of one message of the frame,
+    /// see frame_quotes(..), e.g. of a frame carrying several pids.
+    pub(crate) fn parse_message(
+        json: String,
+        keep_raw: bool,
+        policy: TurnoverPolicy,
+    ) -> Result<Self, serde_json::Error> {
+        let (json, _) = ParseStrategy::decode(Some(json), || None)
+            .ok_or_else(|| de::Error::custom("expected the instrument data in braces"))?;
+        Snapshot::parse_data(json, keep_raw, policy)
+    }
+
+    /// Deserializes the decoded instrument data with the policy, keeping it in `raw` with keep_raw.
+    fn parse_data(
+        json: String,
+        keep_raw: bool,
+        policy: TurnoverPolicy,
+    ) -> Result<Self, serde_json::Error> {
+        let mut snapshot = policy.deserialize(&json)?;
+        if keep_raw {
+            snapshot.raw = Some(json);
+        }
+        Ok(snapshot)
+    }
+
+    /// Parses the JSON object of the instrument data, e.g. one of `to_value()`, like the data of
+    /// a frame: the fields may come under their aliases, and unknown fields are ignored.
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        Snapshot::deserialize(value)
+    }
+
+    /// Returns the server fields as a JSON object, e.g. for consumers of serde_json::Value.
+    /// With `raw`, see StreamBuilder::keep_raw(..), the fields of the server which Snapshot
+    /// does not know are included as well. The fields not sent by the server, such as
+    /// `received_at`, are left out.
+    pub fn to_value(&self) -> serde_json::Value {
+        // no map keys or failing serializers to fail on
+        let mut value = serde_json::to_value(self).unwrap();
+        let raw = self.raw.as_deref().map(serde_json::from_str);
+        if let (Some(object), Some(Ok(serde_json::Value::Object(raw)))) =
+            (value.as_object_mut(), raw)
+        {
+            for (key, field) in raw {
+                // known under its field name already
+                if !FIELD_ALIASES.contains(&key.as_str()) {
+                    object.entry(key).or_insert(field);
+                }
+            }
+        }
+        value
+    }
+
+    /// Same as Snapshot::try_from_str(..) on a frame copied from the browser devtools, to paste
+    /// captured frames into tests and tools: a row of the websocket messages, a message of a
+    /// HAR export with its "data", or a string logged to the console in quotes.
+    pub fn from_devtools_str(src: &str) -> Result<Self, serde_json::Error> {
+        Snapshot::try_from_str(&unwrap_devtools(src))
+    }
+
+    /// Parses a recording of raw frames, one per line, optionally after a millisecond offset and
+    /// a tab as in testing::Fixture. Returns the snapshots and the number of skipped lines
+    /// which failed to parse.
+    ///
+    /// Blank lines, `#` comments and the frames without instrument data, such as the open,
+    /// heartbeat, UID and close frames, are neither returned nor counted.
+    pub fn parse_file(path: impl AsRef<Path>) -> io::Result<(Vec<Snapshot>, usize)> {
+        let mut snapshots = Vec::new();
+        let mut skipped = 0;
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let frame = line.split_once('\t').map_or(line, |(_, frame)| frame);
+            let no_data = match parse_sockjs_messages(frame) {
+                Ok(messages) => messages.is_empty(),
+                Err(e) => matches!(e, Error::Closed { .. }),
+            };
+            if no_data || is_heartbeat_frame(frame) || parse_uid_frame(frame).is_some() {
+                continue;
+            }
+            match Snapshot::try_from_str(frame) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(_) => skipped += 1,
+            }
+        }
+        Ok((snapshots, skipped))
+    }
+
+    /// Same as Snapshot::from_str(..), but only deserializes pid, last_numeric and timestamp.
+    ///
+    /// Run `cargo run --release --example parse_bench` to compare the two.
+    pub fn from_str_minimal(src: &str) -> SnapshotMinimal {
+        let (json, _) = extract_json(src, &FrameMarkers::default()).expect("Expect the braces");
+        serde_json::from_str(&json).unwrap()
+    }
+
+    /// Last price as a number, from `last` or `last_numeric` when `last` is malformed.
+    pub fn last_f64(&self) -> Option<f64> {
+        self.number_format
+            .parse(&self.last)
+            .or(Some(self.last_numeric))
+    }
+
+    /// Last price, see `last_f64()`
+    pub fn last_price(&self) -> Option<Price> {
+        self.last_f64().map(Price)
+    }
+
+    /// Number of decimals of `last` as formatted by the server, e.g. 1 for "18,951.2" and 5
+    /// for "1.09345". None when `last` is empty or malformed.
+    pub fn last_decimals(&self) -> Option<usize> {
+        self.number_format.parse(&self.last)?;
+        Some(self.number_format.decimals(&self.last))
+    }
+
+    /// Decimal places of the instrument inferred from `last`, e.g. to round or display its
+    /// prices consistently: 1 for "18,951.2", 5 for "1.09345" and 0 for "18,951", so a tick
+    /// size of `10^-n`. None when `last` is empty or malformed, see `last_decimals()`.
+    pub fn decimal_places(&self) -> Option<u8> {
+        self.last_decimals()
+            .map(|decimals| decimals.min(u8::MAX as usize) as u8)
+    }
+
+    /// Price change percent of `pcp`, None when empty or malformed.
+    pub fn change_percent(&self) -> Option<Percent> {
+        self.pcp_f64().map(|pcp| Percent(pcp as f32))
+    }
+
+    /// Direction of the last price, from `last_dir`. None when the server sent no direction.
+    pub fn direction(&self) -> Option<Direction> {
+        self.last_dir.as_deref().map(Direction::parse)
+    }
+
+    /// Whether the price went up in the session, from the sign of `pc`, or of `pcp` when `pc`
+    /// is malformed, for instruments without `last_dir` and `pc_col`. False when unchanged.
+    /// None when neither is available.
+    pub fn is_up(&self) -> Option<bool> {
+        self.pc_f64().or_else(|| self.pcp_f64()).map(|pc| pc > 0.0)
+    }
+
+    /// Direction of the session, reconciling the fields telling it: the sign of `pc`, or of
+    /// `pcp` when `pc` is malformed, then the color of `pc_col`, then `last_dir`, which is the
+    /// direction of the last tick only. The numbers win when the fields disagree.
+    pub fn sentiment(&self) -> Sentiment {
+        if let Some(pc) = self.pc_f64().or_else(|| self.pcp_f64()) {
+            return match pc.partial_cmp(&0.0) {
+                Some(Ordering::Greater) => Sentiment::Bullish,
+                Some(Ordering::Less) => Sentiment::Bearish,
+                _ => Sentiment::Flat,
+            };
+        }
+        match Sentiment::from(Direction::parse(&self.pc_col)) {
+            Sentiment::Unknown => self.direction().map_or(Sentiment::Unknown, Sentiment::from),
+            sentiment => sentiment,
+        }
+    }
+
+    /// Whether the market data of both snapshots is the same, ignoring `time` and `timestamp`,
+    /// e.g. to skip ticks without change. See CONTENT_FIELDS.
+    pub fn content_eq(&self, other: &Snapshot) -> bool {
+        self.fields_eq(other, &CONTENT_FIELDS)
+    }
+
+    /// Whether the given fields of both snapshots are the same. `received_at` and
+    /// `number_format` are not server data, and never compared.
+    pub fn fields_eq(&self, other: &Snapshot, fields: &[SnapshotField]) -> bool {
+        fields.iter().all(|field| match field {
+            SnapshotField::Pid => self.pid == other.pid,
+            SnapshotField::LastDir => self.last_dir == other.last_dir,
+            SnapshotField::LastNumeric => self.last_numeric == other.last_numeric,
+            SnapshotField::Last => self.last == other.last,
+            SnapshotField::Bid => self.bid == other.bid,
+            SnapshotField::Ask => self.ask == other.ask,
+            SnapshotField::High => self.high == other.high,
+            SnapshotField::Low => self.low == other.low,
+            SnapshotField::LastClose => self.last_close == other.last_close,
+            SnapshotField::Pc => self.pc == other.pc,
+            SnapshotField::Pcp => self.pcp == other.pcp,
+            SnapshotField::PcCol => self.pc_col == other.pc_col,
+            SnapshotField::Turnover => self.turnover == other.turnover,
+            SnapshotField::TurnoverNumeric => self.turnover_numeric == other.turnover_numeric,
+            SnapshotField::Time => self.time == other.time,
+            SnapshotField::Timestamp => self.timestamp == other.timestamp,
+        })
+    }
+
+    /// Change percent computed from `last` and `last_close`, in the unit of `pcp`, e.g. -1.23
+    /// for -1.23%. None when `last_close` is empty, malformed or zero.
+    pub fn computed_pcp(&self) -> Option<f32> {
+        self.gap_from_close_percent().map(|percent| percent as f32)
+    }
+
+    /// Gap of the last price from the previous close, `last_f64()` minus `last_close_f64()`,
+    /// e.g. the gap at the open for the first snapshot of a session. None when `last_close` is
+    /// empty or malformed.
+    pub fn gap_from_close(&self) -> Option<f64> {
+        Some(self.last_f64()? - self.last_close_f64()?)
+    }
+
+    /// Same as `gap_from_close()` in percent of the previous close, e.g. -1.23 for -1.23%.
+    /// None when `last_close` is empty, malformed or zero.
+    pub fn gap_from_close_percent(&self) -> Option<f64> {
+        let last_close = self
+            .last_close_f64()
+            .filter(|last_close| *last_close != 0.0)?;
+        Some(self.gap_from_close()? / last_close * 100.0)
+    }
+
+    /// Whether `pcp` is within the tolerance, in percentage points, of `computed_pcp()`.
+    /// False when either one is unavailable, so a mismatch also flags incomplete frames.
+    pub fn pcp_matches(&self, tolerance: f32) -> bool {
+        match (self.pcp_f64(), self.computed_pcp()) {
+            (Some(pcp), Some(computed)) => (pcp as f32 - computed).abs() <= tolerance,
+            _ => false,
+        }
+    }
+
+    /// Bid price as a number, None when empty or malformed.
+    pub fn bid_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.bid)
+    }
+
+    /// Ask price as a number, None when empty or malformed.
+    pub fn ask_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.ask)
+    }
+
+    /// Ask minus bid, negative for a crossed quote. None without both.
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.ask_f64()? - self.bid_f64()?)
+    }
+
+    /// Midpoint of the bid and the ask, None without both.
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.bid_f64()? + self.ask_f64()?) / 2.0)
+    }
+
+    /// Spread, mid, crossing and age of the quote in one pass, e.g. to check each snapshot.
+    pub fn quote_quality(&self) -> QuoteQuality {
+        self.quote_quality_with(&SystemClock)
+    }
+
+    /// Same as `quote_quality()`, with the age by the clock, see `age_with(..)`
+    pub fn quote_quality_with(&self, clock: &dyn Clock) -> QuoteQuality {
+        let (bid, ask) = (self.bid_f64(), self.ask_f64());
+        let (spread, mid) = match (bid, ask) {
+            (Some(bid), Some(ask)) => (Some(ask - bid), Some((bid + ask) / 2.0)),
+            _ => (None, None),
+        };
+        QuoteQuality {
+            spread,
+            mid,
+            crossed: spread.is_some_and(|spread| spread < 0.0),
+            age: self.age_with(clock),
+        }
+    }
+
+    /// High price as a number, None when empty or malformed.
+    pub fn high_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.high)
+    }
+
+    /// Low price as a number, None when empty or malformed.
+    pub fn low_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.low)
+    }
+
+    /// Previous close as a number, None when empty or malformed.
+    pub fn last_close_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.last_close)
+    }
+
+    /// Price change as a number, None when empty or malformed.
+    pub fn pc_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.pc)
+    }
+
+    /// Price change percent as a number, e.g. -1.23 for "-1.23%", None when empty or malformed.
+    pub fn pcp_f64(&self) -> Option<f64> {
+        self.number_format.parse(&self.pcp)
+    }
+
+    /// Turnover as a number, from `turnover_numeric`, or `turnover` such as "21.50K" when the
+    /// numeric field is missing. None when neither is available.
+    pub fn turnover_value(&self) -> Option<f64> {
+        if self.turnover_numeric > 0 {
+            return Some(self.turnover_numeric as f64);
+        }
+        self.turnover_parsed().map(|(turnover, _)| turnover)
+    }
+
+    /// Turnover from `turnover_numeric` and `turnover` checked against each other: the exact
+    /// `turnover_numeric` when `turnover` agrees within its rounding, e.g. 21503 for "21.50K",
+    /// or whichever is present. None when neither is, and when they conflict, which is logged
+    /// as a warning, to surface the inconsistencies of the feed rather than pick one.
+    pub fn turnover_consistent(&self) -> Option<u64> {
+        match (self.turnover_numeric, self.turnover_parsed()) {
+            (0, None) => None,
+            (0, Some((turnover, _))) => Some(turnover.round() as u64),
+            (numeric, None) => Some(numeric),
+            (numeric, Some((turnover, tolerance))) => {
+                if (numeric as f64 - turnover).abs() <= tolerance {
+                    Some(numeric)
+                } else {
+                    log::warn!(
+                        "Inconsistent turnover of {}: {:?} and {}",
+                        self.pid,
+                        self.turnover,
+                        numeric
+                    );
+                    None
+                }
+            }
+        }
+    }
+
+    /// Value of `turnover` such as "21.50K", and the half unit of its last digit as the
+    /// tolerance of its rounding, e.g. 5.0
+    fn turnover_parsed(&self) -> Option<(f64, f64)> {
+        let turnover = self.turnover.trim();
+        let (number, scale) = match turnover.chars().last() {
+            Some('K') => (&turnover[..turnover.len() - 1], 1e3),
+            Some('M') => (&turnover[..turnover.len() - 1], 1e6),
+            Some('B') => (&turnover[..turnover.len() - 1], 1e9),
+            _ => (turnover, 1.0),
+        };
+        let value = self.number_format.parse(number)? * scale;
+        let decimals = self.number_format.decimals(number);
+        let tolerance = 0.5 * scale / 10f64.powi(decimals as i32);
+        Some((value, tolerance))
+    }
+
+    /// Returns `time` as a date time in the given offset, which must be the UTC offset of the
+    /// time zone subscribed with, i.e. the tzID of `subscribe_message(..)`
+    ///
+    /// `time` carries no date, so the date is the one of the instant closest to `timestamp`,
+    /// which also holds around midnight. Without `timestamp`, the instant is `received_at`,
+    /// or the current time when parsed outside of the stream, so the result stands in for
+    /// `datetime_utc()`. None when `time` is not "H:MM:SS".
+    pub fn time_at(&self, offset: FixedOffset) -> Option<DateTime<FixedOffset>> {
+        let time = NaiveTime::parse_from_str(&self.time, "%H:%M:%S").ok()?;
+        let server_time = match self.server_time() {
+            Some(server_time) => server_time,
+            None => self.received_at.unwrap_or_else(SystemTime::now).into(),
+        }
+        .with_timezone(&offset);
+        let date = server_time.date_naive();
+
+        [date.pred_opt(), Some(date), date.succ_opt()]
+            .iter()
+            .flatten()
+            .filter_map(|date| offset.from_local_datetime(&date.and_time(time)).single())
+            .min_by_key(|candidate| (*candidate - server_time).abs())
+    }
+
+    /// Returns `time` as a date time in `utc_offset`, see `time_at(..)`. None without
+    /// `utc_offset`, which the stream sets with StreamBuilder::utc_offset(..)
+    ///
+    /// The offset is fixed, so the result is an hour off past a change of the daylight saving
+    /// time of the time zone, e.g. of DEFAULT_TZ_ID.
+    pub fn local_time(&self) -> Option<DateTime<FixedOffset>> {
+        self.time_at(self.utc_offset?)
+    }
+
+    /// Returns `timestamp` as a UTC date time, corrected by `clock_offset`. None without
+    /// `timestamp`, see `time_at(..)` for the date time of `time` instead.
+    pub fn datetime_utc(&self) -> Option<DateTime<Utc>> {
+        self.server_time()?
+            .checked_add_signed(self.clock_offset.unwrap_or_else(chrono::Duration::zero))
+    }
+
+    /// `timestamp` as a UTC date time, None when it is 0.
+    fn server_time(&self) -> Option<DateTime<Utc>> {
+        if self.timestamp == 0 {
+            return None;
+        }
+        Utc.timestamp_opt(self.timestamp as i64, 0).single()
+    }
+
+    /// Returns how old the snapshot is by the local clock, from `datetime_utc()`. None when the
+    /// corrected timestamp is ahead of the local clock.
+    pub fn age(&self) -> Option<Duration> {
+        self.age_with(&SystemClock)
+    }
+
+    /// Same as `age()` by the clock, e.g. a MockClock in tests.
+    pub fn age_with(&self, clock: &dyn Clock) -> Option<Duration> {
+        clock
+            .system_time()
+            .duration_since(self.datetime_utc()?.into())
+            .ok()
+    }
+
+    /// Orders by the server `timestamp` only, e.g. to merge recorded streams with
+    /// `sort_by(Snapshot::cmp_by_timestamp)`, which is stable and keeps
+    /// the recorded order of snapshots within the same second.
+    pub fn cmp_by_timestamp(&self, other: &Snapshot) -> Ordering {
+        self.timestamp.cmp(&other.timestamp)
+    }
+
+    /// Whether the snapshots are the same quote with the prices within `tol` of each other, e.g.
+    /// for assertions on recomputed prices. The `pid`, `timestamp` and `turnover_numeric` are
+    /// compared exactly, as are the prices missing or malformed in either snapshot.
+    pub fn approx_eq(&self, other: &Snapshot, tol: f64) -> bool {
+        let close = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= tol,
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.pid == other.pid
+            && self.timestamp == other.timestamp
+            && self.turnover_numeric == other.turnover_numeric
+            && close(Some(self.last_numeric), Some(other.last_numeric))
+            && close(self.bid_f64(), other.bid_f64())
+            && close(self.ask_f64(), other.ask_f64())
+            && close(self.high_f64(), other.high_f64())
+            && close(self.low_f64(), other.low_f64())
+            && close(self.last_close_f64(), other.last_close_f64())
+            && close(self.pc_f64(), other.pc_f64())
+            && close(self.pcp_f64(), other.pcp_f64())
+    }
 
-    impl<'de> Visitor<'de> for U32Visitor {
-        type Value = u32;
+    /// Returns how stale the snapshot was when it arrived, i.e. `received_at` minus the server `timestamp`.
+    ///
+    /// The server timestamp has one-second resolution. None when `received_at` or `timestamp`
+    /// is unknown, or the local clock is behind the server.
+    pub fn latency(&self) -> Option<Duration> {
+        let server_time: SystemTime = self.server_time()?.into();
+        self.received_at?.duration_since(server_time).ok()
+    }
+}
 
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("u32 or string")
-        }
-        fn visit_u64<E>(self, val: u64) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            Ok(val as Self::Value)
-        }
+/// Returns the snapshots of all the messages of a SockJS array frame carrying instrument data,
+/// in the order of the frame, e.g. of a frame describing several instruments at once. The
+/// batch counterpart of Snapshot::try_from_str(..), which returns the first one only.
+///
+/// The other messages, such as the UID reply, are skipped, and the open and heartbeat
+/// frames have none. Err(..) for a malformed frame or instrument data, and Error::Closed
+/// for a close frame, see parse_sockjs_messages(..)
+pub fn snapshots_from_frame(frame: &str) -> Result<Vec<Snapshot>, Error> {
+    snapshots_from_frame_with(frame, &FrameMarkers::default(), TurnoverPolicy::default())
+}
 
-        fn visit_str<E>(self, sval: &str) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            if sval == "" {
-                return Ok::<Self::Value, E>(default_zero());
-            }
-            sval.parse().map_err(de::Error::custom)
-        }
-    }
-    let visitor = U32Visitor;
-    deserializer.deserialize_any(visitor)
+/// Same as snapshots_from_frame(..) with the markers and the turnover policy of a stream, see
+/// StreamBuilder::frame_markers(..) and turnover_policy(..), which parses its frames the same way.
+pub fn snapshots_from_frame_with(
+    frame: &str,
+    markers: &FrameMarkers,
+    policy: TurnoverPolicy,
+) -> Result<Vec<Snapshot>, Error> {
+    frame_quotes(frame, markers)?
+        .into_iter()
+        .map(|(_, json)| Snapshot::parse_message(json, false, policy).map_err(Error::Parse))
+        .collect()
 }
 
-impl Snapshot {
-    /// Given original data from forexpros wss server, returns the Snapshot with extracted data.
-    pub fn from_str<'a>(src: &'a str) -> Self {
-        let idx_start = src.find("::{").expect("Expect the opening brace");
-        let idx_end = src.find("}").expect("Expect the closing brace");
+/// The pid and the instrument data of each message of a SockJS array frame carrying some, in
+/// the order of the frame, see FrameMarkers::snapshot_message(..)
+pub(crate) fn frame_quotes(
+    frame: &str,
+    markers: &FrameMarkers,
+) -> Result<Vec<(String, String)>, Error> {
+    Ok(parse_sockjs_messages(frame)?
+        .iter()
+        .filter_map(|message| markers.snapshot_message(message))
+        .collect())
+}
 
-        let src = &src[idx_start + 2..idx_end + 1].replace("\\\\\\", "");
-        serde_json::from_str(src).unwrap()
+/// Estimates the offset of the server clock to the local clock from snapshots received by a
+/// stream, as the median of `received_at` minus `timestamp`, less half a second for the
+/// truncation of `timestamp` to seconds. Set it as `clock_offset` to align several sources.
+///
+/// The estimate includes the network delay. None without snapshots with `received_at` and
+/// `timestamp`.
+pub fn estimate_clock_offset<'a, I>(snapshots: I) -> Option<chrono::Duration>
+where
+    I: IntoIterator<Item = &'a Snapshot>,
+{
+    let mut offsets: Vec<i64> = snapshots
+        .into_iter()
+        .filter(|snapshot| snapshot.timestamp != 0)
+        .filter_map(|snapshot| {
+            let received_at = snapshot.received_at?.duration_since(UNIX_EPOCH).ok()?;
+            Some(received_at.as_millis() as i64 - snapshot.timestamp as i64 * 1000 - 500)
+        })
+        .collect();
+    if offsets.is_empty() {
+        return None;
     }
+    offsets.sort_unstable();
+    Some(chrono::Duration::milliseconds(offsets[offsets.len() / 2]))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     #[test]
     pub fn test_from_str() {
@@ -165,6 +1462,36 @@ mod tests {
         assert_eq!(snapshot.timestamp, timestamp);
     }
 
+    #[test]
+    pub fn test_from_str_minimal() {
+        let src = "a[\"{\\\"message\\\":\\\"pid-945629::{\\\\\\\"pid\\\\\\\":\\\\\\\"945629\\\\\\\",\\\\\\\"last_dir\\\\\\\":\\\\\\\"redBg\\\\\\\",\\\\\\\"last_numeric\\\\\\\":18951.2,\\\\\\\"last\\\\\\\":\\\\\\\"18,951.2\\\\\\\",\\\\\\\"pc\\\\\\\":\\\\\\\"-236.8\\\\\\\",\\\\\\\"time\\\\\\\":\\\\\\\"19:21:50\\\\\\\",\\\\\\\"timestamp\\\\\\\":1606850510}\\\"}\"]";
+
+        assert_eq!(
+            Snapshot::from_str_minimal(src),
+            SnapshotMinimal {
+                pid: "945629".to_string(),
+                last_numeric: 18951.2,
+                timestamp: 1606850510,
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_try_from_str() {
+        let src = "a[\"{\\\"message\\\":\\\"pid-945629::{\\\\\\\"pid\\\\\\\":\\\\\\\"945629\\\\\\\",\\\\\\\"last_dir\\\\\\\":\\\\\\\"redBg\\\\\\\",\\\\\\\"last_numeric\\\\\\\":18951.2,\\\\\\\"last\\\\\\\":\\\\\\\"18,951.2\\\\\\\",\\\\\\\"bid\\\\\\\":\\\\\\\"18,954.0\\\\\\\",\\\\\\\"ask\\\\\\\":\\\\\\\"18,956.0\\\\\\\",\\\\\\\"high\\\\\\\":\\\\\\\"19,956.0\\\\\\\",\\\\\\\"low\\\\\\\":\\\\\\\"18,279.0\\\\\\\",\\\\\\\"last_close\\\\\\\":\\\\\\\"19,188.0\\\\\\\",\\\\\\\"pc\\\\\\\":\\\\\\\"-236.8\\\\\\\",\\\\\\\"pcp\\\\\\\":\\\\\\\"-1.23%\\\\\\\",\\\\\\\"pc_col\\\\\\\":\\\\\\\"redFont\\\\\\\",\\\\\\\"turnover\\\\\\\":\\\\\\\"21.50K\\\\\\\",\\\\\\\"turnover_numeric\\\\\\\":21503,\\\\\\\"time\\\\\\\":\\\\\\\"19:21:50\\\\\\\",\\\\\\\"timestamp\\\\\\\":1606850510}\\\"}\"]";
+
+        let snapshot = Snapshot::try_from_str(src).unwrap();
+        assert_eq!(snapshot.pid, "945629");
+        assert_eq!(snapshot.timestamp, 1606850510);
+
+        assert!(Snapshot::try_from_str(&src.replace("1606850510", "oops")).is_err());
+        assert_eq!(snapshot.raw, None);
+
+        let raw = Snapshot::try_from_str_raw(src).unwrap().raw.unwrap();
+        assert!(raw.starts_with(r#"{"pid":"945629","last_dir":"redBg""#));
+        assert!(Snapshot::try_from_str("a[\"{\\\"message\\\":\\\"pid-945629::\\\"}\"]").is_err());
+    }
+
     #[test]
     pub fn test_turnover_as_int() {
         let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
@@ -189,6 +1516,34 @@ mod tests {
         assert_eq!(snapshot.turnover_numeric, 21503);
     }
 
+    #[test]
+    pub fn test_turnover_consistent() {
+        let mut snapshot = Snapshot {
+            turnover: "21.50K".to_string(),
+            turnover_numeric: 21503,
+            ..Default::default()
+        };
+        assert_eq!(snapshot.turnover_consistent(), Some(21503));
+
+        snapshot.turnover_numeric = 21600;
+        assert_eq!(snapshot.turnover_consistent(), None);
+
+        snapshot.turnover_numeric = 0;
+        assert_eq!(snapshot.turnover_consistent(), Some(21500));
+
+        snapshot.turnover = "".to_string();
+        assert_eq!(snapshot.turnover_consistent(), None);
+        snapshot.turnover_numeric = 21503;
+        assert_eq!(snapshot.turnover_consistent(), Some(21503));
+
+        snapshot.turnover = "1.2M".to_string();
+        snapshot.turnover_numeric = 1_249_000;
+        assert_eq!(snapshot.turnover_consistent(), Some(1_249_000));
+        snapshot.turnover = "21503".to_string();
+        snapshot.turnover_numeric = 21504;
+        assert_eq!(snapshot.turnover_consistent(), None);
+    }
+
     #[test]
     pub fn test_turnover_empty_str() {
         let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
@@ -201,6 +1556,225 @@ mod tests {
         assert_eq!(snapshot.turnover_numeric, 0);
     }
 
+    #[test]
+    pub fn test_turnover_u64() {
+        let src = r#"{"pid":"945629","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
+		"pc":"-236.8","pcp":"-1.23%","pc_col":"redFont","turnover_numeric":"5000000000","time":"19:21:50","timestamp":1606850510}"#;
+
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+        assert_eq!(snapshot.turnover_numeric, 5_000_000_000);
+
+        let snapshot: Snapshot =
+            serde_json::from_str(&src.replace(r#""5000000000""#, "-1")).unwrap();
+        assert_eq!(snapshot.turnover_numeric, 0);
+    }
+
+    #[test]
+    pub fn test_parse_file() {
+        let session = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/session.txt");
+        let (snapshots, skipped) = Snapshot::parse_file(session).unwrap();
+        let pids: Vec<_> = snapshots
+            .iter()
+            .map(|snapshot| snapshot.pid.as_str())
+            .collect();
+        assert_eq!(pids, ["945629", "8984", "945629"]);
+        assert_eq!(skipped, 0);
+
+        let path = std::env::temp_dir().join(format!("parse_file_{}.txt", std::process::id()));
+        let recording = fs::read_to_string(session).unwrap() + "garbage\nh\n";
+        fs::write(&path, recording).unwrap();
+        let (snapshots, skipped) = Snapshot::parse_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(skipped, 1);
+
+        assert!(Snapshot::parse_file("missing.txt").is_err());
+    }
+
+    #[test]
+    pub fn test_snapshots_from_frame() {
+        let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2,\\\"timestamp\\\":1606850510}\"}","{\"_event\":\"UID\",\"UID\":0}","{\"message\":\"pid-8984::{\\\"pid\\\":\\\"8984\\\",\\\"last_numeric\\\":24871.5,\\\"timestamp\\\":1606850511}\"}"]"#;
+        let snapshots = snapshots_from_frame(frame).unwrap();
+        let pids: Vec<_> = snapshots.iter().map(|s| s.pid.as_str()).collect();
+        assert_eq!(pids, ["945629", "8984"]);
+        assert_eq!(snapshots[1].last_numeric, 24871.5);
+        assert_eq!(Snapshot::try_from_str(frame).unwrap().pid, snapshots[0].pid);
+
+        assert!(snapshots_from_frame("h").unwrap().is_empty());
+        assert!(
+            snapshots_from_frame(r#"a["{\"_event\":\"UID\",\"UID\":0}"]"#)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            snapshots_from_frame(r#"c[3000,"Go away!"]"#),
+            Err(Error::Closed { code: 3000, .. })
+        ));
+        assert!(matches!(
+            snapshots_from_frame(&frame.replace("24871.5", "x")),
+            Err(Error::Parse(_))
+        ));
+
+        let markers = FrameMarkers {
+            pid_prefix: "quote-".into(),
+            ..Default::default()
+        };
+        let frame = frame.replace("pid-", "quote-").replace(
+            "1606850511}",
+            r#"1606850511,\\\"turnover_numeric\\\":\\\"olia\\\"}"#,
+        );
+        assert!(snapshots_from_frame(&frame).unwrap().is_empty());
+        assert!(snapshots_from_frame_with(&frame, &markers, TurnoverPolicy::Error).is_err());
+        let snapshots = snapshots_from_frame_with(&frame, &markers, TurnoverPolicy::default());
+        assert_eq!(snapshots.unwrap().len(), 2);
+    }
+
+    #[test]
+    pub fn test_from_bytes() {
+        let src = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2,\\\"last\\\":\\\"18,951.2\\\",\\\"timestamp\\\":1606850510}\"}"]"#;
+        let snapshot = Snapshot::from_bytes(src.as_bytes()).unwrap();
+        assert!(snapshot.content_eq(&Snapshot::try_from_str(src).unwrap()));
+        assert_eq!(snapshot.pid, "945629");
+
+        assert!(Snapshot::from_bytes(b"h").is_err());
+        assert!(Snapshot::from_bytes(b"").is_err());
+        assert!(Snapshot::from_bytes(b"a[\"\xff\"]").is_err());
+    }
+
+    #[test]
+    pub fn test_escaping_depth() {
+        let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2,\\\"last\\\":\\\"18,951.2\\\",\\\"timestamp\\\":1606850510}\"}"]"#;
+        let expected = Snapshot::try_from_str(frame).unwrap();
+
+        let message = &parse_sockjs_messages(frame).unwrap()[0];
+        let logged = serde_json::to_string(frame).unwrap();
+        let unquoted = &logged[1..logged.len() - 1];
+        let logged_twice = serde_json::to_string(&logged).unwrap();
+        for src in [message.as_str(), frame, &logged, unquoted, &logged_twice].iter() {
+            let snapshot = Snapshot::try_from_str(src).unwrap();
+            assert!(snapshot.content_eq(&expected), "{}", src);
+            assert!(Snapshot::from_bytes(src.as_bytes())
+                .unwrap()
+                .content_eq(&expected));
+        }
+
+        assert!(Snapshot::try_from_str(r#"{"message":"pid-945629::"}"#).is_err());
+        assert!(Snapshot::try_from_str(r#""h""#).is_err());
+    }
+
+    #[test]
+    pub fn test_from_devtools_str() {
+        let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2,\\\"last\\\":\\\"18,951.2\\\",\\\"timestamp\\\":1606850510}\"}"]"#;
+        let expected = Snapshot::try_from_str(frame).unwrap();
+
+        let har = format!(
+            r#"{{"type":"receive","time":1606850510.214,"opcode":1,"data":{}}}"#,
+            serde_json::to_string(frame).unwrap()
+        );
+        let row = format!("↓ {}\n", frame);
+        let console = format!("'{}'", frame);
+        for src in [frame, &har, &row, &console].iter() {
+            let snapshot = Snapshot::from_devtools_str(src).unwrap();
+            assert!(snapshot.content_eq(&expected), "{}", src);
+        }
+
+        let heartbeat = r#"{"type":"receive","time":1606850510.214,"opcode":1,"data":"h"}"#;
+        assert!(Snapshot::from_devtools_str(heartbeat).is_err());
+        assert!(Snapshot::from_devtools_str("''").is_err());
+    }
+
+    #[test]
+    pub fn test_field_aliases() {
+        let src = r#"{"pid":"945629","last_price":18951.2,"previous_close":"19,188.0","change":"-236.8",
+            "change_percent":"-1.23%","change_color":"redFont","volume":"21.50K","volume_numeric":21503,"timestamp":1606850510}"#;
+
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+        assert_eq!(snapshot.last_numeric, 18951.2);
+        assert_eq!(snapshot.last_close, "19,188.0");
+        assert_eq!(snapshot.pc, "-236.8");
+        assert_eq!(snapshot.pcp, "-1.23%");
+        assert_eq!(snapshot.pc_col, "redFont");
+        assert_eq!(snapshot.turnover, "21.50K");
+        assert_eq!(snapshot.turnover_numeric, 21503);
+
+        // serialized with the field names
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(json.contains(r#""pcp":"-1.23%""#));
+
+        // both names: the field name is taken
+        let src = r#"{"pid":"945629","last_numeric":18951.2,"last_price":1.0,"pcp":"-1.23%","change_percent":"-9.99%",
+            "turnover_numeric":21503,"volume_numeric":1,"timestamp":1606850510}"#;
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+        assert_eq!(
+            (snapshot.last_numeric, snapshot.pcp.as_str()),
+            (18951.2, "-1.23%")
+        );
+        assert_eq!(snapshot.turnover_numeric, 21503);
+        let snapshot = TurnoverPolicy::NoneOnMissing.deserialize(src).unwrap();
+        assert_eq!(snapshot.turnover_numeric_opt, Some(21503));
+    }
+
+    /// Deserializer only recording the fields of the struct deserialized with it.
+    struct FieldNames(&'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for &mut FieldNames {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            self.0 = fields;
+            Err(de::Error::custom("fields recorded"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    #[test]
+    pub fn test_field_aliases_agree() {
+        // the fields of SnapshotData which Snapshot does not serialize are the aliases
+        let mut fields = FieldNames(&[]);
+        assert!(SnapshotData::deserialize(&mut fields).is_err());
+        let value = Snapshot::default().to_value();
+        let mut aliases: Vec<_> = fields
+            .0
+            .iter()
+            .filter(|field| value.get(**field).is_none())
+            .collect();
+        let mut expected: Vec<_> = FIELD_ALIASES.iter().collect();
+        aliases.sort();
+        expected.sort();
+        assert_eq!(aliases, expected);
+    }
+
+    #[test]
+    pub fn test_missing_fields() {
+        let src = r#"{"pid":"8984","last_numeric":24871.5,"timestamp":1597116058}"#;
+
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+        assert_eq!(snapshot.last_dir, None);
+        assert_eq!(snapshot.bid, "");
+        assert_eq!(snapshot.pc_col, "");
+        assert_eq!(snapshot.last_f64(), Some(24871.5));
+
+        let src = r#"{"pid":"8984","last_numeric":24871.5}"#;
+        assert_eq!(serde_json::from_str::<Snapshot>(src).unwrap().timestamp, 0);
+
+        let src = r#"{"pid":"8984","timestamp":1597116058}"#;
+        assert!(serde_json::from_str::<Snapshot>(src).is_err());
+    }
+
     #[test]
     pub fn test_no_turnover() {
         let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
@@ -212,10 +1786,610 @@ mod tests {
         assert_eq!(snapshot.turnover_numeric, 0);
     }
 
-	#[test]
-	#[should_panic(expected = "invalid digit found in string")]
+    #[test]
+    pub fn test_clock_offset() {
+        let snapshots: Vec<_> = [
+            (1606850510, 1_606_850_512_300),
+            (1606850511, 1_606_850_513_900),
+            (1606850512, 1_606_850_514_500),
+        ]
+        .iter()
+        .map(|(timestamp, received_at)| Snapshot {
+            timestamp: *timestamp,
+            received_at: Some(UNIX_EPOCH + Duration::from_millis(*received_at)),
+            ..Default::default()
+        })
+        .collect();
+        let offset = estimate_clock_offset(&snapshots);
+        assert_eq!(offset, Some(chrono::Duration::milliseconds(2000)));
+        assert_eq!(estimate_clock_offset(&[Snapshot::default()]), None);
+
+        let mut snapshot = snapshots[0].clone();
+        assert_eq!(snapshot.datetime_utc().unwrap().timestamp(), 1606850510);
+        snapshot.clock_offset = offset;
+        assert_eq!(snapshot.datetime_utc().unwrap().timestamp(), 1606850512);
+
+        let age = snapshot.age().unwrap();
+        snapshot.clock_offset = Some(chrono::Duration::seconds(-10));
+        assert!(snapshot.age().unwrap() > age + Duration::from_secs(11));
+        snapshot.clock_offset = Some(chrono::Duration::days(365 * 1000));
+        assert_eq!(snapshot.age(), None);
+
+        let clock = MockClock::new();
+        snapshot.clock_offset = None;
+        let age = snapshot.age_with(&clock).unwrap();
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(
+            snapshot.age_with(&clock),
+            Some(age + Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    pub fn test_latency() {
+        let mut snapshot = Snapshot {
+            timestamp: 1606850510,
+            ..Default::default()
+        };
+        assert_eq!(snapshot.latency(), None);
+
+        snapshot.received_at = Some(UNIX_EPOCH + Duration::from_millis(1_606_850_512_250));
+        assert_eq!(snapshot.latency(), Some(Duration::from_millis(2250)));
+
+        snapshot.received_at = Some(UNIX_EPOCH + Duration::from_secs(1606850500));
+        assert_eq!(snapshot.latency(), None);
+    }
+
+    #[test]
+    pub fn test_quote_quality() {
+        let clock = MockClock::new();
+        let timestamp = clock
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut snapshot = Snapshot {
+            bid: "18,954.0".into(),
+            ask: "18,956.0".into(),
+            timestamp,
+            ..Default::default()
+        };
+        clock.advance(Duration::from_secs(5));
+
+        let quality = snapshot.quote_quality_with(&clock);
+        assert_eq!(quality.spread, Some(2.0));
+        assert_eq!(quality.mid, Some(18955.0));
+        assert!(!quality.crossed);
+        assert!(quality.age.unwrap() >= Duration::from_secs(5));
+        assert_eq!(snapshot.spread(), quality.spread);
+        assert_eq!(snapshot.mid(), quality.mid);
+
+        snapshot.bid = "18,957.0".into();
+        assert!(snapshot.quote_quality_with(&clock).crossed);
+
+        // indices come without bid and ask
+        snapshot.bid.clear();
+        let quality = snapshot.quote_quality_with(&clock);
+        assert_eq!((quality.spread, quality.mid), (None, None));
+        assert!(!quality.crossed);
+    }
+
+    #[test]
+    pub fn test_instrument_class() {
+        assert_eq!(InstrumentClass::default(), InstrumentClass::Continuous);
+        assert!(!InstrumentClass::Continuous.is_session_break(Duration::from_secs(86400)));
+        assert!(InstrumentClass::Sessioned.is_session_break(SESSION_BREAK));
+        assert!(!InstrumentClass::Sessioned.is_session_break(Duration::from_secs(600)));
+    }
+
+    #[test]
+    pub fn test_number_format() {
+        assert_eq!(NumberFormat::DotDecimal.parse("18,954.0"), Some(18954.0));
+        assert_eq!(NumberFormat::CommaDecimal.parse("18.954,0"), Some(18954.0));
+        assert_eq!(NumberFormat::CommaDecimal.parse("-1,23%"), Some(-1.23));
+        assert_eq!(NumberFormat::DotDecimal.parse(""), None);
+
+        assert_eq!(NumberFormat::DotDecimal.parse("$1,234.5"), Some(1234.5));
+        assert_eq!(NumberFormat::DotDecimal.parse("-£0.85"), Some(-0.85));
+        assert_eq!(NumberFormat::DotDecimal.parse("¥150.25"), Some(150.25));
+        assert_eq!(NumberFormat::CommaDecimal.parse("€1.234,5"), Some(1234.5));
+        assert_eq!(NumberFormat::DotDecimal.parse("1,234.5"), Some(1234.5));
+        assert_eq!(NumberFormat::DotDecimal.parse("$"), None);
+        assert_eq!(NumberFormat::DotDecimal.parse("1,234.5$"), None);
+
+        let mut snapshot = Snapshot {
+            bid: "18.954,0".to_string(),
+            number_format: NumberFormat::CommaDecimal,
+            ..Default::default()
+        };
+        assert_eq!(snapshot.bid_f64(), Some(18954.0));
+
+        snapshot.number_format = NumberFormat::DotDecimal;
+        assert_eq!(snapshot.bid_f64(), Some(18.954));
+    }
+
+    #[test]
+    pub fn test_pip_precision() {
+        let src = r#"{"pid":"1","last_dir":"greenBg","last_numeric":1.09345,"last":"1.09345","bid":"1.0934","ask":"1.0935","high":"1.0951","low":"0.00012",
+		"last_close":"1.09211","pc":"+0.00134","pcp":"+0.12%","pc_col":"greenFont","time":"19:21:50","timestamp":1606850510}"#;
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+
+        assert_eq!(snapshot.last_numeric, 1.09345);
+        assert_eq!(snapshot.last_f64(), Some(1.09345));
+        assert_eq!(snapshot.bid_f64(), Some(1.0934));
+        assert_eq!(snapshot.low_f64(), Some(0.00012));
+        assert_eq!(snapshot.pc_f64(), Some(0.00134));
+    }
+
+    #[test]
+    pub fn test_direction() {
+        let mut snapshot = Snapshot::default();
+        assert_eq!(snapshot.direction(), None);
+
+        snapshot.last_dir = Some("greenBg".into());
+        assert_eq!(snapshot.direction(), Some(Direction::Up));
+        snapshot.last_dir = Some("redBg".into());
+        assert_eq!(snapshot.direction(), Some(Direction::Down));
+
+        // corrupted greenBg of the doc sample of push
+        snapshot.last_dir = Some("$reenBg".into());
+        assert_eq!(snapshot.direction(), Some(Direction::Unknown));
+        assert_eq!(Direction::parse(""), Direction::Unknown);
+    }
+
+    #[test]
+    pub fn test_value() {
+        let src = r#"{"pid":"945629","last_price":18951.2,"last":"18,951.2","change_percent":"-1.23%","timestamp":1606850510,"is_open":true}"#;
+        let mut snapshot = Snapshot::from_value(&serde_json::from_str(src).unwrap()).unwrap();
+        assert_eq!(snapshot.last_numeric, 18951.2);
+        assert_eq!(snapshot.pcp, "-1.23%");
+
+        let value = snapshot.to_value();
+        assert_eq!(value["last_numeric"], 18951.2);
+        assert_eq!(value["pcp"], "-1.23%");
+        assert!(value.get("is_open").is_none());
+        let rebuilt = Snapshot::from_value(&value).unwrap();
+        assert!(rebuilt.approx_eq(&snapshot, 0.0));
+
+        // the unknown fields of raw, not the aliases
+        snapshot.raw = Some(src.to_string());
+        let value = snapshot.to_value();
+        assert_eq!(value["is_open"], true);
+        assert!(value.get("change_percent").is_none());
+        assert!(Snapshot::from_value(&value).is_ok());
+
+        // every alias sets a field
+        let base = serde_json::json!({"pid": "945629", "last_numeric": 1.0});
+        let base_value = Snapshot::from_value(&base).unwrap().to_value();
+        for alias in FIELD_ALIASES {
+            let mut value = base.clone();
+            let object = value.as_object_mut().unwrap();
+            let field = match alias {
+                "last_price" => object.remove("last_numeric").map(|_| 2.into()).unwrap(),
+                "volume_numeric" => 2.into(),
+                _ => "2".into(),
+            };
+            object.insert(alias.to_string(), field);
+            let value = Snapshot::from_value(&value).unwrap().to_value();
+            assert_ne!(value, base_value, "{}", alias);
+        }
+        assert!(Snapshot::from_value(&serde_json::json!({"pid": "945629"})).is_err());
+    }
+
+    #[test]
+    pub fn test_parse_strategy() {
+        let frame = |data: &str| {
+            let message = serde_json::json!({ "message": format!("pid-945629::{}", data) });
+            format!(
+                "a{}",
+                serde_json::to_string(&[message.to_string()]).unwrap()
+            )
+        };
+
+        let src = frame(r#"{"pid":"945629","last_numeric":18951.2}"#);
+        let (snapshot, strategy) = Snapshot::try_from_str_strategy(&src).unwrap();
+        assert_eq!(strategy, ParseStrategy::Raw);
+        assert_eq!(snapshot.last_numeric, 18951.2);
+
+        let src = frame(r#"{\"pid\":\"945629\",\"last_numeric\":18951.2}"#);
+        let (snapshot, strategy) = Snapshot::try_from_str_strategy(&src).unwrap();
+        assert_eq!(strategy, ParseStrategy::UnescapeOnce);
+        assert_eq!(snapshot.pid, "945629");
+        assert_eq!(Snapshot::from_str(&src).last_numeric, 18951.2);
+
+        let src = frame(r#"{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2}"#);
+        let (snapshot, strategy) = Snapshot::try_from_str_strategy(&src).unwrap();
+        assert_eq!(strategy, ParseStrategy::TripleStrip);
+        assert_eq!(snapshot.last_numeric, 18951.2);
+
+        // the error of the strategy which applies, the data deserialized once
+        let src = frame(r#"{\"pid\":\"945629\"}"#);
+        let err = Snapshot::try_from_str(&src).unwrap_err();
+        assert!(
+            err.to_string().contains("missing field `last_numeric`"),
+            "{}",
+            err
+        );
+
+        let src =
+            serde_json::to_string(&frame(r#"{"pid":"945629","last_numeric":18951.2}"#)).unwrap();
+        let (snapshot, strategy) = Snapshot::try_from_str_strategy(&src).unwrap();
+        assert_eq!(strategy, ParseStrategy::Lenient);
+        assert_eq!(snapshot.last_numeric, 18951.2);
+    }
+
+    #[test]
+    pub fn test_field_stats() {
+        let src = r#"{"pid":"8984","last_numeric":24871.5,"last":"24,871.5","bid":"24,866.0","ask":"","time":"3:20:58","timestamp":1597116058}"#;
+        let snapshot = TurnoverPolicy::ZeroOnMissing.deserialize(src).unwrap();
+        let mut stats = FieldStats::default();
+        stats.record(&snapshot);
+        assert_eq!(
+            stats,
+            FieldStats {
+                snapshots: 1,
+                last_dir: 1,
+                ask: 1,
+                high: 1,
+                low: 1,
+                last_close: 1,
+                pc: 1,
+                pcp: 1,
+                pc_col: 1,
+                turnover: 1,
+                turnover_numeric: 1,
+                ..FieldStats::default()
+            }
+        );
+
+        // a valid turnover of 0 is not missing without TurnoverPolicy::ZeroOnMissing
+        let src =
+            r#"{"pid":"8984","last_numeric":24871.5,"turnover_numeric":0,"timestamp":1597116058}"#;
+        let snapshot = TurnoverPolicy::NoneOnMissing.deserialize(src).unwrap();
+        let mut other = FieldStats::default();
+        other.record(&snapshot);
+        assert_eq!(other.turnover_numeric, 0);
+
+        // unparseable prices count as missing, those valid by their format do not
+        let src = r#"{"pid":"8984","last_numeric":24871.5,"last":"-","bid":"24.866,0","turnover":"21,50K","timestamp":1597116058}"#;
+        let mut snapshot = TurnoverPolicy::ZeroOnMissing.deserialize(src).unwrap();
+        snapshot.number_format = NumberFormat::CommaDecimal;
+        other.record(&snapshot);
+        assert_eq!((other.last, other.bid, other.turnover), (2, 1, 1));
+
+        other.parse_errors += 1;
+        let total = stats.merge(&other);
+        assert_eq!(
+            (
+                total.snapshots,
+                total.parse_errors,
+                total.bid,
+                total.turnover_numeric
+            ),
+            (3, 1, 1, 2)
+        );
+    }
+
+    #[test]
+    pub fn test_approx_eq() {
+        let snapshot = Snapshot {
+            pid: "945629".to_string(),
+            last_numeric: 18951.2,
+            bid: "18,954.0".to_string(),
+            ask: "18,956.0".to_string(),
+            timestamp: 1606850510,
+            ..Snapshot::default()
+        };
+        let recomputed = Snapshot {
+            last_numeric: 18951.2 + 1e-9,
+            bid: "18,954.00001".to_string(),
+            ..snapshot.clone()
+        };
+        assert!(snapshot.approx_eq(&recomputed, 1e-3));
+        assert!(!snapshot.approx_eq(&recomputed, 1e-12));
+
+        // identity and missing prices are exact
+        let other_pid = Snapshot {
+            pid: "8984".to_string(),
+            ..snapshot.clone()
+        };
+        assert!(!snapshot.approx_eq(&other_pid, 1.0));
+        let no_ask = Snapshot {
+            ask: String::new(),
+            ..snapshot.clone()
+        };
+        assert!(!snapshot.approx_eq(&no_ask, 1.0));
+        assert!(no_ask.approx_eq(&no_ask, 0.0));
+    }
+
+    #[test]
+    pub fn test_sentiment() {
+        let mut snapshot = Snapshot {
+            last_dir: Some("greenBg".into()),
+            pc: "+364.0".into(),
+            pcp: "+1.49%".into(),
+            pc_col: "greenFont".into(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.sentiment(), Sentiment::Bullish);
+
+        // the last tick down in an up session
+        snapshot.last_dir = Some("redBg".into());
+        assert_eq!(snapshot.sentiment(), Sentiment::Bullish);
+
+        // the numbers win over the color
+        snapshot.pc_col = "redFont".into();
+        assert_eq!(snapshot.sentiment(), Sentiment::Bullish);
+        snapshot.pc = "-0.5".into();
+        assert_eq!(snapshot.sentiment(), Sentiment::Bearish);
+        snapshot.pc = "0.0".into();
+        assert_eq!(snapshot.sentiment(), Sentiment::Flat);
+
+        // pcp without pc, then the color, then last_dir
+        snapshot.pc.clear();
+        assert_eq!(snapshot.sentiment(), Sentiment::Bullish);
+        snapshot.pcp.clear();
+        assert_eq!(snapshot.sentiment(), Sentiment::Bearish);
+        snapshot.pc_col.clear();
+        snapshot.last_dir = Some("greenBg".into());
+        assert_eq!(snapshot.sentiment(), Sentiment::Bullish);
+        snapshot.last_dir = None;
+        assert_eq!(snapshot.sentiment(), Sentiment::Unknown);
+    }
+
+    #[test]
+    pub fn test_price_percent() {
+        let snapshot = Snapshot {
+            last: "18,951.2".into(),
+            pcp: "-1.23%".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.last_price(), Some(Price(18951.2)));
+        assert_eq!(snapshot.change_percent(), Some(Percent(-1.23)));
+        assert!(Price(18951.2) > Price(18951.1));
+
+        assert_eq!(Price(18951.2).to_string(), "18,951.2");
+        assert_eq!(format!("{:.1}", Price(18954.0)), "18,954.0");
+        assert_eq!(Price(-1234567.5).to_string(), "-1,234,567.5");
+        assert_eq!(Price(1.09345).to_string(), "1.09345");
+        assert_eq!(Percent(-1.23).to_string(), "-1.23%");
+        assert_eq!(format!("{:.2}", Percent(0.5)), "0.50%");
+        assert_eq!(format!("{:+.2}", Percent(0.5)), "+0.50%");
+        assert_eq!(format!("{:.5}", Price(1.0934)), "1.09340");
+    }
+
+    #[test]
+    pub fn test_display() {
+        let mut snapshot = Snapshot {
+            pid: "8984".to_string(),
+            last: "24,871.0".into(),
+            pcp: "+1.49%".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.last_decimals(), Some(1));
+        assert_eq!(snapshot.to_string(), "8984 24,871.0 (+1.49%)");
+        assert_eq!(format!("{:.2}", snapshot), "8984 24,871.00 (+1.49%)");
+
+        snapshot.pid = "1".to_string();
+        snapshot.last = "1.09345".into();
+        snapshot.pcp = "".to_string();
+        assert_eq!(snapshot.last_decimals(), Some(5));
+        assert_eq!(snapshot.to_string(), "1 1.09345");
+
+        // falls back to last_numeric
+        snapshot.last = "".into();
+        snapshot.last_numeric = 1.0934;
+        assert_eq!(snapshot.last_decimals(), None);
+        assert_eq!(snapshot.to_string(), "1 1.0934");
+    }
+
+    #[test]
+    pub fn test_decimal_places() {
+        let mut snapshot = Snapshot::default();
+        for (last, places) in [
+            ("18,951.2", Some(1)),
+            ("1.09345", Some(5)),
+            ("18,951", Some(0)),
+            ("", None),
+            ("n/a", None),
+        ]
+        .iter()
+        {
+            snapshot.last = (*last).into();
+            assert_eq!(snapshot.decimal_places(), *places, "{}", last);
+        }
+
+        snapshot.number_format = NumberFormat::CommaDecimal;
+        snapshot.last = "1.234,56".into();
+        assert_eq!(snapshot.decimal_places(), Some(2));
+    }
+
+    #[test]
+    pub fn test_content_eq() {
+        let snapshot = Snapshot {
+            pid: "945629".to_string(),
+            last: "18,951.2".into(),
+            time: "19:21:50".to_string(),
+            timestamp: 1606850510,
+            ..Default::default()
+        };
+        let mut other = Snapshot {
+            pid: "945629".to_string(),
+            last: "18,951.2".into(),
+            time: "19:21:51".to_string(),
+            timestamp: 1606850511,
+            ..Default::default()
+        };
+        assert!(snapshot.content_eq(&other));
+        assert!(!snapshot.fields_eq(&other, &[SnapshotField::Timestamp]));
+
+        other.last = "18,951.3".into();
+        assert!(!snapshot.content_eq(&other));
+        assert!(snapshot.fields_eq(&other, &[SnapshotField::Pid, SnapshotField::Bid]));
+        assert!(snapshot.fields_eq(&other, &[]));
+    }
+
+    #[test]
+    pub fn test_computed_pcp() {
+        let mut snapshot = Snapshot {
+            last: "18,951.2".into(),
+            last_close: "19,188.0".to_string(),
+            pcp: "-1.23%".to_string(),
+            ..Default::default()
+        };
+        assert!((snapshot.computed_pcp().unwrap() + 1.234).abs() < 0.001);
+        assert!(snapshot.pcp_matches(0.01));
+        assert!(!snapshot.pcp_matches(0.001));
+
+        snapshot.pcp = "+1.23%".to_string();
+        assert!(!snapshot.pcp_matches(0.01));
+
+        snapshot.last_close = "".to_string();
+        assert_eq!(snapshot.computed_pcp(), None);
+        assert!(!snapshot.pcp_matches(0.01));
+    }
+
+    #[test]
+    pub fn test_gap_from_close() {
+        let mut snapshot = Snapshot {
+            last: "18,951.2".into(),
+            last_close: "19,188.0".to_string(),
+            ..Default::default()
+        };
+        assert!((snapshot.gap_from_close().unwrap() + 236.8).abs() < 1e-9);
+        assert!((snapshot.gap_from_close_percent().unwrap() + 1.234105).abs() < 1e-5);
+
+        snapshot.last_close = "0".to_string();
+        assert_eq!(snapshot.gap_from_close(), Some(18951.2));
+        assert_eq!(snapshot.gap_from_close_percent(), None);
+
+        snapshot.last_close = "".to_string();
+        assert_eq!(snapshot.gap_from_close(), None);
+        assert_eq!(snapshot.gap_from_close_percent(), None);
+    }
+
+    #[test]
+    pub fn test_numeric_accessors() {
+        let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.5,"last":"18,951.5","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
+		"last_close":"","pc":"-236.8","pcp":"-1.23%","pc_col":"redFont","turnover":"21.50K",
+		"time":"19:21:50","timestamp":1606850510}"#;
+        let snapshot: Snapshot = serde_json::from_str(src).unwrap();
+
+        assert_eq!(snapshot.last_f64(), Some(18951.5));
+        assert_eq!(snapshot.bid_f64(), Some(18954.0));
+        assert_eq!(snapshot.ask_f64(), Some(18956.0));
+        assert_eq!(snapshot.high_f64(), Some(19956.0));
+        assert_eq!(snapshot.low_f64(), Some(18279.0));
+        assert_eq!(snapshot.last_close_f64(), None);
+        assert_eq!(snapshot.pc_f64(), Some(-236.8));
+        assert_eq!(snapshot.pcp_f64(), Some(-1.23));
+        assert_eq!(snapshot.turnover_value(), Some(21500.0));
+        assert_eq!(snapshot.is_up(), Some(false));
+    }
+
+    #[test]
+    pub fn test_is_up() {
+        let mut snapshot = Snapshot {
+            pc: "+364.0".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.is_up(), Some(true));
+
+        snapshot.pc = "".to_string();
+        assert_eq!(snapshot.is_up(), None);
+        snapshot.pcp = "-1.23%".to_string();
+        assert_eq!(snapshot.is_up(), Some(false));
+    }
+
+    #[test]
+    pub fn test_cmp_by_timestamp() {
+        let snapshot = |pid: &str, timestamp| Snapshot {
+            pid: pid.to_string(),
+            timestamp,
+            ..Default::default()
+        };
+        let mut snapshots = [
+            snapshot("8984", 1606850512),
+            snapshot("945629", 1606850510),
+            snapshot("8984", 1606850510),
+        ];
+
+        snapshots.sort_by(Snapshot::cmp_by_timestamp);
+
+        let pids: Vec<_> = snapshots.iter().map(|s| s.pid.as_str()).collect();
+        assert_eq!(pids, vec!["945629", "8984", "8984"]);
+        assert_eq!(
+            snapshots[0].cmp_by_timestamp(&snapshots[1]),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    pub fn test_time_at() {
+        let eastern = FixedOffset::west_opt(5 * 3600).unwrap();
+        let mut snapshot = Snapshot {
+            // 2020-12-01 19:21:50 UTC
+            timestamp: 1606850510,
+            time: "14:21:50".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            snapshot.time_at(eastern).unwrap().to_rfc3339(),
+            "2020-12-01T14:21:50-05:00"
+        );
+
+        // the time lags behind the timestamp across midnight
+        snapshot.timestamp = 1606885202; // 2020-12-02 00:00:02 -05:00
+        snapshot.time = "23:59:58".to_string();
+        assert_eq!(
+            snapshot.time_at(eastern).unwrap().to_rfc3339(),
+            "2020-12-01T23:59:58-05:00"
+        );
+
+        snapshot.time = "3:20:58".to_string();
+        assert_eq!(
+            snapshot.time_at(eastern).unwrap().to_rfc3339(),
+            "2020-12-02T03:20:58-05:00"
+        );
+
+        assert_eq!(snapshot.local_time(), None);
+        snapshot.utc_offset = Some(eastern);
+        assert_eq!(snapshot.local_time(), snapshot.time_at(eastern));
+
+        snapshot.time = "".to_string();
+        assert_eq!(snapshot.time_at(eastern), None);
+    }
+
+    #[test]
+    pub fn test_missing_timestamp() {
+        let src = r#"{"pid":"945629","last_numeric":18951.2,"last":"18,951.2","time":"14:21:50"}"#;
+        let mut snapshot: Snapshot = serde_json::from_str(src).unwrap();
+        assert_eq!(snapshot.timestamp, 0);
+        assert_eq!(snapshot.datetime_utc(), None);
+        assert_eq!(snapshot.age(), None);
+        assert_eq!(snapshot.latency(), None);
+
+        // the date of received_at instead
+        let eastern = FixedOffset::west_opt(5 * 3600).unwrap();
+        snapshot.received_at = Some(UNIX_EPOCH + Duration::from_secs(1606850512));
+        assert_eq!(snapshot.latency(), None);
+        assert_eq!(
+            snapshot.time_at(eastern).unwrap().to_rfc3339(),
+            "2020-12-01T14:21:50-05:00"
+        );
+        assert_eq!(estimate_clock_offset(&[snapshot.clone()]), None);
+
+        // or of the current time
+        snapshot.received_at = None;
+        let time_at = snapshot.time_at(eastern).unwrap();
+        let now = DateTime::<Utc>::from(SystemTime::now()).with_timezone(&eastern);
+        assert!((time_at - now).num_hours().abs() <= 12);
+        assert_eq!(time_at.time(), NaiveTime::from_hms_opt(14, 21, 50).unwrap());
+    }
+
+    #[test]
     pub fn test_no_turnover_err() {
-		let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
+        let src = r#"{"pid":"945629","last_dir":"redBg","last_numeric":18951.2,"last":"18,951.2","bid":"18,954.0","ask":"18,956.0","high":"19,956.0","low":"18,279.0",
 		"last_close":"19,188.0","pc":"-236.8","pcp":"-1.23%","pc_col":"redFont","turnover":"21.50K",
 		"turnover_numeric":"olia","time":"19:21:50","timestamp":1606850510}"#;
         let snapshot: Snapshot = serde_json::from_str(src).unwrap();
@@ -0,0 +1,202 @@
+//! Snapshots as the fields changed since the previous snapshot of their pid, e.g. to relay the
+//! feed onward without resending the unchanged strings, see StreamBuilder::build_delta(..)
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::Snapshot;
+
+/// Fields of a snapshot which differ from the previous snapshot of the pid, None for the
+/// unchanged ones. The first snapshot of a pid has all of them.
+///
+/// The fields are those received from the server. A `last_dir` gone since the previous
+/// snapshot is an empty string. The unchanged fields are left out of the JSON.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SnapshotDelta {
+    pub pid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_dir: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_numeric: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<Box<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ask: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub high: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_close: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pcp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc_col: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turnover: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turnover_numeric: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+/// The new value when it differs from the previous one, or there is none.
+fn changed<T: PartialEq + Clone>(new: &T, old: Option<&T>) -> Option<T> {
+    match old {
+        Some(old) if old == new => None,
+        _ => Some(new.clone()),
+    }
+}
+
+impl SnapshotDelta {
+    /// Fields of the snapshot which differ from the previous one, all of them without.
+    pub fn diff(snapshot: &Snapshot, previous: Option<&Snapshot>) -> Self {
+        let empty: Box<str> = "".into();
+        let last_dir = snapshot.last_dir.as_ref().unwrap_or(&empty);
+        SnapshotDelta {
+            pid: snapshot.pid.clone(),
+            last_dir: changed(
+                last_dir,
+                previous.map(|previous| previous.last_dir.as_ref().unwrap_or(&empty)),
+            ),
+            last_numeric: changed(
+                &snapshot.last_numeric,
+                previous.map(|previous| &previous.last_numeric),
+            ),
+            last: changed(&snapshot.last, previous.map(|previous| &previous.last)),
+            bid: changed(&snapshot.bid, previous.map(|previous| &previous.bid)),
+            ask: changed(&snapshot.ask, previous.map(|previous| &previous.ask)),
+            high: changed(&snapshot.high, previous.map(|previous| &previous.high)),
+            low: changed(&snapshot.low, previous.map(|previous| &previous.low)),
+            last_close: changed(
+                &snapshot.last_close,
+                previous.map(|previous| &previous.last_close),
+            ),
+            pc: changed(&snapshot.pc, previous.map(|previous| &previous.pc)),
+            pcp: changed(&snapshot.pcp, previous.map(|previous| &previous.pcp)),
+            pc_col: changed(&snapshot.pc_col, previous.map(|previous| &previous.pc_col)),
+            turnover: changed(
+                &snapshot.turnover,
+                previous.map(|previous| &previous.turnover),
+            ),
+            turnover_numeric: changed(
+                &snapshot.turnover_numeric,
+                previous.map(|previous| &previous.turnover_numeric),
+            ),
+            time: changed(&snapshot.time, previous.map(|previous| &previous.time)),
+            timestamp: changed(
+                &snapshot.timestamp,
+                previous.map(|previous| &previous.timestamp),
+            ),
+        }
+    }
+
+    /// Whether no field changed, e.g. a snapshot sent again by the server.
+    pub fn is_empty(&self) -> bool {
+        *self
+            == SnapshotDelta {
+                pid: self.pid.clone(),
+                ..SnapshotDelta::default()
+            }
+    }
+
+    /// Returns the snapshot of the delta over the previous snapshot of the pid, e.g. the one
+    /// rebuilt from the previous delta. The fields not received from the server, such as
+    /// `received_at`, are those of the base.
+    pub fn apply(&self, base: &Snapshot) -> Snapshot {
+        let mut snapshot = base.clone();
+        snapshot.pid = self.pid.clone();
+        if let Some(last_dir) = &self.last_dir {
+            snapshot.last_dir = Some(last_dir.clone()).filter(|last_dir| !last_dir.is_empty());
+        }
+        let replace = |field: &mut String, value: &Option<String>| {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        };
+        replace(&mut snapshot.bid, &self.bid);
+        replace(&mut snapshot.ask, &self.ask);
+        replace(&mut snapshot.high, &self.high);
+        replace(&mut snapshot.low, &self.low);
+        replace(&mut snapshot.last_close, &self.last_close);
+        replace(&mut snapshot.pc, &self.pc);
+        replace(&mut snapshot.pcp, &self.pcp);
+        replace(&mut snapshot.pc_col, &self.pc_col);
+        replace(&mut snapshot.turnover, &self.turnover);
+        replace(&mut snapshot.time, &self.time);
+        if let Some(last) = &self.last {
+            snapshot.last = last.clone();
+        }
+        snapshot.last_numeric = self.last_numeric.unwrap_or(snapshot.last_numeric);
+        snapshot.turnover_numeric = self.turnover_numeric.unwrap_or(snapshot.turnover_numeric);
+        snapshot.timestamp = self.timestamp.unwrap_or(snapshot.timestamp);
+        snapshot
+    }
+}
+
+/// Last snapshot of each pid, diffing the next ones against it.
+#[derive(Debug, Default)]
+pub struct DeltaEncoder {
+    last: HashMap<String, Snapshot>,
+}
+
+impl DeltaEncoder {
+    /// Returns the delta of the snapshot to the last one of its pid, and keeps it as the last.
+    pub fn encode(&mut self, snapshot: Snapshot) -> SnapshotDelta {
+        let delta = SnapshotDelta::diff(&snapshot, self.last.get(&snapshot.pid));
+        self.last.insert(snapshot.pid.clone(), snapshot);
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_encode_apply() {
+        let first = Snapshot {
+            pid: "945629".to_string(),
+            last_dir: Some("redBg".into()),
+            last_numeric: 18951.2,
+            last: "18,951.2".into(),
+            bid: "18,954.0".to_string(),
+            ask: "18,956.0".to_string(),
+            timestamp: 1606850510,
+            ..Snapshot::default()
+        };
+        let second = Snapshot {
+            last_dir: None,
+            last_numeric: 18952.0,
+            last: "18,952.0".into(),
+            timestamp: 1606850511,
+            ..first.clone()
+        };
+
+        let mut encoder = DeltaEncoder::default();
+        let delta = encoder.encode(first.clone());
+        assert_eq!(delta.bid.as_deref(), Some("18,954.0"));
+        assert_eq!(delta.apply(&Snapshot::default()).bid, first.bid);
+
+        let delta = encoder.encode(second.clone());
+        assert_eq!(delta.last_numeric, Some(18952.0));
+        assert_eq!(delta.last_dir.as_deref(), Some(""));
+        assert_eq!(delta.bid, None);
+        assert_eq!(
+            serde_json::to_string(&delta).unwrap(),
+            r#"{"pid":"945629","last_dir":"","last_numeric":18952.0,"last":"18,952.0","timestamp":1606850511}"#
+        );
+        let rebuilt = delta.apply(&first);
+        assert!(rebuilt.approx_eq(&second, 0.0));
+        assert_eq!(rebuilt.last_dir, None);
+
+        assert!(encoder.encode(second).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::io;
+
+/// Failures of the stream to the server
+///
+/// Failures of an underlying library are available from `std::error::Error::source()`, and
+/// left out of the message of the variant, so a report walking the chain shows each once.
+/// Errors of tungstenite are boxed, to keep Error and the results carrying it small.
+/// New variants may be added, so matches need a wildcard arm.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The comma-separated pair ids given to the stream are empty, or contain an empty pair id.
+    #[error("invalid pair ids {0:?}")]
+    InvalidPairId(String),
+
+    /// The domain given to StreamBuilder::domain(..) is empty.
+    #[error("empty domain")]
+    EmptyDomain,
+
+    /// The UTC offset of StreamConfig::utc_offset_secs is a day or more. Carries it, in seconds.
+    #[error("invalid UTC offset of {0} seconds")]
+    InvalidUtcOffset(i32),
+
+    /// Failed to connect the websocket to the server.
+    #[error("failed to connect to server")]
+    Connect(#[source] Box<tungstenite::Error>),
+
+    /// Failed to open the tunnel through the proxy.
+    #[error("failed to connect through proxy")]
+    Proxy(#[source] io::Error),
+
+    /// The websocket upgrade did not complete within the connect timeout.
+    #[error("timed out connecting to server")]
+    ConnectTimeout,
+
+    /// The websocket is upgraded, but the SockJS open frame "o" did not arrive within the
+    /// connect timeout.
+    #[error("timed out waiting for the open frame")]
+    OpenFrameTimeout,
+
+    /// The server closed the connection before sending the SockJS open frame "o".
+    #[error("connection closed before the open frame")]
+    NoOpenFrame,
+
+    /// A frame other than a heartbeat arrived before the SockJS open frame "o". Carries what
+    /// was received.
+    #[error("expected open frame \"o\", received {0:?}")]
+    UnexpectedOpenFrame(String),
+
+    /// Failed to send a message to the server.
+    #[error("failed to send to server")]
+    Send(#[source] Box<tungstenite::Error>),
+
+    /// Failed to read a message from the server.
+    #[error("failed to receive from server")]
+    Receive(#[source] Box<tungstenite::Error>),
+
+    /// A message or a frame of the server exceeded StreamBuilder::max_message_size(..) or
+    /// max_frame_size(..), and was not received. Carries its size and the limit, in bytes.
+    #[error("message of {size} bytes from server exceeds the limit of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+
+    /// The frame is not a SockJS frame "o", "h", "a[..]" or "c[..]". Carries the frame.
+    #[error("invalid SockJS frame {0:?}")]
+    InvalidFrame(String),
+
+    /// The instrument data of a frame is not a valid snapshot.
+    #[error("failed to parse snapshot")]
+    Parse(#[source] serde_json::Error),
+
+    /// No snapshot of the pair ids arrived within the timeout of fetch_snapshot(..) or
+    /// collect(..), or before the connection ended.
+    #[error("no snapshot received")]
+    NoSnapshot,
+
+    /// The server replied to the UID message without a UID. Carries the reply.
+    #[error("server rejected UID: {0}")]
+    Uid(String),
+
+    /// The server replied to the UID message with a UID string which is not an integer.
+    /// Carries the reply.
+    #[error("invalid UID in server reply {reply}")]
+    InvalidUid {
+        reply: String,
+        #[source]
+        source: std::num::ParseIntError,
+    },
+
+    /// The server closed the connection with a SockJS close frame or a websocket Close frame.
+    #[error("closed by server with code {code}: {reason:?}")]
+    Closed { code: u16, reason: String },
+
+    /// The task was stopped through `Stream::abort_handle()`.
+    #[error("stream aborted")]
+    Aborted,
+
+    /// The stream ended, so there is no connection left to send through, see Stream::resubscribe(..)
+    #[error("stream ended")]
+    Ended,
+
+    /// The option of StreamBuilder is not supported by StreamBuilder::build_pool(..), by name.
+    #[error("option {0} is not supported by StreamPool")]
+    UnsupportedOption(&'static str),
+
+    /// Failure of the browser websocket.
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    #[error("websocket failure")]
+    Wasm(#[source] ws_stream_wasm::WsErr),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Error {
+    /// Error::Receive, or Error::MessageTooLarge for a message over the limits.
+    pub(crate) fn receive(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong {
+                size,
+                max_size,
+            }) => Error::MessageTooLarge {
+                size,
+                max: max_size,
+            },
+            e => Error::Receive(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error as _;
+
+    #[test]
+    pub fn test_source() {
+        let e = Error::Proxy(io::Error::other("refused"));
+        assert_eq!(e.to_string(), "failed to connect through proxy");
+        assert_eq!(e.source().unwrap().to_string(), "refused");
+
+        let e = Error::Parse(serde_json::from_str::<u32>("x").unwrap_err());
+        assert_eq!(e.to_string(), "failed to parse snapshot");
+        assert!(e.source().is_some());
+
+        let e = Error::InvalidUid {
+            reply: "{}".to_string(),
+            source: "x".parse::<u64>().unwrap_err(),
+        };
+        assert_eq!(
+            e.source().unwrap().to_string(),
+            "invalid digit found in string"
+        );
+
+        let e = Error::Closed {
+            code: 3000,
+            reason: "Go away!".to_string(),
+        };
+        assert_eq!(
+            e.to_string(),
+            "closed by server with code 3000: \"Go away!\""
+        );
+        assert!(e.source().is_none());
+
+        let e = Error::receive(tungstenite::Error::Capacity(
+            tungstenite::error::CapacityError::MessageTooLong {
+                size: 2048,
+                max_size: 1024,
+            },
+        ));
+        assert!(matches!(
+            e,
+            Error::MessageTooLarge {
+                size: 2048,
+                max: 1024
+            }
+        ));
+        let e = Error::receive(tungstenite::Error::ConnectionClosed);
+        assert!(matches!(e, Error::Receive(_)));
+    }
+}
@@ -0,0 +1,194 @@
+//! Detection of quotes frozen at one price while the snapshots keep arriving, e.g. after a
+//! stall upstream of the server, which the liveness checks such as Stream::healthy(..) miss.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::data::Snapshot;
+
+/// Price of a pid unchanged for longer than the window of FreezeDetector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Freeze {
+    pub pid: String,
+
+    /// `last_numeric` of the snapshots since the last change.
+    pub price: f64,
+
+    /// Time since the price last changed, when the freeze is reported.
+    pub duration: Duration,
+}
+
+/// Price of a pid and since when it is unchanged.
+#[derive(Debug)]
+struct Latest {
+    price: f64,
+    since: Instant,
+    reported: bool,
+}
+
+/// Tracks the last price per pid and reports those unchanged for longer than the window.
+///
+/// The time is the one the snapshots are received at, not the server timestamp, which may be
+/// frozen as well. A freeze is reported once, by the first snapshot past the window or by
+/// FreezeDetector::tick(..) when no snapshot comes, and the pid is watched again once its
+/// price changes.
+#[derive(Debug)]
+pub struct FreezeDetector {
+    window: Duration,
+    latest: HashMap<String, Latest>,
+    freezes: u64,
+}
+
+impl FreezeDetector {
+    /// Detector reporting the prices unchanged for longer than the window.
+    pub fn new(window: Duration) -> Self {
+        FreezeDetector {
+            window,
+            latest: HashMap::new(),
+            freezes: 0,
+        }
+    }
+
+    /// Records the snapshot received at `now`, returns the freeze of its pid if its price is
+    /// unchanged for longer than the window and not reported yet.
+    pub fn check(&mut self, snapshot: &Snapshot, now: Instant) -> Option<Freeze> {
+        let latest = self
+            .latest
+            .entry(snapshot.pid.clone())
+            .or_insert_with(|| Latest {
+                price: snapshot.last_numeric,
+                since: now,
+                reported: false,
+            });
+        if latest.price != snapshot.last_numeric {
+            *latest = Latest {
+                price: snapshot.last_numeric,
+                since: now,
+                reported: false,
+            };
+            return None;
+        }
+
+        let duration = now.saturating_duration_since(latest.since);
+        if latest.reported || duration <= self.window {
+            return None;
+        }
+        latest.reported = true;
+        self.freezes += 1;
+        Some(Freeze {
+            pid: snapshot.pid.clone(),
+            price: latest.price,
+            duration,
+        })
+    }
+
+    /// Returns the freezes of the pids whose price is unchanged for longer than the window at
+    /// `now` and not reported yet, e.g. of a feed still sending heartbeats but no snapshots.
+    pub fn tick(&mut self, now: Instant) -> Vec<Freeze> {
+        let window = self.window;
+        let mut freezes: Vec<_> = self
+            .latest
+            .iter_mut()
+            .filter_map(|(pid, latest)| {
+                let duration = now.saturating_duration_since(latest.since);
+                if latest.reported || duration <= window {
+                    return None;
+                }
+                latest.reported = true;
+                Some(Freeze {
+                    pid: pid.clone(),
+                    price: latest.price,
+                    duration,
+                })
+            })
+            .collect();
+        freezes.sort_by(|a, b| a.pid.cmp(&b.pid));
+        self.freezes += freezes.len() as u64;
+        freezes
+    }
+
+    /// Time a price has to stay unchanged to be reported.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Number of freezes reported so far.
+    pub fn freezes(&self) -> u64 {
+        self.freezes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(pid: &str, last_numeric: f64) -> Snapshot {
+        Snapshot {
+            pid: pid.to_string(),
+            last_numeric,
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    pub fn test_check() {
+        let mut detector = FreezeDetector::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(detector.check(&snapshot("8984", 24871.5), at(0)), None);
+        assert_eq!(detector.check(&snapshot("8984", 24871.5), at(60)), None);
+        assert_eq!(
+            detector.check(&snapshot("8984", 24871.5), at(61)),
+            Some(Freeze {
+                pid: "8984".to_string(),
+                price: 24871.5,
+                duration: Duration::from_secs(61),
+            })
+        );
+        // reported once
+        assert_eq!(detector.check(&snapshot("8984", 24871.5), at(90)), None);
+        // another pid is on its own
+        assert_eq!(detector.check(&snapshot("945629", 18951.2), at(90)), None);
+
+        // a change watches the pid again
+        assert_eq!(detector.check(&snapshot("8984", 24872.0), at(100)), None);
+        assert_eq!(detector.check(&snapshot("8984", 24872.0), at(150)), None);
+        assert!(detector
+            .check(&snapshot("8984", 24872.0), at(161))
+            .is_some());
+        assert_eq!(detector.freezes(), 2);
+    }
+
+    #[test]
+    pub fn test_tick() {
+        let mut detector = FreezeDetector::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(detector.check(&snapshot("8984", 24871.5), at(0)), None);
+        assert_eq!(detector.check(&snapshot("945629", 18951.2), at(30)), None);
+        assert!(detector.tick(at(60)).is_empty());
+
+        // no snapshot since, as with a feed sending only heartbeats
+        let freezes = detector.tick(at(61));
+        assert_eq!(
+            freezes,
+            [Freeze {
+                pid: "8984".to_string(),
+                price: 24871.5,
+                duration: Duration::from_secs(61),
+            }]
+        );
+        let freezes = detector.tick(at(120));
+        assert_eq!(freezes.len(), 1);
+        assert_eq!(freezes[0].pid, "945629");
+
+        // reported once, by the tick or the snapshot
+        assert!(detector.tick(at(180)).is_empty());
+        assert_eq!(detector.check(&snapshot("8984", 24871.5), at(180)), None);
+        assert_eq!(detector.freezes(), 2);
+    }
+}
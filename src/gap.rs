@@ -0,0 +1,147 @@
+//! Detection of gaps in the timestamps of the stream, to flag periods of incomplete data.
+
+use std::{collections::HashMap, time::Duration};
+
+use crate::data::{InstrumentClass, Snapshot};
+
+/// Interval between two consecutive snapshots of a pid longer than the threshold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gap {
+    pub pid: String,
+
+    /// Server timestamp of the snapshot before the gap.
+    pub from: u64,
+
+    /// Server timestamp of the snapshot after the gap.
+    pub to: u64,
+}
+
+impl Gap {
+    /// Length of the gap, with the one-second resolution of the server timestamp.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.to - self.from)
+    }
+}
+
+/// Tracks the latest timestamp per pid and reports intervals longer than the threshold.
+///
+/// The first snapshot of a pid has no predecessor and never reports a gap. A timestamp
+/// older than the latest one of its pid, e.g. from clock skew between server hosts, is
+/// not a gap and does not move the latest timestamp back. The breaks between the sessions of
+/// sessioned instruments are not gaps either, see `instrument_class(..)`
+#[derive(Debug)]
+pub struct GapDetector {
+    threshold: Duration,
+    class: InstrumentClass,
+    latest: HashMap<String, u64>,
+    gaps: u64,
+}
+
+impl GapDetector {
+    /// Detector reporting intervals longer than the threshold.
+    pub fn new(threshold: Duration) -> Self {
+        GapDetector {
+            threshold,
+            class: InstrumentClass::default(),
+            latest: HashMap::new(),
+            gaps: 0,
+        }
+    }
+
+    /// Trading hours of the instruments: with InstrumentClass::Sessioned, the intervals of
+    /// a session break are not reported, see InstrumentClass::is_session_break(..). Default
+    /// is Continuous, reporting every interval over the threshold.
+    pub fn instrument_class(mut self, class: InstrumentClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// Records the snapshot, returns the gap since the previous snapshot of its pid if any.
+    pub fn check(&mut self, snapshot: &Snapshot) -> Option<Gap> {
+        let latest = self.latest.get_mut(&snapshot.pid);
+        let latest = match latest {
+            Some(latest) => latest,
+            None => {
+                self.latest.insert(snapshot.pid.clone(), snapshot.timestamp);
+                return None;
+            }
+        };
+
+        if snapshot.timestamp <= *latest {
+            return None;
+        }
+        let gap = Gap {
+            pid: snapshot.pid.clone(),
+            from: *latest,
+            to: snapshot.timestamp,
+        };
+        *latest = snapshot.timestamp;
+
+        if gap.duration() > self.threshold && !self.class.is_session_break(gap.duration()) {
+            self.gaps += 1;
+            Some(gap)
+        } else {
+            None
+        }
+    }
+
+    /// Number of gaps reported so far.
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(pid: &str, timestamp: u64) -> Snapshot {
+        Snapshot {
+            pid: pid.to_string(),
+            timestamp,
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    pub fn test_check() {
+        let mut detector = GapDetector::new(Duration::from_secs(5));
+
+        assert_eq!(detector.check(&snapshot("8984", 100)), None);
+        assert_eq!(detector.check(&snapshot("8984", 105)), None);
+        assert_eq!(
+            detector.check(&snapshot("8984", 111)),
+            Some(Gap {
+                pid: "8984".to_string(),
+                from: 105,
+                to: 111,
+            })
+        );
+        // first tick of another pid
+        assert_eq!(detector.check(&snapshot("945629", 200)), None);
+        assert_eq!(detector.gaps(), 1);
+    }
+
+    #[test]
+    pub fn test_check_skew() {
+        let mut detector = GapDetector::new(Duration::from_secs(5));
+
+        detector.check(&snapshot("8984", 100));
+        assert_eq!(detector.check(&snapshot("8984", 99)), None);
+        // measured from the latest timestamp, not the skewed one
+        assert_eq!(detector.check(&snapshot("8984", 104)), None);
+        assert_eq!(detector.gaps(), 0);
+    }
+
+    #[test]
+    pub fn test_check_sessioned() {
+        let mut detector =
+            GapDetector::new(Duration::from_secs(5)).instrument_class(InstrumentClass::Sessioned);
+
+        detector.check(&snapshot("8984", 100));
+        assert!(detector.check(&snapshot("8984", 160)).is_some());
+        // overnight
+        assert_eq!(detector.check(&snapshot("8984", 160 + 16 * 3600)), None);
+        assert_eq!(detector.gaps(), 1);
+    }
+}
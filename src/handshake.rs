@@ -0,0 +1,153 @@
+//! Trace of the handshake of each connection at debug level, to tell where a connection
+//! without data stalls: the URL chosen, the websocket upgraded, the SockJS open frame, the
+//! subscribe and UID messages sent, and the first frame received.
+//!
+//! Each step is an event of the target "forexpros_wss::handshake" with the fields `step`,
+//! `url` and `elapsed_ms`, the milliseconds since the URL was chosen. With feature "tracing"
+//! the events go through the tracing crate, otherwise they are logged through log.
+
+use std::time::{Duration, Instant};
+
+/// Steps of one connection attempt, timed from the URL chosen.
+pub(crate) struct Handshake {
+    url: String,
+    started: Instant,
+}
+
+impl Handshake {
+    /// Starts the trace with the URL chosen.
+    pub(crate) fn start(url: &str) -> Self {
+        let handshake = Handshake {
+            url: url.to_string(),
+            started: Instant::now(),
+        };
+        handshake.step("url chosen");
+        handshake
+    }
+
+    pub(crate) fn upgraded(&self) {
+        self.step("websocket upgraded");
+    }
+
+    pub(crate) fn open_frame(&self) {
+        self.step("open frame received");
+    }
+
+    pub(crate) fn subscribe_sent(&self) {
+        self.step("subscribe sent");
+    }
+
+    /// A message of StreamBuilder::connect_messages(..), in place of subscribe and UID.
+    pub(crate) fn connect_message_sent(&self) {
+        self.step("connect message sent");
+    }
+
+    pub(crate) fn uid_sent(&self) {
+        self.step("uid sent");
+    }
+
+    pub(crate) fn first_frame(&self) {
+        self.step("first frame received");
+    }
+
+    fn step(&self, step: &'static str) {
+        emit(step, &self.url, self.started.elapsed());
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn emit(step: &'static str, url: &str, elapsed: Duration) {
+    tracing::debug!(
+        target: "forexpros_wss::handshake",
+        step,
+        url,
+        elapsed_ms = elapsed.as_millis() as u64,
+        "Handshake: {}",
+        step
+    );
+}
+
+#[cfg(not(feature = "tracing"))]
+fn emit(step: &'static str, url: &str, elapsed: Duration) {
+    log::debug!(
+        target: "forexpros_wss::handshake",
+        "Handshake: {} after {} ms, {}",
+        step,
+        elapsed.as_millis(),
+        url
+    );
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use super::*;
+
+    /// Steps of the handshake events
+    #[derive(Clone, Default)]
+    struct TestSubscriber(Arc<Mutex<Vec<String>>>);
+
+    struct StepVisitor<'a>(&'a mut Option<String>);
+
+    impl Visit for StepVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "step" {
+                *self.0 = Some(value.to_string());
+            }
+        }
+
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "forexpros_wss::handshake"
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut step = None;
+            event.record(&mut StepVisitor(&mut step));
+            self.0.lock().unwrap().extend(step);
+        }
+    }
+
+    #[test]
+    pub fn test_handshake() {
+        let subscriber = TestSubscriber::default();
+        tracing::subscriber::with_default(subscriber.clone(), || {
+            let handshake = Handshake::start("wss://stream1.forexpros.com/echo/websocket");
+            handshake.upgraded();
+            handshake.open_frame();
+            handshake.subscribe_sent();
+            handshake.uid_sent();
+            handshake.first_frame();
+        });
+
+        assert_eq!(
+            *subscriber.0.lock().unwrap(),
+            [
+                "url chosen",
+                "websocket upgraded",
+                "open frame received",
+                "subscribe sent",
+                "uid sent",
+                "first frame received"
+            ]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Last snapshots of each pid, replayed to the late receivers of Stream::subscribe_replay()
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::data::Snapshot;
+
+/// Up to `depth` snapshots of each pid, the oldest dropped first. The snapshots are those of
+/// the broadcast, shared rather than copied.
+pub(crate) struct History {
+    depth: usize,
+    snapshots: Mutex<HashMap<String, VecDeque<Arc<Snapshot>>>>,
+}
+
+impl History {
+    pub(crate) fn new(depth: usize) -> Self {
+        History {
+            depth,
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keeps the snapshot and publishes it under the lock, so a receiver subscribing meanwhile
+    /// gets it once, either replayed or published.
+    pub(crate) fn push(&self, snapshot: &Arc<Snapshot>, publish: impl FnOnce()) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let kept = snapshots.entry(snapshot.pid.clone()).or_default();
+        if kept.len() == self.depth {
+            kept.pop_front();
+        }
+        kept.push_back(Arc::clone(snapshot));
+        publish();
+    }
+
+    /// Returns the kept snapshots in the order received, with the receiver subscribed under
+    /// the lock, which gets the snapshots after them.
+    pub(crate) fn replay<T>(&self, subscribe: impl FnOnce() -> T) -> (Vec<Arc<Snapshot>>, T) {
+        let snapshots = self.snapshots.lock().unwrap();
+        let mut replayed: Vec<_> = snapshots.values().flatten().cloned().collect();
+        replayed.sort_by_key(|snapshot| snapshot.seq);
+        (replayed, subscribe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_history() {
+        let snapshot = |pid: &str, seq| {
+            Arc::new(Snapshot {
+                pid: pid.to_string(),
+                seq: Some(seq),
+                ..Default::default()
+            })
+        };
+        let history = History::new(2);
+        let mut published = 0;
+        for (pid, seq) in [("945629", 0), ("8984", 1), ("945629", 2), ("945629", 3)] {
+            history.push(&snapshot(pid, seq), || published += 1);
+        }
+        assert_eq!(published, 4);
+
+        let (replayed, subscribed) = history.replay(|| true);
+        let replayed: Vec<_> = replayed
+            .iter()
+            .map(|snapshot| (snapshot.pid.as_str(), snapshot.seq.unwrap()))
+            .collect();
+        assert_eq!(replayed, [("8984", 1), ("945629", 2), ("945629", 3)]);
+        assert!(subscribed);
+    }
+}
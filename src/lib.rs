@@ -45,5 +45,47 @@
 ///	https://stackoverflow.com/questions/26946646/rust-package-with-both-a-library-and-a-binary/26946705#26946705
 ///	https://www.reddit.com/r/rust/comments/k5sb9o/tokio_block_onjoinhandle_freeze_randomly/
 
+#[cfg(not(target_arch = "wasm32"))]
 pub mod push;
-pub mod data;
\ No newline at end of file
+pub mod candle;
+pub mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+mod coalesce;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+pub mod data;
+pub mod delta;
+pub mod error;
+pub mod freeze;
+pub mod gap;
+#[cfg(not(target_arch = "wasm32"))]
+mod handshake;
+#[cfg(not(target_arch = "wasm32"))]
+mod history;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod manager;
+pub mod metrics;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pool;
+pub mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+pub mod symbols;
+#[cfg(not(target_arch = "wasm32"))]
+mod queue;
+pub mod tick;
+pub mod validate;
+pub mod vwap;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod proxy;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+#[cfg(all(any(test, feature = "testing"), not(target_arch = "wasm32")))]
+pub mod testing;
+
+pub use error::Error;
+
+#[cfg(all(not(target_arch = "wasm32"), not(any(feature = "rustls-tls", feature = "native-tls"))))]
+compile_error!("forexpros_wss needs a TLS backend for wss://, enable feature \"rustls-tls\" or \"native-tls\"");
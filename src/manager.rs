@@ -0,0 +1,203 @@
+//! Supervision of many single-pid streams on one shared runtime.
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use futures_util::future;
+use tokio::{runtime, task::JoinHandle, time};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    data::Snapshot,
+    push::{run, ConnectOptions, Exit, Hooks, Status},
+};
+
+/// Delay before restarting a disconnected stream, see StreamManager::restart_on_disconnect(..)
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Owns the streams of many pids, keyed by pid, all running on one runtime.
+///
+/// Unlike Stream, which creates a runtime per stream, the streams of the manager share
+/// the runtime of the manager. Streams are stopped when removed, and when the manager is dropped.
+pub struct StreamManager {
+    runtime: runtime::Runtime,
+    restart_on_disconnect: bool,
+    streams: HashMap<String, ManagedStream>,
+}
+
+struct ManagedStream {
+    join_handle: JoinHandle<()>,
+    cancellation_token: CancellationToken,
+}
+
+impl Default for StreamManager {
+    fn default() -> Self {
+        StreamManager::new()
+    }
+}
+
+impl StreamManager {
+    /// Manager without streams, with its own runtime.
+    pub fn new() -> Self {
+        StreamManager {
+            runtime: runtime::Runtime::new().unwrap(),
+            restart_on_disconnect: false,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Reconnect a stream after RESTART_DELAY when the connection fails or the server closes it,
+    /// instead of ending it. A stream stopped by its handler is never restarted. Default is false.
+    ///
+    /// Applies to the streams added afterwards.
+    pub fn restart_on_disconnect(mut self, restart: bool) -> Self {
+        self.restart_on_disconnect = restart;
+        self
+    }
+
+    /// Start streaming the pid to the handler, replacing the stream of the pid if any.
+    /// The stream ends when the handler returns Err(()).
+    pub fn add<F>(&mut self, pid: &str, handler: F)
+    where
+        F: FnMut(Snapshot) -> Result<(), ()> + Send + 'static,
+    {
+        self.add_async(pid, into_async(handler))
+    }
+
+    /// Same as StreamManager::add(..), with a handler returning a future, see StreamBuilder::build_async(..)
+    pub fn add_async<F, Fut>(&mut self, pid: &str, mut handler: F)
+    where
+        F: FnMut(Snapshot) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), ()>> + Send + 'static,
+    {
+        self.remove(pid);
+
+        let pair_id = pid.to_string();
+        let restart_on_disconnect = self.restart_on_disconnect;
+        let cancellation_token = CancellationToken::new();
+        let token = cancellation_token.clone();
+
+        let task = async move {
+            let connect_options = ConnectOptions::default();
+            let mut hooks = Hooks::default();
+            let status = Status::default();
+            loop {
+                let client = run(
+                    &pair_id,
+                    &connect_options,
+                    1,
+                    &mut hooks,
+                    &token,
+                    &status,
+                    &mut handler,
+                );
+                let result = tokio::select! {
+                    _ = token.cancelled() => return,
+                    result = client => result,
+                };
+
+                match result {
+                    Ok(Exit::Stopped) => return,
+                    Ok(Exit::Closed) => log::warn!("Stream of {} closed by server", pair_id),
+                    Ok(Exit::Expired) => continue,
+                    Err(e) => log::error!("Stream of {} failed: {}", pair_id, e),
+                }
+                if !restart_on_disconnect {
+                    return;
+                }
+
+                log::info!("Restarting stream of {}", pair_id);
+                tokio::select! {
+                    _ = token.cancelled() => return,
+                    _ = time::sleep(RESTART_DELAY) => {}
+                }
+            }
+        };
+
+        let stream = ManagedStream {
+            join_handle: self.runtime.spawn(task),
+            cancellation_token,
+        };
+        self.streams.insert(pid.to_string(), stream);
+    }
+
+    /// Stop the stream of the pid. Returns false when there is no stream of the pid.
+    pub fn remove(&mut self, pid: &str) -> bool {
+        match self.streams.remove(pid) {
+            Some(stream) => {
+                stream.cancellation_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pids of the streams, including those which already ended.
+    pub fn pids(&self) -> impl Iterator<Item = &str> {
+        self.streams.keys().map(String::as_str)
+    }
+
+    /// Whether the stream of the pid is still running.
+    pub fn is_running(&self, pid: &str) -> bool {
+        self.streams
+            .get(pid)
+            .is_some_and(|stream| !stream.join_handle.is_finished())
+    }
+
+    /// Stop all streams and wait until they are closed.
+    ///
+    /// Blocks the current thread, so don't call it within an async context.
+    pub fn shutdown_all(&mut self) {
+        let join_handles: Vec<_> = self
+            .streams
+            .drain()
+            .map(|(_, stream)| {
+                stream.cancellation_token.cancel();
+                stream.join_handle
+            })
+            .collect();
+
+        self.runtime.block_on(future::join_all(join_handles));
+    }
+}
+
+impl Drop for StreamManager {
+    fn drop(&mut self) {
+        for stream in self.streams.values() {
+            stream.cancellation_token.cancel();
+        }
+    }
+}
+
+/// Wraps a synchronous handler into one returning a ready future.
+fn into_async<F>(mut handler: F) -> impl FnMut(Snapshot) -> future::Ready<Result<(), ()>>
+where
+    F: FnMut(Snapshot) -> Result<(), ()>,
+{
+    move |snapshot| future::ready(handler(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_add_remove() {
+        let mut manager = StreamManager::new();
+
+        manager.add("945629", |_| Ok(()));
+        manager.add("8984", |_| Ok(()));
+        // replaces the stream of the pid
+        manager.add("8984", |_| Ok(()));
+
+        let mut pids: Vec<_> = manager.pids().collect();
+        pids.sort();
+        assert_eq!(pids, vec!["8984", "945629"]);
+
+        assert!(manager.remove("8984"));
+        assert!(!manager.remove("8984"));
+        assert!(!manager.is_running("8984"));
+
+        manager.shutdown_all();
+        assert_eq!(manager.pids().count(), 0);
+    }
+}
@@ -0,0 +1,210 @@
+//! Counters and gauges of the streams, emitted through the facade of the metrics crate with
+//! feature "metrics", e.g. to a Prometheus exporter installed by the application.
+//!
+//! The metrics add up over all the streams of the process. Without the feature nothing is
+//! recorded, and the calls in the receive loop compile to nothing.
+
+/// Counter of the websocket messages received from the server, heartbeats included.
+pub const FRAMES_RECEIVED: &str = "forexpros_wss_frames_received_total";
+
+/// Counter of the snapshots parsed, labeled with the `pid`.
+pub const SNAPSHOTS_PARSED: &str = "forexpros_wss_snapshots_parsed_total";
+
+/// Counter of the snapshot frames which failed to parse, see StreamStats::parse_errors
+pub const PARSE_ERRORS: &str = "forexpros_wss_parse_errors_total";
+
+/// Counter of the connect attempts after a failed one, see StreamBuilder::on_reconnect(..)
+pub const RECONNECTS: &str = "forexpros_wss_reconnects_total";
+
+/// Gauge of the connections up, from the open frame until the connection ends.
+pub const CONNECTIONS: &str = "forexpros_wss_connections";
+
+/// Gauge of the local unix time in seconds when the last snapshot of the `pid` label arrived.
+/// Its age is the current time minus the gauge, e.g. `time() - forexpros_wss_last_update_seconds`
+/// in PromQL.
+pub const LAST_UPDATE: &str = "forexpros_wss_last_update_seconds";
+
+/// Describes the metrics to the installed recorder, for exporters showing help texts.
+#[cfg(feature = "metrics")]
+pub fn describe() {
+    use ::metrics::{describe_counter, describe_gauge, Unit};
+
+    describe_counter!(
+        FRAMES_RECEIVED,
+        "Websocket messages received from the server"
+    );
+    describe_counter!(SNAPSHOTS_PARSED, "Snapshots parsed, by pid");
+    describe_counter!(PARSE_ERRORS, "Snapshot frames which failed to parse");
+    describe_counter!(RECONNECTS, "Connect attempts after a failed one");
+    describe_gauge!(CONNECTIONS, "Connections to the server up");
+    describe_gauge!(
+        LAST_UPDATE,
+        Unit::Seconds,
+        "Unix time of the last snapshot, by pid"
+    );
+}
+
+#[cfg(all(feature = "metrics", not(target_arch = "wasm32")))]
+mod record {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use crate::data::Snapshot;
+
+    pub(crate) fn frame_received() {
+        ::metrics::counter!(FRAMES_RECEIVED).increment(1);
+    }
+
+    pub(crate) fn snapshot_parsed(snapshot: &Snapshot) {
+        let pid = snapshot.pid.clone();
+        ::metrics::counter!(SNAPSHOTS_PARSED, "pid" => pid.clone()).increment(1);
+        let now = snapshot.received_at.unwrap_or_else(SystemTime::now);
+        let seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        ::metrics::gauge!(LAST_UPDATE, "pid" => pid).set(seconds.as_secs_f64());
+    }
+
+    pub(crate) fn parse_error() {
+        ::metrics::counter!(PARSE_ERRORS).increment(1);
+    }
+
+    pub(crate) fn reconnect() {
+        ::metrics::counter!(RECONNECTS).increment(1);
+    }
+
+    /// Counts the connection up in CONNECTIONS until dropped.
+    pub(crate) struct Connected;
+
+    impl Connected {
+        pub(crate) fn new() -> Self {
+            ::metrics::gauge!(CONNECTIONS).increment(1.0);
+            Connected
+        }
+    }
+
+    impl Drop for Connected {
+        fn drop(&mut self) {
+            ::metrics::gauge!(CONNECTIONS).decrement(1.0);
+        }
+    }
+}
+
+#[cfg(all(not(feature = "metrics"), not(target_arch = "wasm32")))]
+mod record {
+    use crate::data::Snapshot;
+
+    pub(crate) fn frame_received() {}
+
+    pub(crate) fn snapshot_parsed(_: &Snapshot) {}
+
+    pub(crate) fn parse_error() {}
+
+    pub(crate) fn reconnect() {}
+
+    pub(crate) struct Connected;
+
+    impl Connected {
+        pub(crate) fn new() -> Self {
+            Connected
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use record::*;
+
+#[cfg(all(test, feature = "metrics", not(target_arch = "wasm32")))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use ::metrics::{
+        Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use super::*;
+    use crate::data::Snapshot;
+
+    /// Values of the counters and the gauges by name and labels
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+    impl TestRecorder {
+        fn value(&self, key: &Key) -> Arc<AtomicU64> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            let name = if labels.is_empty() {
+                key.name().to_string()
+            } else {
+                format!("{}{{{}}}", key.name(), labels.join(","))
+            };
+            self.0.lock().unwrap().entry(name).or_default().clone()
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            self.0.lock().unwrap()[name].load(Ordering::Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.0.lock().unwrap()[name].load(Ordering::Relaxed))
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.value(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.value(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    pub fn test_record() {
+        let recorder = TestRecorder::default();
+        ::metrics::with_local_recorder(&recorder, || {
+            describe();
+            frame_received();
+            frame_received();
+            parse_error();
+            reconnect();
+            let snapshot = Snapshot {
+                pid: "945629".to_string(),
+                received_at: Some(UNIX_EPOCH + Duration::from_secs(1606850510)),
+                ..Default::default()
+            };
+            snapshot_parsed(&snapshot);
+
+            let connected = Connected::new();
+            assert_eq!(recorder.gauge(CONNECTIONS), 1.0);
+            drop(connected);
+        });
+
+        assert_eq!(recorder.counter(FRAMES_RECEIVED), 2);
+        assert_eq!(recorder.counter(PARSE_ERRORS), 1);
+        assert_eq!(recorder.counter(RECONNECTS), 1);
+        assert_eq!(
+            recorder.counter(&format!("{}{{pid=945629}}", SNAPSHOTS_PARSED)),
+            1
+        );
+        let last_update = format!("{}{{pid=945629}}", LAST_UPDATE);
+        assert_eq!(recorder.gauge(&last_update), 1606850510.0);
+        assert_eq!(recorder.gauge(CONNECTIONS), 0.0);
+    }
+}
@@ -0,0 +1,397 @@
+//! Many pair ids sharded across a pool of connections, for servers capping the pair ids a
+//! connection carries, see StreamBuilder::build_pool(..)
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::future::{self, BoxFuture};
+use tokio::{
+    runtime,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    data::{FieldStats, Snapshot},
+    error::Error,
+    manager::RESTART_DELAY,
+    push::{
+        run, ConnectOptions, ConnectionState, DisconnectReason, Exit, Hooks, Status, StreamStats,
+        SubscriptionStatus,
+    },
+};
+
+/// Default of the pair ids per connection of StreamBuilder::build_pool(..)
+pub const DEFAULT_MAX_PIDS_PER_CONNECTION: usize = 50;
+
+/// Handler of one shard, all delivering to the handler of the pool
+pub(crate) type ShardHandler =
+    Box<dyn FnMut(Snapshot) -> BoxFuture<'static, Result<(), ()>> + Send>;
+
+/// Creates the handler of each new shard
+pub(crate) type MakeHandler = Arc<dyn Fn() -> ShardHandler + Send + Sync>;
+
+/// Splits the pair ids into as few shards as the limit per connection allows, of sizes
+/// differing by one at most, in the order of the pair ids. Duplicates are dropped.
+///
+/// ```
+/// use forexpros_wss::pool::shard_pids;
+///
+/// let pids: Vec<String> = (1..=5).map(|pid| pid.to_string()).collect();
+/// assert_eq!(shard_pids(&pids, 2), [vec!["1", "2"], vec!["3", "4"], vec!["5"]]);
+/// ```
+pub fn shard_pids(pids: &[String], max_per_connection: usize) -> Vec<Vec<String>> {
+    let mut seen = HashSet::new();
+    let pids: Vec<_> = pids
+        .iter()
+        .filter(|pid| seen.insert(*pid))
+        .cloned()
+        .collect();
+    let max_per_connection = max_per_connection.max(1);
+    let count = pids.len().div_ceil(max_per_connection);
+    let mut pids = pids.into_iter();
+    (0..count)
+        .map(|idx| {
+            let size = pids.len() / (count - idx) + usize::from(pids.len() % (count - idx) > 0);
+            pids.by_ref().take(size).collect()
+        })
+        .collect()
+}
+
+/// Connection of the pool, streaming the pair ids of its index in the assignment
+struct Shard {
+    status: Arc<Status>,
+    cancellation_token: CancellationToken,
+    reconnect: Arc<Notify>,
+    join_handle: JoinHandle<()>,
+}
+
+/// Pair ids sharded across connections, each reconnecting on its own, with the snapshots of all
+/// of them passed to one handler or queue.
+///
+/// A connection which fails or is closed by the server reconnects after RESTART_DELAY, with the
+/// pair ids of its shard at that time, see StreamPool::set_pids(..). A handler returning
+/// Err(()) stops all the connections. They stop as well when the pool is dropped, so don't drop
+/// it within an async context, unless the runtime is shared through StreamBuilder::runtime(..)
+///
+/// ```no_run
+/// use forexpros_wss::push::Stream;
+///
+/// let pool = Stream::builder("945629,8984,8873,14958,8830".to_string())
+///     .build_pool(2, |snapshot| {
+///         println!("{} {}", snapshot.pid, snapshot.last);
+///         Ok(())
+///     })
+///     .unwrap();
+/// assert_eq!(pool.shards().len(), 3);
+/// ```
+pub struct StreamPool {
+    runtime: Arc<runtime::Runtime>,
+    connect_options: Arc<ConnectOptions>,
+    max_pids_per_connection: usize,
+    assignment: Arc<Mutex<Vec<Vec<String>>>>,
+    cancellation_token: CancellationToken,
+    make_handler: MakeHandler,
+    shards: Vec<Shard>,
+    snapshots: Option<mpsc::Receiver<Snapshot>>,
+}
+
+impl StreamPool {
+    /// Starts the shards of the pair ids, checked by the builder.
+    pub(crate) fn start(
+        pids: &[String],
+        max_pids_per_connection: usize,
+        connect_options: ConnectOptions,
+        runtime: Arc<runtime::Runtime>,
+        make_handler: MakeHandler,
+        snapshots: Option<mpsc::Receiver<Snapshot>>,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        let mut pool = StreamPool {
+            runtime,
+            connect_options: Arc::new(connect_options),
+            max_pids_per_connection: max_pids_per_connection.max(1),
+            assignment: Arc::new(Mutex::new(Vec::new())),
+            cancellation_token,
+            make_handler,
+            shards: Vec::new(),
+            snapshots,
+        };
+        pool.rebalance(pids);
+        pool
+    }
+
+    /// Pair ids of each connection, by shard index.
+    pub fn shards(&self) -> Vec<Vec<String>> {
+        self.assignment.lock().unwrap().clone()
+    }
+
+    /// Index of the shard carrying the pair id, None for pair ids of no shard.
+    pub fn shard_of(&self, pid: &str) -> Option<usize> {
+        self.assignment
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|pids| pids.iter().any(|shard_pid| shard_pid == pid))
+    }
+
+    /// State of the connection of each shard, by shard index.
+    pub fn states(&self) -> Vec<ConnectionState> {
+        self.shards
+            .iter()
+            .map(|shard| *shard.status.state.borrow())
+            .collect()
+    }
+
+    /// Counters of the frames received by all the shards so far, see Stream::stats()
+    pub fn stats(&self) -> StreamStats {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let fields = shard
+                    .status
+                    .field_stats
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .fold(FieldStats::default(), FieldStats::merge);
+                StreamStats {
+                    fields,
+                    ..*shard.status.stats.borrow()
+                }
+            })
+            .fold(StreamStats::default(), |total, stats| StreamStats {
+                ignored_frames: total.ignored_frames + stats.ignored_frames,
+                parse_errors: total.parse_errors + stats.parse_errors,
+                dropped_snapshots: total.dropped_snapshots + stats.dropped_snapshots,
+                fields: total.fields.merge(&stats.fields),
+            })
+    }
+
+    /// Whether any shard is still running.
+    pub fn is_running(&self) -> bool {
+        self.shards
+            .iter()
+            .any(|shard| !shard.join_handle.is_finished())
+    }
+
+    /// Replaces the pair ids of the pool, sharded again: connections are added or stopped for
+    /// the new number of shards, and the others subscribe to the pair ids they gained over
+    /// the current connection. As the server has no unsubscribe, the shards which lost pair
+    /// ids reconnect right away with their new ones, see DisconnectReason::Resharded.
+    ///
+    /// Err(Error::InvalidPairId(..)) without pair ids, for an empty one or one containing a comma.
+    pub fn set_pids(&mut self, pids: &[String]) -> Result<(), Error> {
+        if pids.is_empty() || pids.iter().any(|pid| invalid_pid(pid)) {
+            return Err(Error::InvalidPairId(pids.join(",")));
+        }
+        self.rebalance(pids);
+        Ok(())
+    }
+
+    /// Returns the next snapshot of a pool of StreamBuilder::build_pool_receiver(..), None
+    /// once the pool stopped. Always None for pools with a handler.
+    pub async fn next_snapshot(&mut self) -> Option<Snapshot> {
+        self.snapshots.as_mut()?.recv().await
+    }
+
+    /// Stops all the connections and waits until they are closed.
+    ///
+    /// Blocks the current thread, so don't call it within an async context.
+    pub fn shutdown(&mut self) {
+        self.cancellation_token.cancel();
+        let join_handles = self.shards.drain(..).map(|shard| shard.join_handle);
+        self.runtime.block_on(future::join_all(join_handles));
+    }
+
+    /// Shards the pair ids, starting and stopping connections for the number of shards.
+    fn rebalance(&mut self, pids: &[String]) {
+        let pids: Vec<_> = pids.iter().map(|pid| pid.trim().to_string()).collect();
+        let assignment = shard_pids(&pids, self.max_pids_per_connection);
+        let previous = std::mem::replace(&mut *self.assignment.lock().unwrap(), assignment.clone());
+
+        for shard in self.shards.drain(assignment.len().min(self.shards.len())..) {
+            shard.cancellation_token.cancel();
+        }
+        for ((shard, pids), previous) in self.shards.iter().zip(&assignment).zip(&previous) {
+            if previous.iter().all(|pid| pids.contains(pid)) {
+                resubscribe(shard, pids, &self.connect_options);
+            } else {
+                shard.reconnect.notify_one();
+            }
+        }
+        for idx in self.shards.len()..assignment.len() {
+            let shard = self.spawn_shard(idx);
+            self.shards.push(shard);
+        }
+    }
+
+    fn spawn_shard(&self, idx: usize) -> Shard {
+        let status = Arc::new(Status::default());
+        let cancellation_token = self.cancellation_token.child_token();
+        let reconnect = Arc::new(Notify::new());
+        let task = run_shard(
+            idx,
+            self.connect_options.clone(),
+            self.assignment.clone(),
+            Signals {
+                pool_token: self.cancellation_token.clone(),
+                token: cancellation_token.clone(),
+                reconnect: reconnect.clone(),
+            },
+            status.clone(),
+            (self.make_handler)(),
+        );
+        Shard {
+            status,
+            cancellation_token,
+            reconnect,
+            join_handle: self.runtime.spawn(task),
+        }
+    }
+}
+
+impl Drop for StreamPool {
+    fn drop(&mut self) {
+        self.cancellation_token.cancel();
+    }
+}
+
+/// Whether a pair id cannot be subscribed to, see Stream::subscribe(..)
+pub(crate) fn invalid_pid(pid: &str) -> bool {
+    let pid = pid.trim();
+    pid.is_empty() || pid.contains(',')
+}
+
+/// Aligns the subscriptions of the current connection of the shard with its new pair ids.
+fn resubscribe(shard: &Shard, pids: &[String], connect_options: &ConnectOptions) {
+    let mut added = Vec::new();
+    shard.status.subscriptions.send_modify(|subscriptions| {
+        subscriptions.retain(|pid, _| pids.contains(pid));
+        for pid in pids {
+            if !subscriptions.contains_key(pid) {
+                subscriptions.insert(pid.clone(), SubscriptionStatus::Pending);
+                added.push(pid.clone());
+            }
+        }
+    });
+    // dropped while reconnecting, the new connection subscribing to all the pair ids
+    for batch in added.chunks(connect_options.subscribe_batch_size) {
+        let _ = shard
+            .status
+            .outgoing
+            .send(connect_options.subscribe_message(&batch.join(",")));
+    }
+}
+
+/// Stops of the task of a shard: the token of the pool, stopped by the handler, the token of
+/// the shard, and the reconnect of StreamPool::set_pids(..) taking pair ids from the shard.
+struct Signals {
+    pool_token: CancellationToken,
+    token: CancellationToken,
+    reconnect: Arc<Notify>,
+}
+
+/// Streams the pair ids of the shard, reconnecting until cancelled or the handler stops.
+async fn run_shard(
+    idx: usize,
+    connect_options: Arc<ConnectOptions>,
+    assignment: Arc<Mutex<Vec<Vec<String>>>>,
+    signals: Signals,
+    status: Arc<Status>,
+    mut handler: ShardHandler,
+) {
+    let Signals {
+        pool_token,
+        token,
+        reconnect,
+    } = signals;
+    let mut hooks = Hooks::default();
+    loop {
+        let pids = match assignment.lock().unwrap().get(idx) {
+            Some(pids) => pids.clone(),
+            None => break,
+        };
+        // the pair ids moved to other shards are not subscribed again
+        status
+            .subscriptions
+            .send_modify(|subscriptions| subscriptions.retain(|pid, _| pids.contains(pid)));
+
+        let pair_id = pids.join(",");
+        let client = run(
+            &pair_id,
+            &connect_options,
+            1,
+            &mut hooks,
+            &token,
+            &status,
+            &mut handler,
+        );
+        let result = tokio::select! {
+            _ = token.cancelled() => {
+                status.disconnected(DisconnectReason::Cancelled);
+                break;
+            },
+            _ = reconnect.notified() => {
+                status.disconnected(DisconnectReason::Resharded);
+                continue;
+            },
+            result = client => result,
+        };
+        status.disconnected(match &result {
+            Ok(Exit::Expired) => DisconnectReason::Expired,
+            Ok(Exit::Closed) => DisconnectReason::Closed,
+            Ok(Exit::Stopped) => DisconnectReason::Stopped,
+            Err(e) => DisconnectReason::Failed(e.to_string()),
+        });
+        match result {
+            Ok(Exit::Stopped) => {
+                pool_token.cancel();
+                break;
+            }
+            Ok(Exit::Expired) => continue,
+            Ok(Exit::Closed) => log::warn!("Shard {} closed by server", idx),
+            Err(e) => log::error!("Shard {} failed: {}", idx, e),
+        }
+
+        log::info!("Reconnecting shard {}", idx);
+        status.state.send_replace(ConnectionState::Reconnecting);
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = reconnect.notified() => {}
+            _ = time::sleep(RESTART_DELAY) => {}
+        }
+    }
+    status.state.send_replace(ConnectionState::Closed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_shard_pids() {
+        let pids: Vec<String> = (1..=7).map(|pid| pid.to_string()).collect();
+        let sizes = |max| {
+            shard_pids(&pids, max)
+                .iter()
+                .map(Vec::len)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(3), [3, 2, 2]);
+        assert_eq!(sizes(7), [7]);
+        assert_eq!(sizes(100), [7]);
+        assert_eq!(sizes(1), [1; 7]);
+        assert_eq!(sizes(0), [1; 7]);
+        assert_eq!(shard_pids(&pids, 3)[1], ["4", "5"]);
+
+        // duplicates once
+        let pids: Vec<String> = ["1", "2", "1"].iter().map(|pid| pid.to_string()).collect();
+        assert_eq!(shard_pids(&pids, 1), [vec!["1"], vec!["2"]]);
+        assert!(shard_pids(&[], 3).is_empty());
+    }
+}
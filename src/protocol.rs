@@ -0,0 +1,692 @@
+//! Messages of the wire protocol, shared by every transport.
+
+use std::{
+	borrow::Cow,
+	ops::Range,
+};
+
+use rand::Rng;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+use crate::error::Error;
+
+/// Default key of the data messages carrying the pid and the instrument data, as in
+/// `{"message":"pid-945629::{..}"}`, see FrameMarkers
+pub const MESSAGE_KEY: &str = "message";
+
+/// Default prefix of the pid in the data messages and the bulk-subscribe, see FrameMarkers
+pub const PID_PREFIX: &str = "pid-";
+
+/// Default separator of the pid and the instrument data in the data messages, see FrameMarkers
+pub const DATA_SEPARATOR: &str = "::";
+
+/// Markers of the envelope of the data messages, to adapt the parsing to a changed layout
+/// without forking, see StreamBuilder::frame_markers(..)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameMarkers {
+	/// Key of the message holding the pid and the instrument data, MESSAGE_KEY by default.
+	pub message_key: Cow<'static, str>,
+
+	/// Prefix of the pid, PID_PREFIX by default.
+	pub pid_prefix: Cow<'static, str>,
+
+	/// Separator of the pid and the instrument data, DATA_SEPARATOR by default.
+	pub data_separator: Cow<'static, str>,
+}
+
+const DEFAULT_FRAME_MARKERS: FrameMarkers = FrameMarkers {
+	message_key: Cow::Borrowed ( MESSAGE_KEY ),
+	pid_prefix: Cow::Borrowed ( PID_PREFIX ),
+	data_separator: Cow::Borrowed ( DATA_SEPARATOR ),
+};
+
+impl Default for FrameMarkers {
+	fn default ( ) -> Self {
+		DEFAULT_FRAME_MARKERS
+	}
+}
+
+impl FrameMarkers {
+	/// Returns the pid of a data frame carrying instrument data, found without decoding the frame.
+	pub fn snapshot_pid <'a> ( &self, frame: &'a str ) -> Option<&'a str> {
+		let range = id_range ( frame, &self.escaped_key ( &self.pid_prefix ), &self.data_separator )?;
+		Some ( &frame[range] )
+	}
+
+	/// Returns the pid and the timestamp of a data frame carrying instrument data, found
+	/// without decoding the frame. The timestamp is 0 when the instrument data has none, as
+	/// in Snapshot.
+	pub fn snapshot_pid_and_timestamp <'a> ( &self, frame: &'a str ) -> Option<(&'a str, u64)> {
+		let range = id_range ( frame, &self.escaped_key ( &self.pid_prefix ), &self.data_separator )?;
+		pid_and_timestamp ( frame, range )
+	}
+
+	/// Returns the JSON of the instrument data of a decoded message such as
+	/// `{"message":"pid-945629::{..}"}`, with the message key first as sent by the server, see
+	/// parse_sockjs_messages(..)
+	pub fn snapshot_json ( &self, message: &str ) -> Option<String> {
+		let mut json = self.message_value ( message )?;
+		let end = json.find ( &*self.data_separator )? + self.data_separator.len ( );
+		json.replace_range ( ..end, "" );
+		Some ( json )
+	}
+
+	/// Returns the pid and the JSON of the instrument data of a decoded message, as
+	/// snapshot_json(..) for a message of a pid, see StreamBuilder::frame_markers(..)
+	pub(crate) fn snapshot_message ( &self, message: &str ) -> Option<(String, String)> {
+		let message = self.message_value ( message )?;
+		let (key, json) = message.split_once ( &*self.data_separator )?;
+		Some ( ( key.strip_prefix ( &*self.pid_prefix )?.to_string ( ), json.to_string ( ) ) )
+	}
+
+	/// Returns the decoded value of the message key leading the message, such as
+	/// `pid-945629::{..}` of `{"message":"pid-945629::{..}"}`. Only the prefix up to the value
+	/// is scanned and only the value decoded, not the whole message.
+	fn message_value ( &self, message: &str ) -> Option<String> {
+		let value = message.trim_start ( ).strip_prefix ( '{' )?.trim_start ( )
+			.strip_prefix ( '"' )?.strip_prefix ( &*self.message_key )?.strip_prefix ( '"' )?.trim_start ( )
+			.strip_prefix ( ':' )?.trim_start ( );
+		String::deserialize ( &mut serde_json::Deserializer::from_str ( value ) ).ok ( )
+	}
+
+	/// Returns the first message of the channel in a data frame, keyed by the prefix of the
+	/// channel and the id such as `pidTechSumm-945629::{..}`, see StreamBuilder::on_channel(..)
+	pub fn channel_message ( &self, frame: &str, channel: &str ) -> Option<ChannelMessage> {
+		self.keyed_channel_message ( frame, channel, &self.escaped_key ( channel ) )
+	}
+
+	/// Key of the channel as escaped in the frames, such as `"message\":\"pid-`
+	fn escaped_key ( &self, channel: &str ) -> String {
+		format! ( "\"{}\\\":\\\"{}", self.message_key, channel )
+	}
+
+	/// Same as FrameMarkers::channel_message(..) with the escaped key of the channel.
+	fn keyed_channel_message ( &self, frame: &str, channel: &str, key: &str ) -> Option<ChannelMessage> {
+		// the cheap check first, most frames being of other channels
+		id_range ( frame, key, &self.data_separator )?;
+		parse_sockjs_messages ( frame ).ok ( )?.iter ( ).find_map ( |message| {
+			let message = self.message_value ( message )?;
+			let (key, data) = message.split_once ( &*self.data_separator )?;
+			Some ( ChannelMessage {
+				channel: channel.to_string ( ),
+				id: key.strip_prefix ( channel )?.to_string ( ),
+				data: data.to_string ( ),
+			} )
+		} )
+	}
+}
+
+/// Key of the pid as escaped in the frames with the default markers, see FrameMarkers
+const PID_KEY: &str = r#""message\":\"pid-"#;
+
+/// Key of the timestamp as escaped in the instrument data of the frames
+const TIMESTAMP_KEY: &str = r#"\\\"timestamp\\\":"#;
+
+/// Position of the id after the escaped key in the frame, up to the separator, e.g. of the pid.
+fn id_range ( frame: &str, key: &str, separator: &str ) -> Option<Range<usize>> {
+	let start = frame.find ( key )? + key.len ( );
+	let len = frame[start..].find ( separator )?;
+	Some ( start..start + len )
+}
+
+/// The pid at the range of the frame, and the timestamp of its instrument data, 0 without.
+fn pid_and_timestamp ( frame: &str, range: Range<usize> ) -> Option<(&str, u64)> {
+	// the flat object of the instrument data, after the pid
+	let data = &frame[range.end..];
+	let data = &data[..data.find ( '}' ).unwrap_or ( data.len ( ) )];
+
+	let timestamp = match data.find ( TIMESTAMP_KEY ) {
+		Some ( idx ) => {
+			let value = data[idx + TIMESTAMP_KEY.len ( )..].trim_start ( );
+			let digits = value.find ( |c: char| !c.is_ascii_digit ( ) ).unwrap_or ( value.len ( ) );
+			value[..digits].parse ( ).ok ( )?
+		},
+		None => 0,
+	};
+	Some ( ( &frame[range], timestamp ) )
+}
+
+/// FrameMarkers of a stream with the keys of the pid and of the channels escaped once, so
+/// each frame is scanned without allocating, see StreamBuilder::frame_markers(..)
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub(crate) struct FrameKeys {
+	markers: FrameMarkers,
+	pid_key: String,
+	channel_keys: Vec<(String, String)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FrameKeys {
+	/// Escapes the keys of the markers and of the channels of StreamBuilder::on_channel(..) so far.
+	pub(crate) fn new ( markers: FrameMarkers, channels: &[String] ) -> Self {
+		let pid_key = markers.escaped_key ( &markers.pid_prefix );
+		let mut keys = FrameKeys { markers, pid_key, channel_keys: Vec::new ( ) };
+		for channel in channels {
+			keys.add_channel ( channel );
+		}
+		keys
+	}
+
+	pub(crate) fn markers ( &self ) -> &FrameMarkers {
+		&self.markers
+	}
+
+	/// Escapes the key of the channel of StreamBuilder::on_channel(..)
+	pub(crate) fn add_channel ( &mut self, channel: &str ) {
+		let key = self.markers.escaped_key ( channel );
+		self.channel_keys.push ( ( channel.to_string ( ), key ) );
+	}
+
+	/// Same as FrameMarkers::snapshot_pid(..)
+	pub(crate) fn snapshot_pid <'a> ( &self, frame: &'a str ) -> Option<&'a str> {
+		let range = id_range ( frame, &self.pid_key, &self.markers.data_separator )?;
+		Some ( &frame[range] )
+	}
+
+	/// Same as FrameMarkers::channel_message(..), for the channels added.
+	pub(crate) fn channel_message ( &self, frame: &str, channel: &str ) -> Option<ChannelMessage> {
+		let (_, key) = self.channel_keys.iter ( ).find ( |( added, _ )| added == channel )?;
+		self.markers.keyed_channel_message ( frame, channel, key )
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for FrameKeys {
+	fn default ( ) -> Self {
+		FrameKeys::new ( FrameMarkers::default ( ), &[ ] )
+	}
+}
+
+/// Message of a data channel besides the quotes, such as a technical summary, see
+/// StreamBuilder::on_channel(..)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMessage {
+	/// Prefix of the channel, such as "pidTechSumm-"
+	pub channel: String,
+
+	/// Id after the prefix, usually the pid.
+	pub id: String,
+
+	/// Data after the separator, usually a JSON object.
+	pub data: String,
+}
+
+fn prepare_pair_msg(pair_ids: String) -> String {
+	prepare_channel_msg ( PID_PREFIX, &pair_ids )
+}
+
+fn prepare_channel_msg(channel: &str, pair_ids: &str) -> String {
+	let split: Vec<String> = pair_ids.split(",").map(|s| format ! ("{}{}:", channel, s.to_string())).collect();
+	let joined = split.join("%%");
+    return joined;
+}
+
+/// Events sent to the server, serialized as `{"_event":"<name>",..}` in the field order
+#[derive(Serialize)]
+#[serde(tag = "_event")]
+enum Event<'a> {
+	#[serde(rename = "bulk-subscribe")]
+	BulkSubscribe {
+		#[serde(rename = "tzID")]
+		tz_id: String,
+		message: String,
+	},
+	#[serde(rename = "UID")]
+	Uid {
+		#[serde(rename = "UID")]
+		uid: u64,
+	},
+	#[serde(rename = "heartbeat")]
+	Heartbeat {
+		data: &'a str,
+	},
+}
+
+/// Returns the SockJS message carrying the event: the JSON of the event as the only string
+/// of an array, so the JSON is escaped once more.
+fn sockjs_message ( event: &Event ) -> String {
+	// neither can fail, all the keys are strings
+	let json = serde_json::to_string ( event ).unwrap ( );
+	serde_json::to_string ( &[ json ] ).unwrap ( )
+}
+
+/// Returns the bulk-subscribe message for the comma-separated pair ids, e.g. "945629,8984",
+/// with the time zone id of the time field in the stream.
+pub fn subscribe_message ( pair_ids: &str, tz_id: u32 ) -> String {
+	sockjs_message ( &Event::BulkSubscribe { tz_id: tz_id.to_string ( ), message: prepare_pair_msg ( pair_ids.to_string ( ) ) } )
+}
+
+/// Same as subscribe_message(..) for a data channel besides the quotes, the pair ids
+/// prefixed with the prefix of the channel, such as "pidTechSumm-", in place of "pid-".
+pub fn channel_subscribe_message ( channel: &str, pair_ids: &str, tz_id: u32 ) -> String {
+	sockjs_message ( &Event::BulkSubscribe { tz_id: tz_id.to_string ( ), message: prepare_channel_msg ( channel, pair_ids ) } )
+}
+
+/// Returns the UID message sent after the bulk-subscribe.
+pub fn uid_message ( uid: u64 ) -> String {
+	sockjs_message ( &Event::Uid { uid } )
+}
+
+/// Returns the heartbeat message to keep the connection alive.
+pub fn heartbeat_message ( ) -> String {
+	sockjs_message ( &Event::Heartbeat { data: "h" } )
+}
+
+/// Returns the pair id of a data frame carrying instrument data, from its `"message":"pid-<pid>::{..}"`
+/// key, None for any other frame. See FrameMarkers for other layouts.
+pub fn parse_snapshot_pid ( frame: &str ) -> Option<&str> {
+	let range = id_range ( frame, PID_KEY, DATA_SEPARATOR )?;
+	Some ( &frame[range] )
+}
+
+/// Returns the first message of the channel in a data frame, None for frames without, see
+/// FrameMarkers::channel_message(..)
+pub fn parse_channel_message ( frame: &str, channel: &str ) -> Option<ChannelMessage> {
+	DEFAULT_FRAME_MARKERS.channel_message ( frame, channel )
+}
+
+/// Returns the pair id and the timestamp of a data frame carrying instrument data, None for
+/// any other frame, e.g. to route the frames by pid and by time before parsing them, or
+/// instead of parsing those filtered out. See FrameMarkers::snapshot_pid_and_timestamp(..)
+pub fn peek_pid_and_ts ( frame: &str ) -> Option<(&str, u64)> {
+	let range = id_range ( frame, PID_KEY, DATA_SEPARATOR )?;
+	pid_and_timestamp ( frame, range )
+}
+
+/// Returns whether the frame is the reply of the server to heartbeat_message(), such as
+/// `a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]`
+pub fn is_heartbeat_frame ( frame: &str ) -> bool {
+	frame.starts_with ( 'a' ) && frame.contains ( r#"\"_event\":\"heartbeat\""# )
+}
+
+/// Returns whether the frame is the SockJS heartbeat frame "h", sent by the server to keep a
+/// quiet connection alive, unlike the reply to heartbeat_message(), see is_heartbeat_frame(..)
+pub fn is_sockjs_heartbeat ( frame: &str ) -> bool {
+	frame == "h"
+}
+
+/// Returns the messages of a SockJS frame: the decoded strings of an array frame such as
+/// `a["{\"_event\":\"UID\",\"UID\":0}"]`, and none for the open frame "o" and the heartbeat
+/// frame "h". Error::Closed for a close frame such as `c[3000,"Go away!"]`, see parse_close_frame(..)
+pub fn parse_sockjs_messages ( frame: &str ) -> Result<Vec<String>, Error> {
+	match frame {
+		"o" | "h" => Ok ( Vec::new ( ) ),
+		_ if frame.starts_with ( 'a' ) => serde_json::from_str ( &frame[1..] ).map_err ( Error::Parse ),
+		_ => match parse_close_frame ( frame ) {
+			Some ( ( code, reason ) ) => Err ( Error::Closed { code, reason } ),
+			None => Err ( Error::InvalidFrame ( frame.to_string ( ) ) ),
+		},
+	}
+}
+
+/// Returns the code and reason of a SockJS close frame such as `c[3000,"Go away!"]`,
+/// None for any other frame.
+pub fn parse_close_frame ( frame: &str ) -> Option<(u16, String)> {
+	serde_json::from_str ( frame.strip_prefix ( 'c' )? ).ok ( )
+}
+
+/// Close codes of a server rejecting a connection for now, which another host or a later
+/// attempt may accept: SockJS 2010 "Another connection still open", and the websocket codes
+/// 1012 service restart and 1013 try again later.
+pub const RETRYABLE_CLOSE_CODES: [u16; 3] = [ 2010, 1012, 1013 ];
+
+/// Whether the close code is one of RETRYABLE_CLOSE_CODES
+pub fn is_retryable_close ( code: u16 ) -> bool {
+	RETRYABLE_CLOSE_CODES.contains ( &code )
+}
+
+/// Returns the reply of the server to the UID message, in a data frame such as
+/// `a["{\"_event\":\"UID\",\"UID\":0}"]`: the UID, a number or a string of one, else
+/// Error::Uid with the whole reply when it carries no UID, and Error::InvalidUid for a string
+/// which is not an integer. None for any other frame.
+pub fn parse_uid_frame ( frame: &str ) -> Option<Result<u64, Error>> {
+	parse_sockjs_messages ( frame ).ok ( )?.iter ( )
+		.filter_map ( |message| serde_json::from_str::<serde_json::Value> ( message ).ok ( ) )
+		.find ( |message| message["_event"] == "UID" )
+		.map ( |message| match &message["UID"] {
+			serde_json::Value::String ( uid ) => uid.trim ( ).parse ( )
+				.map_err ( |source| Error::InvalidUid { reply: message.to_string ( ), source } ),
+			uid => uid.as_u64 ( ).ok_or_else ( || Error::Uid ( message.to_string ( ) ) ),
+		} )
+}
+
+/// Control message of the server, tagged by `_event` unlike the data messages, see parse_event(..)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerEvent {
+	/// Reply to heartbeat_message()
+	Heartbeat,
+
+	/// Reply to uid_message(..) with the UID, None when it carries none.
+	Uid ( Option<u64> ),
+
+	/// Reply to subscribe_message(..)
+	BulkSubscribe,
+
+	/// Event "error", or any event with an "error" key such as a rejected UID: the JSON of the message.
+	Error ( String ),
+
+	/// Any other event, with the JSON of the message.
+	Other { event: String, message: String },
+}
+
+impl ServerEvent {
+	/// Event of a decoded message, None without `_event`.
+	fn from_message ( message: &serde_json::Value ) -> Option<Self> {
+		let event = message.get ( "_event" )?.as_str ( )?;
+		Some ( match event {
+			_ if event.eq_ignore_ascii_case ( "error" ) || message.get ( "error" ).is_some ( ) => ServerEvent::Error ( message.to_string ( ) ),
+			"heartbeat" => ServerEvent::Heartbeat,
+			"UID" => ServerEvent::Uid ( message["UID"].as_u64 ( ).or_else ( || message["UID"].as_str ( )?.trim ( ).parse ( ).ok ( ) ) ),
+			"bulk-subscribe" => ServerEvent::BulkSubscribe,
+			_ => ServerEvent::Other { event: event.to_string ( ), message: message.to_string ( ) },
+		} )
+	}
+}
+
+/// Returns the event of the first control message of a data frame, such as
+/// `a["{\"_event\":\"UID\",\"UID\":0}"]`. None for the frames without event, e.g. those
+/// carrying instrument data, and for the open, heartbeat "h" and close frames.
+pub fn parse_event ( frame: &str ) -> Option<ServerEvent> {
+	parse_sockjs_messages ( frame ).ok ( )?.iter ( )
+		.filter_map ( |message| serde_json::from_str::<serde_json::Value> ( message ).ok ( ) )
+		.find_map ( |message| ServerEvent::from_message ( &message ) )
+}
+
+/// Default time zone id of the bulk-subscribe, see StreamBuilder::tz_id(..): 8 is the US
+/// Eastern time of the site, whose UTC offset changes with the daylight saving time.
+pub const DEFAULT_TZ_ID: u32 = 8;
+
+/// Default domain of StreamBuilder::domain(..)
+pub const DEFAULT_DOMAIN: &str = "forexpros.com";
+
+/// Default path of StreamBuilder::path_template(..)
+pub const DEFAULT_PATH_TEMPLATE: &str = "/echo/{server}/{session}/websocket";
+
+/// Returns generated URL of wss stream in forexpros.com
+pub fn generate_stream_url ( ) -> String {
+	stream_url ( DEFAULT_DOMAIN, DEFAULT_PATH_TEMPLATE )
+}
+
+/// Number of the SockJS servers of the {server} segment, see stream_url_for_server(..)
+pub const SERVER_COUNT: u8 = 100;
+
+/// Returns generated URL of wss stream in the streaming host of the domain, with the path template
+/// where {server} and {session} are replaced by random SockJS server and session segments.
+pub fn stream_url ( domain: &str, path_template: &str ) -> String {
+	stream_url_for_server ( domain, path_template, rand::thread_rng ( ).gen::<u8> ( ) % SERVER_COUNT )
+}
+
+/// Same as stream_url(..), with the server below SERVER_COUNT instead of a random one.
+pub fn stream_url_for_server ( domain: &str, path_template: &str, server: u8 ) -> String {
+	stream_url_on_host ( &format ! ( "wss://streaming.{}", domain ), path_template, server )
+}
+
+/// Same as stream_url_for_server(..) on the host with its scheme, such as
+/// "wss://streaming.forexpros.com", instead of the streaming host of a domain.
+pub fn stream_url_on_host ( host: &str, path_template: &str, server: u8 ) -> String {
+	let mut rnd = rand::thread_rng ( );
+
+	let path = path_template
+		//1 + rnd.gen::<u16> ( ) % 280,
+		.replace ( "{server}", &format ! ( "{:03x}", server ) )
+		// rnd.gen::<u32> ( )
+		.replace ( "{session}", &format ! ( "{:08x}", rnd.gen::<u16> ( ) % 0xfff ) );
+
+	format ! ( "{}{}", host.trim_end_matches ( '/' ), path )
+}
+
+/// Source of the URLs to connect to, one per connect attempt, see StreamBuilder::url_provider(..)
+///
+/// Implemented by the closures returning a URL, e.g. for a round-robin over known hosts.
+pub trait UrlProvider {
+	/// URL of the next connect attempt.
+	fn next_url ( &mut self ) -> String;
+}
+
+impl <F> UrlProvider for F
+where
+	F: FnMut ( ) -> String,
+{
+	fn next_url ( &mut self ) -> String {
+		self ( )
+	}
+}
+
+/// URLs generated as stream_url(..) does, the URLs of the stream by default. Given to
+/// StreamBuilder::url_provider(..), any server may come next: unlike the default URLs of the
+/// stream, it does not skip the servers cooling down, see StreamBuilder::host_cooldown(..)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedUrls {
+	pub domain: String,
+	pub path_template: String,
+}
+
+impl Default for GeneratedUrls {
+	fn default ( ) -> Self {
+		GeneratedUrls {
+			domain: DEFAULT_DOMAIN.to_string ( ),
+			path_template: DEFAULT_PATH_TEMPLATE.to_string ( ),
+		}
+	}
+}
+
+impl UrlProvider for GeneratedUrls {
+	fn next_url ( &mut self ) -> String {
+		stream_url ( &self.domain, &self.path_template )
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	pub fn test_generate_stream_url ( ) {
+		use regex::Regex;
+
+		let url = generate_stream_url();
+		
+		assert! ( Regex::new ( r#"wss://streaming.forexpros.com/echo/[0-9a-zA-Z]{3}/[0-9a-zA-Z]{8}/websocket"# ).unwrap ( ).is_match ( url.as_str ( ) ), "Generated: {}", url );
+	}
+
+	#[test]
+	pub fn test_stream_url ( ) {
+		use regex::Regex;
+
+		let url = stream_url ( DEFAULT_DOMAIN, "/sockjs/{server}/{session}/websocket" );
+
+		assert! ( Regex::new ( r#"^wss://streaming.forexpros.com/sockjs/[0-9a-f]{3}/[0-9a-f]{8}/websocket$"# ).unwrap ( ).is_match ( &url ), "Generated: {}", url );
+		assert_eq! ( stream_url ( "investing.com", "/websocket" ), "wss://streaming.investing.com/websocket" );
+		assert! ( stream_url_for_server ( DEFAULT_DOMAIN, DEFAULT_PATH_TEMPLATE, 42 ).starts_with ( "wss://streaming.forexpros.com/echo/02a/" ) );
+		assert! ( stream_url_on_host ( "ws://127.0.0.1:8080/", DEFAULT_PATH_TEMPLATE, 42 ).starts_with ( "ws://127.0.0.1:8080/echo/02a/" ) );
+	}
+
+	#[test]
+	pub fn test_url_provider ( ) {
+		let mut generated = GeneratedUrls { domain: "investing.com".to_string ( ), ..GeneratedUrls::default ( ) };
+		assert! ( generated.next_url ( ).starts_with ( "wss://streaming.investing.com/echo/" ) );
+
+		let hosts = [ "wss://a.example/websocket", "wss://b.example/websocket" ];
+		let mut idx = 0;
+		let mut round_robin = || {
+			idx += 1;
+			hosts [ ( idx - 1 ) % hosts.len ( ) ].to_string ( )
+		};
+		let urls: Vec<_> = ( 0..3 ).map ( |_| round_robin.next_url ( ) ).collect ( );
+		assert_eq! ( urls, [ hosts [ 0 ], hosts [ 1 ], hosts [ 0 ] ] );
+	}
+
+	#[test]
+	pub fn test_subscribe_message ( ) {
+		assert_eq! ( subscribe_message ( "945629", 8 ), r#"["{\"_event\":\"bulk-subscribe\",\"tzID\":\"8\",\"message\":\"pid-945629:\"}"]"# );
+		assert_eq! ( subscribe_message ( "945629,8984", 55 ), r#"["{\"_event\":\"bulk-subscribe\",\"tzID\":\"55\",\"message\":\"pid-945629:%%pid-8984:\"}"]"# );
+	}
+
+	#[test]
+	pub fn test_uid_message ( ) {
+		assert_eq! ( uid_message ( 0 ), r#"["{\"_event\":\"UID\",\"UID\":0}"]"# );
+		assert_eq! ( uid_message ( 123 ), r#"["{\"_event\":\"UID\",\"UID\":123}"]"# );
+	}
+
+	#[test]
+	pub fn test_heartbeat_message ( ) {
+		assert_eq! ( heartbeat_message ( ), r#"["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# );
+	}
+
+	#[test]
+	pub fn test_sockjs_message ( ) {
+		// quotes and backslashes are escaped in the event, then again in the SockJS array
+		let message = subscribe_message ( r#"94"56\29"#, 8 );
+		assert_eq! ( message, r#"["{\"_event\":\"bulk-subscribe\",\"tzID\":\"8\",\"message\":\"pid-94\\\"56\\\\29:\"}"]"# );
+
+		// and come back through the same two levels
+		let messages: Vec<String> = serde_json::from_str ( &message ).unwrap ( );
+		let event: serde_json::Value = serde_json::from_str ( &messages [ 0 ] ).unwrap ( );
+		assert_eq! ( event [ "message" ], r#"pid-94"56\29:"# );
+		for message in [ subscribe_message ( "945629,8984", 55 ), uid_message ( 7 ), heartbeat_message ( ) ] {
+			let messages: Vec<String> = serde_json::from_str ( &message ).unwrap ( );
+			assert! ( serde_json::from_str::<serde_json::Value> ( &messages [ 0 ] ).is_ok ( ) );
+		}
+	}
+
+	#[test]
+	pub fn test_parse_snapshot_pid ( ) {
+		assert_eq! ( parse_snapshot_pid ( r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\"}\"}"]"# ), Some ( "945629" ) );
+		assert_eq! ( parse_snapshot_pid ( r#"a["{\"message\":\"pid-945629"]"# ), None );
+		assert_eq! ( parse_snapshot_pid ( r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# ), None );
+	}
+
+	#[test]
+	pub fn test_peek_pid_and_ts ( ) {
+		let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":18951.2,\\\"timestamp\\\":1606850510}\"}","{\"message\":\"pid-8984::{\\\"pid\\\":\\\"8984\\\",\\\"timestamp\\\":1606850999}\"}"]"#;
+		assert_eq! ( peek_pid_and_ts ( frame ), Some ( ( "945629", 1606850510 ) ) );
+
+		// the timestamp of the first instrument only, 0 without
+		let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\"}\"}","{\"message\":\"pid-8984::{\\\"timestamp\\\":1606850999}\"}"]"#;
+		assert_eq! ( peek_pid_and_ts ( frame ), Some ( ( "945629", 0 ) ) );
+
+		assert_eq! ( peek_pid_and_ts ( r#"a["{\"message\":\"pid-945629::{\\\"timestamp\\\":\\\"x\\\"}\"}"]"# ), None );
+
+		// the key only, not a value naming it
+		let frame = r#"a["{\"message\":\"pid-945629::{\\\"pid\\\":\\\"945629\\\",\\\"pc_col\\\":\\\"timestamp\\\",\\\"time\\\":\\\"no timestamp: 1\\\",\\\"timestamp\\\":1606850510}\"}"]"#;
+		assert_eq! ( peek_pid_and_ts ( frame ), Some ( ( "945629", 1606850510 ) ) );
+		assert_eq! ( peek_pid_and_ts ( r#"a["{\"_event\":\"UID\",\"UID\":0}"]"# ), None );
+	}
+
+	#[test]
+	pub fn test_frame_markers ( ) {
+		let markers = FrameMarkers {
+			message_key: "data".into ( ),
+			pid_prefix: "instrument-".into ( ),
+			data_separator: "|".into ( ),
+		};
+		let frame = r#"a["{\"data\":\"instrument-945629|{\\\"pid\\\":\\\"945629\\\"}\"}"]"#;
+		assert_eq! ( markers.snapshot_pid ( frame ), Some ( "945629" ) );
+		assert_eq! ( FrameMarkers::default ( ).snapshot_pid ( frame ), None );
+
+		let messages = parse_sockjs_messages ( frame ).unwrap ( );
+		assert_eq! ( markers.snapshot_json ( &messages[0] ).as_deref ( ), Some ( r#"{"pid":"945629"}"# ) );
+		assert_eq! ( FrameMarkers::default ( ).snapshot_json ( &messages[0] ), None );
+		assert_eq! ( FrameMarkers::default ( ).escaped_key ( PID_PREFIX ), PID_KEY );
+		// only the value of the leading message key is decoded, the rest of the message not
+		let message = r#" { "message" : "pid-8984::{\"pid\":\"8984\"}", "rest": [ "#;
+		assert_eq! ( FrameMarkers::default ( ).snapshot_json ( message ).as_deref ( ), Some ( r#"{"pid":"8984"}"# ) );
+		assert_eq! ( FrameMarkers::default ( ).snapshot_json ( r#"{"messages":"pid-8984::{}"}"# ), None );
+
+		let keys = FrameKeys::new ( markers, &[ "instrumentTech-".to_string ( ) ] );
+		assert_eq! ( keys.snapshot_pid ( frame ), Some ( "945629" ) );
+		let frame = r#"a["{\"data\":\"instrumentTech-945629|{}\"}"]"#;
+		assert_eq! ( keys.channel_message ( frame, "instrumentTech-" ).unwrap ( ).id, "945629" );
+		assert_eq! ( keys.channel_message ( frame, "pidTechSumm-" ), None );
+	}
+
+	#[test]
+	pub fn test_is_heartbeat_frame ( ) {
+		assert! ( is_heartbeat_frame ( r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# ) );
+		assert! ( !is_heartbeat_frame ( &heartbeat_message ( ) ) );
+		assert! ( !is_heartbeat_frame ( r#"a["{\"_event\":\"UID\",\"UID\":0}"]"# ) );
+		assert! ( !is_heartbeat_frame ( "h" ) );
+		assert! ( is_sockjs_heartbeat ( "h" ) );
+		assert! ( !is_sockjs_heartbeat ( r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# ) );
+	}
+
+	#[test]
+	pub fn test_parse_sockjs_messages ( ) {
+		assert_eq! ( parse_sockjs_messages ( "o" ).unwrap ( ), Vec::<String>::new ( ) );
+		assert_eq! ( parse_sockjs_messages ( "h" ).unwrap ( ), Vec::<String>::new ( ) );
+		assert_eq! ( parse_sockjs_messages ( r#"a["{\"_event\":\"UID\",\"UID\":0}","x"]"# ).unwrap ( ), vec! [ r#"{"_event":"UID","UID":0}"#, "x" ] );
+		assert! ( matches! ( parse_sockjs_messages ( r#"c[3000,"Go away!"]"# ), Err ( Error::Closed { code: 3000, .. } ) ) );
+		assert! ( matches! ( parse_sockjs_messages ( "a[" ), Err ( Error::Parse ( _ ) ) ) );
+		assert! ( matches! ( parse_sockjs_messages ( "ok" ), Err ( Error::InvalidFrame ( _ ) ) ) );
+	}
+
+	#[test]
+	pub fn test_parse_close_frame ( ) {
+		assert_eq! ( parse_close_frame ( r#"c[3000,"Go away!"]"# ), Some ( ( 3000, "Go away!".to_string ( ) ) ) );
+		assert_eq! ( parse_close_frame ( "o" ), None );
+		assert_eq! ( parse_close_frame ( r#"a["c[3000,\"Go away!\"]"]"# ), None );
+		assert! ( is_retryable_close ( 2010 ) );
+		assert! ( !is_retryable_close ( 3000 ) );
+	}
+
+	#[test]
+	pub fn test_parse_uid_frame ( ) {
+		assert! ( matches! ( parse_uid_frame ( r#"a["{\"_event\":\"UID\",\"UID\":123}"]"# ), Some ( Ok ( 123 ) ) ) );
+		assert! ( matches! ( parse_uid_frame ( r#"a["{\"_event\":\"UID\",\"UID\":\"123\"}"]"# ), Some ( Ok ( 123 ) ) ) );
+		match parse_uid_frame ( r#"a["{\"_event\":\"UID\",\"error\":\"denied\"}"]"# ) {
+			Some ( Err ( Error::Uid ( reply ) ) ) => assert_eq! ( reply, r#"{"_event":"UID","error":"denied"}"# ),
+			other => panic! ( "unexpected {:?}", other ),
+		}
+		assert! ( matches! ( parse_uid_frame ( r#"a["{\"_event\":\"UID\",\"UID\":\"x\"}"]"# ), Some ( Err ( Error::InvalidUid { .. } ) ) ) );
+		assert! ( parse_uid_frame ( r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# ).is_none ( ) );
+		assert! ( parse_uid_frame ( "o" ).is_none ( ) );
+	}
+
+	#[test]
+	pub fn test_parse_event ( ) {
+		assert_eq! ( parse_event ( r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"# ), Some ( ServerEvent::Heartbeat ) );
+		assert_eq! ( parse_event ( r#"a["{\"_event\":\"UID\",\"UID\":123}"]"# ), Some ( ServerEvent::Uid ( Some ( 123 ) ) ) );
+		assert_eq! ( parse_event ( r#"a["{\"_event\":\"bulk-subscribe\"}"]"# ), Some ( ServerEvent::BulkSubscribe ) );
+		assert_eq! ( parse_event ( r#"a["{\"_event\":\"UID\",\"error\":\"denied\"}"]"# ), Some ( ServerEvent::Error ( r#"{"_event":"UID","error":"denied"}"#.to_string ( ) ) ) );
+		assert_eq! ( parse_event ( r#"a["{\"_event\":\"Error\",\"message\":\"rate limited\"}"]"# ), Some ( ServerEvent::Error ( r#"{"_event":"Error","message":"rate limited"}"#.to_string ( ) ) ) );
+		assert_eq! (
+			parse_event ( r#"a["x","{\"_event\":\"tick\"}"]"# ),
+			Some ( ServerEvent::Other { event: "tick".to_string ( ), message: r#"{"_event":"tick"}"#.to_string ( ) } )
+		);
+
+		// no event
+		assert_eq! ( parse_event ( r#"a["{\"message\":\"pid-945629::{}\"}"]"# ), None );
+		assert_eq! ( parse_event ( "o" ), None );
+		assert_eq! ( parse_event ( "h" ), None );
+		assert_eq! ( parse_event ( r#"c[3000,"Go away!"]"# ), None );
+	}
+
+	#[test]
+	pub fn test_prepare_pair_msg ( ) {
+		assert_eq! ( prepare_pair_msg("1234".to_string()), "pid-1234:");
+		assert_eq! ( prepare_pair_msg("olia,haha,1234".to_string()), "pid-olia:%%pid-haha:%%pid-1234:");
+	}
+
+	#[test]
+	pub fn test_channel_message ( ) {
+		assert_eq! (
+			channel_subscribe_message ( "pidTechSumm-", "945629,8984", 8 ),
+			r#"["{\"_event\":\"bulk-subscribe\",\"tzID\":\"8\",\"message\":\"pidTechSumm-945629:%%pidTechSumm-8984:\"}"]"#
+		);
+
+		let frame = r#"a["{\"message\":\"pidTechSumm-945629::{\\\"summary\\\":\\\"Buy\\\"}\"}"]"#;
+		assert_eq! ( parse_channel_message ( frame, "pidTechSumm-" ), Some ( ChannelMessage {
+			channel: "pidTechSumm-".to_string ( ),
+			id: "945629".to_string ( ),
+			data: r#"{"summary":"Buy"}"#.to_string ( ),
+		} ) );
+		// not a quote, nor another channel
+		assert_eq! ( parse_snapshot_pid ( frame ), None );
+		assert_eq! ( parse_channel_message ( frame, "event-" ), None );
+		assert_eq! ( parse_channel_message ( "h", "pidTechSumm-" ), None );
+	}
+}
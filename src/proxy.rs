@@ -0,0 +1,347 @@
+//! Tunnel of the websocket connection through an HTTP or SOCKS5 proxy.
+
+use std::io;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Proxy server to reach the stream hosts through, see StreamBuilder::proxy(..)
+#[derive(Clone, Debug)]
+pub enum Proxy {
+    /// HTTP proxy using the CONNECT method, with its address such as "proxy.example.com:3128".
+    Http {
+        addr: String,
+        auth: Option<(String, String)>,
+    },
+
+    /// SOCKS5 proxy, with its address such as "127.0.0.1:1080".
+    Socks5 {
+        addr: String,
+        auth: Option<(String, String)>,
+    },
+}
+
+impl Proxy {
+    /// HTTP proxy at the address, without authentication.
+    pub fn http(addr: &str) -> Self {
+        Proxy::Http {
+            addr: addr.to_string(),
+            auth: None,
+        }
+    }
+
+    /// SOCKS5 proxy at the address, without authentication.
+    pub fn socks5(addr: &str) -> Self {
+        Proxy::Socks5 {
+            addr: addr.to_string(),
+            auth: None,
+        }
+    }
+
+    /// Authenticate to the proxy with user name and password.
+    pub fn with_auth(mut self, user: &str, password: &str) -> Self {
+        let credentials = Some((user.to_string(), password.to_string()));
+        match &mut self {
+            Proxy::Http { auth, .. } | Proxy::Socks5 { auth, .. } => *auth = credentials,
+        }
+        self
+    }
+
+    /// Connect to the proxy and open a tunnel to host:port.
+    pub(crate) async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        match self {
+            Proxy::Http { addr, auth } => {
+                let mut stream = TcpStream::connect(addr.as_str()).await?;
+                http_connect(&mut stream, host, port, auth.as_ref()).await?;
+                Ok(stream)
+            }
+            Proxy::Socks5 { addr, auth } => {
+                let mut stream = TcpStream::connect(addr.as_str()).await?;
+                socks5_connect(&mut stream, host, port, auth.as_ref()).await?;
+                Ok(stream)
+            }
+        }
+    }
+}
+
+fn proxy_error(msg: String) -> io::Error {
+    io::Error::other(msg)
+}
+
+/// Open the tunnel with HTTP CONNECT, and consume the response header of the proxy.
+async fn http_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    auth: Option<&(String, String)>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if let Some((user, password)) = auth {
+        request += &format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::encode(format!("{}:{}", user, password))
+        );
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte, so nothing after the header is taken from the tunnel
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err(proxy_error(
+                "HTTP proxy response header too large".to_string(),
+            ));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(proxy_error(format!(
+            "HTTP proxy refused CONNECT: {}",
+            status_line
+        ))),
+    }
+}
+
+/// Open the tunnel with SOCKS5 CONNECT, RFC 1928, with user/password authentication of RFC 1929.
+async fn socks5_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    auth: Option<&(String, String)>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    const NO_AUTH: u8 = 0x00;
+    const USER_PASSWORD: u8 = 0x02;
+
+    let method = if auth.is_some() {
+        USER_PASSWORD
+    } else {
+        NO_AUTH
+    };
+    stream.write_all(&[0x05, 0x01, method]).await?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != 0x05 || reply[1] != method {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy does not accept auth method {}",
+            method
+        )));
+    }
+
+    if let Some((user, password)) = auth {
+        if user.len() > 255 || password.len() > 255 {
+            return Err(proxy_error("SOCKS5 credentials too long".to_string()));
+        }
+        let mut request = vec![0x01, user.len() as u8];
+        request.extend_from_slice(user.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+
+        stream.read_exact(&mut reply).await?;
+        if reply != [0x01, 0x00] {
+            return Err(proxy_error(format!(
+                "SOCKS5 authentication failed with reply {:?}",
+                reply
+            )));
+        }
+    }
+
+    if host.len() > 255 {
+        return Err(proxy_error("SOCKS5 host name too long".to_string()));
+    }
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != 0x05 {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy replied version {} to CONNECT",
+            reply[0]
+        )));
+    }
+    if reply[1] != 0x00 {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy refused CONNECT with reply {}",
+            reply[1]
+        )));
+    }
+
+    // skip the bound address and port
+    let addr_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        atyp => {
+            return Err(proxy_error(format!(
+                "SOCKS5 proxy replied unknown address type {}",
+                atyp
+            )))
+        }
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    /// Returns a connected pair of client and server side sockets.
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap());
+        let (client, server) = tokio::join!(client, listener.accept());
+        (client.unwrap(), server.unwrap().0)
+    }
+
+    #[test]
+    pub fn test_http_connect() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut client, mut server) = socket_pair().await;
+            let auth = ("user".to_string(), "pass".to_string());
+
+            let proxy = async {
+                let mut request = vec![0u8; 4096];
+                let len = server.read(&mut request).await.unwrap();
+                server
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\no")
+                    .await
+                    .unwrap();
+                String::from_utf8(request[..len].to_vec()).unwrap()
+            };
+            let (result, request) = tokio::join!(
+                http_connect(&mut client, "stream.example.com", 443, Some(&auth)),
+                proxy
+            );
+
+            result.unwrap();
+            assert_eq!(
+                request,
+                "CONNECT stream.example.com:443 HTTP/1.1\r\nHost: stream.example.com:443\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+            );
+            // data after the proxy response header belongs to the tunnel
+            assert_eq!(client.read_u8().await.unwrap(), b'o');
+        });
+    }
+
+    #[test]
+    pub fn test_http_connect_refused() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut client, mut server) = socket_pair().await;
+
+            let proxy = async {
+                let expected = "CONNECT stream.example.com:443 HTTP/1.1\r\nHost: stream.example.com:443\r\n\r\n";
+                let mut request = vec![0u8; expected.len()];
+                server.read_exact(&mut request).await.unwrap();
+                assert_eq!(request, expected.as_bytes());
+                server
+                    .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                    .await
+                    .unwrap();
+            };
+            let (result, _) = tokio::join!(
+                http_connect(&mut client, "stream.example.com", 443, None),
+                proxy
+            );
+
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    pub fn test_socks5_connect() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let (mut client, mut server) = socket_pair().await;
+            let auth = ("user".to_string(), "pass".to_string());
+
+            let proxy = async {
+                let mut greeting = [0u8; 3];
+                server.read_exact(&mut greeting).await.unwrap();
+                server.write_all(&[0x05, 0x02]).await.unwrap();
+
+                let mut credentials = [0u8; 11];
+                server.read_exact(&mut credentials).await.unwrap();
+                server.write_all(&[0x01, 0x00]).await.unwrap();
+
+                let mut request = [0u8; 7 + 11];
+                server.read_exact(&mut request).await.unwrap();
+                server
+                    .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x01, 0xbb])
+                    .await
+                    .unwrap();
+                (greeting, credentials, request)
+            };
+            let (result, (greeting, credentials, request)) = tokio::join!(
+                socks5_connect(&mut client, "example.com", 443, Some(&auth)),
+                proxy
+            );
+
+            result.unwrap();
+            assert_eq!(greeting, [0x05, 0x01, 0x02]);
+            assert_eq!(&credentials, b"\x01\x04user\x04pass");
+            assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+        });
+    }
+
+    #[test]
+    pub fn test_socks5_connect_refused() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let auth = ("user".to_string(), "pass".to_string());
+
+            // authentication failed
+            let (mut client, mut server) = socket_pair().await;
+            let proxy = async {
+                let mut greeting = [0u8; 3];
+                server.read_exact(&mut greeting).await.unwrap();
+                server.write_all(&[0x05, 0x02]).await.unwrap();
+                let mut credentials = [0u8; 11];
+                server.read_exact(&mut credentials).await.unwrap();
+                server.write_all(&[0x01, 0x01]).await.unwrap();
+            };
+            let (result, _) = tokio::join!(
+                socks5_connect(&mut client, "example.com", 443, Some(&auth)),
+                proxy
+            );
+            assert!(result.is_err());
+
+            // not a SOCKS5 reply to CONNECT
+            let (mut client, mut server) = socket_pair().await;
+            let proxy = async {
+                let mut greeting = [0u8; 3];
+                server.read_exact(&mut greeting).await.unwrap();
+                server.write_all(&[0x05, 0x00]).await.unwrap();
+                let mut request = [0u8; 7 + 11];
+                server.read_exact(&mut request).await.unwrap();
+                server.write_all(&[0x04, 0x00, 0x00, 0x01]).await.unwrap();
+            };
+            let (result, _) =
+                tokio::join!(socks5_connect(&mut client, "example.com", 443, None), proxy);
+            assert!(result.is_err());
+        });
+    }
+}
@@ -41,32 +41,419 @@
 
 use tokio_tungstenite::{
 	self,
+	tungstenite::{
+		client::IntoClientRequest,
+		protocol::WebSocketConfig,
+		Message,
+	},
+	MaybeTlsStream,
+	WebSocketStream,
 };
 
-use rand::Rng;
-
 use tokio::{
+	net::TcpStream,
 	runtime,
+	sync::{
+		broadcast,
+		mpsc,
+		watch,
+	},
 	time,
 	task::JoinHandle,
 };
 
-use std::time::Duration;
+use std::{
+	collections::{
+		BTreeMap,
+		HashMap,
+		HashSet,
+		VecDeque,
+	},
+	io::Write,
+	ops::ControlFlow,
+	sync::{
+		atomic::{
+			AtomicBool,
+			AtomicU64,
+			Ordering,
+		},
+		Arc,
+		Mutex,
+		Weak,
+	},
+	time::{
+		Duration,
+		Instant,
+		SystemTime,
+	},
+};
 
-use futures::prelude::*;
+use futures::{
+	future::{
+		AbortHandle,
+		Abortable,
+	},
+};
 use futures_util::{
+	future::{
+		self,
+		FutureExt,
+		TryFutureExt,
+	},
 	sink::SinkExt,
-	stream::StreamExt,
+	stream::{
+		self,
+		BoxStream,
+		SplitSink,
+		SplitStream,
+		StreamExt,
+		TryStreamExt,
+	},
+};
+
+use std::future::Future;
+
+use chrono::FixedOffset;
+
+use rand::{
+	seq::SliceRandom,
+	Rng,
 };
 
-use crate::data::Snapshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+	candle::{
+		Candle,
+		CandleBuilder,
+		PriceSource,
+	},
+	clock::{
+		Clock,
+		SystemClock,
+	},
+	config::StreamConfig,
+	data::{
+		frame_quotes,
+		FieldStats,
+		InstrumentClass,
+		NumberFormat,
+		Snapshot,
+		TurnoverPolicy,
+	},
+	delta::{
+		DeltaEncoder,
+		SnapshotDelta,
+	},
+	error::Error,
+	freeze::{
+		Freeze,
+		FreezeDetector,
+	},
+	gap::{
+		Gap,
+		GapDetector,
+	},
+	coalesce::Coalescer,
+	handshake::Handshake,
+	history::History,
+	metrics,
+	pool::{
+		MakeHandler,
+		StreamPool,
+	},
+	protocol::FrameKeys,
+	proxy::Proxy,
+	queue,
+};
+
+pub use crate::protocol::{
+	channel_subscribe_message,
+	generate_stream_url,
+	stream_url,
+	stream_url_for_server,
+	stream_url_on_host,
+	DEFAULT_DOMAIN,
+	DEFAULT_PATH_TEMPLATE,
+	DEFAULT_TZ_ID,
+	RETRYABLE_CLOSE_CODES,
+	SERVER_COUNT,
+	ChannelMessage,
+	FrameMarkers,
+	GeneratedUrls,
+	ServerEvent,
+	UrlProvider,
+	heartbeat_message,
+	is_heartbeat_frame,
+	is_retryable_close,
+	is_sockjs_heartbeat,
+	parse_channel_message,
+	parse_close_frame,
+	parse_event,
+	parse_snapshot_pid,
+	peek_pid_and_ts,
+	parse_sockjs_messages,
+	parse_uid_frame,
+	subscribe_message,
+	uid_message,
+};
+
+/// Default of StreamBuilder::connect_timeout(..)
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs ( 10 );
+
+/// Default of StreamBuilder::max_connect_attempts(..)
+pub const DEFAULT_MAX_CONNECT_ATTEMPTS: u32 = 3;
+
+/// Time a dropped Stream waits for its task to stop before aborting it, see Stream
+pub const DROP_TIMEOUT: Duration = Duration::from_secs ( 1 );
+
+/// Default of StreamBuilder::active_window(..)
+pub const DEFAULT_ACTIVE_WINDOW: Duration = Duration::from_secs ( 60 );
+
+/// How long the task waits for the websocket to close when the stream stops.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs ( 1 );
+
+/// How long connecting waits after the open frame for the close frame of a server rejecting
+/// the session on arrival, e.g. c[2010,"Another connection still open"] right after "o"
+const REJECT_WINDOW: Duration = Duration::from_millis ( 50 );
+
+/// Default of StreamBuilder::host_cooldown(..)
+pub const DEFAULT_HOST_COOLDOWN: Duration = Duration::from_secs ( 5 * 60 );
+
+/// Number of the last disconnects kept by Stream::disconnects()
+pub const DISCONNECT_HISTORY: usize = 16;
+
+/// Default capacity of StreamBuilder::channel(..)
+pub const SNAPSHOT_BUFFER: usize = 1024;
+
+/// Last snapshot of each pid with when it arrived, see Stream::latest(..) and Stream::last_update(..)
+type LatestSnapshots = HashMap<String, (Instant, Arc<Snapshot>)>;
+
+/// Snapshots of Stream::into_tokio_stream()
+pub type SnapshotStream = BoxStream<'static, Snapshot>;
+
+/// Snapshots of Stream::subscribe_replay(), shared with the other receivers
+pub type SharedSnapshotStream = BoxStream<'static, Arc<Snapshot>>;
+
+/// Snapshots of Stream::windowed(..), those of each window together
+pub type WindowStream = BoxStream<'static, Vec<Snapshot>>;
+
+/// Blocking iterator of the snapshots, see Stream::iter()
+pub struct SnapshotIter<'a> {
+	stream: &'a mut Stream,
+}
+
+impl Iterator for SnapshotIter<'_> {
+	type Item = Snapshot;
+
+	fn next ( &mut self ) -> Option<Snapshot> {
+		if runtime::Handle::try_current ( ).is_ok ( ) {
+			panic! ( "Cannot block the current thread from within an async context, use Stream::next_snapshot() instead" );
+		}
+		// a current-thread runtime only runs the stream while driven, by this thread then
+		if self.stream.runtime.handle ( ).runtime_flavor ( ) == runtime::RuntimeFlavor::CurrentThread {
+			let runtime = self.stream.runtime.clone ( );
+			return runtime.block_on ( self.stream.next_snapshot ( ) );
+		}
+		self.stream.snapshots.as_mut ( )?.blocking_recv ( )
+	}
+}
+
+/// Handler of Stream chosen at runtime, see Stream::new_boxed(..)
+pub type Handler = Box<dyn Fn ( Snapshot ) -> Result<(), ()> + Send + Sync>;
+
+/// State of the connection of Stream, see Stream::state(..)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+	/// Connecting to the first host.
+	Connecting,
+
+	/// Receiving snapshots from the server.
+	Connected,
+
+	/// The previous host was unreachable, connecting to another one, or the connection ended
+	/// mid-session, e.g. with StreamBuilder::session_max_age(..), connecting again.
+	Reconnecting,
+
+	/// The stream ended, see Stream::stream_handle_spawn for why.
+	Closed,
+}
+
+/// How the task of Stream::shutdown_timeout(..) ended.
+#[derive(Debug)]
+pub enum ShutdownResult {
+	/// The task stopped within the timeout. Carries its result, Err if it failed before the
+	/// shutdown, or Err(Error::Aborted) if it panicked.
+	Clean ( Result<(), Error> ),
+
+	/// The task did not stop within the timeout and was aborted.
+	Forced,
+}
+
+/// Diagnostics of StreamBuilder::probe(..)
+#[derive(Clone, Debug)]
+pub struct ProbeReport {
+	/// URL of the successful attempt.
+	pub url: String,
+
+	/// Server number in the URL, None for StreamBuilder::url(..)
+	pub server: Option<u8>,
+
+	/// Connect attempts, 1 unless the first ones failed.
+	pub attempts: u32,
+
+	/// From connecting to the open frame, of the successful attempt.
+	pub latency: Duration,
+
+	/// UID acknowledged by the server in reply to the handshake, None without handshake or
+	/// without reply within the connect timeout.
+	pub uid: Option<u64>,
+}
+
+/// Why a connection of Stream ended, see DisconnectEvent
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+	/// The connection reached StreamBuilder::session_max_age(..), and was replaced by a new one.
+	Expired,
+
+	/// The server closed the connection.
+	Closed,
+
+	/// The handler stopped the stream.
+	Stopped,
+
+	/// The stream was cancelled, see StreamBuilder::cancellation_token(..)
+	Cancelled,
+
+	/// The stream reached StreamBuilder::max_lifetime(..), and ended.
+	Lifetime,
+
+	/// The shard of StreamPool lost pair ids to StreamPool::set_pids(..), and reconnected without them.
+	Resharded,
+
+	/// The connection failed with the error, as text.
+	Failed ( String ),
+}
+
+/// End of a connection of Stream, see Stream::disconnects()
+#[derive(Clone, Debug)]
+pub struct DisconnectEvent {
+	/// When the connection ended.
+	pub at: SystemTime,
+
+	/// How long the connection was up.
+	pub uptime: Duration,
+
+	/// SockJS server of the connection, see Stream::connected_server()
+	pub server: Option<u8>,
+
+	pub reason: DisconnectReason,
+}
+
+/// Connect attempt after a failed one, see StreamBuilder::on_reconnect(..)
+#[derive(Debug)]
+pub enum ReconnectEvent<'a> {
+	/// The attempt failed with the error. The next attempt, with a new URL, follows after the delay.
+	Retry { attempt: u32, delay: Duration, error: &'a Error },
+
+	/// Connected at the attempt, after the failed ones, or at the first attempt after the
+	/// connection ended mid-session, e.g. with StreamBuilder::session_max_age(..)
+	Reconnected { attempt: u32 },
+}
+
+/// What to do with a snapshot when the queue of Stream::next_snapshot() is full, see StreamBuilder::channel(..)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelPolicy {
+	/// Wait until a snapshot is taken, pausing the receive loop. Nothing is lost, e.g. for a recorder.
+	Backpressure,
+
+	/// Drop the oldest queued snapshot. With capacity 1, always the latest snapshot, e.g. for a UI.
+	DropOldest,
+
+	/// Drop the new snapshot.
+	DropNewest,
+}
+
+/// Counters of the frames received by Stream, see Stream::stats()
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+	/// Data frames received but not recognized: no close, UID or heartbeat frame, and no snapshot
+	/// of the subscribed pair ids. Growing while no snapshot arrives means frames arrive for
+	/// something else than the pair ids.
+	pub ignored_frames: u64,
+
+	/// Snapshot frames of the subscribed pair ids which failed to parse, and were skipped.
+	pub parse_errors: u64,
+
+	/// Snapshots dropped by the ChannelPolicy of StreamBuilder::channel(..)
+	pub dropped_snapshots: u64,
+
+	/// Snapshots missing each field, of all the pair ids, with StreamBuilder::field_stats(..)
+	/// See Stream::field_stats() for those of each pair id.
+	pub fields: FieldStats,
+}
+
+/// Subscription state of a pair id, see Stream::subscriptions()
+/// 
+/// The server does not acknowledge bulk-subscribe, and ignores unknown pair ids silently,
+/// so a pair id is known to be accepted only once its first snapshot arrives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+	/// Subscribed, no snapshot received yet.
+	Pending,
+
+	/// At least one snapshot received.
+	Active,
+
+	/// No snapshot within StreamBuilder::first_data_timeout(..), likely an unknown pair id.
+	/// Turns Active if a snapshot arrives later.
+	NoData,
+}
 
 /// Stream to the server, keep returning the Snapshot from wss server
 /// to Fn given in Stream::new(..)
+/// 
+/// Stream is Send and Sync: handlers are required to be Send, so it can be moved into
+/// another thread or kept in a shared structure, e.g. a manager owning many streams.
+/// Don't drop it within an async context though, as dropping its runtime blocks.
+/// 
+/// Dropping the stream stops it as StreamBuilder::cancellation_token(..) does, and waits up to
+/// DROP_TIMEOUT for the task to close the connection before dropping its runtime, aborting the
+/// task past the timeout. With a runtime shared through StreamBuilder::runtime(..), the drop
+/// only stops the task, which ends on the shared runtime.
 pub struct Stream {
-	pub stream_handle_spawn: JoinHandle<Result<(),()>>,
-	pub runtime: runtime::Runtime,
+	/// First, so the task stops before the runtime is dropped
+	_stop_on_drop: StopOnDrop,
+	pub stream_handle_spawn: JoinHandle<Result<(), Error>>,
+	/// Runtime of the task, its own unless shared through StreamBuilder::runtime(..)
+	pub runtime: Arc<runtime::Runtime>,
 	pub pair_id: Box<str>,
+	abort_handle: AbortHandle,
+	cancellation_token: CancellationToken,
+	state: watch::Receiver<ConnectionState>,
+	uid: watch::Receiver<Option<u64>>,
+	subscriptions: watch::Receiver<HashMap<String, SubscriptionStatus>>,
+	subscriptions_sender: Weak<watch::Sender<HashMap<String, SubscriptionStatus>>>,
+	outgoing: mpsc::UnboundedSender<String>,
+	subscribe_batch_size: usize,
+	tz_id: u32,
+	pid_prefix: String,
+	utc_offset: Option<FixedOffset>,
+	stats: watch::Receiver<StreamStats>,
+	field_stats: Arc<Mutex<HashMap<String, FieldStats>>>,
+	connected_at: watch::Receiver<Option<Instant>>,
+	last_activity: watch::Receiver<Option<Instant>>,
+	url: watch::Receiver<Option<(String, Option<u8>)>>,
+	disconnects: watch::Receiver<Vec<DisconnectEvent>>,
+	snapshots: Option<queue::Receiver>,
+	broadcast: Weak<broadcast::Sender<Arc<Snapshot>>>,
+	history: Option<Arc<History>>,
+	latest: Arc<Mutex<LatestSnapshots>>,
+	active_window: Duration,
+	instrument_class: InstrumentClass,
+	clock: Arc<dyn Clock>,
+	handler_slot: Arc<HandlerSlot>,
+	config: StreamConfig,
 }
 
 impl Stream {
@@ -80,137 +467,2781 @@ impl Stream {
 	/// 	"14958"	NASDAQ Composite
 	/// 	"8830"	Gold Futures
 	/// 
-	/// For further pair id, hack the websocket in some browser debugger, such as Chrome inspect.
-	pub fn new <'a, F> ( pair_id: String, handler: F ) -> Result<Self, ()>
+	/// For further pair id, hack the websocket in some browser debugger, such as Chrome inspect.
+	/// 
+	/// Fails with Error::InvalidPairId for empty pair ids. Failures of connecting and streaming
+	/// happen in the spawned task, and are returned by stream_handle_spawn.
+	pub fn new <'a, F> ( pair_id: String, handler: F ) -> Result<Self, Error>
+	where
+		F: Fn ( Snapshot ) -> Result<(), ()> + Send + Sync + 'static,
+	{
+		Self::new_boxed ( pair_id, Box::new ( handler ) )
+	}
+
+	/// Same as Stream::new(..), with the handler as a trait object. Streams don't depend on the
+	/// handler type, so streams with handlers selected at runtime can share a `Vec<Stream>`.
+	pub fn new_boxed ( pair_id: String, handler: Handler ) -> Result<Self, Error> {
+		StreamBuilder::new ( pair_id ).build_boxed ( handler )
+	}
+
+	/// Same as Stream::new(..), with a handler keeping state across snapshots, e.g. a counter.
+	/// 
+	/// The stream owns the handler and calls it from the receive loop only, so it needs to be
+	/// Send but not Sync, and captured state needs no Arc<Mutex<..>>. The state is not shared
+	/// back: keep an Arc<Mutex<..>> or a channel in the handler to read it from outside.
+	pub fn new_mut <F> ( pair_id: String, handler: F ) -> Result<Self, Error>
+	where
+		F: FnMut ( Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		StreamBuilder::new ( pair_id ).build_mut ( handler )
+	}
+
+	/// Same as Stream::new_mut(..), with a handler deciding when it is done, e.g. once a target
+	/// price is hit: ControlFlow::Break(()) ends the receive loop and closes the connection,
+	/// and stream_handle_spawn resolves Ok(()).
+	pub fn new_until <F> ( pair_id: String, handler: F ) -> Result<Self, Error>
+	where
+		F: FnMut ( Snapshot ) -> ControlFlow<()> + Send + 'static,
+	{
+		StreamBuilder::new ( pair_id ).build_until ( handler )
+	}
+
+	/// Same as Stream::new(..), without handler, for snapshots consumed through
+	/// Stream::subscribe_broadcast(), see StreamBuilder::build_without_handler()
+	pub fn new_without_handler ( pair_id: String ) -> Result<Self, Error> {
+		StreamBuilder::new ( pair_id ).build_without_handler ( )
+	}
+
+	/// Returns a builder to configure the stream before connecting.
+	pub fn builder ( pair_id: String ) -> StreamBuilder {
+		StreamBuilder::new ( pair_id )
+	}
+
+	/// Same as Stream::new(..) with the options of the configuration, e.g. loaded from a file.
+	/// Fails as StreamConfig::validate() when the configuration is invalid.
+	pub fn from_config <F> ( config: &StreamConfig, handler: F ) -> Result<Self, Error>
+	where
+		F: Fn ( Snapshot ) -> Result<(), ()> + Send + Sync + 'static,
+	{
+		config.builder ( )?.build ( handler )
+	}
+
+	/// Returns a handle to stop the spawned task abruptly, without dropping the runtime.
+	/// 
+	/// The aborted task resolves Err(Error::Aborted). Aborting in the middle of a send may leave
+	/// the server connection half-open; prefer StreamBuilder::cancellation_token(..) for a clean stop.
+	pub fn abort_handle ( &self ) -> AbortHandle {
+		self.abort_handle.clone ( )
+	}
+
+	/// Replaces the handler of the snapshots from the next one on, without reconnecting, e.g.
+	/// to start or stop recording. The handlers of StreamBuilder::on(..) keep their pids. May be
+	/// called from within the handler, and the last of several calls in between two snapshots wins.
+	/// 
+	/// The replaced handler is dropped in the task, and with build_receiver() the snapshots no
+	/// longer reach Stream::next_snapshot(). Until the first call, the cost is an atomic load
+	/// per snapshot.
+	pub fn set_handler <F> ( &self, handler: F )
+	where
+		F: FnMut ( Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		self.handler_slot.replace ( Box::new ( handler ) );
+	}
+
+	/// Stops the stream as StreamBuilder::cancellation_token(..) does, and waits up to the
+	/// timeout for the task to close the connection. Aborts the task past the timeout, e.g. when
+	/// the socket is wedged, so a service stops within a deadline.
+	/// 
+	/// Blocks the current thread on the runtime of the stream, so call it outside of async code.
+	pub fn shutdown_timeout ( self, timeout: Duration ) -> ShutdownResult {
+		self.cancellation_token.cancel ( );
+		let mut task = self.stream_handle_spawn;
+		match self.runtime.block_on ( async { time::timeout ( timeout, &mut task ).await } ) {
+			Ok ( result ) => ShutdownResult::Clean ( result.unwrap_or ( Err ( Error::Aborted ) ) ),
+			Err ( _ ) => {
+				log::warn! ( "Stream did not stop within {:?}, aborting", timeout );
+				self.abort_handle.abort ( );
+				ShutdownResult::Forced
+			}
+		}
+	}
+
+	/// Returns the current state of the connection.
+	pub fn state ( &self ) -> ConnectionState {
+		*self.state.borrow ( )
+	}
+
+	/// Returns a receiver to await the transitions of the connection state, e.g. with
+	/// `changed().await`. Each observer takes its own receiver.
+	pub fn state_receiver ( &self ) -> watch::Receiver<ConnectionState> {
+		self.state.clone ( )
+	}
+
+	/// Returns how long the current connection is up, zero while not connected.
+	pub fn uptime ( &self ) -> Duration {
+		self.connected_at.borrow ( ).map_or ( Duration::ZERO, |connected_at| self.clock.now ( ).saturating_duration_since ( connected_at ) )
+	}
+
+	/// Returns when the last frame of any kind arrived, None before the first one. The SockJS
+	/// heartbeat frames count, to tell a quiet but alive connection from a dead one, unlike
+	/// Stream::last_update(..) which only counts the snapshots.
+	pub fn last_activity ( &self ) -> Option<Instant> {
+		*self.last_activity.borrow ( )
+	}
+
+	/// Returns the URL of the current or the last connection, None before connecting.
+	pub fn connected_url ( &self ) -> Option<String> {
+		self.url.borrow ( ).as_ref ( ).map ( |( url, _ )| url.clone ( ) )
+	}
+
+	/// Returns the SockJS server of the current or the last connection, the {server} segment
+	/// picked at random for the generated URL, to correlate the failures with the servers, as
+	/// StreamBuilder::host_cooldown(..) does. None before connecting, and with StreamBuilder::url(..)
+	pub fn connected_server ( &self ) -> Option<u8> {
+		self.url.borrow ( ).as_ref ( ).and_then ( |( _, server )| *server )
+	}
+
+	/// Returns the last DISCONNECT_HISTORY ends of connections, the oldest first, e.g. to tell
+	/// a stable session from a flapping one.
+	pub fn disconnects ( &self ) -> Vec<DisconnectEvent> {
+		self.disconnects.borrow ( ).clone ( )
+	}
+
+	/// Returns the UID acknowledged by the server in reply to the UID message, None until then.
+	pub fn uid ( &self ) -> Option<u64> {
+		*self.uid.borrow ( )
+	}
+
+	/// Returns the time zone id the pair ids are subscribed with, which the `time` of the
+	/// snapshots is in, see StreamBuilder::tz_id(..)
+	pub fn tz_id ( &self ) -> u32 {
+		self.tz_id
+	}
+
+	/// Returns the UTC offset of the time zone id given to StreamBuilder::utc_offset(..), also
+	/// set on each Snapshot. None by default, the server does not send it.
+	pub fn utc_offset ( &self ) -> Option<FixedOffset> {
+		self.utc_offset
+	}
+
+	/// Returns the subscription state of each pair id, empty until subscribed.
+	pub fn subscriptions ( &self ) -> HashMap<String, SubscriptionStatus> {
+		self.subscriptions.borrow ( ).clone ( )
+	}
+
+	/// Returns the pair ids subscribed by the stream, whatever their SubscriptionStatus,
+	/// empty until subscribed. Compare it with the wanted pair ids for reconciliation.
+	pub fn subscribed_pids ( &self ) -> HashSet<String> {
+		self.subscriptions.borrow ( ).keys ( ).cloned ( ).collect ( )
+	}
+
+	/// Returns a receiver to await changes of the subscription states, see Stream::state_receiver()
+	pub fn subscriptions_receiver ( &self ) -> watch::Receiver<HashMap<String, SubscriptionStatus>> {
+		self.subscriptions.clone ( )
+	}
+
+	/// Sends the bulk-subscribe of some of the pair ids again over the current connection, e.g.
+	/// of the ones turned SubscriptionStatus::NoData, leaving the other pair ids alone. The pair
+	/// ids turn Pending until their next snapshot. Resubscribing a pair id twice is harmless, the
+	/// server sends its data once. The messages go in batches of
+	/// StreamBuilder::subscribe_batch_size(..), without delay. While reconnecting they are
+	/// dropped, as the new connection subscribes to all the pair ids.
+	/// 
+	/// Err(Error::InvalidPairId(..)) for pair ids the stream is not subscribed to,
+	/// Err(Error::Ended) once the stream ended.
+	pub fn resubscribe ( &self, pids: &[String] ) -> Result<(), Error> {
+		let unknown: Vec<_> = {
+			let subscriptions = self.subscriptions.borrow ( );
+			pids.iter ( ).filter ( |pid| !subscriptions.contains_key ( *pid ) ).cloned ( ).collect ( )
+		};
+		if !unknown.is_empty ( ) {
+			return Err ( Error::InvalidPairId ( unknown.join ( "," ) ) );
+		}
+		self.send_subscribe ( pids )
+	}
+
+	/// Subscribes to one more pair id over the current connection, and to it on the
+	/// reconnects as well. It turns Active with its first snapshot like the others, but is not
+	/// checked by StreamBuilder::first_data_timeout(..), see Stream::subscribe_and_wait(..)
+	/// instead. Nothing is sent for a pair id subscribed already.
+	/// 
+	/// Err(Error::InvalidPairId(..)) for an empty pair id or one containing a comma,
+	/// Err(Error::Ended) once the stream ended.
+	pub fn subscribe ( &self, pid: &str ) -> Result<(), Error> {
+		let pid = pid.trim ( );
+		if pid.is_empty ( ) || pid.contains ( ',' ) {
+			return Err ( Error::InvalidPairId ( pid.to_string ( ) ) );
+		}
+		if self.subscriptions.borrow ( ).contains_key ( pid ) {
+			return Ok ( ( ) );
+		}
+		self.send_subscribe ( &[ pid.to_string ( ) ] )
+	}
+
+	/// Same as Stream::subscribe(..), and resolves with the first snapshot of the pair id from
+	/// then on, e.g. to confirm a new subscription is live. Err(Error::NoSnapshot) when none
+	/// arrives within the timeout, likely an unknown pair id, which stays subscribed.
+	/// 
+	/// The snapshots are those delivered to the handler, see Stream::subscribe_broadcast(), so
+	/// one dropped by StreamBuilder::filter(..) does not count. Await it within the runtime of
+	/// the stream, e.g. `stream.runtime.block_on ( stream.subscribe_and_wait ( .. ) )`.
+	pub async fn subscribe_and_wait ( &self, pid: &str, timeout: Duration ) -> Result<Snapshot, Error> {
+		// receiving before subscribing, not to miss the first snapshot
+		let mut shared = self.subscribe_broadcast ( );
+		self.subscribe ( pid )?;
+		let pid = pid.trim ( );
+		let first = async {
+			while let Some ( snapshot ) = recv_broadcast ( &mut shared ).await {
+				if snapshot.pid == pid {
+					return Ok ( ( *snapshot ).clone ( ) );
+				}
+			}
+			Err ( Error::Ended )
+		};
+		time::timeout ( timeout, first )
+			.await
+			.unwrap_or ( Err ( Error::NoSnapshot ) )
+	}
+
+	/// Marks the pair ids Pending and sends their bulk-subscribe in batches.
+	fn send_subscribe ( &self, pids: &[String] ) -> Result<(), Error> {
+		let subscriptions_sender = self.subscriptions_sender.upgrade ( ).ok_or ( Error::Ended )?;
+		// before sending, so their first frames are not dropped as not subscribed
+		subscriptions_sender.send_modify ( |subscriptions| {
+			for pid in pids {
+				subscriptions.insert ( pid.clone ( ), SubscriptionStatus::Pending );
+			}
+		} );
+		for batch in pids.chunks ( self.subscribe_batch_size ) {
+			self.outgoing.send ( channel_subscribe_message ( &self.pid_prefix, &batch.join ( "," ), self.tz_id ) )
+				.map_err ( |_| Error::Ended )
+				?;
+		}
+		Ok ( ( ) )
+	}
+
+	/// Returns the next snapshot of a stream of StreamBuilder::build_receiver(), None once the
+	/// stream ended. Always None for streams with a handler.
+	/// 
+	/// ```no_run
+	/// use forexpros_wss::push::Stream;
+	/// 
+	/// let mut stream = Stream::builder ( "945629".to_string ( ) ).build_receiver ( ).unwrap ( );
+	/// futures::executor::block_on ( async {
+	///     while let Some ( snapshot ) = stream.next_snapshot ( ).await {
+	///         println ! ( "{:?}", snapshot );
+	///     }
+	/// } );
+	/// ```
+	pub async fn next_snapshot ( &mut self ) -> Option<Snapshot> {
+		self.snapshots.as_mut ( )?.recv ( ).await
+	}
+
+	/// Returns the snapshots of Stream::next_snapshot() as a blocking iterator, ending once the
+	/// stream ended, e.g. `for snapshot in stream.iter() { .. }` in a script. Empty for
+	/// streams with a handler.
+	/// 
+	/// Each call of next() blocks the thread, so it panics when called within an async
+	/// context, like the blocking_recv() of tokio. Use next_snapshot() there. With
+	/// StreamBuilder::current_thread(..), next() drives the runtime of the stream meanwhile.
+	pub fn iter ( &mut self ) -> SnapshotIter<'_> {
+		SnapshotIter { stream: self }
+	}
+
+	/// Returns the snapshots of Stream::next_snapshot() as a futures::Stream, which is the
+	/// Stream trait of tokio_stream as well, so the combinators of tokio_stream::StreamExt
+	/// such as timeout(..) apply. Empty for streams with a handler.
+	/// 
+	/// The adapter owns the stream: dropping the adapter stops the connection like dropping
+	/// Stream, so drop it outside of async contexts, unless the runtime is shared through
+	/// StreamBuilder::runtime(..) and outlives it.
+	pub fn into_tokio_stream ( self ) -> SnapshotStream {
+		stream::unfold ( self, |mut stream| async move {
+			let snapshot = stream.next_snapshot ( ).await?;
+			Some ( ( snapshot, stream ) )
+		} ).boxed ( )
+	}
+
+	/// Returns the snapshots of Stream::next_snapshot() grouped by the window of time they are
+	/// received in, e.g. for a computation over all the ticks of each minute rather than the
+	/// OHLC of StreamBuilder::on_candle(..). The windows follow each other from the first poll.
+	/// 
+	/// A window without snapshots is an empty Vec with emit_empty, else skipped. The window
+	/// in progress when the stream ends comes last, unless empty. Panics for a zero window,
+	/// like tokio::time::interval(..). See into_tokio_stream() for dropping the adapter.
+	pub fn windowed ( self, window: Duration, emit_empty: bool ) -> WindowStream {
+		let snapshots = self.into_tokio_stream ( );
+		stream::unfold ( ( snapshots, None, false ), move |( mut snapshots, ticks, ended )| async move {
+			if ended {
+				return None;
+			}
+			let mut ticks = ticks.unwrap_or_else ( || time::interval_at ( time::Instant::now ( ) + window, window ) );
+			let mut snapshots_of_window = Vec::new ( );
+			loop {
+				tokio::select! {
+					snapshot = snapshots.next ( ) => match snapshot {
+						Some ( snapshot ) => snapshots_of_window.push ( snapshot ),
+						None if snapshots_of_window.is_empty ( ) => return None,
+						None => return Some ( ( snapshots_of_window, ( snapshots, Some ( ticks ), true ) ) ),
+					},
+					_ = ticks.tick ( ) => {
+						if emit_empty || !snapshots_of_window.is_empty ( ) {
+							return Some ( ( snapshots_of_window, ( snapshots, Some ( ticks ), false ) ) );
+						}
+					},
+				}
+			}
+		} ).boxed ( )
+	}
+
+	/// Returns a receiver of the snapshots of the stream from now on, shared by all the
+	/// receivers instead of cloned for each, e.g. for a recorder, a strategy and a UI on one
+	/// connection. Snapshots are only sent while a receiver exists. A consumer modifying them
+	/// takes its own copy, e.g. with `Arc::unwrap_or_clone(..)`
+	/// 
+	/// The channel keeps StreamBuilder::broadcast_capacity(..) snapshots: a receiver lagging
+	/// further behind gets RecvError::Lagged(n) from recv() and skips the n oldest ones, without
+	/// slowing down the stream or the other receivers. recv() returns RecvError::Closed once
+	/// the stream ended.
+	/// 
+	/// Read it with recv_broadcast(..) to skip the snapshots missed by a lagging receiver with
+	/// a logged warning.
+	pub fn subscribe_broadcast ( &self ) -> broadcast::Receiver<Arc<Snapshot>> {
+		// the handler owns the sender, gone with the task
+		self.broadcast.upgrade ( )
+			.map_or_else ( || broadcast::channel ( 1 ).1, |broadcast| broadcast.subscribe ( ) )
+	}
+
+	/// Same as Stream::subscribe_broadcast() as a futures::Stream, starting with the last
+	/// snapshots of each pid kept with StreamBuilder::replay_depth(..), in the order received,
+	/// e.g. for a consumer attaching late to get the current prices and the recent ticks. Each
+	/// snapshot comes once, either replayed or live. The snapshots missed by a lagging receiver
+	/// are skipped as with recv_broadcast(..), and the stream ends with the Stream.
+	pub fn subscribe_replay ( &self ) -> SharedSnapshotStream {
+		let (replayed, receiver) = match &self.history {
+			Some ( history ) => history.replay ( || self.subscribe_broadcast ( ) ),
+			None => ( Vec::new ( ), self.subscribe_broadcast ( ) ),
+		};
+		let live = stream::unfold ( receiver, |mut receiver| async move {
+			let snapshot = recv_broadcast ( &mut receiver ).await?;
+			Some ( ( snapshot, receiver ) )
+		} );
+		stream::iter ( replayed ).chain ( live ).boxed ( )
+	}
+
+	/// Returns the last snapshot of the pid, None before the first one, e.g. to read the
+	/// current price on demand instead of keeping it in the handler.
+	pub fn latest ( &self, pid: &str ) -> Option<Snapshot> {
+		self.latest.lock ( ).unwrap ( ).get ( pid ).map ( |( _, snapshot )| ( **snapshot ).clone ( ) )
+	}
+
+	/// Returns the last snapshot of each pid, e.g. to dump the current state periodically. The
+	/// pids without snapshot yet are missing.
+	/// 
+	/// The map is a consistent instant: it is copied under the lock the stream updates it with,
+	/// so it is the state of all the pids at one moment, not a mix of reads at different times.
+	/// A snapshot is in it before the handler is called with it.
+	pub fn latest_all ( &self ) -> HashMap<String, Snapshot> {
+		self.latest.lock ( ).unwrap ( ).iter ( )
+			.map ( |( pid, ( _, snapshot ) )| ( pid.clone ( ), ( **snapshot ).clone ( ) ) )
+			.collect ( )
+	}
+
+	/// Returns when the last snapshot of the pid arrived, None before the first one.
+	pub fn last_update ( &self, pid: &str ) -> Option<Instant> {
+		self.latest.lock ( ).unwrap ( ).get ( pid ).map ( |( last, _ )| *last )
+	}
+
+	/// Whether a snapshot of the pid arrived within StreamBuilder::active_window(..), e.g. to
+	/// tell a market ticking from a market closed while the stream stays connected.
+	/// 
+	/// A heuristic only, not the market status: an illiquid instrument may be quiet for longer
+	/// than the window during trading hours, see Stream::state() for the connection.
+	pub fn is_active ( &self, pid: &str ) -> bool {
+		self.last_update ( pid ).is_some_and ( |last| self.clock.now ( ).saturating_duration_since ( last ) <= self.active_window )
+	}
+
+	/// Whether the connection is up and a snapshot of any pid arrived within the duration, e.g.
+	/// for a readiness probe. Only reads the state of the stream, so it returns at once.
+	/// See Stream::is_active(..) for a single pid.
+	/// 
+	/// With InstrumentClass::Sessioned, see StreamBuilder::instrument_class(..), a quiet period
+	/// long enough to be a session break, since the last snapshot or else the connect, is healthy
+	/// as well, as the market may be closed. A shorter one is not.
+	pub async fn healthy ( &self, within: Duration ) -> bool {
+		if self.state ( ) != ConnectionState::Connected {
+			return false;
+		}
+		let now = self.clock.now ( );
+		let last = self.latest.lock ( ).unwrap ( ).values ( ).map ( |( last, _ )| *last ).max ( );
+		if last.is_some_and ( |last| now.saturating_duration_since ( last ) <= within ) {
+			return true;
+		}
+		last.or ( *self.connected_at.borrow ( ) )
+			.is_some_and ( |since| self.instrument_class.is_session_break ( now.saturating_duration_since ( since ) ) )
+	}
+
+	/// Returns the options the stream was built with, all set to their effective values, e.g.
+	/// to log them or to check which layer of configuration took effect. Options left None are
+	/// off, such as StreamConfig::ping_interval_ms. The callbacks are not part of it.
+	pub fn config ( &self ) -> &StreamConfig {
+		&self.config
+	}
+
+	/// Returns the counters of the received frames so far.
+	pub fn stats ( &self ) -> StreamStats {
+		StreamStats {
+			dropped_snapshots: self.snapshots.as_ref ( ).map_or ( 0, queue::Receiver::dropped ),
+			fields: self.field_stats.lock ( ).unwrap ( ).values ( ).fold ( FieldStats::default ( ), FieldStats::merge ),
+			..*self.stats.borrow ( )
+		}
+	}
+
+	/// Returns the snapshots missing each field per pair id, e.g. to tell which fields an
+	/// instrument does not send. Empty without StreamBuilder::field_stats(..)
+	pub fn field_stats ( &self ) -> HashMap<String, FieldStats> {
+		self.field_stats.lock ( ).unwrap ( ).clone ( )
+	}
+}
+
+/// Stops the task of Stream when dropped, see there.
+struct StopOnDrop {
+	cancellation_token: CancellationToken,
+	abort_handle: AbortHandle,
+	state: watch::Receiver<ConnectionState>,
+	/// Runtime to wait on for the stop, if the stream owns it
+	runtime: Option<Arc<runtime::Runtime>>,
+}
+
+impl Drop for StopOnDrop {
+	fn drop ( &mut self ) {
+		self.cancellation_token.cancel ( );
+		// the runtime of the caller goes on, and blocking within it is not allowed
+		let runtime = match &self.runtime {
+			Some ( runtime ) if runtime::Handle::try_current ( ).is_err ( ) => runtime,
+			_ => return,
+		};
+		let mut state = self.state.clone ( );
+		let stopped = async move {
+			// the sender is gone with the task as well
+			while *state.borrow_and_update ( ) != ConnectionState::Closed {
+				if state.changed ( ).await.is_err ( ) {
+					break;
+				}
+			}
+		};
+		if runtime.block_on ( async { time::timeout ( DROP_TIMEOUT, stopped ).await } ).is_err ( ) {
+			log::warn! ( "Stream did not stop within {:?} of its drop, aborting", DROP_TIMEOUT );
+			self.abort_handle.abort ( );
+		}
+	}
+}
+
+/// Cancel the token when dropped, so the heartbeat stops together with the receive loop.
+struct CancelOnDrop ( CancellationToken );
+
+impl Drop for CancelOnDrop {
+	fn drop ( &mut self ) {
+		self.0.cancel ( );
+	}
+}
+
+/// Detector and callback of StreamBuilder::on_gap(..)
+pub(crate) type GapHandler = (GapDetector, Box<dyn FnMut ( Gap ) + Send>);
+
+/// Detector and callback of StreamBuilder::on_freeze(..)
+pub(crate) type FreezeHandler = (FreezeDetector, Box<dyn FnMut ( Freeze ) + Send>);
+
+/// Callback of StreamBuilder::on_ignored_frame(..)
+pub(crate) type IgnoredFrameHandler = Box<dyn FnMut ( &str ) + Send>;
+
+/// Callback of StreamBuilder::on_event(..)
+pub(crate) type EventHandler = Box<dyn FnMut ( ServerEvent ) + Send>;
+
+/// Prefix and callback of StreamBuilder::on_channel(..)
+pub(crate) type ChannelHandler = (String, Box<dyn FnMut ( ChannelMessage ) + Send>);
+
+/// Callback of StreamBuilder::on_parse_error(..)
+pub(crate) type ParseErrorHandler = Box<dyn FnMut ( Error, &str ) + Send>;
+
+/// Callback of StreamBuilder::on_send(..), shared with the heartbeat task
+pub(crate) type SendHandler = Arc<dyn Fn ( &str ) + Send + Sync>;
+
+/// Callback of StreamBuilder::on_reconnect(..)
+pub(crate) type ReconnectHandler = Box<dyn FnMut ( ReconnectEvent ) + Send>;
+
+/// Candles of StreamBuilder::on_candle(..), passing the open candles to on_candle when dropped
+/// with the stream, marked partial, if emit_partial.
+pub(crate) struct CandleHandler {
+	builder: CandleBuilder,
+	emit_partial: bool,
+	on_candle: Box<dyn FnMut ( Candle ) + Send>,
+}
+
+impl Drop for CandleHandler {
+	fn drop ( &mut self ) {
+		if self.emit_partial {
+			for candle in self.builder.flush ( ) {
+				( self.on_candle ) ( candle );
+			}
+		}
+	}
+}
+
+/// When StreamBuilder::emit_jsonl(..) flushes the writer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonlFlush {
+	/// After each line, e.g. for a pipe read live by another tool.
+	Line,
+
+	/// After this many lines and when the stream ends, sparing the system calls of a busy
+	/// stream, e.g. to a file.
+	Lines(usize),
+}
+
+/// Writer of StreamBuilder::emit_jsonl(..), flushing the lines left when dropped with the stream
+pub(crate) struct JsonlWriter {
+	writer: Box<dyn Write + Send>,
+	flush: JsonlFlush,
+	unflushed: usize,
+}
+
+impl JsonlWriter {
+	/// Writes the snapshot as a line of JSON. Failures are logged, the stream goes on.
+	fn write ( &mut self, snapshot: &Snapshot ) {
+		let result = serde_json::to_writer ( &mut self.writer, snapshot )
+			.map_err ( std::io::Error::from )
+			.and_then ( |_| self.writer.write_all ( b"\n" ) );
+		if let Err ( e ) = result {
+			log::warn! ( "Failed to write snapshot of {}: {}", snapshot.pid, e );
+			return;
+		}
+		self.unflushed += 1;
+		let limit = match self.flush {
+			JsonlFlush::Line => 1,
+			JsonlFlush::Lines ( lines ) => lines.max ( 1 ),
+		};
+		if self.unflushed >= limit {
+			self.flush ( );
+		}
+	}
+
+	fn flush ( &mut self ) {
+		self.unflushed = 0;
+		if let Err ( e ) = self.writer.flush ( ) {
+			log::warn! ( "Failed to flush snapshots: {}", e );
+		}
+	}
+}
+
+impl Drop for JsonlWriter {
+	fn drop ( &mut self ) {
+		if self.unflushed > 0 {
+			self.flush ( );
+		}
+	}
+}
+
+/// Callbacks of run(..) besides the handler
+#[derive(Default)]
+pub(crate) struct Hooks {
+	pub(crate) gap_handler: Option<GapHandler>,
+	pub(crate) freeze_handler: Option<FreezeHandler>,
+	pub(crate) on_ignored_frame: Option<IgnoredFrameHandler>,
+	pub(crate) on_event: Option<EventHandler>,
+	pub(crate) channel_handlers: Vec<ChannelHandler>,
+	pub(crate) on_parse_error: Option<ParseErrorHandler>,
+	pub(crate) on_reconnect: Option<ReconnectHandler>,
+	pub(crate) on_send: Option<SendHandler>,
+	pub(crate) on_initial: Option<InitialHandler>,
+	pub(crate) filter: Option<FilterHandler>,
+}
+
+/// Callback of StreamBuilder::on_initial(..)
+pub(crate) type InitialHandler = Box<dyn FnMut ( &Snapshot ) + Send>;
+
+/// Predicate of StreamBuilder::filter(..)
+pub(crate) type FilterHandler = Box<dyn FnMut ( &Snapshot ) -> bool + Send>;
+
+/// Handler of a single pid, see StreamBuilder::on(..)
+pub(crate) type PidHandler = Box<dyn FnMut ( Snapshot ) -> Result<(), ()> + Send>;
+
+/// Handler of Stream::set_handler(..) on its way to the task, which owns it from the next snapshot
+#[derive(Default)]
+pub(crate) struct HandlerSlot {
+	replaced: AtomicBool,
+	handler: Mutex<Option<PidHandler>>,
+}
+
+impl HandlerSlot {
+	fn replace ( &self, handler: PidHandler ) {
+		*self.handler.lock ( ).unwrap ( ) = Some ( handler );
+		self.replaced.store ( true, Ordering::Release );
+	}
+
+	/// The handler replacing the current one, if any since the last call.
+	fn take ( &self ) -> Option<PidHandler> {
+		if !self.replaced.swap ( false, Ordering::Acquire ) {
+			return None;
+		}
+		self.handler.lock ( ).unwrap ( ).take ( )
+	}
+}
+
+/// Builder of Stream for options beyond Stream::new(..)
+/// 
+/// Websocket compression (permessage-deflate) is not available: tungstenite does not
+/// implement the extension, so frames are always sent and received uncompressed.
+pub struct StreamBuilder {
+	pair_id: String,
+	cancellation_token: Option<CancellationToken>,
+	max_lifetime: Option<Duration>,
+	coalesce: Option<Duration>,
+	source: Option<mpsc::Receiver<Snapshot>>,
+	connect_options: ConnectOptions,
+	handler_concurrency: usize,
+	hooks: Hooks,
+	pid_handlers: HashMap<String, PidHandler>,
+	channel: (ChannelPolicy, usize),
+	candle_handler: Option<CandleHandler>,
+	forward_fill_candles: bool,
+	jsonl_writer: Option<JsonlWriter>,
+	clock_offset: Option<chrono::Duration>,
+	utc_offset: Option<FixedOffset>,
+	runtime: Option<Arc<runtime::Runtime>>,
+	current_thread: bool,
+	broadcast_capacity: usize,
+	replay_depth: usize,
+	active_window: Duration,
+	numeric_pair_ids: bool,
+	instrument_class: InstrumentClass,
+	clock: Arc<dyn Clock>,
+}
+
+impl StreamBuilder {
+	/// Create builder with the comma-separated pair ids, see Stream::new(..)
+	pub fn new ( pair_id: String ) -> Self {
+		StreamBuilder {
+			pair_id,
+			cancellation_token: None,
+			max_lifetime: None,
+			coalesce: None,
+			source: None,
+			connect_options: ConnectOptions::default ( ),
+			handler_concurrency: 1,
+			hooks: Hooks::default ( ),
+			pid_handlers: HashMap::new ( ),
+			channel: ( ChannelPolicy::Backpressure, SNAPSHOT_BUFFER ),
+			candle_handler: None,
+			forward_fill_candles: false,
+			jsonl_writer: None,
+			clock_offset: None,
+			utc_offset: None,
+			runtime: None,
+			current_thread: false,
+			broadcast_capacity: SNAPSHOT_BUFFER,
+			replay_depth: 0,
+			active_window: DEFAULT_ACTIVE_WINDOW,
+			numeric_pair_ids: false,
+			instrument_class: InstrumentClass::default ( ),
+			clock: Arc::new ( SystemClock ),
+		}
+	}
+
+	/// Stop the stream when the token is cancelled.
+	/// 
+	/// The receive loop and the heartbeat stop promptly, as do the connect attempts and the
+	/// retry_delay(..) between them, and the spawned task resolves Ok(()).
+	pub fn cancellation_token ( mut self, token: CancellationToken ) -> Self {
+		self.cancellation_token = Some ( token );
+		self
+	}
+
+	/// End the stream once it has run this long, as cancelling it does: the receive loop and
+	/// the heartbeat stop, the connection closes, and the spawned task resolves Ok(()). Unlike
+	/// session_max_age(..), it does not reconnect, e.g. to collect for an hour. Default is None.
+	pub fn max_lifetime ( mut self, lifetime: Option<Duration> ) -> Self {
+		self.max_lifetime = lifetime;
+		self
+	}
+
+	/// Give up connecting when the websocket upgrade and the SockJS open frame take longer
+	/// than this, with Error::ConnectTimeout before the upgrade and Error::OpenFrameTimeout
+	/// after it. Default is 10 seconds.
+	pub fn connect_timeout ( mut self, timeout: Duration ) -> Self {
+		self.connect_options.timeout = timeout;
+		self
+	}
+
+	/// Number of hosts to try when connecting. Some randomly generated hosts are unreachable,
+	/// so a failed connect or a connect timeout is retried with a newly generated URL, as is a
+	/// server closing the session on arrival with one of RETRYABLE_CLOSE_CODES, in place of the
+	/// open frame "o" or right after it. Other close codes fail the stream with Error::Closed.
+	/// Default is 3.
+	pub fn max_connect_attempts ( mut self, attempts: u32 ) -> Self {
+		self.connect_options.max_attempts = attempts.max ( 1 );
+		self
+	}
+
+	/// Wait this long before retrying a failed connect, see max_connect_attempts(..). Default is
+	/// no delay, the generated hosts being independent.
+	pub fn retry_delay ( mut self, delay: Duration ) -> Self {
+		self.connect_options.retry_delay = delay;
+		self
+	}
+
+	/// Add a random delay up to the jitter to each retry_delay(..), so the streams of a process
+	/// do not retry in lockstep. Default is none.
+	pub fn retry_jitter ( mut self, jitter: Duration ) -> Self {
+		self.connect_options.retry_jitter = jitter;
+		self
+	}
+
+	/// Avoid the generated servers which failed to connect for the cooldown, as some are
+	/// unreachable for long. The failed servers are shared by the streams of the process, and
+	/// forgotten on restart, while the failed preferred hosts are kept by each stream. Once all
+	/// servers are cooling down, any is tried. Zero disables it, default is DEFAULT_HOST_COOLDOWN.
+	pub fn host_cooldown ( mut self, cooldown: Duration ) -> Self {
+		self.connect_options.host_cooldown = cooldown;
+		self
+	}
+
+	/// Maximum size of a websocket message, None for no limit. Default is 64 MiB.
+	/// 
+	/// Raise it when subscribing to many pair ids, where a single a[...] frame can be large.
+	/// A larger message, reassembled from its fragments, ends the stream with Error::MessageTooLarge.
+	pub fn max_message_size ( mut self, size: Option<usize> ) -> Self {
+		self.connect_options.websocket_config.max_message_size = size;
+		self
+	}
+
+	/// Maximum size of a single websocket frame, None for no limit. Default is 16 MiB.
+	/// A larger frame ends the stream with Error::MessageTooLarge.
+	pub fn max_frame_size ( mut self, size: Option<usize> ) -> Self {
+		self.connect_options.websocket_config.max_frame_size = size;
+		self
+	}
+
+	/// Send a websocket ping frame at this interval, besides the heartbeat message, for proxies
+	/// and NAT devices dropping connections without websocket control frames. Default is None,
+	/// no pings. Pings of the server are always answered with a pong.
+	pub fn ping_interval ( mut self, interval: Option<Duration> ) -> Self {
+		self.connect_options.ping_interval = interval;
+		self
+	}
+
+	/// Connect to this URL instead of generated ones, e.g. the URL of testing::MockServer.
+	/// Each connect attempt uses the same URL. Replaces StreamBuilder::url_provider(..)
+	pub fn url ( mut self, url: &str ) -> Self {
+		self.connect_options.url = Some ( url.to_string ( ) );
+		self.connect_options.url_provider = None;
+		self
+	}
+
+	/// Connect to the URLs of the provider instead of generated ones, one per connect attempt,
+	/// e.g. a round-robin over known hosts or a fixed sequence in tests. The host cooldown does
+	/// not apply to them. Replaces StreamBuilder::url(..)
+	pub fn url_provider ( mut self, provider: impl UrlProvider + Send + 'static ) -> Self {
+		self.connect_options.url_provider = Some ( Arc::new ( Mutex::new ( provider ) ) );
+		self.connect_options.url = None;
+		self
+	}
+
+	/// Try these hosts in order before the generated URLs, e.g. the hosts found stable, on each
+	/// connect and reconnect. A host is a name such as "streaming.forexpros.com", connected
+	/// over wss://, or comes with its scheme, e.g. "ws://127.0.0.1:8080" for a local server.
+	/// Each attempt gets a fresh SockJS server and session on the path_template(..), as the
+	/// generated URLs do. The generated URLs follow once each preferred host failed or is
+	/// cooling down, see host_cooldown(..), which applies to both. Ignored with
+	/// StreamBuilder::url(..) and url_provider(..). Default is none.
+	pub fn preferred_hosts ( mut self, hosts: Vec<String> ) -> Self {
+		self.connect_options.preferred_hosts = hosts;
+		self
+	}
+
+	/// Domain of the generated URLs, connecting to its "streaming." host, e.g. a regional or
+	/// staging domain of the provider. Default is DEFAULT_DOMAIN, "forexpros.com". Ignored
+	/// with StreamBuilder::url(..) and url_provider(..)
+	/// 
+	/// Building the stream fails with Error::EmptyDomain when the domain is empty.
+	pub fn domain ( mut self, domain: &str ) -> Self {
+		self.connect_options.domain = domain.to_string ( );
+		self
+	}
+
+	/// Path of the generated URLs, where {server} and {session} are replaced by random segments
+	/// of each connect attempt, for when the provider reshapes its SockJS URLs. Default is
+	/// DEFAULT_PATH_TEMPLATE, "/echo/{server}/{session}/websocket". Ignored with
+	/// StreamBuilder::url(..) and url_provider(..)
+	pub fn path_template ( mut self, template: &str ) -> Self {
+		self.connect_options.path_template = template.to_string ( );
+		self
+	}
+
+	/// UID sent to the server after the bulk-subscribe. Default is 0, anonymous.
+	/// The UID acknowledged by the server is available from Stream::uid()
+	pub fn uid ( mut self, uid: u64 ) -> Self {
+		self.connect_options.uid = uid;
+		self
+	}
+
+	/// Time zone id of the bulk-subscribe, which the server formats the `time` of the snapshots
+	/// in. Default is DEFAULT_TZ_ID, the US Eastern time. Give its UTC offset with utc_offset(..)
+	pub fn tz_id ( mut self, tz_id: u32 ) -> Self {
+		self.connect_options.tz_id = tz_id;
+		self
+	}
+
+	/// UTC offset of the time zone id of tz_id(..), set on each Snapshot for Snapshot::local_time().
+	/// Default is none: the server does not send it.
+	///
+	/// The offset is fixed for the life of the stream, while the one of DEFAULT_TZ_ID changes
+	/// with the daylight saving time: past a change, Snapshot::local_time() is an hour off until
+	/// the stream is rebuilt with the new offset. Use Snapshot::time_at(..) with the offset of
+	/// the day instead to cross the changes.
+	pub fn utc_offset ( mut self, offset: FixedOffset ) -> Self {
+		self.utc_offset = Some ( offset );
+		self
+	}
+
+	/// Raw messages sent in order after each connect, instead of subscribe_message(pair ids, tz_id)
+	/// and uid_message(uid), e.g. to subscribe to other events of the provider. Include those
+	/// two to keep receiving the snapshots of the pair ids, which are still tracked by
+	/// Stream::subscriptions() as if subscribed.
+	pub fn connect_messages ( mut self, messages: Vec<String> ) -> Self {
+		self.connect_options.connect_messages = Some ( messages );
+		self
+	}
+
+	/// Subscribe to at most this many pair ids per bulk-subscribe message, sending several
+	/// messages for a large portfolio, e.g. to stay below the limits of the server. Default is
+	/// all the pair ids in one message. Ignored with StreamBuilder::connect_messages(..)
+	pub fn subscribe_batch_size ( mut self, size: usize ) -> Self {
+		self.connect_options.subscribe_batch_size = size.max ( 1 );
+		self
+	}
+
+	/// Wait this long between the bulk-subscribe messages of subscribe_batch_size(..), against
+	/// rate limits. Default is no delay.
+	pub fn subscribe_batch_delay ( mut self, delay: Duration ) -> Self {
+		self.connect_options.subscribe_batch_delay = delay;
+		self
+	}
+
+	/// Parse the data messages with the markers of their envelope instead of the defaults, e.g.
+	/// after the provider changed the layout, and subscribe with their pid prefix.
+	pub fn frame_markers ( mut self, markers: FrameMarkers ) -> Self {
+		self.connect_options.frame_keys = FrameKeys::new ( markers, &self.connect_options.channels );
+		self
+	}
+
+	/// Keep the JSON object of the instrument data of each snapshot in Snapshot::raw, e.g. to
+	/// debug a field which looks wrong or to record the frames. Default is false, sparing the allocation.
+	pub fn keep_raw ( mut self, keep: bool ) -> Self {
+		self.connect_options.keep_raw = keep;
+		self
+	}
+
+	/// Count the snapshots missing each field, see Stream::field_stats() and StreamStats::fields.
+	/// Default is false, sparing the counting.
+	pub fn field_stats ( mut self, enabled: bool ) -> Self {
+		self.connect_options.field_stats = enabled;
+		self
+	}
+
+	/// Read binary frames of invalid UTF-8 with the invalid bytes replaced by U+FFFD, instead
+	/// of skipping them. Either way such a frame is logged as a warning and does not end the
+	/// stream. Skipped frames count in StreamStats::ignored_frames. Default is false.
+	pub fn lossy_utf8 ( mut self, lossy: bool ) -> Self {
+		self.connect_options.lossy_utf8 = lossy;
+		self
+	}
+
+	/// Reconnect with a newly generated URL once a connection is this old, before the server
+	/// recycles the SockJS session, subscribing to the same pair ids again. Futures of
+	/// build_async(..) still running are dropped. Default is None.
+	/// 
+	/// Each rotation costs a handshake and may miss snapshots meanwhile, keep it in hours.
+	pub fn session_max_age ( mut self, age: Option<Duration> ) -> Self {
+		self.connect_options.session_max_age = age;
+		self
+	}
+
+	/// Mark the pair ids without any snapshot this long after subscribing as
+	/// SubscriptionStatus::NoData, see Stream::subscriptions_receiver() to react to it.
+	/// Set it longer than the quiet periods of the market, to tell them from unknown pair ids. Default is None.
+	pub fn first_data_timeout ( mut self, timeout: Option<Duration> ) -> Self {
+		self.connect_options.first_data_timeout = timeout;
+		self
+	}
+
+	/// Connect to the stream hosts through an HTTP or SOCKS5 proxy.
+	pub fn proxy ( mut self, proxy: Proxy ) -> Self {
+		self.connect_options.proxy = Some ( proxy );
+		self
+	}
+
+	/// Fail with Error::InvalidPairId unless each pair id is all digits, as the pair ids of the
+	/// server are, to catch a symbol such as "EUR/USD" given by mistake. Default is false:
+	/// only empty pair ids fail.
+	pub fn numeric_pair_ids ( mut self, numeric: bool ) -> Self {
+		self.numeric_pair_ids = numeric;
+		self
+	}
+
+	/// Number of handler futures of StreamBuilder::build_async(..) running at the same time.
+	/// Default is 1: snapshots are handled one after another, in the order received.
+	/// 
+	/// With a limit above 1, the next snapshot is handled while earlier handlers are still
+	/// waiting on I/O, so handlers may complete out of order, also for the same pid. Keep 1 when
+	/// the handler depends on the order, e.g. to compute changes between ticks.
+	/// Synchronous handlers complete before the next snapshot is read, so this has no effect on them.
+	pub fn handler_concurrency ( mut self, limit: usize ) -> Self {
+		self.handler_concurrency = limit.max ( 1 );
+		self
+	}
+
+	/// Pass the handler, and the handlers of on(..), only the newest snapshot of each pid every
+	/// interval instead of every snapshot, e.g. to redraw a board during fast markets. A flush
+	/// passes the snapshots in the order their pids first arrived since the previous one. The
+	/// pending snapshots are flushed when the stream ends, unless the handler stopped it.
+	/// 
+	/// The callbacks such as on_candle(..) and the receivers of subscribe_broadcast() still get
+	/// every snapshot. Default is None.
+	pub fn coalesce ( mut self, interval: Option<Duration> ) -> Self {
+		self.coalesce = interval;
+		self
+	}
+
+	/// Take the snapshots sent by the test to the receiver instead of connecting, for tests of
+	/// the code consuming a Stream, see testing::in_memory_stream(..)
+	/// 
+	/// Nothing is connected nor parsed: the snapshots go through the hooks and the handler as
+	/// if received, and the stream closes once the senders are dropped.
+	#[cfg(any(test, feature = "testing"))]
+	pub fn snapshot_source ( mut self, source: mpsc::Receiver<Snapshot> ) -> Self {
+		self.source = Some ( source );
+		self
+	}
+
+	/// Call on_gap with the Gap when the server timestamps of consecutive snapshots of a pid are
+	/// further apart than the threshold, e.g. to flag periods of incomplete recorded data.
+	/// 
+	/// Called before the handler of the snapshot after the gap. See GapDetector for the first
+	/// snapshot of a pid and timestamps going back.
+	pub fn on_gap <G> ( mut self, threshold: Duration, on_gap: G ) -> Self
+	where
+		G: FnMut ( Gap ) + Send + 'static,
+	{
+		self.hooks.gap_handler = Some ( ( GapDetector::new ( threshold ), Box::new ( on_gap ) ) );
+		self
+	}
+
+	/// Call on_freeze with the Freeze when the last_numeric of a pid stays the same for longer
+	/// than the window while its snapshots keep arriving, e.g. to flag a stall upstream which
+	/// the frames still flowing hide from Stream::healthy(..)
+	/// 
+	/// Called before the handler of the first snapshot past the window, once per freeze, or
+	/// on the check every half window when no snapshot of the pid comes at all, e.g. with only
+	/// heartbeats left in the feed. See FreezeDetector for the time taken.
+	pub fn on_freeze <G> ( mut self, window: Duration, on_freeze: G ) -> Self
+	where
+		G: FnMut ( Freeze ) + Send + 'static,
+	{
+		self.hooks.freeze_handler = Some ( ( FreezeDetector::new ( window ), Box::new ( on_freeze ) ) );
+		self
+	}
+
+	/// Aggregate the snapshots into candles of the interval from the prices of the source, and
+	/// call on_candle with each candle when its interval closes, see CandleBuilder.
+	/// 
+	/// With emit_partial, the candles still open when the stream ends are passed to on_candle
+	/// as well, marked Candle::partial in the order of their pids, otherwise they are
+	/// discarded. Called before the handler of the snapshot. See forward_fill_candles(..) for
+	/// the intervals without snapshots.
+	pub fn on_candle <G> ( mut self, interval: Duration, source: PriceSource, emit_partial: bool, on_candle: G ) -> Self
+	where
+		G: FnMut ( Candle ) + Send + 'static,
+	{
+		self.candle_handler = Some ( CandleHandler {
+			builder: CandleBuilder::new ( interval, source ),
+			emit_partial,
+			on_candle: Box::new ( on_candle ),
+		} );
+		self
+	}
+
+	/// Pass the flat candles of the intervals without snapshots to on_candle(..) as well, after
+	/// the candle they follow, see CandleBuilder::forward_fill(..). Default is false, keeping
+	/// the gaps.
+	pub fn forward_fill_candles ( mut self, fill: bool ) -> Self {
+		self.forward_fill_candles = fill;
+		self
+	}
+
+	/// Write each snapshot to the writer as a line of JSON of its Serialize form, e.g. to pipe
+	/// the parsed snapshots into other tools. Unlike a recording of the raw frames, the lines
+	/// carry the snapshots as delivered, after StreamBuilder::filter(..). Written before the
+	/// handler of the snapshot. Failures to write are logged and skip the snapshot.
+	pub fn emit_jsonl <W> ( mut self, writer: W, flush: JsonlFlush ) -> Self
+	where
+		W: Write + Send + 'static,
+	{
+		self.jsonl_writer = Some ( JsonlWriter { writer: Box::new ( writer ), flush, unflushed: 0 } );
+		self
+	}
+
+	/// Call on_ignored_frame with each data frame counted in StreamStats::ignored_frames, e.g. to
+	/// log frames of an unexpected format while debugging a subscription.
+	pub fn on_ignored_frame <G> ( mut self, on_ignored_frame: G ) -> Self
+	where
+		G: FnMut ( &str ) + Send + 'static,
+	{
+		self.hooks.on_ignored_frame = Some ( Box::new ( on_ignored_frame ) );
+		self
+	}
+
+	/// Call on_event with the event of each control message of the server, see parse_event(..),
+	/// e.g. to follow the bulk-subscribe replies. The error events are logged as warnings as well.
+	/// Without it the control messages are not decoded, apart from the UID reply.
+	pub fn on_event <G> ( mut self, on_event: G ) -> Self
+	where
+		G: FnMut ( ServerEvent ) + Send + 'static,
+	{
+		self.hooks.on_event = Some ( Box::new ( on_event ) );
+		self
+	}
+
+	/// Subscribe to a data channel of the pair ids besides the quotes, by the prefix of its keys
+	/// such as "pidTechSumm-" in place of "pid-", and call on_message with each of its
+	/// messages, see ChannelMessage. The quotes go to the handler as usual. Pair ids added by
+	/// Stream::subscribe(..) get the quotes only.
+	pub fn on_channel <G> ( mut self, channel: &str, on_message: G ) -> Self
+	where
+		G: FnMut ( ChannelMessage ) + Send + 'static,
+	{
+		self.connect_options.channels.push ( channel.to_string ( ) );
+		self.connect_options.frame_keys.add_channel ( channel );
+		self.hooks.channel_handlers.push ( ( channel.to_string ( ), Box::new ( on_message ) ) );
+		self
+	}
+
+	/// Call on_parse_error with Error::Parse and the frame of each snapshot frame failing to parse,
+	/// counted in StreamStats::parse_errors. The stream skips such frames and goes on with the next ones.
+	pub fn on_parse_error <G> ( mut self, on_parse_error: G ) -> Self
+	where
+		G: FnMut ( Error, &str ) + Send + 'static,
+	{
+		self.hooks.on_parse_error = Some ( Box::new ( on_parse_error ) );
+		self
+	}
+
+	/// Call on_initial with the first snapshot of each pid on each connection, the full snapshot
+	/// the server sends on subscribing, before the handler receives it as well. E.g. to set a
+	/// baseline before reacting to the updates, again after a reconnect.
+	pub fn on_initial <G> ( mut self, on_initial: G ) -> Self
+	where
+		G: FnMut ( &Snapshot ) + Send + 'static,
+	{
+		self.hooks.on_initial = Some ( Box::new ( on_initial ) );
+		self
+	}
+
+	/// Deliver only the snapshots for which the predicate returns true, evaluated in the receive
+	/// loop before the handler, the channel of build_receiver(), the broadcast receivers and the
+	/// candles, so the others cost no delivery. E.g. only the ticks moving the price by more than
+	/// a step from the last delivered one, keeping that price in the closure.
+	/// 
+	/// The other snapshots still mark the subscriptions active, and reach on_gap(..) and
+	/// on_initial(..), but not Stream::is_active(..)
+	pub fn filter <G> ( mut self, filter: G ) -> Self
+	where
+		G: FnMut ( &Snapshot ) -> bool + Send + 'static,
+	{
+		self.hooks.filter = Some ( Box::new ( filter ) );
+		self
+	}
+
+	/// Call on_send with each text message just before it is sent to the server: the
+	/// bulk-subscribe, the UID and the heartbeats. With on_ignored_frame(..), a wire trace to
+	/// diagnose the handshake. Called from the heartbeat task as well, hence Fn and Sync.
+	pub fn on_send <G> ( mut self, on_send: G ) -> Self
+	where
+		G: Fn ( &str ) + Send + Sync + 'static,
+	{
+		self.hooks.on_send = Some ( Arc::new ( on_send ) );
+		self
+	}
+
+	/// Call on_reconnect with ReconnectEvent::Retry before each retry of a failed connect, and
+	/// with ReconnectEvent::Reconnected once a retry succeeds, as well as on each reconnect of a
+	/// connection ended mid-session, e.g. to log or alert on flapping hosts.
+	pub fn on_reconnect <G> ( mut self, on_reconnect: G ) -> Self
+	where
+		G: FnMut ( ReconnectEvent ) + Send + 'static,
+	{
+		self.hooks.on_reconnect = Some ( Box::new ( on_reconnect ) );
+		self
+	}
+
+	/// Pass the snapshots of the pid to this handler instead of the handler given to build(..),
+	/// which gets the snapshots of the other pair ids. Registering the pid again replaces its handler.
+	/// 
+	/// Like the handler of build(..), the stream stops when it returns Err(()).
+	pub fn on <F> ( mut self, pid: &str, handler: F ) -> Self
+	where
+		F: FnMut ( Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		self.pid_handlers.insert ( pid.to_string ( ), Box::new ( handler ) );
+		self
+	}
+
+	/// Queue up to capacity snapshots for Stream::next_snapshot(), then apply the policy.
+	/// Default is ChannelPolicy::Backpressure with SNAPSHOT_BUFFER. Only used by StreamBuilder::build_receiver()
+	pub fn channel ( mut self, policy: ChannelPolicy, capacity: usize ) -> Self {
+		self.channel = ( policy, capacity.max ( 1 ) );
+		self
+	}
+
+	/// Run the stream on the runtime, shared with other streams, instead of a multi-threaded
+	/// runtime of its own, e.g. to subscribe to many instruments without a thread pool each.
+	/// The runtime stops when the last stream or other owner drops it.
+	pub fn runtime ( mut self, runtime: Arc<runtime::Runtime> ) -> Self {
+		self.runtime = Some ( runtime );
+		self
+	}
+
+	/// Run the stream on a current-thread runtime of its own instead of a multi-threaded one,
+	/// e.g. for many lightweight collectors on a small box. Ignored with runtime(..)
+	/// 
+	/// The receive loop and the heartbeat then only progress while the runtime is driven, e.g.
+	/// by `stream.runtime.block_on(..)` on Stream::stream_handle_spawn or Stream::next_snapshot(),
+	/// and a blocking handler stalls both.
+	pub fn current_thread ( mut self, current_thread: bool ) -> Self {
+		self.current_thread = current_thread;
+		self
+	}
+
+	/// Number of snapshots kept for the receivers of Stream::subscribe_broadcast() before the
+	/// laggards skip the oldest ones. Default is SNAPSHOT_BUFFER.
+	pub fn broadcast_capacity ( mut self, capacity: usize ) -> Self {
+		self.broadcast_capacity = capacity.max ( 1 );
+		self
+	}
+
+	/// Number of the last snapshots of each pid kept for Stream::subscribe_replay(), replayed to
+	/// each new receiver before the live ones. Default is 0, keeping none.
+	pub fn replay_depth ( mut self, depth: usize ) -> Self {
+		self.replay_depth = depth;
+		self
+	}
+
+	/// Read the time from the clock instead of the real one, e.g. a MockClock to test
+	/// Stream::is_active(..) and Stream::uptime() without waiting. Also sets Snapshot::received_at.
+	pub fn clock ( mut self, clock: Arc<dyn Clock> ) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// How recent the last snapshot of a pid has to be for Stream::is_active(..)
+	pub fn active_window ( mut self, window: Duration ) -> Self {
+		self.active_window = window;
+		self
+	}
+
+	/// Trading hours of the pair ids, for the heuristics telling a quiet market from a feed
+	/// problem: with InstrumentClass::Sessioned, the session breaks are not gaps of on_gap(..),
+	/// Stream::healthy(..) does not expect recent snapshots over them, and the candles of
+	/// on_candle(..) are built with CandleBuilder::instrument_class(..). Default is
+	/// InstrumentClass::Continuous, e.g. for BTC/USD.
+	pub fn instrument_class ( mut self, class: InstrumentClass ) -> Self {
+		self.instrument_class = class;
+		self
+	}
+
+	/// Correction of the server clock set on each Snapshot, see Snapshot::datetime_utc() and
+	/// data::estimate_clock_offset(..) to measure it. Default is none.
+	pub fn clock_offset ( mut self, offset: chrono::Duration ) -> Self {
+		self.clock_offset = Some ( offset );
+		self
+	}
+
+	/// What to do with a missing, empty or malformed turnover_numeric, see TurnoverPolicy.
+	/// Default is TurnoverPolicy::ZeroOnMissing.
+	/// 
+	/// With TurnoverPolicy::Error such a snapshot is skipped as failing to parse, see on_parse_error(..)
+	pub fn turnover_policy ( mut self, policy: TurnoverPolicy ) -> Self {
+		self.connect_options.turnover_policy = policy;
+		self
+	}
+
+	/// Separators of the prices of the pair ids, set on each Snapshot for its numeric accessors.
+	/// Default is NumberFormat::DotDecimal, "18,954.0"
+	pub fn number_format ( mut self, number_format: NumberFormat ) -> Self {
+		self.connect_options.number_format = number_format;
+		self
+	}
+
+	/// Create connection to the server. The new data is sent to given handler in Snapshot struct.
+	pub fn build <F> ( self, handler: F ) -> Result<Stream, Error>
+	where
+		F: Fn ( Snapshot ) -> Result<(), ()> + Send + Sync + 'static,
+	{
+		self.build_boxed ( Box::new ( handler ) )
+	}
+
+	/// Same as StreamBuilder::build(..), with the handler as a trait object.
+	pub fn build_boxed ( self, handler: Handler ) -> Result<Stream, Error> {
+		self.build_mut ( handler )
+	}
+
+	/// Same as StreamBuilder::build(..), with a stateful handler, see Stream::new_mut(..)
+	pub fn build_mut <F> ( self, mut handler: F ) -> Result<Stream, Error>
+	where
+		F: FnMut ( Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		self.build_async ( move |snapshot| future::ready ( handler ( snapshot ) ) )
+	}
+
+	/// Same as StreamBuilder::build(..), with a handler deciding when it is done, see Stream::new_until(..)
+	pub fn build_until <F> ( self, mut handler: F ) -> Result<Stream, Error>
+	where
+		F: FnMut ( Snapshot ) -> ControlFlow<()> + Send + 'static,
+	{
+		self.build_mut ( move |snapshot| match handler ( snapshot ) {
+			ControlFlow::Continue ( ( ) ) => Ok ( ( ) ),
+			ControlFlow::Break ( ( ) ) => Err ( ( ) ),
+		} )
+	}
+
+	/// Same as StreamBuilder::build(..), with the frame each snapshot was parsed from, exactly as
+	/// received, e.g. to keep both for an audit trail. The pairing holds whatever the handler
+	/// concurrency, unlike a separate recording of the frames.
+	/// 
+	/// The frame is carried in Snapshot::frame up to the handler, leaving Snapshot::raw to
+	/// keep_raw(..). Snapshots of StreamBuilder::snapshot_source(..) come with their frame, if
+	/// any, else an empty frame.
+	pub fn build_raw <F> ( mut self, mut handler: F ) -> Result<Stream, Error>
+	where
+		F: FnMut ( String, Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		self.connect_options.keep_frame = true;
+		self.build_mut ( move |mut snapshot| {
+			let frame = snapshot.frame.take ( ).unwrap_or_default ( );
+			handler ( frame, snapshot )
+		} )
+	}
+
+	/// Same as StreamBuilder::build_mut(..), with the fields of each snapshot which changed since
+	/// the previous snapshot of its pid, e.g. to relay the feed onward, see SnapshotDelta. The
+	/// first snapshot of each pid comes whole. The callbacks such as on_candle(..) and the
+	/// receivers of subscribe_broadcast() still see whole snapshots.
+	pub fn build_delta <F> ( self, mut handler: F ) -> Result<Stream, Error>
+	where
+		F: FnMut ( SnapshotDelta ) -> Result<(), ()> + Send + 'static,
+	{
+		let mut encoder = DeltaEncoder::default ( );
+		self.build_mut ( move |snapshot| handler ( encoder.encode ( snapshot ) ) )
+	}
+
+	/// Same as StreamBuilder::build(..), without handler: the snapshots are only passed to the
+	/// receivers of Stream::subscribe_broadcast(), and to the
+	/// callbacks such as on(..) and on_candle(..). Nothing is queued, unlike build_receiver().
+	pub fn build_without_handler ( self ) -> Result<Stream, Error> {
+		self.build ( |_| Ok ( ( ) ) )
+	}
+
+	/// Same as StreamBuilder::build(..), without handler: read the snapshots with Stream::next_snapshot()
+	/// 
+	/// Snapshots are queued by StreamBuilder::channel(..), by default up to SNAPSHOT_BUFFER
+	/// before the stream waits until they are read. The stream stops when dropped, like with a
+	/// handler returning Err(()).
+	pub fn build_receiver ( self ) -> Result<Stream, Error> {
+		let (policy, capacity) = self.channel;
+		let (tx, rx) = queue::channel ( policy, capacity );
+		let tx = Arc::new ( tx );
+		let mut stream = self.build_async ( move |snapshot| {
+			let tx = tx.clone ( );
+			async move { tx.send ( snapshot ).await }
+		} )?;
+		stream.snapshots = Some ( rx );
+		Ok ( stream )
+	}
+
+	/// Same as StreamBuilder::build_mut(..), with the pair ids sharded across connections of up
+	/// to max_pids_per_connection pair ids each, e.g. DEFAULT_MAX_PIDS_PER_CONNECTION, see StreamPool.
+	/// 
+	/// The connections take the connect options of the builder and the runtime of
+	/// StreamBuilder::runtime(..) if any, as well as number_format(..), clock_offset(..), utc_offset(..)
+	/// and cancellation_token(..). The callbacks, such as on_gap(..) or on_channel(..), and the other
+	/// options driving the snapshots of a Stream fail with Err(Error::UnsupportedOption(..)).
+	pub fn build_pool <F> ( self, max_pids_per_connection: usize, handler: F ) -> Result<StreamPool, Error>
+	where
+		F: FnMut ( Snapshot ) -> Result<(), ()> + Send + 'static,
+	{
+		let handler = Arc::new ( Mutex::new ( handler ) );
+		self.start_pool ( max_pids_per_connection, None, Arc::new ( move || {
+			let handler = handler.clone ( );
+			Box::new ( move |snapshot| future::ready ( handler.lock ( ).unwrap ( ) ( snapshot ) ).boxed ( ) )
+		} ) )
+	}
+
+	/// Same as StreamBuilder::build_pool(..), without handler: read the snapshots of all the
+	/// connections with StreamPool::next_snapshot(), queued up to SNAPSHOT_BUFFER before the
+	/// connections wait until they are read.
+	pub fn build_pool_receiver ( self, max_pids_per_connection: usize ) -> Result<StreamPool, Error> {
+		let (tx, rx) = mpsc::channel ( SNAPSHOT_BUFFER );
+		self.start_pool ( max_pids_per_connection, Some ( rx ), Arc::new ( move || {
+			let tx = tx.clone ( );
+			Box::new ( move |snapshot| {
+				let tx = tx.clone ( );
+				async move { tx.send ( snapshot ).await.map_err ( |_| ( ) ) }.boxed ( )
+			} )
+		} ) )
+	}
+
+	fn start_pool (
+		self,
+		max_pids_per_connection: usize,
+		snapshots: Option<mpsc::Receiver<Snapshot>>,
+		make_handler: MakeHandler,
+	) -> Result<StreamPool, Error> {
+		self.validate ( )?;
+		if let Some ( option ) = self.pool_unsupported ( ) {
+			return Err ( Error::UnsupportedOption ( option ) );
+		}
+		let pids: Vec<String> = self.pair_id.split ( ',' ).map ( String::from ).collect ( );
+		let (number_format, clock_offset, utc_offset) = ( self.connect_options.number_format, self.clock_offset, self.utc_offset );
+		let runtime = self.runtime.unwrap_or_else ( || Arc::new ( runtime::Runtime::new ( ).unwrap ( ) ) );
+		let make_handler: MakeHandler = Arc::new ( move || {
+			let mut handler = make_handler ( );
+			Box::new ( move |mut snapshot: Snapshot| {
+				snapshot.number_format = number_format;
+				snapshot.clock_offset = clock_offset;
+				snapshot.utc_offset = utc_offset;
+				handler ( snapshot )
+			} )
+		} );
+		let cancellation_token = self.cancellation_token.map_or_else ( CancellationToken::new, |token| token.child_token ( ) );
+		Ok ( StreamPool::start ( &pids, max_pids_per_connection, self.connect_options, runtime, make_handler, snapshots, cancellation_token ) )
+	}
+
+	/// First option set on the builder which the connections of StreamPool cannot honor.
+	fn pool_unsupported ( &self ) -> Option<&'static str> {
+		let hooks = &self.hooks;
+		[
+			( "on_gap", hooks.gap_handler.is_some ( ) ),
+			( "on_freeze", hooks.freeze_handler.is_some ( ) ),
+			( "on_ignored_frame", hooks.on_ignored_frame.is_some ( ) ),
+			( "on_event", hooks.on_event.is_some ( ) ),
+			( "on_channel", !hooks.channel_handlers.is_empty ( ) ),
+			( "on_parse_error", hooks.on_parse_error.is_some ( ) ),
+			( "on_reconnect", hooks.on_reconnect.is_some ( ) ),
+			( "on_send", hooks.on_send.is_some ( ) ),
+			( "on_initial", hooks.on_initial.is_some ( ) ),
+			( "filter", hooks.filter.is_some ( ) ),
+			( "on", !self.pid_handlers.is_empty ( ) ),
+			( "on_candle", self.candle_handler.is_some ( ) ),
+			( "emit_jsonl", self.jsonl_writer.is_some ( ) ),
+			( "coalesce", self.coalesce.is_some ( ) ),
+			( "snapshot_source", self.source.is_some ( ) ),
+			( "max_lifetime", self.max_lifetime.is_some ( ) ),
+		].iter ( ).find ( |( _, set )| *set ).map ( |( option, _ )| *option )
+	}
+
+	/// Options of the builder which can be written down, with the defaults in place of None
+	/// where None is not the default itself, see Stream::config()
+	fn effective_config ( &self ) -> StreamConfig {
+		let millis = |duration: Duration| duration.as_millis ( ) as u64;
+		let options = &self.connect_options;
+		StreamConfig {
+			pids: self.pair_id.split ( ',' ).map ( |pid| pid.trim ( ).to_string ( ) ).collect ( ),
+			url: options.url.clone ( ),
+			domain: Some ( options.domain.clone ( ) ),
+			path_template: Some ( options.path_template.clone ( ) ),
+			preferred_hosts: options.preferred_hosts.clone ( ),
+			uid: Some ( options.uid ),
+			tz_id: Some ( options.tz_id ),
+			utc_offset_secs: self.utc_offset.map ( |offset| offset.local_minus_utc ( ) ),
+			connect_timeout_ms: Some ( millis ( options.timeout ) ),
+			max_connect_attempts: Some ( options.max_attempts ),
+			retry_delay_ms: Some ( millis ( options.retry_delay ) ),
+			retry_jitter_ms: Some ( millis ( options.retry_jitter ) ),
+			host_cooldown_ms: Some ( millis ( options.host_cooldown ) ),
+			ping_interval_ms: options.ping_interval.map ( millis ),
+			first_data_timeout_ms: options.first_data_timeout.map ( millis ),
+			session_max_age_ms: options.session_max_age.map ( millis ),
+			max_lifetime_ms: self.max_lifetime.map ( millis ),
+			subscribe_batch_size: Some ( options.subscribe_batch_size ),
+			subscribe_batch_delay_ms: Some ( millis ( options.subscribe_batch_delay ) ),
+			handler_concurrency: Some ( self.handler_concurrency ),
+			coalesce_ms: self.coalesce.map ( millis ),
+			active_window_ms: Some ( millis ( self.active_window ) ),
+			replay_depth: Some ( self.replay_depth ),
+			number_format: Some ( options.number_format ),
+			instrument_class: Some ( self.instrument_class ),
+			turnover_policy: Some ( options.turnover_policy ),
+			keep_raw: options.keep_raw,
+			field_stats: options.field_stats,
+			lossy_utf8: options.lossy_utf8,
+			numeric_pair_ids: self.numeric_pair_ids,
+			current_thread: self.current_thread,
+		}
+	}
+
+	/// Checks the pair ids and the domain, see StreamConfig::validate()
+	fn validate ( &self ) -> Result<(), Error> {
+		let invalid = |pid: &str| {
+			let pid = pid.trim ( );
+			pid.is_empty ( ) || ( self.numeric_pair_ids && !pid.bytes ( ).all ( |b| b.is_ascii_digit ( ) ) )
+		};
+		if self.pair_id.split ( ',' ).any ( invalid ) {
+			return Err ( Error::InvalidPairId ( self.pair_id.clone ( ) ) );
+		}
+		if self.connect_options.domain.is_empty ( ) {
+			return Err ( Error::EmptyDomain );
+		}
+		Ok ( ( ) )
+	}
+
+	/// Same as StreamBuilder::build(..), with a handler returning a future, e.g. to write the
+	/// snapshot to a database. The stream stops when a future resolves Err(()).
+	/// 
+	/// Up to StreamBuilder::handler_concurrency(..) futures run at the same time, see there for the order.
+	pub fn build_async <F, Fut> ( self, mut handler: F ) -> Result<Stream, Error>
 	where
-		F: Fn ( Snapshot ) -> Result<(), ()> + Send + Sync + 'static,
+		F: FnMut ( Snapshot ) -> Fut + Send + 'static,
+		Fut: Future<Output = Result<(), ()>> + Send + 'static,
 	{
-		let pair_id_str = pair_id.clone ( ).into_boxed_str ( );
+		self.validate ( )?;
 
-		let pair_msg = prepare_pair_msg(pair_id.clone());
-		// println!("using pairs str: {}", pair_msg);
+		let config = self.effective_config ( );
+		let handler_concurrency = self.handler_concurrency;
+		let mut hooks = self.hooks;
+		let instrument_class = self.instrument_class;
+		hooks.gap_handler = hooks.gap_handler
+			.map ( |( detector, on_gap )| ( detector.instrument_class ( instrument_class ), on_gap ) );
+		let mut candle_handler = self.candle_handler;
+		if let Some ( candles ) = &mut candle_handler {
+			candles.builder = candles.builder.clone ( )
+				.instrument_class ( instrument_class )
+				.forward_fill ( self.forward_fill_candles );
+		}
+		let number_format = self.connect_options.number_format;
+		let clock_offset = self.clock_offset;
+		let utc_offset = self.utc_offset;
+		let mut pid_handlers = self.pid_handlers;
+		let mut jsonl_writer = self.jsonl_writer;
+		let broadcast = Arc::new ( broadcast::channel ( self.broadcast_capacity ).0 );
+		let broadcast_sender = broadcast.clone ( );
+		let history = Some ( self.replay_depth ).filter ( |depth| *depth > 0 ).map ( |depth| Arc::new ( History::new ( depth ) ) );
+		let history_sender = history.clone ( );
+		let latest = Arc::new ( Mutex::new ( HashMap::new ( ) ) );
+		let latest_sender = latest.clone ( );
+		let clock = self.clock.clone ( );
+		let handler_slot = Arc::new ( HandlerSlot::default ( ) );
+		let handler_slot_receiver = handler_slot.clone ( );
+		let mut replaced_handler = None;
+		let deliver = move |snapshot: Snapshot| {
+			if let Some ( handler ) = handler_slot_receiver.take ( ) {
+				replaced_handler = Some ( handler );
+			}
+			match ( pid_handlers.get_mut ( &snapshot.pid ), &mut replaced_handler ) {
+				( Some ( pid_handler ), _ ) => future::Either::Left ( future::ready ( pid_handler ( snapshot ) ) ),
+				( None, Some ( replaced_handler ) ) => future::Either::Left ( future::ready ( replaced_handler ( snapshot ) ) ),
+				( None, None ) => future::Either::Right ( handler ( snapshot ) ),
+			}
+		};
+		// with coalesce(..), the snapshots wait for the flushes of the task instead
+		let coalescer = self.coalesce.map ( |_| Arc::new ( Coalescer::default ( ) ) );
+		let coalescer_sender = coalescer.clone ( );
+		let (mut deliver, mut flushed) = match ( self.coalesce, coalescer ) {
+			( Some ( interval ), Some ( coalescer ) ) => ( None, Some ( ( interval, coalescer, deliver ) ) ),
+			_ => ( Some ( deliver ), None ),
+		};
+		let mut handler = move |mut snapshot: Snapshot| {
+			snapshot.number_format = number_format;
+			snapshot.clock_offset = clock_offset;
+			snapshot.utc_offset = utc_offset;
+			// one copy for the latest snapshots and the broadcast receivers, under a single lock
+			let shared_snapshot = Arc::new ( snapshot.clone ( ) );
+			latest_sender.lock ( ).unwrap ( ).insert ( snapshot.pid.clone ( ), ( clock.now ( ), shared_snapshot.clone ( ) ) );
+			let publish = || {
+				if broadcast_sender.receiver_count ( ) > 0 {
+					// fails only when the receivers are gone meanwhile
+					let _ = broadcast_sender.send ( shared_snapshot.clone ( ) );
+				}
+			};
+			match &history_sender {
+				Some ( history ) => history.push ( &shared_snapshot, publish ),
+				None => publish ( ),
+			}
+			if let Some ( candles ) = &mut candle_handler {
+				for candle in candles.builder.update_all ( &snapshot ) {
+					( candles.on_candle ) ( candle );
+				}
+			}
+			if let Some ( jsonl_writer ) = &mut jsonl_writer {
+				jsonl_writer.write ( &snapshot );
+			}
+			match &mut deliver {
+				Some ( deliver ) => future::Either::Right ( deliver ( snapshot ) ),
+				None => {
+					if let Some ( coalescer ) = &coalescer_sender {
+						coalescer.push ( snapshot );
+					}
+					future::Either::Left ( future::ready ( Ok ( ( ) ) ) )
+				}
+			}
+		};
+		let pair_id = self.pair_id;
+		let pair_id_str = pair_id.clone ( ).into_boxed_str ( );
+		let connect_options = self.connect_options;
+		// a child, so Stream::shutdown_timeout(..) leaves the token of the caller alone
+		let cancellation_token = self.cancellation_token.unwrap_or_default ( ).child_token ( );
+		let stream_cancellation_token = cancellation_token.clone ( );
+		let max_lifetime = self.max_lifetime;
+		let mut source = self.source;
+		let (abort_handle, abort_registration) = AbortHandle::new_pair ( );
+		let status = Status { clock: self.clock.clone ( ), ..Status::default ( ) };
+		let state = status.state.subscribe ( );
+		let uid = status.uid.subscribe ( );
+		let subscriptions = status.subscriptions.subscribe ( );
+		let subscriptions_sender = Arc::downgrade ( &status.subscriptions );
+		let outgoing = status.outgoing.clone ( );
+		let subscribe_batch_size = connect_options.subscribe_batch_size;
+		let tz_id = connect_options.tz_id;
+		let pid_prefix = connect_options.frame_keys.markers ( ).pid_prefix.to_string ( );
+		let stats = status.stats.subscribe ( );
+		let field_stats = status.field_stats.clone ( );
+		let connected_at = status.connected_at.subscribe ( );
+		let last_activity = status.last_activity.subscribe ( );
+		let url = status.url.subscribe ( );
+		let disconnects = status.disconnects.subscribe ( );
 
 		// https://stackoverflow.com/questions/61752896/how-to-create-a-dedicated-threadpool-for-cpu-intensive-work-in-tokio
-		let rt_main = runtime::Runtime::new ( ).unwrap ( );
-		let rt_heartbeat = rt_main
-			.handle ( ).clone ( );
+		let current_thread = self.current_thread;
+		let own_runtime = self.runtime.is_none ( );
+		let rt_main = self.runtime.unwrap_or_else ( || {
+			let runtime = if current_thread {
+				runtime::Builder::new_current_thread ( ).enable_all ( ).build ( )
+			} else {
+				runtime::Runtime::new ( )
+			};
+			Arc::new ( runtime.unwrap ( ) )
+		} );
 
-		let stream = Stream {
-			stream_handle_spawn: rt_main
-			.spawn ( async {
-				let url = generate_stream_url ( );
-				log::info!("URL: {}", url);
-				tokio_tungstenite::connect_async (
-					&url
-				)
-				.then ( |stream_response| async move {
-					stream_response.expect ( "Failed to get tokio_tungstenite::connect_async(..)" )
-				} )
-				.then ( |(mut stream, _response)| async move {
-					if stream.next ( ).await.unwrap ( ).unwrap ( ).to_text ( ).unwrap ( ) == "o" {
-						Ok ( stream.split ( ) )
-					} else {
-						Err ( () )
+		let task = async move {
+			let client = async {
+				let client = async {
+					loop {
+						let result = match &mut source {
+							Some ( source ) => run_source ( source, &pair_id, handler_concurrency, &mut hooks, &status, &mut handler ).await,
+							None => run ( &pair_id, &connect_options, handler_concurrency, &mut hooks, &cancellation_token, &status, &mut handler ).await,
+						};
+						status.disconnected ( match &result {
+							Ok ( Exit::Expired ) => DisconnectReason::Expired,
+							Ok ( Exit::Closed ) => DisconnectReason::Closed,
+							Ok ( Exit::Stopped ) => DisconnectReason::Stopped,
+							Err ( e ) => DisconnectReason::Failed ( e.to_string ( ) ),
+						} );
+						match result {
+							Ok ( Exit::Expired ) => log::info! ( "Session expired, reconnecting" ),
+							result => break result,
+						}
+					}
+				};
+
+				let lifetime = async {
+					match max_lifetime {
+						Some ( lifetime ) => time::sleep ( lifetime ).await,
+						None => future::pending ( ).await,
 					}
-				} )
-				.and_then ( |(mut tx, mut rx)| async move {
-					// TODO: react to the server
-					tx.send ( format ! ( "[\"{{\\\"_event\\\":\\\"bulk-subscribe\\\",\\\"tzID\\\":\\\"8\\\",\\\"message\\\":\\\"{}\\\"}}\"]", &pair_msg ).into ( ) )
-						.await
-						.expect ( "Expect tx.send(bulk-subscribe, tzID, pid) to server" )
-						;
-					tx.send ( "[\"{\\\"_event\\\":\\\"UID\\\",\\\"UID\\\":0}\"]".into ( ) )
-						.await
-						.expect ( "Expect tx.send(UID=0) to server" )
-						;
-					
-					// send heartbeat responses to server
-					rt_heartbeat
-						.spawn ( async move {
+				};
+
+				// taken from the coalescer but not delivered yet, kept when another branch wins
+				let mut in_flight = VecDeque::new ( );
+				// resolves when the handler stops the stream
+				let flush = async {
+					match &mut flushed {
+						Some ( ( interval, coalescer, deliver ) ) => {
+							let mut flushes = time::interval_at ( time::Instant::now ( ) + *interval, *interval );
 							loop {
-								tx.send ( "[\"{\\\"_event\\\":\\\"heartbeat\\\",\\\"data\\\":\\\"h\\\"}\"]".into ( ) )
-									.await
-									.expect ( "Expect tx.send(heartbeat) to server" )
-									;
-								time::sleep ( Duration::from_millis ( 3200u64 ) ).await;
-							}
-						} );
-					
-					let key = "\"message\\\":\\\"pid-".to_string();
-					let key = key.as_str ( );
-					
-					while let Some ( msg ) = rx.next ( ).await {
-						let msg = msg.unwrap ( );
-						let msg = msg.to_text ( ).unwrap ( );
-						// println!("got msg {}", msg);
-						if msg.contains ( key ) {
-							let stop = handler (
-								Snapshot::from_str (
-									msg
-								)
-							);
-							
-							if let Err ( _ ) = stop {
-								return Ok(());
+								flushes.tick ( ).await;
+								in_flight.extend ( coalescer.take ( ) );
+								while let Some ( snapshot ) = in_flight.pop_front ( ) {
+									if deliver ( snapshot ).await.is_err ( ) {
+										return;
+									}
+								}
 							}
 						}
+						None => future::pending ( ).await,
 					}
+				};
+				let mut stopped = false;
 
-					/*
-					how to handle the panic in WebSocketStream :: !UnwindSafe
-					//rx.for_each_concurrent (  2, |msg| async {
-					rx.for_each ( |msg| async {
-						let msg = msg.unwrap ( );
-						let msg = msg.to_text ( ).unwrap ( );
-						if msg.contains ( key ) {
-							let stop = handler (
-								Snapshot::from_str (
-									msg
-								)
-							);
-							
-							if stop == true {
-								panic ! ( );
-							}
+				// cancellation stops connecting as well as the receive loop, so does the lifetime
+				let result = tokio::select! {
+					_ = cancellation_token.cancelled ( ) => {
+						status.disconnected ( DisconnectReason::Cancelled );
+						Ok ( ( ) )
+					},
+					_ = lifetime => {
+						log::info! ( "Stream reached its lifetime of {:?}", max_lifetime.unwrap_or_default ( ) );
+						status.disconnected ( DisconnectReason::Lifetime );
+						Ok ( ( ) )
+					},
+					_ = flush => {
+						stopped = true;
+						status.disconnected ( DisconnectReason::Stopped );
+						Ok ( ( ) )
+					},
+					result = client => result.map ( |_| ( ) ),
+				};
+
+				// the last flush, so nothing pending is lost
+				if let ( Some ( ( _, coalescer, deliver ) ), false ) = ( &mut flushed, stopped ) {
+					in_flight.extend ( coalescer.take ( ) );
+					for snapshot in in_flight {
+						if deliver ( snapshot ).await.is_err ( ) {
+							break;
 						}
-					} )
-					.await;
-					*/
-							
-					println ! ( "EOD" );
-					Ok ( ( ) )
-				} )
-				.or_else ( |e| async move {
-					println ! ( "Failed: {:?}", e );
-					Err ( e )
-				} )
+					}
+				}
+
+				if let Err ( e ) = &result {
+					log::error! ( "Failed: {}", e );
+				}
+				result
+			};
+
+			let result = Abortable::new ( client, abort_registration )
 				.await
-			} ),
+				.unwrap_or ( Err ( Error::Aborted ) );
+			status.state.send_replace ( ConnectionState::Closed );
+			result
+		};
+
+		let stop_on_drop = StopOnDrop {
+			cancellation_token: stream_cancellation_token.clone ( ),
+			abort_handle: abort_handle.clone ( ),
+			state: state.clone ( ),
+			runtime: if own_runtime { Some ( rt_main.clone ( ) ) } else { None },
+		};
+		let stream = Stream {
+			_stop_on_drop: stop_on_drop,
+			stream_handle_spawn: rt_main.spawn ( task ),
 			runtime: rt_main,	// keep this runtime in the same or outer scope of the spawn
 			pair_id: pair_id_str,
+			abort_handle,
+			cancellation_token: stream_cancellation_token,
+			state,
+			uid,
+			subscriptions,
+			subscriptions_sender,
+			outgoing,
+			subscribe_batch_size,
+			tz_id,
+			pid_prefix,
+			utc_offset,
+			stats,
+			field_stats,
+			connected_at,
+			last_activity,
+			url,
+			disconnects,
+			snapshots: None,
+			broadcast: Arc::downgrade ( &broadcast ),
+			history,
+			latest,
+			active_window: self.active_window,
+			instrument_class,
+			clock: self.clock,
+			handler_slot,
+			config,
 		};
 		
 		Ok ( stream )
 	}
+
+	/// Same as fetch_snapshot(..), with the options of the builder: the first snapshot of any of
+	/// the pair ids, within the timeout after subscribing. The handler options are not used.
+	pub async fn fetch_snapshot ( self, timeout: Duration ) -> Result<Snapshot, Error> {
+		let mut snapshots = self.collect ( 1, timeout ).await?;
+		Ok ( snapshots.remove ( 0 ) )
+	}
+
+	/// Connects as the stream would, and disconnects without streaming, e.g. for a smoke test of
+	/// a deployment. With `handshake`, sends the connect messages as well, and waits for the UID
+	/// reply within the connect timeout. Fails as connecting the stream fails.
+	pub async fn probe ( self, handshake: bool ) -> Result<ProbeReport, Error> {
+		let pair_id = self.pair_id;
+		let connect_options = self.connect_options;
+		let status = Status { clock: self.clock, ..Status::default ( ) };
+		let mut attempts = 0;
+		let mut preferred = 0;
+		let mut last = ( String::new ( ), None, Instant::now ( ) );
+		let next_url = || {
+			let (url, server) = connect_options.next_url ( &mut preferred, &status.failed_hosts );
+			attempts += 1;
+			last = ( url.clone ( ), server, Instant::now ( ) );
+			( url, server )
+		};
+		let (stream, trace, early) = connect_any ( next_url, &connect_options, &status, &mut None ).await?;
+		let (mut tx, rx) = stream.split ( );
+		let mut rx = stream::iter ( early.map ( Ok ) ).chain ( rx );
+		let (url, server, started) = last;
+		let latency = started.elapsed ( );
+
+		let mut result = Ok ( None );
+		if handshake {
+			let reply = async {
+				send_connect_messages ( &mut tx, &connect_options, &pair_id, &None, &trace ).await?;
+				while let Some ( msg ) = rx.next ( ).await {
+					let msg = msg.map_err ( Error::receive )?;
+					filter_frame ( msg, &status, &connect_options, &mut None, &mut None, &mut Vec::new ( ), &mut None )?;
+					if let Some ( uid ) = *status.uid.borrow ( ) {
+						return Ok ( Some ( uid ) );
+					}
+				}
+				Ok ( None )
+			};
+			result = time::timeout ( connect_options.timeout, reply )
+				.await
+				.unwrap_or ( Ok ( None ) );
+		}
+
+		let _ = tx.close ( ).await;
+		Ok ( ProbeReport { url, server, attempts, latency, uid: result? } )
+	}
+
+	/// Same as collect(..), with the options of the builder: the first `n` snapshots of any of
+	/// the pair ids, within the timeout after subscribing. The handler options are not used.
+	pub async fn collect ( self, n: usize, timeout: Duration ) -> Result<Vec<Snapshot>, Error> {
+		if n == 0 {
+			return Ok ( Vec::new ( ) );
+		}
+		let pair_id = self.pair_id;
+		let clock_offset = self.clock_offset;
+		let utc_offset = self.utc_offset;
+		let connect_options = self.connect_options;
+		let status = Status { clock: self.clock, ..Status::default ( ) };
+		let mut preferred = 0;
+		let next_url = || connect_options.next_url ( &mut preferred, &status.failed_hosts );
+		let (stream, handshake, early) = connect_any ( next_url, &connect_options, &status, &mut None ).await?;
+		let (mut tx, rx) = stream.split ( );
+		let mut rx = stream::iter ( early.map ( Ok ) ).chain ( rx );
+
+		send_connect_messages ( &mut tx, &connect_options, &pair_id, &None, &handshake ).await?;
+		status.subscriptions.send_modify ( |subscriptions| {
+			for pid in pair_id.split ( ',' ) {
+				subscriptions.insert ( pid.to_string ( ), SubscriptionStatus::Pending );
+			}
+		} );
+
+		let mut snapshots = Vec::with_capacity ( n );
+		let gather = async {
+			while let Some ( msg ) = rx.next ( ).await {
+				let msg = msg.map_err ( Error::receive )?;
+				for mut snapshot in filter_frame ( msg, &status, &connect_options, &mut None, &mut None, &mut Vec::new ( ), &mut None )? {
+					snapshot.clock_offset = clock_offset;
+					snapshot.utc_offset = utc_offset;
+					snapshots.push ( snapshot );
+					if snapshots.len ( ) == n {
+						return Ok ( ( ) );
+					}
+				}
+			}
+			Ok ( ( ) )
+		};
+		let result = time::timeout ( timeout, gather )
+			.await
+			.unwrap_or ( Ok ( ( ) ) );
+
+		let _ = tx.close ( ).await;
+		result?;
+		if snapshots.is_empty ( ) {
+			return Err ( Error::NoSnapshot );
+		}
+		Ok ( snapshots )
+	}
+}
+
+/// Senders of what run(..) observes, received through Stream
+pub(crate) struct Status {
+	pub(crate) state: watch::Sender<ConnectionState>,
+	pub(crate) uid: watch::Sender<Option<u64>>,
+	pub(crate) subscriptions: Arc<watch::Sender<HashMap<String, SubscriptionStatus>>>,
+	/// Messages to send over the current connection, see Stream::resubscribe(..)
+	pub(crate) outgoing: mpsc::UnboundedSender<String>,
+	pub(crate) outgoing_receiver: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<String>>>,
+	pub(crate) stats: watch::Sender<StreamStats>,
+	/// Snapshots missing each field per pair id, see StreamBuilder::field_stats(..)
+	pub(crate) field_stats: Arc<Mutex<HashMap<String, FieldStats>>>,
+	pub(crate) connected_at: watch::Sender<Option<Instant>>,
+	pub(crate) last_activity: watch::Sender<Option<Instant>>,
+	/// URL of the current or last connection, with its generated server if any
+	pub(crate) url: watch::Sender<Option<(String, Option<u8>)>>,
+	/// Snapshot::seq of the next snapshot
+	pub(crate) next_seq: AtomicU64,
+	pub(crate) disconnects: watch::Sender<Vec<DisconnectEvent>>,
+	pub(crate) clock: Arc<dyn Clock>,
+	/// Preferred hosts cooling down, see StreamBuilder::preferred_hosts(..)
+	pub(crate) failed_hosts: FailedHosts,
+}
+
+impl Status {
+	/// Counts in the field stats of the pid, allocating its entry only for its first frame.
+	fn record_fields ( &self, pid: &str, record: impl FnOnce ( &mut FieldStats ) ) {
+		let mut field_stats = self.field_stats.lock ( ).unwrap ( );
+		match field_stats.get_mut ( pid ) {
+			Some ( stats ) => record ( stats ),
+			None => record ( field_stats.entry ( pid.to_string ( ) ).or_default ( ) ),
+		}
+	}
+
+	/// Records the end of the connection in the history, if one is up.
+	pub(crate) fn disconnected ( &self, reason: DisconnectReason ) {
+		if let Some ( connected_at ) = self.connected_at.send_replace ( None ) {
+			let uptime = self.clock.now ( ).saturating_duration_since ( connected_at );
+			let server = self.url.borrow ( ).as_ref ( ).and_then ( |( _, server )| *server );
+			let event = DisconnectEvent { at: self.clock.system_time ( ), uptime, server, reason };
+			self.disconnects.send_modify ( |disconnects| {
+				if disconnects.len ( ) == DISCONNECT_HISTORY {
+					disconnects.remove ( 0 );
+				}
+				disconnects.push ( event );
+			} );
+		}
+	}
+}
+
+impl Default for Status {
+	fn default ( ) -> Self {
+		let (outgoing, outgoing_receiver) = mpsc::unbounded_channel ( );
+		Status {
+			state: watch::channel ( ConnectionState::Connecting ).0,
+			uid: watch::channel ( None ).0,
+			subscriptions: Arc::new ( watch::channel ( HashMap::new ( ) ).0 ),
+			outgoing,
+			outgoing_receiver: Arc::new ( tokio::sync::Mutex::new ( outgoing_receiver ) ),
+			stats: watch::channel ( StreamStats::default ( ) ).0,
+			field_stats: Arc::new ( Mutex::new ( HashMap::new ( ) ) ),
+			connected_at: watch::channel ( None ).0,
+			last_activity: watch::channel ( None ).0,
+			url: watch::channel ( None ).0,
+			next_seq: AtomicU64::new ( 0 ),
+			disconnects: watch::channel ( Vec::new ( ) ).0,
+			clock: Arc::new ( SystemClock ),
+			failed_hosts: Mutex::new ( BTreeMap::new ( ) ),
+		}
+	}
+}
+
+/// How run(..) ended without error
+pub(crate) enum Exit {
+	/// The handler returned Err(()) to stop the stream.
+	Stopped,
+
+	/// The server closed the connection.
+	Closed,
+
+	/// The connection reached ConnectOptions::session_max_age, to be replaced by a new one.
+	Expired,
+}
+
+/// Connect, subscribe to the pair ids and pass the snapshots to the handler until the handler
+/// stops or the connection ends. The heartbeat runs on the current runtime until then.
+pub(crate) async fn run <F, Fut> (
+	pair_id: &str,
+	connect_options: &ConnectOptions,
+	handler_concurrency: usize,
+	hooks: &mut Hooks,
+	cancellation_token: &CancellationToken,
+	status: &Status,
+	handler: F,
+) -> Result<Exit, Error>
+where
+	F: FnMut ( Snapshot ) -> Fut,
+	Fut: Future<Output = Result<(), ()>>,
+{
+	let mut url = ( String::new ( ), None );
+	let mut preferred = 0;
+	let next_url = || {
+		url = connect_options.next_url ( &mut preferred, &status.failed_hosts );
+		url.clone ( )
+	};
+	let (stream, handshake, early) = connect_any ( next_url, connect_options, status, &mut hooks.on_reconnect ).await?;
+	let (mut tx, rx) = stream.split ( );
+	let rx = stream::iter ( early.map ( Ok ) ).chain ( rx );
+	status.url.send_replace ( Some ( url ) );
+	status.state.send_replace ( ConnectionState::Connected );
+	status.connected_at.send_replace ( Some ( status.clock.now ( ) ) );
+	let _connected = metrics::Connected::new ( );
+
+	// the pair ids added by Stream::subscribe(..) as well, after those of the builder
+	let added: Vec<String> = {
+		let initial: HashSet<&str> = pair_id.split ( ',' ).collect ( );
+		let mut added: Vec<_> = status.subscriptions.borrow ( ).keys ( )
+			.filter ( |pid| !initial.contains ( pid.as_str ( ) ) )
+			.cloned ( )
+			.collect ( );
+		added.sort ( );
+		added
+	};
+	let pair_id = std::iter::once ( pair_id.to_string ( ) ).chain ( added ).collect::<Vec<_>> ( ).join ( "," );
+
+	// TODO: react to the server
+	send_connect_messages ( &mut tx, connect_options, &pair_id, &hooks.on_send, &handshake ).await?;
+	status.subscriptions.send_modify ( |subscriptions| {
+		for pid in pair_id.split ( ',' ) {
+			subscriptions.entry ( pid.to_string ( ) ).or_insert ( SubscriptionStatus::Pending );
+		}
+	} );
+	
+	// send heartbeat responses to server until the receive loop is done
+	let cancellation_token_heartbeat = cancellation_token.child_token ( );
+	let _heartbeat_guard = CancelOnDrop ( cancellation_token_heartbeat.clone ( ) );
+	let mut ping = connect_options.ping_interval
+		.map ( |interval| time::interval_at ( time::Instant::now ( ) + interval, interval ) );
+	let on_send = hooks.on_send.clone ( );
+	let outgoing = status.outgoing_receiver.clone ( );
+	let heartbeat_task = tokio::spawn ( async move {
+		let send = async {
+			// the connect messages subscribed to all the pair ids, drop the earlier resubscribes
+			let mut outgoing = outgoing.lock ( ).await;
+			while outgoing.try_recv ( ).is_ok ( ) {}
+			send_heartbeats ( &mut tx, &mut ping, &mut outgoing, &on_send ).await
+		};
+		// cancelled also while a send is pending on a wedged socket
+		let result = tokio::select! {
+			_ = cancellation_token_heartbeat.cancelled ( ) => Ok ( ( ) ),
+			result = send => result,
+		};
+		if result.is_ok ( ) && time::timeout ( CLOSE_TIMEOUT, tx.close ( ) ).await.is_err ( ) {
+			log::warn! ( "Failed to close the connection within {:?}", CLOSE_TIMEOUT );
+		}
+		result
+	} );
+	// a failed heartbeat ends the stream with its error, e.g. for the restart of Manager
+	let heartbeat_failed = async {
+		match heartbeat_task.await {
+			Ok ( Err ( e ) ) => e,
+			_ => future::pending ( ).await,
+		}
+	};
+	
+	let Hooks { gap_handler, freeze_handler, on_ignored_frame, on_event, channel_handlers, on_parse_error, on_initial, filter, .. } = hooks;
+	// the first frame of any kind ends the trace of the handshake
+	let mut handshake = Some ( handshake );
+	let snapshots = rx
+		.inspect ( move |_| if let Some ( handshake ) = handshake.take ( ) { handshake.first_frame ( ) } )
+		.map_err ( |e| Some ( Error::receive ( e ) ) )
+		.and_then ( |msg| future::ready ( filter_frame ( msg, status, connect_options, on_ignored_frame, on_event, channel_handlers, on_parse_error ).map_err ( Some ) ) )
+		.map_ok ( |snapshots| stream::iter ( snapshots.into_iter ( ).map ( Ok ) ) )
+		.try_flatten ( );
+	let snapshot_hooks = SnapshotHooks { gap_handler, freeze_handler, on_initial, filter };
+	let received = handle_snapshots ( snapshots, handler_concurrency, status, snapshot_hooks, handler );
+
+	// mark the pair ids without data once the first data timeout elapses
+	let first_data = async {
+		if let Some ( timeout ) = connect_options.first_data_timeout {
+			time::sleep ( timeout ).await;
+			status.subscriptions.send_modify ( |subscriptions| {
+				for ( pid, subscription ) in subscriptions.iter_mut ( ) {
+					if *subscription == SubscriptionStatus::Pending {
+						log::warn! ( "No data for pair id {} within {:?}", pid, timeout );
+						*subscription = SubscriptionStatus::NoData;
+					}
+				}
+			} );
+		}
+		future::pending::<()> ( ).await
+	};
+	// rotate the session before the server recycles it
+	let session_expired = async {
+		match connect_options.session_max_age {
+			Some ( age ) => time::sleep ( age ).await,
+			None => future::pending ( ).await,
+		}
+	};
+	let received = tokio::select! {
+		received = received => received,
+		e = heartbeat_failed => Err ( Some ( e ) ),
+		_ = session_expired => return Ok ( Exit::Expired ),
+		_ = first_data => unreachable! ( ),
+	};
+
+	match received {
+		Err ( None ) => return Ok ( Exit::Stopped ),
+		Err ( Some ( e ) ) => return Err ( e ),
+		Ok ( ( ) ) => {}
+	}
+
+	/*
+	how to handle the panic in WebSocketStream :: !UnwindSafe
+	//rx.for_each_concurrent (  2, |msg| async {
+	rx.for_each ( |msg| async {
+		let msg = msg.unwrap ( );
+		let msg = msg.to_text ( ).unwrap ( );
+		if msg.contains ( key ) {
+			let stop = handler (
+				Snapshot::from_str (
+					msg
+				)
+			);
+			
+			if stop == true {
+				panic ! ( );
+			}
+		}
+	} )
+	.await;
+	*/
+			
+	log::debug! ( "Connection closed" );
+	Ok ( Exit::Closed )
+}
+
+/// Same as run(..) with the snapshots of StreamBuilder::snapshot_source(..) in place of the
+/// server, until the senders are dropped or the handler stops the stream.
+async fn run_source <F, Fut> (
+	source: &mut mpsc::Receiver<Snapshot>,
+	pair_id: &str,
+	handler_concurrency: usize,
+	hooks: &mut Hooks,
+	status: &Status,
+	handler: F,
+) -> Result<Exit, Error>
+where
+	F: FnMut ( Snapshot ) -> Fut,
+	Fut: Future<Output = Result<(), ()>>,
+{
+	status.state.send_replace ( ConnectionState::Connected );
+	status.connected_at.send_replace ( Some ( status.clock.now ( ) ) );
+	status.subscriptions.send_modify ( |subscriptions| {
+		for pid in pair_id.split ( ',' ) {
+			subscriptions.entry ( pid.to_string ( ) ).or_insert ( SubscriptionStatus::Pending );
+		}
+	} );
+
+	// the fields a received snapshot gets, unless the test set them
+	let snapshots = stream::poll_fn ( |cx| source.poll_recv ( cx ) ).map ( |mut snapshot| {
+		status.last_activity.send_replace ( Some ( status.clock.now ( ) ) );
+		snapshot.received_at.get_or_insert_with ( || status.clock.system_time ( ) );
+		snapshot.seq.get_or_insert_with ( || status.next_seq.fetch_add ( 1, Ordering::Relaxed ) );
+		Ok ( snapshot )
+	} );
+	let Hooks { gap_handler, freeze_handler, on_initial, filter, .. } = hooks;
+	let snapshot_hooks = SnapshotHooks { gap_handler, freeze_handler, on_initial, filter };
+	match handle_snapshots ( snapshots, handler_concurrency, status, snapshot_hooks, handler ).await {
+		Err ( None ) => Ok ( Exit::Stopped ),
+		Err ( Some ( e ) ) => Err ( e ),
+		Ok ( ( ) ) => Ok ( Exit::Closed ),
+	}
+}
+
+/// Hooks of each snapshot, borrowed apart from those of the frames
+struct SnapshotHooks <'a> {
+	gap_handler: &'a mut Option<GapHandler>,
+	freeze_handler: &'a mut Option<FreezeHandler>,
+	on_initial: &'a mut Option<InitialHandler>,
+	filter: &'a mut Option<FilterHandler>,
+}
+
+/// Pass the snapshots through the hooks to the handler, up to handler_concurrency at a time.
+/// Err(None) when the handler stops the stream.
+async fn handle_snapshots <S, F, Fut> (
+	snapshots: S,
+	handler_concurrency: usize,
+	status: &Status,
+	hooks: SnapshotHooks<'_>,
+	mut handler: F,
+) -> Result<(), Option<Error>>
+where
+	S: futures_util::Stream<Item = Result<Snapshot, Option<Error>>>,
+	F: FnMut ( Snapshot ) -> Fut,
+	Fut: Future<Output = Result<(), ()>>,
+{
+	let SnapshotHooks { gap_handler, freeze_handler, on_initial, filter } = hooks;
+	let mut initial_pids = HashSet::new ( );
+	// shared by the snapshots and the check of the pids without any, never locked across an await
+	let freeze_handler = Mutex::new ( freeze_handler );
+	let freeze_ticks = async {
+		let window = freeze_handler.lock ( ).unwrap ( ).as_ref ( ).map ( |( detector, _ )| detector.window ( ) );
+		let window = match window {
+			Some ( window ) => window,
+			None => return future::pending ( ).await,
+		};
+		let mut interval = time::interval ( ( window / 2 ).max ( Duration::from_millis ( 1 ) ) );
+		interval.set_missed_tick_behavior ( time::MissedTickBehavior::Delay );
+		loop {
+			interval.tick ( ).await;
+			if let Some ( ( detector, on_freeze ) ) = &mut **freeze_handler.lock ( ).unwrap ( ) {
+				for freeze in detector.tick ( status.clock.now ( ) ) {
+					on_freeze ( freeze );
+				}
+			}
+		}
+	};
+	let handled = snapshots.try_for_each_concurrent ( handler_concurrency, |snapshot| {
+		if status.subscriptions.borrow ( ).get ( &snapshot.pid ) != Some ( &SubscriptionStatus::Active ) {
+			status.subscriptions.send_modify ( |subscriptions| {
+				subscriptions.insert ( snapshot.pid.clone ( ), SubscriptionStatus::Active );
+			} );
+		}
+		if let Some ( ( detector, on_gap ) ) = gap_handler {
+			if let Some ( gap ) = detector.check ( &snapshot ) {
+				on_gap ( gap );
+			}
+		}
+		if let Some ( ( detector, on_freeze ) ) = &mut **freeze_handler.lock ( ).unwrap ( ) {
+			if let Some ( freeze ) = detector.check ( &snapshot, status.clock.now ( ) ) {
+				on_freeze ( freeze );
+			}
+		}
+		if let Some ( on_initial ) = on_initial {
+			if !initial_pids.contains ( &snapshot.pid ) {
+				initial_pids.insert ( snapshot.pid.clone ( ) );
+				on_initial ( &snapshot );
+			}
+		}
+		if let Some ( filter ) = filter {
+			if !filter ( &snapshot ) {
+				return future::Either::Left ( future::ready ( Ok ( ( ) ) ) );
+			}
+		}
+		future::Either::Right ( handler ( snapshot ).map_err ( |_| None ) )
+	} );
+	tokio::select! {
+		handled = handled => handled,
+		_ = freeze_ticks => unreachable! ( ),
+	}
+}
+
+/// Returns the snapshots of the subscribed pair ids in a data frame, in the order of the frame,
+/// none for frames without snapshot. Err(..) when the server closes the connection or rejects
+/// the UID.
+fn filter_frame (
+	msg: Message,
+	status: &Status,
+	connect_options: &ConnectOptions,
+	on_ignored_frame: &mut Option<IgnoredFrameHandler>,
+	on_event: &mut Option<EventHandler>,
+	channel_handlers: &mut [ChannelHandler],
+	on_parse_error: &mut Option<ParseErrorHandler>,
+) -> Result<Vec<Snapshot>, Error> {
+	let received_at = status.clock.system_time ( );
+	status.last_activity.send_replace ( Some ( status.clock.now ( ) ) );
+	metrics::frame_received ( );
+	if let Message::Close ( Some ( frame ) ) = &msg {
+		return Err ( Error::Closed { code: frame.code.into ( ), reason: frame.reason.to_string ( ) } );
+	}
+	let data = msg.is_text ( ) || msg.is_binary ( );
+	let lossy;
+	let msg = match ( msg.to_text ( ), &msg ) {
+		( Ok ( text ), _ ) => text,
+		( Err ( _ ), Message::Binary ( bytes ) ) if connect_options.lossy_utf8 => {
+			lossy = String::from_utf8_lossy ( bytes ).into_owned ( );
+			log::warn! ( "Replaced invalid UTF-8 of frame {}", lossy );
+			&lossy
+		}
+		( Err ( e ), _ ) => {
+			log::warn! ( "Skipped frame of invalid UTF-8: {}", e );
+			status.stats.send_modify ( |stats| stats.ignored_frames += 1 );
+			return Ok ( Vec::new ( ) );
+		}
+	};
+	if is_sockjs_heartbeat ( msg ) {
+		log::trace! ( "SockJS heartbeat" );
+		return Ok ( Vec::new ( ) );
+	}
+	if let Some ( ( code, reason ) ) = parse_close_frame ( msg ) {
+		return Err ( Error::Closed { code, reason } );
+	}
+	// control messages and those of the other channels never carry a pair id
+	let frame_keys = &connect_options.frame_keys;
+	let pid = frame_keys.snapshot_pid ( msg );
+	let quote = pid.is_some ( );
+	if !quote {
+		for ( channel, on_message ) in channel_handlers.iter_mut ( ) {
+			if let Some ( message ) = frame_keys.channel_message ( msg, channel ) {
+				on_message ( message );
+				return Ok ( Vec::new ( ) );
+			}
+		}
+	}
+	// the frames are decoded for their event only for the hook
+	if let ( Some ( on_event ), false ) = ( on_event, quote ) {
+		if let Some ( event ) = parse_event ( msg ) {
+			if let ServerEvent::Error ( message ) = &event {
+				log::warn! ( "Server error: {}", message );
+			}
+			on_event ( event );
+		}
+	}
+	// the cheap check first, the UID reply being rare, and the quotes of its frame still parsed
+	if msg.contains ( "UID" ) {
+		match parse_uid_frame ( msg ) {
+			Some ( Ok ( uid ) ) => {
+				status.uid.send_replace ( Some ( uid ) );
+				if !quote {
+					return Ok ( Vec::new ( ) );
+				}
+			}
+			Some ( Err ( e ) ) => return Err ( e ),
+			None => {}
+		}
+	}
+	// a frame may carry several pair ids, those other than the subscribed ones are ignored
+	if let Some ( pid ) = pid {
+		let ConnectOptions { keep_raw, keep_frame, turnover_policy, field_stats, number_format, .. } = *connect_options;
+		let markers = frame_keys.markers ( );
+		let mut quotes: Vec<_> = frame_quotes ( msg, markers ).unwrap_or_default ( ).into_iter ( )
+			.map ( |( pid, json )| ( pid, Some ( json ) ) )
+			.collect ( );
+		// escaped unlike the frames of the server, parsed whole as the data of its first pair id
+		if quotes.is_empty ( ) {
+			quotes.push ( ( pid.to_string ( ), None ) );
+		}
+		let mut subscribed = false;
+		let mut snapshots = Vec::with_capacity ( quotes.len ( ) );
+		for ( pid, json ) in quotes {
+			if !status.subscriptions.borrow ( ).contains_key ( &pid ) {
+				continue;
+			}
+			subscribed = true;
+			let snapshot = match json {
+				Some ( json ) => Snapshot::parse_message ( json, keep_raw, turnover_policy ),
+				None => Snapshot::parse_frame ( msg, markers, keep_raw, turnover_policy ),
+			};
+			match snapshot {
+				Ok ( mut snapshot ) => {
+					if keep_frame {
+						snapshot.frame = Some ( msg.to_string ( ) );
+					}
+					snapshot.received_at = Some ( received_at );
+					snapshot.seq = Some ( status.next_seq.fetch_add ( 1, Ordering::Relaxed ) );
+					snapshot.number_format = number_format;
+					if field_stats {
+						status.record_fields ( &snapshot.pid, |stats| stats.record ( &snapshot ) );
+					}
+					metrics::snapshot_parsed ( &snapshot );
+					snapshots.push ( snapshot );
+				}
+				// skip the instrument, the next ones are likely fine
+				Err ( e ) => {
+					log::warn! ( "Failed to parse {} of {}: {}", pid, msg, e );
+					let e = Error::Parse ( e );
+					status.stats.send_modify ( |stats| stats.parse_errors += 1 );
+					if field_stats {
+						status.record_fields ( &pid, |stats| stats.parse_errors += 1 );
+					}
+					metrics::parse_error ( );
+					if let Some ( on_parse_error ) = on_parse_error {
+						on_parse_error ( e, msg );
+					}
+				}
+			}
+		}
+		if subscribed {
+			return Ok ( snapshots );
+		}
+	}
+
+	// pings, pongs and heartbeat replies are expected without snapshot
+	if data && !is_heartbeat_frame ( msg ) {
+		status.stats.send_modify ( |stats| stats.ignored_frames += 1 );
+		if let Some ( on_ignored_frame ) = on_ignored_frame {
+			on_ignored_frame ( msg );
+		}
+	}
+	Ok ( Vec::new ( ) )
+}
+
+/// Send the messages of ConnectOptions::connect_messages(..), each after its delay.
+async fn send_connect_messages (
+	tx: &mut WsSink,
+	connect_options: &ConnectOptions,
+	pair_id: &str,
+	on_send: &Option<SendHandler>,
+	handshake: &Handshake,
+) -> Result<(), Error> {
+	let messages = connect_options.connect_messages ( pair_id );
+	let count = messages.len ( );
+	for ( idx, ( delay, message ) ) in messages.into_iter ( ).enumerate ( ) {
+		if !delay.is_zero ( ) {
+			time::sleep ( delay ).await;
+		}
+		if let Some ( on_send ) = on_send {
+			on_send ( &message );
+		}
+		tx.send ( message.into ( ) )
+			.await
+			.map_err ( |e| Error::Send ( Box::new ( e ) ) )
+			?;
+		// the UID message comes last
+		match ( &connect_options.connect_messages, idx + 1 == count ) {
+			( Some ( _ ), _ ) => handshake.connect_message_sent ( ),
+			( None, true ) => handshake.uid_sent ( ),
+			( None, false ) => handshake.subscribe_sent ( ),
+		}
+	}
+	Ok ( ( ) )
+}
+
+/// Send the heartbeat message, the pings and the outgoing messages until a send fails.
+async fn send_heartbeats (
+	tx: &mut WsSink,
+	ping: &mut Option<time::Interval>,
+	outgoing: &mut mpsc::UnboundedReceiver<String>,
+	on_send: &Option<SendHandler>,
+) -> Result<(), Error> {
+	let mut heartbeat = time::interval ( Duration::from_millis ( 3200u64 ) );
+	loop {
+		tokio::select! {
+			_ = heartbeat.tick ( ) => {
+				// the socket is gone once a heartbeat fails, stop instead of panicking
+				let heartbeat = heartbeat_message ( );
+				if let Some ( on_send ) = on_send {
+					on_send ( &heartbeat );
+				}
+				if let Err ( e ) = tx.send ( heartbeat.into ( ) ).await {
+					log::warn! ( "Failed to send heartbeat to server: {}", e );
+					return Err ( Error::Send ( Box::new ( e ) ) );
+				}
+			}
+			_ = tick ( ping ) => {
+				if let Err ( e ) = tx.send ( Message::Ping ( Vec::new ( ) ) ).await {
+					log::warn! ( "Failed to send ping to server: {}", e );
+					return Err ( Error::Send ( Box::new ( e ) ) );
+				}
+			}
+			Some ( message ) = outgoing.recv ( ) => {
+				if let Some ( on_send ) = on_send {
+					on_send ( &message );
+				}
+				if let Err ( e ) = tx.send ( message.into ( ) ).await {
+					log::warn! ( "Failed to send to server: {}", e );
+					return Err ( Error::Send ( Box::new ( e ) ) );
+				}
+			}
+		}
+	}
+}
+
+/// Returns the next value of a receiver of Stream::subscribe_broadcast(), None once the
+/// stream ended. The values missed by a receiver lagging behind are skipped with a logged warning.
+pub async fn recv_broadcast <T: Clone> ( receiver: &mut broadcast::Receiver<T> ) -> Option<T> {
+	loop {
+		match receiver.recv ( ).await {
+			Ok ( value ) => return Some ( value ),
+			Err ( broadcast::error::RecvError::Lagged ( skipped ) ) => log::warn! ( "Receiver lagging behind, skipped {} snapshots", skipped ),
+			Err ( broadcast::error::RecvError::Closed ) => return None,
+		}
+	}
+}
+
+/// Websocket to the server, after the SockJS open frame
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Sending half of the websocket returned by connect()
+pub type WsSink = SplitSink<WsStream, Message>;
+
+/// Receiving half of the websocket returned by connect()
+pub type WsSource = SplitStream<WsStream>;
+
+/// Connect to a generated stream URL and return the split halves of the websocket, to drive
+/// the protocol without Stream.
+/// 
+/// The SockJS open frame "o" is already consumed. Nothing is sent: subscribe with
+/// subscribe_message(..) and uid_message(..), and keep sending heartbeat_message(..) every few
+/// seconds, or the server drops the connection. Data frames containing "pid-" carry snapshots,
+/// see Snapshot::from_str(..)
+pub async fn connect ( ) -> Result<(WsSink, WsSource), Error> {
+	let (stream, _handshake, early) = connect_any ( || ( generate_stream_url ( ), None ), &ConnectOptions::default ( ), &Status::default ( ), &mut None ).await?;
+	if let Some ( frame ) = early {
+		// nothing is subscribed yet, so this is no data
+		log::warn! ( "Dropped the frame before subscribing: {}", frame );
+	}
+	Ok ( stream.split ( ) )
+}
+
+/// Returns the current snapshot of the pid: connect, subscribe, and close the connection once
+/// the first snapshot arrives, without the heartbeat and the runtime of Stream.
+/// 
+/// Error::NoSnapshot when no snapshot arrives within the timeout. See
+/// StreamBuilder::fetch_snapshot(..) for options.
+pub async fn fetch_snapshot ( pid: &str, timeout: Duration ) -> Result<Snapshot, Error> {
+	StreamBuilder::new ( pid.to_string ( ) ).fetch_snapshot ( timeout ).await
+}
+
+/// Connects to a generated URL, and disconnects once the open frame arrived, e.g. to check the
+/// server is reachable from the environment. See StreamBuilder::probe(..) for options and the
+/// handshake.
+pub async fn probe ( ) -> Result<ProbeReport, Error> {
+	StreamBuilder::new ( String::new ( ) ).probe ( false ).await
+}
+
+/// Returns the first `n` snapshots of the pid: connect, subscribe, and close the connection once
+/// they arrived, without the heartbeat and the runtime of Stream.
+/// 
+/// Returns the snapshots arrived so far when the timeout elapses or the connection ends before
+/// `n`, and Error::NoSnapshot when none arrived. See StreamBuilder::collect(..) for options.
+pub async fn collect ( pid: &str, n: usize, timeout: Duration ) -> Result<Vec<Snapshot>, Error> {
+	StreamBuilder::new ( pid.to_string ( ) ).collect ( n, timeout ).await
+}
+
+/// Ticks of the interval, or never without interval.
+async fn tick ( interval: &mut Option<time::Interval> ) {
+	match interval {
+		Some ( interval ) => { interval.tick ( ).await; },
+		None => future::pending ( ).await,
+	}
+}
+
+/// Options of connecting to the server, see StreamBuilder
+#[derive(Clone)]
+pub(crate) struct ConnectOptions {
+	timeout: Duration,
+	max_attempts: u32,
+	retry_delay: Duration,
+	retry_jitter: Duration,
+	host_cooldown: Duration,
+	websocket_config: WebSocketConfig,
+	proxy: Option<Proxy>,
+	ping_interval: Option<Duration>,
+	url: Option<String>,
+	url_provider: Option<Arc<Mutex<dyn UrlProvider + Send>>>,
+	preferred_hosts: Vec<String>,
+	domain: String,
+	path_template: String,
+	uid: u64,
+	pub(crate) tz_id: u32,
+	connect_messages: Option<Vec<String>>,
+	/// Prefixes of the data channels of StreamBuilder::on_channel(..)
+	channels: Vec<String>,
+	/// Markers of StreamBuilder::frame_markers(..) with the keys of the pid and the channels
+	frame_keys: FrameKeys,
+	pub(crate) subscribe_batch_size: usize,
+	subscribe_batch_delay: Duration,
+	first_data_timeout: Option<Duration>,
+	session_max_age: Option<Duration>,
+	keep_raw: bool,
+	field_stats: bool,
+	lossy_utf8: bool,
+	/// Keep the whole frame in Snapshot::frame, see StreamBuilder::build_raw(..)
+	keep_frame: bool,
+	turnover_policy: TurnoverPolicy,
+	/// See StreamBuilder::number_format(..)
+	number_format: NumberFormat,
+}
+
+impl ConnectOptions {
+	/// URL of the next connect attempt, with its generated server if any. The preferred hosts
+	/// come first, from the index `preferred` of the connect on, skipping those cooling down.
+	fn next_url ( &self, preferred: &mut usize, failed_hosts: &FailedHosts ) -> (String, Option<u8>) {
+		if let Some ( provider ) = &self.url_provider {
+			return ( provider.lock ( ).unwrap ( ).next_url ( ), None );
+		}
+		match &self.url {
+			Some ( url ) => ( url.clone ( ), None ),
+			None => {
+				while let Some ( host ) = self.preferred_hosts.get ( *preferred ) {
+					*preferred += 1;
+					if !is_cooling_down ( failed_hosts, host ) {
+						let server = rand::thread_rng ( ).gen::<u8> ( ) % SERVER_COUNT;
+						return ( stream_url_on_host ( &host_base ( host ), &self.path_template, server ), None );
+					}
+				}
+				let server = next_server ( );
+				( stream_url_for_server ( &self.domain, &self.path_template, server ), Some ( server ) )
+			}
+		}
+	}
+
+	/// Delay before the next connect attempt, retry_delay plus up to retry_jitter.
+	fn next_retry_delay ( &self ) -> Duration {
+		let jitter = self.retry_jitter.as_millis ( ) as u64;
+		match jitter {
+			0 => self.retry_delay,
+			_ => self.retry_delay + Duration::from_millis ( rand::thread_rng ( ).gen_range ( 0, jitter + 1 ) ),
+		}
+	}
+
+	/// Messages sent after connecting, with the delay before each: the bulk-subscribe of the
+	/// pair ids in batches, then of the channels of StreamBuilder::on_channel(..), and the UID
+	/// by default.
+	fn connect_messages ( &self, pair_id: &str ) -> Vec<(Duration, String)> {
+		if let Some ( messages ) = &self.connect_messages {
+			return messages.iter ( ).map ( |message| ( Duration::ZERO, message.clone ( ) ) ).collect ( );
+		}
+
+		let pids: Vec<&str> = pair_id.split ( ',' ).collect ( );
+		let mut messages: Vec<_> = pids.chunks ( self.subscribe_batch_size )
+			.enumerate ( )
+			.map ( |( idx, batch )| {
+				let delay = if idx == 0 { Duration::ZERO } else { self.subscribe_batch_delay };
+				( delay, self.subscribe_message ( &batch.join ( "," ) ) )
+			} )
+			.collect ( );
+		for channel in &self.channels {
+			for batch in pids.chunks ( self.subscribe_batch_size ) {
+				messages.push ( ( self.subscribe_batch_delay, channel_subscribe_message ( channel, &batch.join ( "," ), self.tz_id ) ) );
+			}
+		}
+		messages.push ( ( Duration::ZERO, uid_message ( self.uid ) ) );
+		messages
+	}
+
+	/// Bulk-subscribe message of the comma-separated pair ids, with the pid prefix of the markers.
+	pub(crate) fn subscribe_message ( &self, pair_ids: &str ) -> String {
+		channel_subscribe_message ( &self.frame_keys.markers ( ).pid_prefix, pair_ids, self.tz_id )
+	}
+}
+
+impl Default for ConnectOptions {
+	fn default ( ) -> Self {
+		ConnectOptions {
+			timeout: DEFAULT_CONNECT_TIMEOUT,
+			max_attempts: DEFAULT_MAX_CONNECT_ATTEMPTS,
+			retry_delay: Duration::ZERO,
+			retry_jitter: Duration::ZERO,
+			host_cooldown: DEFAULT_HOST_COOLDOWN,
+			websocket_config: WebSocketConfig::default ( ),
+			proxy: None,
+			ping_interval: None,
+			url: None,
+			url_provider: None,
+			preferred_hosts: Vec::new ( ),
+			domain: DEFAULT_DOMAIN.to_string ( ),
+			path_template: DEFAULT_PATH_TEMPLATE.to_string ( ),
+			uid: 0,
+			tz_id: DEFAULT_TZ_ID,
+			connect_messages: None,
+			channels: Vec::new ( ),
+			frame_keys: FrameKeys::default ( ),
+			subscribe_batch_size: usize::MAX,
+			subscribe_batch_delay: Duration::ZERO,
+			first_data_timeout: None,
+			session_max_age: None,
+			keep_raw: false,
+			field_stats: false,
+			lossy_utf8: false,
+			keep_frame: false,
+			turnover_policy: TurnoverPolicy::default ( ),
+			number_format: NumberFormat::default ( ),
+		}
+	}
 }
 
+/// Generated servers which failed to connect, until the end of their cooldown
+static FAILED_SERVERS: Mutex<BTreeMap<u8, Instant>> = Mutex::new ( BTreeMap::new ( ) );
 
-fn prepare_pair_msg(pair_ids: String) -> String {
-	let split: Vec<String> = pair_ids.split(",").map(|s| format ! ("pid-{}:", s.to_string())).collect();
-	let joined = split.join("%%");
-    return joined;
+/// Random server to generate the URL with, not cooling down if possible.
+fn next_server ( ) -> u8 {
+	pick_server ( &mut FAILED_SERVERS.lock ( ).unwrap ( ) )
 }
 
-/// Returns generated URL of wss stream in forexpros.com
-pub fn generate_stream_url ( ) -> String {
+/// Random server not in failed, forgetting the servers whose cooldown is over.
+fn pick_server ( failed: &mut BTreeMap<u8, Instant> ) -> u8 {
+	let now = Instant::now ( );
+	failed.retain ( |_, until| *until > now );
+
 	let mut rnd = rand::thread_rng ( );
+	let available: Vec<u8> = ( 0..SERVER_COUNT ).filter ( |server| !failed.contains_key ( server ) ).collect ( );
+	match available.choose ( &mut rnd ) {
+		Some ( server ) => *server,
+		None => rnd.gen::<u8> ( ) % SERVER_COUNT,
+	}
+}
+
+/// Preferred hosts of a stream which failed to connect, until the end of their cooldown
+pub(crate) type FailedHosts = Mutex<BTreeMap<String, Instant>>;
+
+/// Base of the URLs on the preferred host, its scheme defaulting to wss://
+fn host_base ( host: &str ) -> String {
+	match host.contains ( "://" ) {
+		true => host.to_string ( ),
+		false => format ! ( "wss://{}", host ),
+	}
+}
+
+/// Preferred host the URL is on, if any.
+fn preferred_host <'a> ( options: &'a ConnectOptions, url: &str ) -> Option<&'a String> {
+	options.preferred_hosts.iter ( ).find ( |host| {
+		url.strip_prefix ( host_base ( host ).trim_end_matches ( '/' ) )
+			.is_some_and ( |path| path.starts_with ( '/' ) )
+	} )
+}
+
+/// Whether the preferred host failed to connect within its cooldown.
+fn is_cooling_down ( failed_hosts: &FailedHosts, host: &str ) -> bool {
+	let now = Instant::now ( );
+	let mut failed = failed_hosts.lock ( ).unwrap ( );
+	failed.retain ( |_, until| *until > now );
+	failed.contains_key ( host )
+}
+
+/// Skip the preferred host in ConnectOptions::next_url(..) for the cooldown.
+fn cool_down_host ( failed_hosts: &FailedHosts, host: &str, cooldown: Duration ) {
+	if !cooldown.is_zero ( ) {
+		log::info! ( "Avoiding host {} for {:?}", host, cooldown );
+		failed_hosts.lock ( ).unwrap ( ).insert ( host.to_string ( ), Instant::now ( ) + cooldown );
+	}
+}
+
+/// Avoid the server in next_server() for the cooldown.
+fn cool_down ( server: u8, cooldown: Duration ) {
+	if !cooldown.is_zero ( ) {
+		log::info! ( "Avoiding server {:03x} for {:?}", server, cooldown );
+		FAILED_SERVERS.lock ( ).unwrap ( ).insert ( server, Instant::now ( ) + cooldown );
+	}
+}
+
+/// Connect to the URLs from next_url until one is reachable, at most options.max_attempts URLs.
+/// A server closing the session on arrival with one of RETRYABLE_CLOSE_CODES, see
+/// connect_url(..), counts as unreachable. The state turns to Reconnecting from the second
+/// attempt, or at once after a connection of the stream ended. The generated servers failing
+/// to connect cool down, as do the preferred hosts, see StreamBuilder::host_cooldown(..)
+async fn connect_any <U> (
+	mut next_url: U,
+	options: &ConnectOptions,
+	status: &Status,
+	on_reconnect: &mut Option<ReconnectHandler>,
+) -> Result<(WsStream, Handshake, Option<Message>), Error>
+where
+	U: FnMut ( ) -> (String, Option<u8>),
+{
+	// a connection of the stream ended before, so this is a reconnect even at the first attempt
+	let reconnect = !status.disconnects.borrow ( ).is_empty ( );
+	if reconnect {
+		status.state.send_replace ( ConnectionState::Reconnecting );
+	}
+	let mut attempt = 1;
+	loop {
+		let (url, server) = next_url ( );
+		log::info!("URL: {}", url);
+
+		let result = connect_url ( &url, options ).await;
+		let retry = match &result {
+			Err ( Error::Connect ( _ ) ) | Err ( Error::ConnectTimeout ) | Err ( Error::OpenFrameTimeout ) | Err ( Error::Proxy ( _ ) ) => true,
+			// the server rejecting the connection on arrival, e.g. c[2010,"Another connection still open"]
+			Err ( Error::Closed { code, .. } ) => is_retryable_close ( *code ),
+			_ => false,
+		};
+		if retry && !matches! ( result, Err ( Error::Proxy ( _ ) ) ) {
+			match server {
+				Some ( server ) => cool_down ( server, options.host_cooldown ),
+				None => if let Some ( host ) = preferred_host ( options, &url ) {
+					cool_down_host ( &status.failed_hosts, host, options.host_cooldown );
+				},
+			}
+		}
+		match result {
+			Err ( e ) if retry && attempt < options.max_attempts => {
+				log::warn! ( "Failed to connect to {} (attempt {}/{}): {}", url, attempt, options.max_attempts, e );
+				let delay = options.next_retry_delay ( );
+				if let Some ( on_reconnect ) = on_reconnect {
+					on_reconnect ( ReconnectEvent::Retry { attempt, delay, error: &e } );
+				}
+				attempt += 1;
+				metrics::reconnect ( );
+				status.state.send_replace ( ConnectionState::Reconnecting );
+				if !delay.is_zero ( ) {
+					time::sleep ( delay ).await;
+				}
+			}
+			Ok ( connected ) => {
+				if let ( Some ( on_reconnect ), true ) = ( on_reconnect, attempt > 1 || reconnect ) {
+					on_reconnect ( ReconnectEvent::Reconnected { attempt } );
+				}
+				return Ok ( connected );
+			}
+			result => return result,
+		}
+	}
+}
+
+/// Connect to the server and wait for the SockJS open frame "o", both within the timeout, then
+/// for the close frame of a server rejecting the session, within REJECT_WINDOW. Returns the
+/// trace of the handshake as well, to continue with the next steps, and the frame received
+/// within the window if any other, which comes first after the open frame.
+async fn connect_url ( url: &str, options: &ConnectOptions ) -> Result<(WsStream, Handshake, Option<Message>), Error> {
+	let handshake = Handshake::start ( url );
+	let deadline = time::Instant::now ( ) + options.timeout;
+	let upgrade = async {
+		let (stream, _response) = match &options.proxy {
+			None => tokio_tungstenite::connect_async_with_config ( url, Some ( options.websocket_config ), false ).await,
+			Some ( proxy ) => {
+				let request = url.into_client_request ( ).map_err ( |e| Error::Connect ( Box::new ( e ) ) )?;
+				let host = request.uri ( ).host ( ).unwrap_or_default ( ).to_string ( );
+				let port = request.uri ( ).port_u16 ( )
+					.unwrap_or ( if request.uri ( ).scheme_str ( ) == Some ( "ws" ) { 80 } else { 443 } );
+
+				let socket = proxy.connect ( &host, port ).await.map_err ( Error::Proxy )?;
+				tokio_tungstenite::client_async_tls_with_config ( request, socket, Some ( options.websocket_config ), None ).await
+			},
+		}
+		.map_err ( |e| Error::Connect ( Box::new ( e ) ) )
+		?;
+		Ok ( stream )
+	};
+
+	let mut stream = time::timeout_at ( deadline, upgrade )
+		.await
+		.map_err ( |_| Error::ConnectTimeout )
+		??;
+	handshake.upgraded ( );
+	time::timeout_at ( deadline, wait_open_frame ( &mut stream ) )
+		.await
+		.map_err ( |_| Error::OpenFrameTimeout )
+		??;
+	handshake.open_frame ( );
+	let early = match time::timeout ( REJECT_WINDOW, wait_rejection ( &mut stream ) ).await {
+		Ok ( early ) => early?,
+		Err ( _ ) => None,
+	};
+	Ok ( ( stream, handshake, early ) )
+}
 
-	format ! ( "wss://streaming.forexpros.com/echo/{:03x}/{:08x}/websocket",
-	//1 + rnd.gen::<u16> ( ) % 280,
-	rnd.gen::<u8> ( ) % 100,
-	rnd.gen::<u16> ( ) % 0xfff,
-	// rnd.gen::<u32> ( )
-)
+/// Wait for the frame following the open frame, skipping the heartbeats and the websocket pings
+/// and pongs. Error::Closed when it is a close frame, the frame otherwise, None when the
+/// connection ends.
+async fn wait_rejection ( stream: &mut WsStream ) -> Result<Option<Message>, Error> {
+	loop {
+		match stream.next ( ).await {
+			Some ( Ok ( Message::Text ( frame ) ) ) if frame == "h" => {},
+			Some ( Ok ( Message::Text ( frame ) ) ) => return match parse_close_frame ( &frame ) {
+				Some ( ( code, reason ) ) => Err ( Error::Closed { code, reason } ),
+				None => Ok ( Some ( Message::Text ( frame ) ) ),
+			},
+			Some ( Ok ( Message::Ping ( _ ) ) ) | Some ( Ok ( Message::Pong ( _ ) ) ) => {},
+			Some ( Ok ( msg ) ) => return Ok ( Some ( msg ) ),
+			Some ( Err ( e ) ) => return Err ( Error::receive ( e ) ),
+			None => return Ok ( None ),
+		}
+	}
+}
+
+/// Wait for the SockJS open frame "o", skipping the heartbeats "h" and the websocket pings and
+/// pongs which may arrive before it. Error::Closed when the server closes the session instead.
+async fn wait_open_frame ( stream: &mut WsStream ) -> Result<(), Error> {
+	loop {
+		match stream.next ( ).await {
+			Some ( Ok ( Message::Text ( frame ) ) ) if frame == "o" => return Ok ( ( ) ),
+			Some ( Ok ( Message::Text ( frame ) ) ) if frame == "h" => log::debug! ( "Heartbeat before the open frame" ),
+			Some ( Ok ( Message::Text ( frame ) ) ) => return Err ( match parse_close_frame ( &frame ) {
+				Some ( ( code, reason ) ) => Error::Closed { code, reason },
+				None => Error::UnexpectedOpenFrame ( frame ),
+			} ),
+			Some ( Ok ( Message::Ping ( _ ) ) ) | Some ( Ok ( Message::Pong ( _ ) ) ) => {},
+			Some ( Ok ( msg ) ) => return Err ( Error::UnexpectedOpenFrame ( msg.to_string ( ) ) ),
+			Some ( Err ( e ) ) => return Err ( Error::receive ( e ) ),
+			None => return Err ( Error::NoOpenFrame ),
+		}
+	}
 }
 
 /*
@@ -280,17 +3311,296 @@ mod tests {
 	}
 
 	#[test]
-	pub fn test_generate_stream_url ( ) {
-		use regex::Regex;
+	pub fn test_stream_send ( ) {
+		fn assert_send_sync <T: Send + Sync> ( ) {}
+		assert_send_sync::<Stream> ( );
 
-		let url = generate_stream_url();
-		
-		assert_eq! ( Regex::new ( r#"wss://streaming.forexpros.com/echo/[0-9a-zA-Z]{3}/[0-9a-zA-Z]{8}/websocket"# ).unwrap ( ).is_match ( url.as_str ( ) ), true, "Generated: {}", url );
+		let token = CancellationToken::new ( );
+		token.cancel ( );
+
+		let stream = Stream::builder ( "945629".to_string ( ) )
+			.cancellation_token ( token )
+			.build ( |_| Ok ( ( ) ) )
+			.expect ( "Failed to create stream" );
+
+		let result = std::thread::spawn ( move || stream.runtime.block_on ( stream.stream_handle_spawn ) )
+			.join ( )
+			.unwrap ( );
+		assert! ( result.unwrap ( ).is_ok ( ) );
 	}
 
 	#[test]
-	pub fn test_prepare_pair_msg ( ) {
-		assert_eq! ( prepare_pair_msg("1234".to_string()), "pid-1234:");
-		assert_eq! ( prepare_pair_msg("olia,haha,1234".to_string()), "pid-olia:%%pid-haha:%%pid-1234:");
+	pub fn test_cancellation_token ( ) {
+		let token = CancellationToken::new ( );
+		token.cancel ( );
+
+		let stream = Stream::builder ( "945629".to_string ( ) )
+			.cancellation_token ( token )
+			.build ( |_| Ok ( ( ) ) )
+			.expect ( "Failed to create stream" );
+
+		let mut state = stream.state_receiver ( );
+		assert! ( stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).is_ok ( ) );
+		assert_eq! ( *state.borrow_and_update ( ), ConnectionState::Closed );
+	}
+
+	#[test]
+	pub fn test_invalid_options ( ) {
+		assert! ( matches! ( Stream::builder ( "945629".to_string ( ) ).domain ( "" ).build ( |_| Ok ( ( ) ) ), Err ( Error::EmptyDomain ) ) );
+		assert! ( matches! ( Stream::new ( "".to_string ( ), |_| Ok ( ( ) ) ), Err ( Error::InvalidPairId ( _ ) ) ) );
+		assert! ( matches! ( Stream::new ( "945629,,8984".to_string ( ), |_| Ok ( ( ) ) ), Err ( Error::InvalidPairId ( _ ) ) ) );
+		assert! ( matches! ( Stream::builder ( "945629,EUR/USD".to_string ( ) ).numeric_pair_ids ( true ).build ( |_| Ok ( ( ) ) ), Err ( Error::InvalidPairId ( _ ) ) ) );
+	}
+
+	#[test]
+	pub fn test_connect_refused ( ) {
+		let result = runtime::Runtime::new ( )
+			.unwrap ( )
+			.block_on ( connect_url ( "ws://127.0.0.1:1/echo/websocket", &ConnectOptions::default ( ) ) );
+
+		assert! ( matches! ( result, Err ( Error::Connect ( _ ) ) ) );
+	}
+
+	#[test]
+	pub fn test_connect_any_attempts ( ) {
+		let mut attempts = 0;
+		let retries = Arc::new ( std::sync::Mutex::new ( Vec::new ( ) ) );
+		let retries_clone = retries.clone ( );
+		let mut on_reconnect: Option<ReconnectHandler> = Some ( Box::new ( move |event| {
+			if let ReconnectEvent::Retry { attempt, delay, error: Error::Connect ( _ ) } = event {
+				retries_clone.lock ( ).unwrap ( ).push ( ( attempt, delay ) );
+			}
+		} ) );
+		let status = Status::default ( );
+		let state = status.state.subscribe ( );
+		let result = runtime::Runtime::new ( )
+			.unwrap ( )
+			.block_on ( connect_any ( || {
+				attempts += 1;
+				( "ws://127.0.0.1:1/echo/websocket".to_string ( ), None )
+			}, &ConnectOptions { retry_delay: Duration::from_millis ( 10 ), ..ConnectOptions::default ( ) }, &status, &mut on_reconnect ) );
+
+		assert! ( matches! ( result, Err ( Error::Connect ( _ ) ) ) );
+		assert_eq! ( attempts, 3 );
+		assert_eq! ( *retries.lock ( ).unwrap ( ), vec! [ ( 1, Duration::from_millis ( 10 ) ), ( 2, Duration::from_millis ( 10 ) ) ] );
+		assert_eq! ( *state.borrow ( ), ConnectionState::Reconnecting );
+	}
+
+	#[test]
+	pub fn test_next_server ( ) {
+		let now = Instant::now ( );
+		let mut failed: BTreeMap<u8, Instant> = ( 0..SERVER_COUNT )
+			.filter ( |server| *server != 7 )
+			.map ( |server| ( server, now + Duration::from_secs ( 60 ) ) )
+			.collect ( );
+		let servers: HashSet<u8> = ( 0..20 ).map ( |_| pick_server ( &mut failed ) ).collect ( );
+		assert_eq! ( servers, HashSet::from ( [ 7 ] ) );
+
+		// cooldown over
+		failed.insert ( 8, now );
+		failed.insert ( 7, now + Duration::from_secs ( 60 ) );
+		assert_eq! ( pick_server ( &mut failed ), 8 );
+		assert! ( !failed.contains_key ( &8 ) );
+
+		let options = ConnectOptions { retry_delay: Duration::from_millis ( 10 ), retry_jitter: Duration::from_millis ( 5 ), ..ConnectOptions::default ( ) };
+		for _ in 0..20 {
+			let delay = options.next_retry_delay ( );
+			assert! ( delay >= Duration::from_millis ( 10 ) && delay <= Duration::from_millis ( 15 ), "{:?}", delay );
+		}
+	}
+
+	#[test]
+	pub fn test_next_url_preferred ( ) {
+		let options = ConnectOptions {
+			preferred_hosts: vec! [ "one.preferred.test".to_string ( ), "ws://two.preferred.test:8080".to_string ( ) ],
+			..ConnectOptions::default ( )
+		};
+		let failed_hosts = FailedHosts::default ( );
+		let mut preferred = 0;
+		let (url, server) = options.next_url ( &mut preferred, &failed_hosts );
+		assert! ( url.starts_with ( "wss://one.preferred.test/echo/" ), "{}", url );
+		assert_eq! ( ( preferred_host ( &options, &url ), server ), ( Some ( &options.preferred_hosts [ 0 ] ), None ) );
+		let (url, _) = options.next_url ( &mut preferred, &failed_hosts );
+		assert! ( url.starts_with ( "ws://two.preferred.test:8080/echo/" ), "{}", url );
+		assert_eq! ( preferred_host ( &options, &url ), Some ( &options.preferred_hosts [ 1 ] ) );
+		// then the generated ones
+		let (url, server) = options.next_url ( &mut preferred, &failed_hosts );
+		assert! ( server.is_some ( ) );
+		assert_eq! ( preferred_host ( &options, &url ), None );
+
+		// a fresh session on each attempt
+		let sessions: HashSet<String> = ( 0..20 )
+			.map ( |_| options.next_url ( &mut 0, &failed_hosts ).0 )
+			.collect ( );
+		assert! ( sessions.len ( ) > 1 );
+
+		// the next connect starts over, skipping the hosts cooling down
+		cool_down_host ( &failed_hosts, "one.preferred.test", Duration::from_secs ( 60 ) );
+		let mut preferred = 0;
+		assert! ( options.next_url ( &mut preferred, &failed_hosts ).0.starts_with ( "ws://two.preferred.test:8080/" ) );
+		assert! ( options.next_url ( &mut preferred, &failed_hosts ).1.is_some ( ) );
+		// on the stream which failed only
+		assert! ( options.next_url ( &mut 0, &FailedHosts::default ( ) ).0.starts_with ( "wss://one.preferred.test/" ) );
+	}
+
+	#[test]
+	pub fn test_connect_any_reconnected ( ) {
+		runtime::Runtime::new ( ).unwrap ( ).block_on ( async {
+			let listener = tokio::net::TcpListener::bind ( "127.0.0.1:0" ).await.unwrap ( );
+			let mut urls = vec! [
+				format ! ( "ws://{}/echo/websocket", listener.local_addr ( ).unwrap ( ) ),
+				"ws://127.0.0.1:1/echo/websocket".to_string ( ),
+			];
+			tokio::spawn ( async move {
+				let (socket, _) = listener.accept ( ).await.unwrap ( );
+				let mut ws = tokio_tungstenite::accept_async ( socket ).await.unwrap ( );
+				ws.send ( Message::Text ( "o".to_string ( ) ) ).await.unwrap ( );
+				// open past the window of a rejection
+				time::sleep ( REJECT_WINDOW * 4 ).await;
+			} );
+
+			let events = Arc::new ( std::sync::Mutex::new ( Vec::new ( ) ) );
+			let events_clone = events.clone ( );
+			let mut on_reconnect: Option<ReconnectHandler> = Some ( Box::new ( move |event| {
+				events_clone.lock ( ).unwrap ( ).push ( format ! ( "{:?}", event ).split ( ' ' ).next ( ).unwrap ( ).to_string ( ) );
+			} ) );
+			let result = connect_any ( || ( urls.pop ( ).unwrap ( ), None ), &ConnectOptions::default ( ), &Status::default ( ), &mut on_reconnect ).await;
+
+			assert! ( result.is_ok ( ) );
+			assert_eq! ( *events.lock ( ).unwrap ( ), vec! [ "Retry", "Reconnected" ] );
+		} );
+	}
+
+	#[test]
+	pub fn test_connect_messages ( ) {
+		let options = ConnectOptions {
+			subscribe_batch_size: 2,
+			subscribe_batch_delay: Duration::from_millis ( 100 ),
+			..ConnectOptions::default ( )
+		};
+
+		assert_eq! ( options.connect_messages ( "945629,8984,1" ), vec! [
+			( Duration::ZERO, subscribe_message ( "945629,8984", 8 ) ),
+			( Duration::from_millis ( 100 ), subscribe_message ( "1", 8 ) ),
+			( Duration::ZERO, uid_message ( 0 ) ),
+		] );
+		assert_eq! ( ConnectOptions::default ( ).connect_messages ( "945629,8984,1" ).len ( ), 2 );
+	}
+
+	#[test]
+	pub fn test_connect_timeout ( ) {
+		// accepts TCP connections, but never answers the websocket upgrade
+		let listener = std::net::TcpListener::bind ( "127.0.0.1:0" ).unwrap ( );
+		let url = format ! ( "ws://{}/echo/websocket", listener.local_addr ( ).unwrap ( ) );
+
+		let result = runtime::Runtime::new ( )
+			.unwrap ( )
+			.block_on ( connect_url ( &url, &ConnectOptions {
+				timeout: Duration::from_millis ( 100 ),
+				..ConnectOptions::default ( )
+			} ) );
+
+		assert! ( matches! ( result, Err ( Error::ConnectTimeout ) ) );
+	}
+
+	/// Runs the stream against a server sending the open frame, then the frames after the subscription.
+	async fn run_with_server ( frames: Vec<Message> ) -> Result<Exit, Error> {
+		let listener = tokio::net::TcpListener::bind ( "127.0.0.1:0" ).await.unwrap ( );
+		let options = ConnectOptions {
+			url: Some ( format ! ( "ws://{}/echo/websocket", listener.local_addr ( ).unwrap ( ) ) ),
+			..ConnectOptions::default ( )
+		};
+
+		let server = async {
+			let (socket, _) = listener.accept ( ).await.unwrap ( );
+			let mut ws = tokio_tungstenite::accept_async ( socket ).await.unwrap ( );
+			ws.send ( Message::Text ( "o".to_string ( ) ) ).await.unwrap ( );
+			// bulk-subscribe and UID
+			ws.next ( ).await.unwrap ( ).unwrap ( );
+			ws.next ( ).await.unwrap ( ).unwrap ( );
+			for frame in frames {
+				ws.send ( frame ).await.unwrap ( );
+			}
+			ws
+		};
+		let status = Status::default ( );
+		let token = CancellationToken::new ( );
+		let mut hooks = Hooks::default ( );
+		let client = run ( "945629", &options, 1, &mut hooks, &token, &status, |_| future::ready ( Ok ( ( ) ) ) );
+
+		let (result, _ws) = tokio::join! ( client, server );
+		result
+	}
+
+	#[test]
+	pub fn test_run_close_frame ( ) {
+		let rt = runtime::Runtime::new ( ).unwrap ( );
+
+		let result = rt.block_on ( run_with_server ( vec! [ Message::Text ( r#"c[3000,"Go away!"]"#.to_string ( ) ) ] ) );
+		assert! ( matches! ( result, Err ( Error::Closed { code: 3000, ref reason } ) if reason == "Go away!" ) );
+
+		let close = tungstenite::protocol::CloseFrame {
+			code: tungstenite::protocol::frame::coding::CloseCode::Again,
+			reason: "rate limited".into ( ),
+		};
+		let result = rt.block_on ( run_with_server ( vec! [ Message::Close ( Some ( close ) ) ] ) );
+		assert! ( matches! ( result, Err ( Error::Closed { code: 1013, ref reason } ) if reason == "rate limited" ) );
+	}
+
+	#[test]
+	pub fn test_tick ( ) {
+		runtime::Runtime::new ( ).unwrap ( ).block_on ( async {
+			let mut ping = Some ( time::interval ( Duration::from_millis ( 10 ) ) );
+			assert! ( time::timeout ( Duration::from_millis ( 100 ), tick ( &mut ping ) ).await.is_ok ( ) );
+
+			let mut no_ping = None;
+			assert! ( time::timeout ( Duration::from_millis ( 100 ), tick ( &mut no_ping ) ).await.is_err ( ) );
+		} );
+	}
+
+	#[test]
+	pub fn test_connect_open_frame ( ) {
+		runtime::Runtime::new ( ).unwrap ( ).block_on ( async {
+			let listener = tokio::net::TcpListener::bind ( "127.0.0.1:0" ).await.unwrap ( );
+			let url = format ! ( "ws://{}/echo/websocket", listener.local_addr ( ).unwrap ( ) );
+
+			let server = async {
+				let (socket, _) = listener.accept ( ).await.unwrap ( );
+				let mut ws = tokio_tungstenite::accept_async ( socket ).await.unwrap ( );
+				ws.send ( Message::Text ( "o".to_string ( ) ) ).await.unwrap ( );
+				ws.send ( Message::Text ( "h".to_string ( ) ) ).await.unwrap ( );
+				ws.send ( Message::Text ( r#"a["welcome"]"#.to_string ( ) ) ).await.unwrap ( );
+				ws.send ( Message::Text ( r#"a["data"]"#.to_string ( ) ) ).await.unwrap ( );
+				ws
+			};
+			let options = ConnectOptions::default ( );
+			let (result, _ws) = tokio::join! ( connect_url ( &url, &options ), server );
+
+			// the open frame and the heartbeat are consumed, the first frame after them is
+			// returned, the next goes to the caller
+			let (stream, _, early) = result.unwrap ( );
+			assert_eq! ( early, Some ( Message::Text ( r#"a["welcome"]"#.to_string ( ) ) ) );
+			let (_tx, mut rx) = stream.split ( );
+			assert_eq! ( rx.next ( ).await.unwrap ( ).unwrap ( ), Message::Text ( r#"a["data"]"#.to_string ( ) ) );
+		} );
+	}
+
+	#[test]
+	pub fn test_heartbeat_send_failure ( ) {
+		let server = crate::testing::MockServer::start ( Vec::new ( ) );
+		runtime::Runtime::new ( ).unwrap ( ).block_on ( async {
+			let (stream, _, _) = connect_url ( &server.url ( ), &ConnectOptions::default ( ) ).await.unwrap ( );
+			let (mut tx, _rx) = stream.split ( );
+			tx.close ( ).await.unwrap ( );
+
+			// the socket is closed by the time the first heartbeat is due
+			let (_outgoing, mut outgoing) = mpsc::unbounded_channel ( );
+			let sent = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+			let sent_clone = sent.clone ( );
+			let on_send: Option<SendHandler> = Some ( Arc::new ( move |message: &str| sent_clone.lock ( ).unwrap ( ).push ( message.to_string ( ) ) ) );
+			let result = time::timeout ( Duration::from_secs ( 5 ), send_heartbeats ( &mut tx, &mut None, &mut outgoing, &on_send ) ).await;
+			assert! ( matches! ( result, Ok ( Err ( Error::Send ( _ ) ) ) ) );
+			assert_eq! ( *sent.lock ( ).unwrap ( ), vec! [ heartbeat_message ( ) ] );
+		} );
 	}
 }
\ No newline at end of file
@@ -0,0 +1,229 @@
+//! Bounded queue of snapshots from the stream to Stream::next_snapshot(), see ChannelPolicy.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use crate::{data::Snapshot, push::ChannelPolicy};
+
+struct Shared {
+    buffer: Mutex<VecDeque<Snapshot>>,
+    capacity: usize,
+    policy: ChannelPolicy,
+    readable: Notify,
+    /// Notified with `readable`, for Receiver::blocking_recv()
+    readable_blocking: Condvar,
+    writable: Notify,
+    sender_closed: AtomicBool,
+    receiver_closed: AtomicBool,
+    dropped: AtomicU64,
+}
+
+/// Sending half, owned by the handler of the stream. The receiver gets None once it is dropped.
+pub(crate) struct Sender(Arc<Shared>);
+
+/// Receiving half, owned by Stream. The sender fails once it is dropped.
+pub(crate) struct Receiver(Arc<Shared>);
+
+/// Returns the halves of a queue of at least one snapshot.
+pub(crate) fn channel(policy: ChannelPolicy, capacity: usize) -> (Sender, Receiver) {
+    let shared = Arc::new(Shared {
+        buffer: Mutex::new(VecDeque::new()),
+        capacity: capacity.max(1),
+        policy,
+        readable: Notify::new(),
+        readable_blocking: Condvar::new(),
+        writable: Notify::new(),
+        sender_closed: AtomicBool::new(false),
+        receiver_closed: AtomicBool::new(false),
+        dropped: AtomicU64::new(0),
+    });
+    (Sender(shared.clone()), Receiver(shared))
+}
+
+impl Sender {
+    /// Queue the snapshot, or apply the policy when the queue is full.
+    /// Err(()) once the receiver is dropped.
+    pub(crate) async fn send(&self, snapshot: Snapshot) -> Result<(), ()> {
+        loop {
+            if self.0.receiver_closed.load(Ordering::Acquire) {
+                return Err(());
+            }
+            {
+                let mut buffer = self.0.buffer.lock().unwrap();
+                if buffer.len() < self.0.capacity {
+                    buffer.push_back(snapshot);
+                    self.0.readable.notify_one();
+                    self.0.readable_blocking.notify_one();
+                    return Ok(());
+                }
+                match self.0.policy {
+                    ChannelPolicy::Backpressure => {}
+                    ChannelPolicy::DropOldest => {
+                        buffer.pop_front();
+                        buffer.push_back(snapshot);
+                        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    ChannelPolicy::DropNewest => {
+                        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                }
+            }
+            // a permit is stored when the receiver takes one before this waits
+            self.0.writable.notified().await;
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        // under the lock, so a blocking receiver checks the flag either before or after waiting
+        let _buffer = self.0.buffer.lock().unwrap();
+        self.0.sender_closed.store(true, Ordering::Release);
+        self.0.readable.notify_one();
+        self.0.readable_blocking.notify_one();
+    }
+}
+
+impl Receiver {
+    /// Returns the oldest queued snapshot, None once the queue is empty and the sender dropped.
+    pub(crate) async fn recv(&mut self) -> Option<Snapshot> {
+        loop {
+            {
+                let mut buffer = self.0.buffer.lock().unwrap();
+                if let Some(snapshot) = buffer.pop_front() {
+                    self.0.writable.notify_one();
+                    return Some(snapshot);
+                }
+                if self.0.sender_closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            self.0.readable.notified().await;
+        }
+    }
+
+    /// Same as recv(), blocking the thread instead, without an executor, see Stream::iter()
+    pub(crate) fn blocking_recv(&mut self) -> Option<Snapshot> {
+        let mut buffer = self.0.buffer.lock().unwrap();
+        loop {
+            if let Some(snapshot) = buffer.pop_front() {
+                self.0.writable.notify_one();
+                return Some(snapshot);
+            }
+            if self.0.sender_closed.load(Ordering::Acquire) {
+                return None;
+            }
+            buffer = self.0.readable_blocking.wait(buffer).unwrap();
+        }
+    }
+
+    /// Number of snapshots dropped by the policy so far.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.0.receiver_closed.store(true, Ordering::Release);
+        self.0.writable.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::runtime;
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    async fn drain(tx: Sender, mut rx: Receiver) -> (Vec<u64>, u64) {
+        drop(tx);
+        let mut timestamps = Vec::new();
+        while let Some(snapshot) = rx.recv().await {
+            timestamps.push(snapshot.timestamp);
+        }
+        (timestamps, rx.dropped())
+    }
+
+    #[test]
+    pub fn test_drop_oldest() {
+        runtime::Runtime::new().unwrap().block_on(async {
+            let (tx, rx) = channel(ChannelPolicy::DropOldest, 2);
+            for timestamp in 1..=4 {
+                tx.send(snapshot(timestamp)).await.unwrap();
+            }
+            assert_eq!(drain(tx, rx).await, (vec![3, 4], 2));
+        });
+    }
+
+    #[test]
+    pub fn test_drop_newest() {
+        runtime::Runtime::new().unwrap().block_on(async {
+            let (tx, rx) = channel(ChannelPolicy::DropNewest, 2);
+            for timestamp in 1..=4 {
+                tx.send(snapshot(timestamp)).await.unwrap();
+            }
+            assert_eq!(drain(tx, rx).await, (vec![1, 2], 2));
+        });
+    }
+
+    #[test]
+    pub fn test_backpressure() {
+        runtime::Runtime::new().unwrap().block_on(async {
+            let (tx, mut rx) = channel(ChannelPolicy::Backpressure, 1);
+            tx.send(snapshot(1)).await.unwrap();
+
+            let send = tokio::spawn(async move {
+                tx.send(snapshot(2)).await.unwrap();
+                tx
+            });
+            assert_eq!(rx.recv().await.unwrap().timestamp, 1);
+            let tx = send.await.unwrap();
+            assert_eq!(drain(tx, rx).await, (vec![2], 0));
+        });
+    }
+
+    #[test]
+    pub fn test_receiver_dropped() {
+        runtime::Runtime::new().unwrap().block_on(async {
+            let (tx, rx) = channel(ChannelPolicy::Backpressure, 1);
+            tx.send(snapshot(1)).await.unwrap();
+            drop(rx);
+            assert_eq!(tx.send(snapshot(2)).await, Err(()));
+        });
+    }
+
+    #[test]
+    pub fn test_blocking_recv() {
+        let runtime = runtime::Runtime::new().unwrap();
+        let (tx, mut rx) = channel(ChannelPolicy::Backpressure, 1);
+        runtime.spawn(async move {
+            for timestamp in 1..=3 {
+                tx.send(snapshot(timestamp)).await.unwrap();
+            }
+        });
+
+        // the thread blocks without driving the sender, which waits for room meanwhile
+        let mut timestamps = Vec::new();
+        while let Some(snapshot) = rx.blocking_recv() {
+            timestamps.push(snapshot.timestamp);
+        }
+        assert_eq!(timestamps, vec![1, 2, 3]);
+    }
+}
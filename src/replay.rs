@@ -0,0 +1,210 @@
+//! Replay of recorded sessions as snapshots, e.g. for backtests. The recordings are the files
+//! of Snapshot::parse_file(..), raw frames one per line, optionally after a millisecond offset.
+//!
+//! The replay waits between the snapshots as long as their server timestamps are apart,
+//! divided by the speed, and does not wait by default.
+
+use std::{io, path::Path, thread, time::Duration, vec};
+
+use crate::data::Snapshot;
+
+/// Blocking iterator over the snapshots of one or more recordings.
+///
+/// ```no_run
+/// use forexpros_wss::replay::ReplayStream;
+///
+/// let replay = ReplayStream::merge(&["btc.txt", "hsi.txt"]).unwrap().speed(60.0);
+/// for snapshot in replay {
+///     println!("{} {}", snapshot.pid, snapshot.last);
+/// }
+/// ```
+pub struct ReplayStream {
+    snapshots: vec::IntoIter<Snapshot>,
+    speed: f64,
+    skipped: usize,
+    last_timestamp: Option<u64>,
+}
+
+impl ReplayStream {
+    /// Replays the snapshots of a recording in the recorded order.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (snapshots, skipped) = Snapshot::parse_file(path)?;
+        Ok(Self::new(snapshots, skipped))
+    }
+
+    /// Replays a recording from the time `start` into it, by the timestamps from the first
+    /// snapshot: the snapshots before the first one at or after `start` are skipped. Empty
+    /// when `start` is beyond the end of the recording.
+    pub fn from_file_at(path: impl AsRef<Path>, start: Duration) -> io::Result<Self> {
+        let (snapshots, skipped) = Snapshot::parse_file(path)?;
+        let first = snapshots.first().map_or(0, |snapshot| snapshot.timestamp);
+        let snapshots = snapshots
+            .into_iter()
+            .skip_while(|snapshot| {
+                Duration::from_secs(snapshot.timestamp.saturating_sub(first)) < start
+            })
+            .collect();
+        Ok(Self::new(snapshots, skipped))
+    }
+
+    /// Replays the snapshots of several recordings, e.g. one per instrument, merged in the
+    /// order of their timestamps. Snapshots of the same timestamp keep the order of the
+    /// paths, then the recorded order, so the merge is the same on every run.
+    pub fn merge<P: AsRef<Path>>(paths: &[P]) -> io::Result<Self> {
+        let mut snapshots = Vec::new();
+        let mut skipped = 0;
+        for path in paths {
+            let (file_snapshots, file_skipped) = Snapshot::parse_file(path)?;
+            snapshots.extend(file_snapshots);
+            skipped += file_skipped;
+        }
+        // stable, see Snapshot::cmp_by_timestamp(..)
+        snapshots.sort_by(Snapshot::cmp_by_timestamp);
+        Ok(Self::new(snapshots, skipped))
+    }
+
+    fn new(snapshots: Vec<Snapshot>, skipped: usize) -> Self {
+        ReplayStream {
+            snapshots: snapshots.into_iter(),
+            speed: f64::INFINITY,
+            skipped,
+            last_timestamp: None,
+        }
+    }
+
+    /// Multiplier of the recorded pace, e.g. 60.0 replays a minute per second and 1.0 in real
+    /// time. Default is f64::INFINITY, without waiting, as do zero and negative speeds.
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Number of the recorded lines which failed to parse, see Snapshot::parse_file(..)
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Time to wait before the snapshot of the timestamp.
+    fn delay(&self, timestamp: u64) -> Option<Duration> {
+        let elapsed = timestamp.saturating_sub(self.last_timestamp?);
+        if elapsed == 0 || !self.speed.is_finite() || self.speed <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(elapsed as f64 / self.speed))
+    }
+}
+
+impl Iterator for ReplayStream {
+    type Item = Snapshot;
+
+    fn next(&mut self) -> Option<Snapshot> {
+        let snapshot = self.snapshots.next()?;
+        if let Some(delay) = self.delay(snapshot.timestamp) {
+            thread::sleep(delay);
+        }
+        self.last_timestamp = Some(snapshot.timestamp);
+        Some(snapshot)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.snapshots.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::Instant};
+
+    use super::*;
+    use crate::testing::snapshot_frame;
+
+    /// Writes a recording of the pair id with the timestamps, returning its path.
+    fn record(name: &str, pid: &str, timestamps: &[u64]) -> PathBuf {
+        let frames: String = timestamps
+            .iter()
+            .map(|timestamp| {
+                let json = format!(
+                    r#"{{"pid":"{}","last_numeric":24871.5,"last":"24,871.5","bid":"24,866.0","ask":"24,877.0","high":"24,979.0","low":"24,533.0","pc":"+364.0","pcp":"+1.49%","pc_col":"greenFont","time":"3:20:58","timestamp":{}}}"#,
+                    pid, timestamp
+                );
+                snapshot_frame(&json) + "\n"
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("replay_{}_{}.txt", name, std::process::id()));
+        fs::write(&path, frames).unwrap();
+        path
+    }
+
+    #[test]
+    pub fn test_merge() {
+        let long = record("merge_long", "945629", &[10, 12, 14, 16, 18, 20]);
+        let short = record("merge_short", "8984", &[12, 13]);
+
+        let replay = ReplayStream::merge(&[&long, &short]).unwrap();
+        assert_eq!(replay.skipped(), 0);
+        assert_eq!(replay.size_hint(), (8, Some(8)));
+        let merged: Vec<_> = replay
+            .map(|snapshot| (snapshot.pid, snapshot.timestamp))
+            .collect();
+        let expected = [
+            ("945629", 10),
+            ("945629", 12),
+            ("8984", 12),
+            ("8984", 13),
+            ("945629", 14),
+            ("945629", 16),
+            ("945629", 18),
+            ("945629", 20),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(pid, timestamp)| (pid.to_string(), *timestamp))
+            .collect();
+        assert_eq!(merged, expected);
+
+        // the ties follow the order of the paths
+        let replay = ReplayStream::merge(&[&short, &long]).unwrap();
+        let pids: Vec<_> = replay.map(|snapshot| snapshot.pid).collect();
+        assert_eq!(pids[1..3], ["8984", "945629"]);
+
+        fs::remove_file(&long).unwrap();
+        fs::remove_file(&short).unwrap();
+        assert!(ReplayStream::merge(&["missing.txt"]).is_err());
+    }
+
+    #[test]
+    pub fn test_from_file_at() {
+        let path = record("at", "945629", &[10, 11, 13, 20]);
+        let timestamps = |start| {
+            ReplayStream::from_file_at(&path, start)
+                .unwrap()
+                .map(|snapshot| snapshot.timestamp)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(timestamps(Duration::ZERO), [10, 11, 13, 20]);
+        assert_eq!(timestamps(Duration::from_secs(2)), [13, 20]);
+        assert_eq!(timestamps(Duration::from_millis(2500)), [13, 20]);
+        assert_eq!(timestamps(Duration::from_secs(4)), [20]);
+        assert!(timestamps(Duration::from_secs(3600)).is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    pub fn test_speed() {
+        let path = record("speed", "945629", &[10, 11, 13]);
+
+        let start = Instant::now();
+        assert_eq!(ReplayStream::from_file(&path).unwrap().count(), 3);
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // 3 seconds at 30 times the pace
+        let start = Instant::now();
+        let replay = ReplayStream::from_file(&path).unwrap().speed(30.0);
+        assert_eq!(replay.count(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! Readable names of the pair ids, e.g. for logs, see `symbol_for(..)`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Mutex, RwLock},
+};
+
+use crate::data::Snapshot;
+
+/// Bundled names of common pair ids, found by `symbol_for(..)` unless registered otherwise.
+pub const BUILTIN_SYMBOLS: [(&str, &str); 12] = [
+    ("1", "EUR/USD"),
+    ("2", "GBP/USD"),
+    ("3", "USD/JPY"),
+    ("4", "USD/CHF"),
+    ("5", "AUD/USD"),
+    ("166", "US 500"),
+    ("169", "Dow Jones"),
+    ("8830", "Gold Futures"),
+    ("8849", "Crude Oil WTI Futures"),
+    ("8984", "HK50 Futures"),
+    ("14958", "Nasdaq 100"),
+    ("945629", "BTC/USD"),
+];
+
+static REGISTERED: RwLock<BTreeMap<String, &'static str>> = RwLock::new(BTreeMap::new());
+
+/// Registered names, each allocated once however often registered.
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Name of the pair id, registered with `register_symbol(..)` or else bundled in BUILTIN_SYMBOLS.
+pub fn symbol_for(pid: &str) -> Option<&'static str> {
+    if let Some(name) = REGISTERED.read().unwrap().get(pid) {
+        return Some(name);
+    }
+    BUILTIN_SYMBOLS
+        .iter()
+        .find(|(builtin, _)| *builtin == pid)
+        .map(|(_, name)| *name)
+}
+
+/// Registers the name of the pair id for the whole process, replacing a bundled or registered one.
+///
+/// The names are interned and never freed, so registering the same names again, e.g. on each
+/// start of a stream, does not grow the memory, unlike names changing over time.
+pub fn register_symbol(pid: &str, name: &str) {
+    let name = intern(name);
+    REGISTERED.write().unwrap().insert(pid.to_string(), name);
+}
+
+/// Returns the name allocated for the process, allocating it on its first registration.
+fn intern(name: &str) -> &'static str {
+    let mut names = NAMES.lock().unwrap();
+    match names.get(name) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+            names.insert(interned);
+            interned
+        }
+    }
+}
+
+impl Snapshot {
+    /// Name of the pid of the snapshot, see `symbol_for(..)`
+    pub fn symbol(&self) -> Option<&'static str> {
+        symbol_for(&self.pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_symbol_for() {
+        assert_eq!(symbol_for("945629"), Some("BTC/USD"));
+        assert_eq!(symbol_for("8984"), Some("HK50 Futures"));
+        assert_eq!(symbol_for("olia"), None);
+
+        register_symbol("olia", "Olia");
+        register_symbol("8984", "Hang Seng 50");
+        assert_eq!(symbol_for("olia"), Some("Olia"));
+        assert_eq!(symbol_for("8984"), Some("Hang Seng 50"));
+
+        // the same name registered again is not allocated again
+        register_symbol("olia2", "Olia");
+        assert!(std::ptr::eq(
+            symbol_for("olia").unwrap(),
+            symbol_for("olia2").unwrap()
+        ));
+
+        let snapshot = Snapshot {
+            pid: "945629".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.symbol(), Some("BTC/USD"));
+    }
+}
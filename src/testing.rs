@@ -0,0 +1,349 @@
+//! Mock SockJS server for tests without the live server, enabled by the `testing` feature.
+//! in_memory_stream(..) skips the server as well, for tests of the code consuming a Stream.
+//!
+//! ```no_run
+//! use forexpros_wss::{push::Stream, testing::{snapshot_frame, MockServer}};
+//!
+//! let server = MockServer::start(vec![snapshot_frame(
+//!     r#"{"pid":"8984","last_numeric":24871.5,"last":"24,871.5","bid":"24,866.0","ask":"24,877.0","high":"24,979.0","low":"24,533.0","pc":"+364.0","pcp":"+1.49%","pc_col":"greenFont","time":"3:20:58","timestamp":1597116058}"#,
+//! )]);
+//!
+//! let stream = Stream::builder("8984".to_string())
+//!     .url(&server.url())
+//!     .build(|snapshot| {
+//!         println!("{:?}", snapshot);
+//!         Err(())
+//!     })
+//!     .unwrap();
+//! stream.runtime.block_on(stream.stream_handle_spawn).unwrap().unwrap();
+//! ```
+
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{net::TcpListener, runtime, sync::mpsc, time};
+use tokio_tungstenite::tungstenite::{
+    protocol::frame::{
+        coding::{Data, OpCode},
+        Frame,
+    },
+    Message,
+};
+
+use crate::{
+    data::Snapshot,
+    error::Error,
+    protocol::parse_close_frame,
+    push::{Stream, SNAPSHOT_BUFFER},
+};
+
+/// Answer of the server to heartbeat_message()
+const HEARTBEAT_FRAME: &str = r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"#;
+
+/// Server accepting any number of connections on 127.0.0.1, each playing the same script:
+///
+/// 1. sends the SockJS open frame "o", or the frames of start_with_open(..)
+/// 2. reads the bulk-subscribe and UID messages
+/// 3. sends the scripted frames, at their offsets with start_timed(..), and closes the
+///    connection after a close frame `c[..]`
+/// 4. answers each heartbeat message with the heartbeat data frame, until the client closes
+///
+/// The server stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<String>>>,
+    connections: Arc<Mutex<usize>>,
+    _runtime: runtime::Runtime,
+}
+
+impl MockServer {
+    /// Start the server with the frames to send after the subscription.
+    pub fn start(frames: Vec<String>) -> Self {
+        Self::start_timed(
+            frames
+                .into_iter()
+                .map(|frame| (Duration::ZERO, frame))
+                .collect(),
+        )
+    }
+
+    /// Start the server with the frames to send at their offsets from the subscription,
+    /// e.g. of `Fixture::load(..)`
+    pub fn start_timed(frames: Vec<(Duration, String)>) -> Self {
+        Self::start_with(open_frame(), text_frames(frames), None)
+    }
+
+    /// Same as MockServer::start(..), sending the frames as binary websocket frames, which
+    /// unlike text frames may be of invalid UTF-8.
+    pub fn start_binary(frames: Vec<Vec<u8>>) -> Self {
+        Self::start_with(
+            open_frame(),
+            frames
+                .into_iter()
+                .map(|frame| (Duration::ZERO, Message::Binary(frame)))
+                .collect(),
+            None,
+        )
+    }
+
+    /// Same as MockServer::start(..), sending the open frames in place of the SockJS open
+    /// frame "o", e.g. a heartbeat "h" before it, or no "o" at all.
+    pub fn start_with_open(open: Vec<String>, frames: Vec<String>) -> Self {
+        Self::start_with(
+            open,
+            text_frames(frames.into_iter().map(|frame| (Duration::ZERO, frame))),
+            None,
+        )
+    }
+
+    /// Same as MockServer::start(..), sending the frames longer than the fragment size as a
+    /// websocket text frame and continuation frames of at most the fragment size each, as
+    /// large messages may arrive.
+    pub fn start_fragmented(frames: Vec<String>, fragment_size: usize) -> Self {
+        Self::start_with(
+            open_frame(),
+            text_frames(frames.into_iter().map(|frame| (Duration::ZERO, frame))),
+            Some(fragment_size.max(1)),
+        )
+    }
+
+    fn start_with(
+        open: Vec<String>,
+        frames: Vec<(Duration, Message)>,
+        fragment_size: Option<usize>,
+    ) -> Self {
+        let runtime = runtime::Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(0));
+
+        let accept_received = received.clone();
+        let accept_connections = connections.clone();
+        runtime.spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                *accept_connections.lock().unwrap() += 1;
+                let open = open.clone();
+                let frames = frames.clone();
+                let received = accept_received.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(socket, open, frames, fragment_size, received).await {
+                        log::warn!("Mock server connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        MockServer {
+            addr,
+            received,
+            connections,
+            _runtime: runtime,
+        }
+    }
+
+    /// URL of the server, for StreamBuilder::url(..)
+    pub fn url(&self) -> String {
+        format!("ws://{}/echo/websocket", self.addr)
+    }
+
+    /// Text messages received from all clients so far, in order.
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+}
+
+/// The SockJS open frame alone, sent by default.
+fn open_frame() -> Vec<String> {
+    vec!["o".to_string()]
+}
+
+/// The frames as websocket text frames.
+fn text_frames(frames: impl IntoIterator<Item = (Duration, String)>) -> Vec<(Duration, Message)> {
+    frames
+        .into_iter()
+        .map(|(offset, frame)| (offset, Message::Text(frame)))
+        .collect()
+}
+
+async fn serve(
+    socket: tokio::net::TcpStream,
+    open: Vec<String>,
+    frames: Vec<(Duration, Message)>,
+    fragment_size: Option<usize>,
+    received: Arc<Mutex<Vec<String>>>,
+) -> Result<(), tungstenite::Error> {
+    let mut ws = tokio_tungstenite::accept_async(socket).await?;
+    for frame in open {
+        ws.send(Message::Text(frame)).await?;
+    }
+
+    let mut subscribed = 0;
+    while subscribed < 2 {
+        match ws.next().await {
+            Some(Ok(Message::Text(msg))) => {
+                received.lock().unwrap().push(msg);
+                subscribed += 1;
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e),
+            None => return Ok(()),
+        }
+    }
+
+    let subscribed_at = time::Instant::now();
+    for (offset, frame) in frames {
+        time::sleep_until(subscribed_at + offset).await;
+        let close = frame
+            .to_text()
+            .is_ok_and(|frame| parse_close_frame(frame).is_some());
+        match (fragment_size, &frame) {
+            (Some(size), Message::Text(frame)) if frame.len() > size => {
+                let fragments: Vec<_> = frame.as_bytes().chunks(size).collect();
+                for (idx, fragment) in fragments.iter().enumerate() {
+                    let opcode = match idx {
+                        0 => OpCode::Data(Data::Text),
+                        _ => OpCode::Data(Data::Continue),
+                    };
+                    let is_final = idx + 1 == fragments.len();
+                    let fragment = Frame::message(fragment.to_vec(), opcode, is_final);
+                    ws.send(Message::Frame(fragment)).await?;
+                }
+            }
+            _ => ws.send(frame).await?,
+        }
+        if close {
+            return ws.close(None).await;
+        }
+    }
+
+    while let Some(msg) = ws.next().await {
+        if let Message::Text(msg) = msg? {
+            let heartbeat = msg.contains("heartbeat");
+            received.lock().unwrap().push(msg);
+            if heartbeat {
+                ws.send(Message::Text(HEARTBEAT_FRAME.to_string())).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recorded session of frames from the server, to replay with `MockServer::start_timed(..)`
+///
+/// A fixture file has a line per frame, `OFFSET\tFRAME`: the offset of the frame in
+/// milliseconds from the subscription, a tab, and the raw frame as received. Empty lines and
+/// lines starting with '#' are skipped.
+///
+/// See tests/fixtures/session.txt of the crate.
+pub struct Fixture;
+
+impl Fixture {
+    /// Reads the frames of the fixture file with their offsets.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<(Duration, String)>> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Same as Fixture::load(..), from the content of a fixture file.
+    pub fn parse(src: &str) -> io::Result<Vec<(Duration, String)>> {
+        src.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(idx, line)| {
+                let invalid = |what| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {} of fixture: {}", idx + 1, what),
+                    )
+                };
+                let (offset, frame) = line.split_once('\t').ok_or_else(|| invalid("no tab"))?;
+                let offset = offset.parse().map_err(|_| invalid("invalid offset"))?;
+                Ok((Duration::from_millis(offset), frame.to_string()))
+            })
+            .collect()
+    }
+
+    /// Returns the content of a fixture file of the frames, the reverse of Fixture::parse(..)
+    pub fn format(frames: &[(Duration, String)]) -> String {
+        frames
+            .iter()
+            .map(|(offset, frame)| format!("{}\t{}\n", offset.as_millis(), frame))
+            .collect()
+    }
+}
+
+/// Returns the data frame carrying the instrument data, given as the JSON object of Snapshot.
+pub fn snapshot_frame(json: &str) -> String {
+    let pid = serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .and_then(|value| value["pid"].as_str().map(str::to_string))
+        .unwrap_or_default();
+
+    format!(
+        "a[\"{{\\\"message\\\":\\\"pid-{}::{}\\\"}}\"]",
+        pid,
+        json.replace('"', "\\\\\\\"")
+    )
+}
+
+/// Returns a stream of the pair ids taking the snapshots sent to the sender, without server
+/// nor protocol, for tests of the code consuming a Stream. Read them with
+/// Stream::next_snapshot(), which returns None once the sender is dropped.
+///
+/// See StreamBuilder::snapshot_source(..) for the other options of the stream, e.g. a handler.
+pub fn in_memory_stream(pair_id: &str) -> Result<(Stream, mpsc::Sender<Snapshot>), Error> {
+    let (tx, rx) = mpsc::channel(SNAPSHOT_BUFFER);
+    let stream = Stream::builder(pair_id.to_string())
+        .snapshot_source(rx)
+        .build_receiver()?;
+    Ok((stream, tx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_snapshot_frame() {
+        let json = r#"{"pid":"8984","last_numeric":24871.5,"last":"24,871.5","bid":"24,866.0","ask":"24,877.0","high":"24,979.0","low":"24,533.0","pc":"+364.0","pcp":"+1.49%","pc_col":"greenFont","time":"3:20:58","timestamp":1597116058}"#;
+
+        let snapshot = Snapshot::from_str(&snapshot_frame(json));
+
+        assert_eq!(snapshot.pid, "8984");
+        assert_eq!(snapshot.last_numeric, 24871.5);
+        assert_eq!(snapshot.timestamp, 1597116058);
+    }
+
+    #[test]
+    pub fn test_fixture() {
+        let frames = vec![
+            (Duration::ZERO, "o".to_string()),
+            (
+                Duration::from_millis(1500),
+                r#"c[3000,"Go away!"]"#.to_string(),
+            ),
+        ];
+        let src = Fixture::format(&frames);
+        assert_eq!(src, "0\to\n1500\tc[3000,\"Go away!\"]\n");
+        assert_eq!(
+            Fixture::parse(&format!("# comment\n\n{}", src)).unwrap(),
+            frames
+        );
+
+        assert!(Fixture::parse("0 o").is_err());
+        assert!(Fixture::parse("x\to").is_err());
+    }
+}
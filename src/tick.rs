@@ -0,0 +1,85 @@
+//! Numeric tick of a snapshot, for crates depending on the prices without the string fields.
+
+use std::{convert::TryFrom, fmt};
+
+use crate::data::Snapshot;
+
+/// Prices of a snapshot as numbers, see `Tick::try_from(&snapshot)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tick {
+    pub pid: u64,
+
+    /// Last price, see `Snapshot::last_f64()`
+    pub price: f64,
+    pub bid: f64,
+    pub ask: f64,
+
+    /// Server timestamp, in seconds since the Unix epoch.
+    pub ts: i64,
+}
+
+/// Field of the snapshot which failed to convert into Tick.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TickError {
+    /// Name of the Snapshot field, e.g. "bid"
+    pub field: &'static str,
+}
+
+impl fmt::Display for TickError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {} of snapshot", self.field)
+    }
+}
+
+impl std::error::Error for TickError {}
+
+impl TryFrom<&Snapshot> for Tick {
+    type Error = TickError;
+
+    /// Parses the pid, the prices with the NumberFormat of the snapshot, and the timestamp.
+    fn try_from(snapshot: &Snapshot) -> Result<Self, Self::Error> {
+        let invalid = |field| TickError { field };
+
+        Ok(Tick {
+            pid: snapshot.pid.parse().map_err(|_| invalid("pid"))?,
+            price: snapshot.last_f64().ok_or_else(|| invalid("last"))?,
+            bid: snapshot.bid_f64().ok_or_else(|| invalid("bid"))?,
+            ask: snapshot.ask_f64().ok_or_else(|| invalid("ask"))?,
+            ts: i64::try_from(snapshot.timestamp).map_err(|_| invalid("timestamp"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_try_from() {
+        let mut snapshot = Snapshot {
+            pid: "945629".to_string(),
+            last: "18,951.2".into(),
+            bid: "18,954.0".to_string(),
+            ask: "18,956.0".to_string(),
+            timestamp: 1606850510,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Tick::try_from(&snapshot),
+            Ok(Tick {
+                pid: 945629,
+                price: 18951.2,
+                bid: 18954.0,
+                ask: 18956.0,
+                ts: 1606850510,
+            })
+        );
+
+        snapshot.bid = "".to_string();
+        assert_eq!(Tick::try_from(&snapshot), Err(TickError { field: "bid" }));
+
+        snapshot.pid = "pid".to_string();
+        assert_eq!(Tick::try_from(&snapshot), Err(TickError { field: "pid" }));
+    }
+}
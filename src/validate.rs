@@ -0,0 +1,183 @@
+//! Sanity rules of snapshots, to drop or flag bad frames, see `Snapshot::validate()`.
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::data::Snapshot;
+
+/// Earliest plausible timestamp, 2000-01-01 00:00:00 UTC
+pub const MIN_TIMESTAMP: u64 = 946_684_800;
+
+/// How far ahead of the local clock a timestamp is still plausible, for clock skew.
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Violation of a rule, see ValidationRules.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    /// The pid is empty.
+    EmptyPid,
+
+    /// The bid is above the ask.
+    CrossedMarket { bid: f64, ask: f64 },
+
+    /// The last price is outside of the low and the high of the session.
+    LastOutOfRange { low: f64, last: f64, high: f64 },
+
+    /// The timestamp is before MIN_TIMESTAMP or more than MAX_CLOCK_SKEW ahead of the local clock.
+    ImplausibleTimestamp(u64),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::EmptyPid => write!(f, "empty pid"),
+            ValidationError::CrossedMarket { bid, ask } => {
+                write!(f, "bid {} above ask {}", bid, ask)
+            }
+            ValidationError::LastOutOfRange { low, last, high } => {
+                write!(f, "last {} outside of low {} and high {}", last, low, high)
+            }
+            ValidationError::ImplausibleTimestamp(timestamp) => {
+                write!(f, "implausible timestamp {}", timestamp)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Rules checked by `Snapshot::validate_with(..)`, all enabled by default.
+///
+/// Rules comparing prices are skipped when a price is empty or malformed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidationRules {
+    /// The pid is not empty.
+    pub pid: bool,
+
+    /// The bid is at most the ask. Markets cross briefly and legitimately, disable it to keep those.
+    pub bid_ask: bool,
+
+    /// The last price is within the low and the high.
+    pub last_in_range: bool,
+
+    /// The timestamp is plausible, see ValidationError::ImplausibleTimestamp.
+    pub timestamp: bool,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        ValidationRules {
+            pid: true,
+            bid_ask: true,
+            last_in_range: true,
+            timestamp: true,
+        }
+    }
+}
+
+impl Snapshot {
+    /// Checks the snapshot against all the rules, see ValidationRules.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        self.validate_with(&ValidationRules::default())
+    }
+
+    /// Checks the snapshot against the enabled rules. Returns every violation.
+    pub fn validate_with(&self, rules: &ValidationRules) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+
+        if rules.pid && self.pid.is_empty() {
+            errors.push(ValidationError::EmptyPid);
+        }
+        if rules.bid_ask {
+            if let (Some(bid), Some(ask)) = (self.bid_f64(), self.ask_f64()) {
+                if bid > ask {
+                    errors.push(ValidationError::CrossedMarket { bid, ask });
+                }
+            }
+        }
+        if rules.last_in_range {
+            if let (Some(low), Some(last), Some(high)) =
+                (self.low_f64(), self.last_f64(), self.high_f64())
+            {
+                if last < low || last > high {
+                    errors.push(ValidationError::LastOutOfRange { low, last, high });
+                }
+            }
+        }
+        if rules.timestamp {
+            let latest = (SystemTime::now() + MAX_CLOCK_SKEW)
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |latest| latest.as_secs());
+            if self.timestamp < MIN_TIMESTAMP || self.timestamp > latest {
+                errors.push(ValidationError::ImplausibleTimestamp(self.timestamp));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            pid: "945629".to_string(),
+            last: "18,951.2".into(),
+            bid: "18,954.0".to_string(),
+            ask: "18,956.0".to_string(),
+            high: "19,956.0".to_string(),
+            low: "18,279.0".to_string(),
+            timestamp: 1606850510,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn test_validate() {
+        assert_eq!(snapshot().validate(), Ok(()));
+
+        let mut snapshot = snapshot();
+        snapshot.pid = "".to_string();
+        snapshot.bid = "18,957.0".to_string();
+        snapshot.last = "20,000.0".into();
+        snapshot.timestamp = 0;
+        assert_eq!(
+            snapshot.validate(),
+            Err(vec![
+                ValidationError::EmptyPid,
+                ValidationError::CrossedMarket {
+                    bid: 18957.0,
+                    ask: 18956.0
+                },
+                ValidationError::LastOutOfRange {
+                    low: 18279.0,
+                    last: 20000.0,
+                    high: 19956.0
+                },
+                ValidationError::ImplausibleTimestamp(0),
+            ])
+        );
+    }
+
+    #[test]
+    pub fn test_validate_with() {
+        let mut snapshot = snapshot();
+        snapshot.bid = "18,957.0".to_string();
+        snapshot.high = "".to_string();
+
+        let rules = ValidationRules {
+            bid_ask: false,
+            ..Default::default()
+        };
+        // crossed market allowed, no high to compare the last with
+        assert_eq!(snapshot.validate_with(&rules), Ok(()));
+    }
+}
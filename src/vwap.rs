@@ -0,0 +1,134 @@
+//! Running volume-weighted average price of an instrument.
+
+use crate::data::Snapshot;
+
+/// How `Snapshot::turnover_value()` relates to the traded volume, see Vwap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnoverMode {
+    /// The turnover is the running total of the session, as sent by the server. The volume
+    /// of a snapshot is the increase over the previous one; a decrease starts a new total.
+    Cumulative,
+
+    /// The turnover of each snapshot is the volume traded since the previous one.
+    PerTick,
+}
+
+/// Accumulates cumulative (price × volume) and cumulative volume of the snapshots fed in.
+///
+/// The price of a snapshot is `last_f64()` and its volume comes from `turnover_value()`,
+/// by default as a cumulative total, see TurnoverMode. Feed the snapshots of a single pid
+/// in order, and reset at session boundaries.
+#[derive(Clone, Debug)]
+pub struct Vwap {
+    mode: TurnoverMode,
+    price_volume: f64,
+    volume: f64,
+    last_turnover: Option<f64>,
+}
+
+impl Default for Vwap {
+    fn default() -> Self {
+        Vwap::new(TurnoverMode::Cumulative)
+    }
+}
+
+impl Vwap {
+    /// Empty accumulator with the given interpretation of the turnover.
+    pub fn new(mode: TurnoverMode) -> Self {
+        Vwap {
+            mode,
+            price_volume: 0.0,
+            volume: 0.0,
+            last_turnover: None,
+        }
+    }
+
+    /// Adds the snapshot. Snapshots without price or turnover are skipped.
+    ///
+    /// In cumulative mode the first snapshot only sets the starting total, as the volume
+    /// traded before it is unknown.
+    pub fn update(&mut self, snapshot: &Snapshot) {
+        let (price, turnover) = match (snapshot.last_f64(), snapshot.turnover_value()) {
+            (Some(price), Some(turnover)) => (price, turnover),
+            _ => return,
+        };
+
+        let volume = match self.mode {
+            TurnoverMode::PerTick => turnover,
+            TurnoverMode::Cumulative => {
+                let volume = match self.last_turnover {
+                    Some(last) if turnover >= last => turnover - last,
+                    Some(_) => turnover,
+                    None => 0.0,
+                };
+                self.last_turnover = Some(turnover);
+                volume
+            }
+        };
+
+        self.price_volume += price * volume;
+        self.volume += volume;
+    }
+
+    /// Volume-weighted average price, None before any volume was traded.
+    pub fn value(&self) -> Option<f64> {
+        if self.volume > 0.0 {
+            Some(self.price_volume / self.volume)
+        } else {
+            None
+        }
+    }
+
+    /// Cumulative volume since the last reset.
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    /// Starts over, e.g. at a session boundary.
+    pub fn reset(&mut self) {
+        *self = Vwap::new(self.mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(last: &str, turnover_numeric: u64) -> Snapshot {
+        Snapshot {
+            last: last.into(),
+            turnover_numeric,
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    pub fn test_cumulative() {
+        let mut vwap = Vwap::default();
+
+        vwap.update(&snapshot("100.0", 1000));
+        assert_eq!(vwap.value(), None);
+
+        vwap.update(&snapshot("110.0", 1010));
+        vwap.update(&snapshot("1,000.0", 1010));
+        vwap.update(&snapshot("120.0", 1040));
+        assert_eq!(vwap.volume(), 40.0);
+        assert_eq!(vwap.value(), Some((110.0 * 10.0 + 120.0 * 30.0) / 40.0));
+
+        // the total restarts with a new session
+        vwap.update(&snapshot("130.0", 20));
+        assert_eq!(vwap.volume(), 60.0);
+
+        vwap.reset();
+        assert_eq!(vwap.value(), None);
+    }
+
+    #[test]
+    pub fn test_per_tick() {
+        let mut vwap = Vwap::new(TurnoverMode::PerTick);
+
+        vwap.update(&snapshot("100.0", 10));
+        vwap.update(&snapshot("120.0", 30));
+        assert_eq!(vwap.value(), Some(115.0));
+    }
+}
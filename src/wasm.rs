@@ -0,0 +1,147 @@
+//! Stream in the browser, enabled by the `wasm` feature on `wasm32-unknown-unknown`.
+//!
+//! The browser WebSocket through ws_stream_wasm replaces tokio-tungstenite, the tasks are
+//! spawned on the browser event loop with wasm-bindgen-futures instead of a tokio Runtime,
+//! and gloo-timers drives the heartbeat. Parsing of the frames is shared with `push`.
+//!
+//! `Snapshot::received_at` is left None, since the system clock is not available.
+
+use futures::{
+    future::{AbortHandle, Abortable},
+    SinkExt, StreamExt,
+};
+use gloo_timers::future::TimeoutFuture;
+use ws_stream_wasm::{WsMessage, WsMeta};
+
+use crate::{
+    data::Snapshot,
+    error::Error,
+    protocol::{
+        generate_stream_url, heartbeat_message, parse_close_frame, parse_snapshot_pid,
+        subscribe_message, uid_message, DEFAULT_TZ_ID,
+    },
+};
+
+/// Stream to the server in the browser, keep returning the Snapshot to the handler
+pub struct Stream {
+    pub pair_id: Box<str>,
+    abort_handle: AbortHandle,
+}
+
+impl Stream {
+    /// Spawn the connection to the server with the comma-separated pair ids, see `push::Stream::new(..)`.
+    ///
+    /// The handler returns Err(()) to stop the stream. Failures are logged.
+    pub fn new<F>(pair_id: String, handler: F) -> Self
+    where
+        F: Fn(Snapshot) -> Result<(), ()> + 'static,
+    {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let pair_id_str = pair_id.clone().into_boxed_str();
+
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Ok(Err(e)) = Abortable::new(run(pair_id, handler), abort_registration).await {
+                log::error!("Failed: {}", e);
+            }
+        });
+
+        Stream {
+            pair_id: pair_id_str,
+            abort_handle,
+        }
+    }
+
+    /// Stop the stream and its heartbeat.
+    pub fn close(&self) {
+        self.abort_handle.abort();
+    }
+}
+
+/// Abort the heartbeat when dropped, so it stops together with the receive loop.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn run<F>(pair_id: String, handler: F) -> Result<(), Error>
+where
+    F: Fn(Snapshot) -> Result<(), ()>,
+{
+    let url = generate_stream_url();
+    log::info!("URL: {}", url);
+
+    let (_meta, stream) = WsMeta::connect(url.as_str(), None)
+        .await
+        .map_err(Error::Wasm)?;
+    let (mut tx, mut rx) = stream.split();
+
+    // heartbeats may arrive before the open frame
+    loop {
+        match rx.next().await {
+            Some(WsMessage::Text(frame)) if frame == "o" => break,
+            Some(WsMessage::Text(frame)) if frame == "h" => {}
+            Some(WsMessage::Text(frame)) => {
+                return Err(match parse_close_frame(&frame) {
+                    Some((code, reason)) => Error::Closed { code, reason },
+                    None => Error::UnexpectedOpenFrame(frame),
+                })
+            }
+            Some(WsMessage::Binary(data)) => {
+                return Err(Error::UnexpectedOpenFrame(format!(
+                    "Binary Data<length={}>",
+                    data.len()
+                )))
+            }
+            None => return Err(Error::NoOpenFrame),
+        }
+    }
+
+    tx.send(WsMessage::Text(subscribe_message(&pair_id, DEFAULT_TZ_ID)))
+        .await
+        .map_err(Error::Wasm)?;
+    tx.send(WsMessage::Text(uid_message(0)))
+        .await
+        .map_err(Error::Wasm)?;
+
+    // send heartbeat responses to server until the receive loop is done
+    let (heartbeat_abort, heartbeat_registration) = AbortHandle::new_pair();
+    let _heartbeat_guard = AbortOnDrop(heartbeat_abort);
+    let heartbeat = async move {
+        loop {
+            if let Err(e) = tx.send(WsMessage::Text(heartbeat_message())).await {
+                log::warn!("Failed to send heartbeat to server: {}", e);
+                break;
+            }
+            TimeoutFuture::new(3200).await;
+        }
+    };
+    wasm_bindgen_futures::spawn_local(async move {
+        let _ = Abortable::new(heartbeat, heartbeat_registration).await;
+    });
+
+    while let Some(msg) = rx.next().await {
+        if let WsMessage::Text(msg) = msg {
+            if let Some((code, reason)) = parse_close_frame(&msg) {
+                return Err(Error::Closed { code, reason });
+            }
+            let subscribed =
+                parse_snapshot_pid(&msg).is_some_and(|pid| pair_id.split(',').any(|p| p == pid));
+            if !subscribed {
+                continue;
+            }
+            match Snapshot::try_from_str(&msg) {
+                Ok(snapshot) => {
+                    if handler(snapshot).is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(e) => log::warn!("Failed to parse {}: {}", msg, e),
+            }
+        }
+    }
+
+    Ok(())
+}
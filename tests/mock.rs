@@ -0,0 +1,2152 @@
+//! # Logic test: stream against the mock server
+//!
+//! Run with the testing feature:
+//!
+//!     cargo test --features testing --test mock
+
+use chrono::FixedOffset;
+use forexpros_wss::{
+	candle::PriceSource,
+	clock::{
+		Clock,
+		MockClock,
+	},
+	config::StreamConfig,
+	delta::SnapshotDelta,
+	data::{
+		InstrumentClass,
+		NumberFormat,
+		Snapshot,
+		TurnoverPolicy,
+	},
+	push::{
+		self,
+		ChannelPolicy,
+		ConnectionState,
+		DisconnectReason,
+		JsonlFlush,
+		ServerEvent,
+		Stream,
+		SubscriptionStatus,
+	},
+	testing::{
+		in_memory_stream,
+		snapshot_frame,
+		Fixture,
+		MockServer,
+	},
+	Error,
+};
+use std::{
+	collections::HashSet,
+	io::{
+		self,
+		Write,
+	},
+	ops::ControlFlow,
+	sync::{
+		Arc,
+		Mutex,
+	},
+	thread,
+	time::{
+		Duration,
+		Instant,
+	},
+};
+use tokio_util::sync::CancellationToken;
+
+fn frame ( pid: &str, timestamp: u64 ) -> String {
+	snapshot_frame ( &format ! ( r#"{{"pid":"{}","last_numeric":24871.5,"last":"24,871.5","bid":"24,866.0","ask":"24,877.0","high":"24,979.0","low":"24,533.0","pc":"+364.0","pcp":"+1.49%","pc_col":"greenFont","time":"3:20:58","timestamp":{}}}"#, pid, timestamp ) )
+}
+
+#[test]
+fn test_mock_snapshots ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( move |s| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( ( s.pid, s.timestamp ) );
+			if received.len ( ) < 3 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [
+		( "945629".to_string ( ), 1597116058 ),
+		( "8984".to_string ( ), 1597116059 ),
+		( "945629".to_string ( ), 1597116060 ),
+	] );
+	assert_eq! ( server.received ( )[..2], [ push::subscribe_message ( "945629,8984", 8 ), push::uid_message ( 0 ) ] );
+	assert_eq! ( server.connections ( ), 1 );
+}
+
+#[test]
+fn test_mock_fixture ( ) {
+	let frames = Fixture::load ( concat! ( env! ( "CARGO_MANIFEST_DIR" ), "/tests/fixtures/session.txt" ) ).unwrap ( );
+	assert_eq! ( frames.len ( ), 5 );
+	let server = MockServer::start_timed ( frames );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( move |s| {
+			received_clone.lock ( ).unwrap ( ).push ( ( s.pid, s.timestamp ) );
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	let started = Instant::now ( );
+	let result = stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( );
+
+	assert! ( matches! ( result, Err ( Error::Closed { code: 3000, .. } ) ) );
+	assert! ( started.elapsed ( ) >= Duration::from_millis ( 600 ) );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [
+		( "945629".to_string ( ), 1597116058 ),
+		( "8984".to_string ( ), 1597116059 ),
+		( "945629".to_string ( ), 1597116060 ),
+	] );
+}
+
+#[test]
+fn test_mock_pid_handlers ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116059 ),
+		frame ( "1", 1597116060 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_945629 = received.clone ( );
+	let received_8984 = received.clone ( );
+	let received_default = received.clone ( );
+	let stream = Stream::builder ( "945629,8984,1".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on ( "945629", move |s| {
+			received_945629.lock ( ).unwrap ( ).push ( ( "945629", s.timestamp ) );
+			Ok ( ( ) )
+		} )
+		.on ( "8984", move |s| {
+			received_8984.lock ( ).unwrap ( ).push ( ( "8984", s.timestamp ) );
+			Ok ( ( ) )
+		} )
+		.build ( move |s| {
+			received_default.lock ( ).unwrap ( ).push ( ( "default", s.timestamp ) );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [
+		( "945629", 1597116058 ),
+		( "8984", 1597116059 ),
+		( "default", 1597116060 ),
+	] );
+}
+
+#[test]
+fn test_mock_candles ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116120 ),
+	] );
+
+	let candles = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let candles_clone = candles.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_candle ( Duration::from_secs ( 60 ), PriceSource::Last, true, move |candle| {
+			candles_clone.lock ( ).unwrap ( ).push ( ( candle.start, candle.ticks, candle.partial ) );
+		} )
+		.build ( |s| if s.timestamp < 1597116120 { Ok ( ( ) ) } else { Err ( ( ) ) } )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	// the open candle is emitted when the stream ends, marked partial
+	assert_eq! ( *candles.lock ( ).unwrap ( ), vec! [ ( 1597116000, 2, false ), ( 1597116120, 1, true ) ] );
+}
+
+#[test]
+fn test_mock_candles_forward_fill ( ) {
+	let overnight = 1597116240 + 16 * 3600;
+	let server = MockServer::start ( vec! [
+		frame ( "8984", 1597116058 ),
+		frame ( "8984", 1597116240 ),
+		frame ( "8984", overnight ),
+	] );
+
+	let candles = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let candles_clone = candles.clone ( );
+	let stream = Stream::builder ( "8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_candle ( Duration::from_secs ( 60 ), PriceSource::Last, false, move |candle| {
+			candles_clone.lock ( ).unwrap ( ).push ( ( candle.start, candle.ticks ) );
+		} )
+		.forward_fill_candles ( true )
+		.instrument_class ( InstrumentClass::Sessioned )
+		.build ( move |s| if s.timestamp < overnight { Ok ( ( ) ) } else { Err ( ( ) ) } )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	// the quiet intervals are filled, not the session break
+	assert_eq! ( *candles.lock ( ).unwrap ( ), vec! [ ( 1597116000, 1 ), ( 1597116060, 0 ), ( 1597116120, 0 ), ( 1597116180, 0 ), ( 1597116240, 1 ) ] );
+}
+
+#[test]
+fn test_mock_connect_messages ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let messages = vec! [
+		r#"["{\"_event\":\"subscribe\",\"channel\":\"calendar\"}"]"#.to_string ( ),
+		push::subscribe_message ( "945629", 8 ),
+		push::uid_message ( 123 ),
+	];
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.connect_messages ( messages.clone ( ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( server.received ( )[..3], messages[..] );
+}
+
+#[test]
+fn test_mock_keep_raw ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let raw = Arc::new ( Mutex::new ( None ) );
+	let raw_clone = raw.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.keep_raw ( true )
+		.build ( move |s| {
+			*raw_clone.lock ( ).unwrap ( ) = s.raw;
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let raw = raw.lock ( ).unwrap ( ).clone ( ).unwrap ( );
+	assert! ( raw.starts_with ( r#"{"pid":"945629","last_numeric":24871.5"# ), "{}", raw );
+}
+
+#[test]
+fn test_mock_build_mut ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+	] );
+
+	// the state of the handler, sent out once the handler stops the stream
+	let (tx, rx) = std::sync::mpsc::channel ( );
+	let mut timestamps = Vec::new ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_mut ( move |snapshot| {
+			timestamps.push ( snapshot.timestamp );
+			if timestamps.len ( ) < 3 {
+				return Ok ( ( ) );
+			}
+			tx.send ( timestamps.clone ( ) ).unwrap ( );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( rx.recv ( ).unwrap ( ), vec! [ 1597116058, 1597116059, 1597116060 ] );
+}
+
+#[test]
+fn test_mock_build_async ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.handler_concurrency ( 4 )
+		.build_async ( move |snapshot| {
+			let received = received_clone.clone ( );
+			async move {
+				received.lock ( ).unwrap ( ).push ( snapshot.timestamp );
+				tokio::time::sleep ( Duration::from_millis ( 10 ) ).await;
+				if snapshot.timestamp < 1597116060 { Ok ( ( ) ) } else { Err ( ( ) ) }
+			}
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	let mut received = received.lock ( ).unwrap ( ).clone ( );
+	received.sort ( );
+	assert_eq! ( received, vec! [ 1597116058, 1597116059, 1597116060 ] );
+}
+
+#[test]
+fn test_mock_build_boxed ( ) {
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let handler = |idx: usize| -> push::Handler {
+		let received = received.clone ( );
+		Box::new ( move |snapshot| {
+			let mut received = received.lock ( ).unwrap ( );
+			received.push ( ( idx, snapshot.timestamp ) );
+			if snapshot.timestamp < 1597116059 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+	};
+	let handlers = vec! [ handler ( 0 ), handler ( 1 ) ];
+
+	let servers: Vec<_> = handlers.iter ( )
+		.map ( |_| MockServer::start ( vec! [ frame ( "945629", 1597116058 ), frame ( "945629", 1597116059 ) ] ) )
+		.collect ( );
+	let streams: Vec<Stream> = handlers.into_iter ( )
+		.zip ( &servers )
+		.map ( |( handler, server )| Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.build_boxed ( handler )
+			.expect ( "Failed to create stream" ) )
+		.collect ( );
+	for mut stream in streams {
+		stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	}
+
+	let mut received = received.lock ( ).unwrap ( ).clone ( );
+	received.sort ( );
+	assert_eq! ( received, vec! [ ( 0, 1597116058 ), ( 0, 1597116059 ), ( 1, 1597116058 ), ( 1, 1597116059 ) ] );
+}
+
+#[test]
+fn test_mock_build_raw ( ) {
+	let frames = vec! [ frame ( "945629", 1597116058 ), frame ( "945629", 1597116059 ) ];
+	let server = MockServer::start ( frames.clone ( ) );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.keep_raw ( true )
+		.build_raw ( move |frame, snapshot| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( ( frame, snapshot ) );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let received = received.lock ( ).unwrap ( );
+	for ( ( frame, snapshot ), expected ) in received.iter ( ).zip ( &frames ) {
+		assert_eq! ( frame, expected );
+		assert_eq! ( snapshot.timestamp, Snapshot::from_str ( frame ).timestamp );
+		// keep_raw(..) still keeps the instrument data, apart from the frame
+		assert_eq! ( snapshot.raw, Snapshot::try_from_str_raw ( frame ).unwrap ( ).raw );
+		assert_eq! ( snapshot.to_value ( ) [ "timestamp" ], snapshot.timestamp );
+		assert_eq! ( snapshot.frame, None );
+	}
+	assert_eq! ( received.len ( ), 2 );
+}
+
+#[test]
+fn test_mock_build_delta ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ), frame ( "945629", 1597116059 ) ] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_delta ( move |delta| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( delta );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let received = received.lock ( ).unwrap ( );
+	assert_eq! ( received [ 0 ].bid.as_deref ( ), Some ( "24,866.0" ) );
+	// the timestamp only changed
+	assert_eq! ( received [ 1 ], SnapshotDelta { pid: "945629".to_string ( ), timestamp: Some ( 1597116059 ), ..SnapshotDelta::default ( ) } );
+	let rebuilt = received [ 1 ].apply ( &received [ 0 ].apply ( &Snapshot::default ( ) ) );
+	assert! ( rebuilt.approx_eq ( &Snapshot::from_str ( &frame ( "945629", 1597116059 ) ), 0.0 ) );
+}
+
+#[test]
+fn test_mock_until ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_until ( move |s| {
+			received_clone.lock ( ).unwrap ( ).push ( s.timestamp );
+			if s.timestamp < 1597116059 { ControlFlow::Continue ( ( ) ) } else { ControlFlow::Break ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ 1597116058, 1597116059 ] );
+}
+
+#[test]
+fn test_mock_shared_runtime ( ) {
+	let runtime = Arc::new ( tokio::runtime::Runtime::new ( ).unwrap ( ) );
+	let servers: Vec<_> = [ "945629", "8984" ].iter ( )
+		.map ( |pid| ( pid.to_string ( ), MockServer::start ( vec! [ frame ( pid, 1597116058 ) ] ) ) )
+		.collect ( );
+
+	let streams: Vec<_> = servers.iter ( )
+		.map ( |( pid, server )| Stream::builder ( pid.clone ( ) )
+			.url ( &server.url ( ) )
+			.runtime ( runtime.clone ( ) )
+			.build ( |_| Err ( ( ) ) )
+			.expect ( "Failed to create stream" ) )
+		.collect ( );
+
+	for stream in streams {
+		assert! ( Arc::ptr_eq ( &stream.runtime, &runtime ) );
+		runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	}
+}
+
+#[test]
+fn test_mock_on_send ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let sent = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let sent_clone = sent.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_send ( move |message| sent_clone.lock ( ).unwrap ( ).push ( message.to_string ( ) ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( sent.lock ( ).unwrap ( )[..2], [ push::subscribe_message ( "945629", 8 ), push::uid_message ( 0 ) ] );
+}
+
+#[test]
+fn test_mock_session_max_age ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let reconnected = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let reconnected_clone = reconnected.clone ( );
+	let state = Arc::new ( Mutex::new ( None ) );
+	let state_clone = state.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.session_max_age ( Some ( Duration::from_millis ( 200 ) ) )
+		.on_reconnect ( move |event| if let push::ReconnectEvent::Reconnected { attempt } = event {
+			let state = state_clone.lock ( ).unwrap ( ).as_ref ( ).map ( |state: &tokio::sync::watch::Receiver<_>| *state.borrow ( ) );
+			reconnected_clone.lock ( ).unwrap ( ).push ( ( attempt, state ) );
+		} )
+		.build ( move |snapshot| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( snapshot.seq );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+	*state.lock ( ).unwrap ( ) = Some ( stream.state_receiver ( ) );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	assert_eq! ( server.connections ( ), 2 );
+	assert_eq! ( server.received ( ).iter ( ).filter ( |m| m.contains ( "bulk-subscribe" ) ).count ( ), 2 );
+	// the sequence numbers go on across the reconnect
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ Some ( 0 ), Some ( 1 ) ] );
+	// the first connect is no reconnect, the one after the session expired is
+	assert_eq! ( *reconnected.lock ( ).unwrap ( ), vec! [ ( 1, Some ( ConnectionState::Reconnecting ) ) ] );
+
+	let reasons: Vec<_> = stream.disconnects ( ).into_iter ( ).map ( |event| event.reason ).collect ( );
+	assert_eq! ( reasons, vec! [ DisconnectReason::Expired, DisconnectReason::Stopped ] );
+	assert! ( stream.disconnects ( ) [ 0 ].uptime >= Duration::from_millis ( 200 ) );
+	assert_eq! ( stream.uptime ( ), Duration::ZERO );
+	assert_eq! ( stream.connected_url ( ), Some ( server.url ( ) ) );
+	assert_eq! ( stream.connected_server ( ), None );
+	assert_eq! ( stream.disconnects ( ) [ 0 ].server, None );
+}
+
+#[test]
+fn test_mock_max_lifetime ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let start = Instant::now ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.max_lifetime ( Some ( Duration::from_millis ( 300 ) ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	// ends without reconnecting
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert! ( start.elapsed ( ) >= Duration::from_millis ( 300 ) );
+	assert_eq! ( server.connections ( ), 1 );
+	assert_eq! ( stream.state ( ), ConnectionState::Closed );
+	let reasons: Vec<_> = stream.disconnects ( ).into_iter ( ).map ( |event| event.reason ).collect ( );
+	assert_eq! ( reasons, vec! [ DisconnectReason::Lifetime ] );
+}
+
+#[test]
+fn test_mock_coalesce ( ) {
+	let frames = vec! [
+		( Duration::ZERO, frame ( "945629", 1 ) ),
+		( Duration::ZERO, frame ( "945629", 2 ) ),
+		( Duration::ZERO, frame ( "8984", 3 ) ),
+		( Duration::from_millis ( 300 ), frame ( "945629", 4 ) ),
+	];
+	let server = MockServer::start_timed ( frames );
+	let coalesced = |interval: u64, lifetime: u64| {
+		let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+		let received_clone = received.clone ( );
+		let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+			.url ( &server.url ( ) )
+			.coalesce ( Some ( Duration::from_millis ( interval ) ) )
+			.max_lifetime ( Some ( Duration::from_millis ( lifetime ) ) )
+			.build ( move |snapshot| {
+				received_clone.lock ( ).unwrap ( ).push ( ( snapshot.pid, snapshot.timestamp ) );
+				Ok ( ( ) )
+			} )
+			.expect ( "Failed to create stream" );
+		stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+		let received = received.lock ( ).unwrap ( ).clone ( );
+		received
+	};
+	let expected = |snapshots: &[( &str, u64 )]| snapshots.iter ( ).map ( |( pid, timestamp )| ( pid.to_string ( ), *timestamp ) ).collect::<Vec<_>> ( );
+
+	// the newest of each pid per flush
+	assert_eq! ( coalesced ( 200, 500 ), expected ( &[ ( "945629", 2 ), ( "8984", 3 ), ( "945629", 4 ) ] ) );
+	// the pending ones are flushed at the end
+	assert_eq! ( coalesced ( 10_000, 200 ), expected ( &[ ( "945629", 2 ), ( "8984", 3 ) ] ) );
+
+	// the lifetime ends in the middle of a flush, the rest of which is delivered at the end
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.coalesce ( Some ( Duration::from_millis ( 100 ) ) )
+		.max_lifetime ( Some ( Duration::from_millis ( 150 ) ) )
+		.build_async ( move |snapshot| {
+			received_clone.lock ( ).unwrap ( ).push ( ( snapshot.pid, snapshot.timestamp ) );
+			async {
+				tokio::time::sleep ( Duration::from_millis ( 100 ) ).await;
+				Ok ( ( ) )
+			}
+		} )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), expected ( &[ ( "945629", 2 ), ( "8984", 3 ) ] ) );
+}
+
+#[test]
+fn test_mock_in_memory_stream ( ) {
+	let snapshot = |pid: &str, timestamp| Snapshot { pid: pid.to_string ( ), timestamp, ..Default::default ( ) };
+	let (mut stream, tx) = in_memory_stream ( "945629,8984" ).unwrap ( );
+	let runtime = stream.runtime.clone ( );
+	runtime.block_on ( async {
+		tx.send ( snapshot ( "945629", 1 ) ).await.unwrap ( );
+		tx.send ( snapshot ( "8984", 2 ) ).await.unwrap ( );
+
+		let first = stream.next_snapshot ( ).await.unwrap ( );
+		assert_eq! ( ( first.pid.as_str ( ), first.timestamp, first.seq ), ( "945629", 1, Some ( 0 ) ) );
+		assert_eq! ( stream.next_snapshot ( ).await.unwrap ( ).timestamp, 2 );
+		assert_eq! ( stream.state ( ), ConnectionState::Connected );
+		assert_eq! ( stream.subscriptions ( ) [ "8984" ], SubscriptionStatus::Active );
+		assert_eq! ( stream.latest ( "945629" ).unwrap ( ).timestamp, 1 );
+
+		// dropping the sender closes the stream
+		drop ( tx );
+		assert! ( stream.next_snapshot ( ).await.is_none ( ) );
+	} );
+	runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	// with a handler, which stops the stream
+	let (tx, rx) = tokio::sync::mpsc::channel ( 8 );
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.snapshot_source ( rx )
+		.build ( move |snapshot| {
+			received_clone.lock ( ).unwrap ( ).push ( snapshot.timestamp );
+			if snapshot.timestamp < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+	for timestamp in 1..=3 {
+		tx.try_send ( snapshot ( "945629", timestamp ) ).unwrap ( );
+	}
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), [ 1, 2 ] );
+	assert_eq! ( stream.disconnects ( ) [ 0 ].reason, DisconnectReason::Stopped );
+}
+
+#[test]
+fn test_mock_ignored_frames ( ) {
+	let server = MockServer::start ( vec! [
+		r#"a["{\"_event\":\"tick\"}"]"#.to_string ( ),
+		r#"a["{\"_event\":\"heartbeat\",\"data\":\"h\"}"]"#.to_string ( ),
+		frame ( "945629", 1597116058 ),
+	] );
+
+	let ignored = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let ignored_clone = ignored.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_ignored_frame ( move |frame| ignored_clone.lock ( ).unwrap ( ).push ( frame.to_string ( ) ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.stats ( ).ignored_frames, 1 );
+	assert_eq! ( *ignored.lock ( ).unwrap ( ), vec! [ r#"a["{\"_event\":\"tick\"}"]"#.to_string ( ) ] );
+}
+
+#[test]
+fn test_mock_on_channel ( ) {
+	let server = MockServer::start ( vec! [
+		r#"a["{\"message\":\"pidTechSumm-945629::{\\\"summary\\\":\\\"Buy\\\"}\"}"]"#.to_string ( ),
+		frame ( "945629", 1597116058 ),
+	] );
+
+	let messages = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let messages_clone = messages.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_channel ( "pidTechSumm-", move |message| messages_clone.lock ( ).unwrap ( ).push ( message ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[1], push::channel_subscribe_message ( "pidTechSumm-", "945629", 8 ) );
+	let messages = messages.lock ( ).unwrap ( );
+	assert_eq! ( messages.len ( ), 1 );
+	assert_eq! ( ( messages[0].id.as_str ( ), messages[0].data.as_str ( ) ), ( "945629", r#"{"summary":"Buy"}"# ) );
+	assert_eq! ( stream.stats ( ).ignored_frames, 0 );
+}
+
+#[test]
+fn test_mock_frame_markers ( ) {
+	let server = MockServer::start ( vec! [
+		// the default layout is no quote of the stream
+		frame ( "945629", 1597116058 ),
+		r#"a["{\"data\":\"instrument-945629|{\\\"pid\\\":\\\"945629\\\",\\\"last_numeric\\\":24871.5,\\\"timestamp\\\":1597116059}\"}"]"#.to_string ( ),
+	] );
+
+	let markers = push::FrameMarkers {
+		message_key: "data".into ( ),
+		pid_prefix: "instrument-".into ( ),
+		data_separator: "|".into ( ),
+	};
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.frame_markers ( markers )
+		.build ( |snapshot| {
+			assert_eq! ( snapshot.timestamp, 1597116059 );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[0], push::channel_subscribe_message ( "instrument-", "945629", 8 ) );
+	assert_eq! ( stream.stats ( ).ignored_frames, 1 );
+}
+
+#[test]
+fn test_mock_tz_id ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let eastern = FixedOffset::west_opt ( 4 * 3600 ).unwrap ( );
+
+	let offsets = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let offsets_clone = offsets.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.tz_id ( 55 )
+		.utc_offset ( eastern )
+		.build ( move |snapshot| {
+			offsets_clone.lock ( ).unwrap ( ).push ( snapshot.utc_offset );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[0], push::subscribe_message ( "945629", 55 ) );
+	assert_eq! ( *offsets.lock ( ).unwrap ( ), vec! [ Some ( eastern ) ] );
+	assert_eq! ( ( stream.tz_id ( ), stream.utc_offset ( ) ), ( 55, Some ( eastern ) ) );
+	assert_eq! ( ( stream.config ( ).tz_id, stream.config ( ).utc_offset_secs ), ( Some ( 55 ), Some ( -4 * 3600 ) ) );
+}
+
+#[test]
+fn test_mock_field_stats ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		snapshot_frame ( r#"{"pid":"8984","last_numeric":[],"timestamp":1597116059}"# ),
+		snapshot_frame ( r#"{"pid":"8984","last_numeric":24871.5,"last_close":"24,507.0","turnover_numeric":21503,"timestamp":1597116059}"# ),
+	] );
+
+	let mut received = 0;
+	let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.field_stats ( true )
+		.build_mut ( move |_| {
+			received += 1;
+			if received < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let fields = stream.field_stats ( );
+	assert_eq! ( ( fields [ "945629" ].snapshots, fields [ "945629" ].bid, fields [ "945629" ].last_close ), ( 1, 0, 1 ) );
+	assert_eq! ( ( fields [ "8984" ].bid, fields [ "8984" ].last_close, fields [ "8984" ].turnover_numeric ), ( 1, 0, 0 ) );
+	assert_eq! ( ( fields [ "945629" ].parse_errors, fields [ "8984" ].parse_errors ), ( 0, 1 ) );
+	let total = stream.stats ( ).fields;
+	assert_eq! ( ( total.snapshots, total.parse_errors, total.bid, total.last_close, total.turnover_numeric ), ( 2, 1, 1, 1, 1 ) );
+}
+
+#[test]
+fn test_mock_on_event ( ) {
+	let server = MockServer::start ( vec! [
+		r#"a["{\"_event\":\"UID\",\"UID\":42}"]"#.to_string ( ),
+		r#"a["{\"_event\":\"error\",\"message\":\"bad\"}"]"#.to_string ( ),
+		frame ( "945629", 1597116058 ),
+	] );
+
+	let events = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let events_clone = events.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_event ( move |event| events_clone.lock ( ).unwrap ( ).push ( event ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *events.lock ( ).unwrap ( ), vec! [
+		ServerEvent::Uid ( Some ( 42 ) ),
+		ServerEvent::Error ( r#"{"_event":"error","message":"bad"}"#.to_string ( ) ),
+	] );
+}
+
+#[test]
+fn test_mock_invalid_utf8 ( ) {
+	let invalid = b"a[\"\xff\xfe\"]".to_vec ( );
+	let server = MockServer::start_binary ( vec! [ invalid, frame ( "945629", 1597116058 ).into_bytes ( ) ] );
+
+	// skipped
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.stats ( ).ignored_frames, 1 );
+
+	// replaced
+	let ignored = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let ignored_clone = ignored.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.lossy_utf8 ( true )
+		.on_ignored_frame ( move |frame| ignored_clone.lock ( ).unwrap ( ).push ( frame.to_string ( ) ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *ignored.lock ( ).unwrap ( ), vec! [ "a[\"\u{FFFD}\u{FFFD}\"]".to_string ( ) ] );
+}
+
+#[test]
+fn test_mock_unsubscribed_pid ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "94562", 1597116057 ),
+		frame ( "945629", 1597116058 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( move |s| {
+			received_clone.lock ( ).unwrap ( ).push ( s.pid );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ "945629".to_string ( ) ] );
+	assert_eq! ( stream.stats ( ).ignored_frames, 1 );
+}
+
+#[test]
+fn test_mock_several_pids_per_frame ( ) {
+	// one frame of the three instruments, the first of them not subscribed
+	let frames = [ frame ( "94562", 1597116057 ), frame ( "945629", 1597116058 ), frame ( "8984", 1597116058 ) ];
+	let messages: Vec<_> = frames.iter ( ).map ( |frame| &frame[2..frame.len ( ) - 1] ).collect ( );
+	let server = MockServer::start ( vec! [ format! ( "a[{}]", messages.join ( "," ) ) ] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( move |s| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( s.pid );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ "945629".to_string ( ), "8984".to_string ( ) ] );
+	assert_eq! ( stream.stats ( ).ignored_frames, 0 );
+}
+
+#[test]
+fn test_mock_next_snapshot ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_receiver ( )
+		.expect ( "Failed to create stream" );
+
+	let mut timestamps = Vec::new ( );
+	futures::executor::block_on ( async {
+		while let Some ( snapshot ) = stream.next_snapshot ( ).await {
+			timestamps.push ( snapshot.timestamp );
+		}
+	} );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116059 ] );
+}
+
+#[test]
+fn test_mock_pool ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116058 ),
+		frame ( "8873", 1597116058 ),
+	] );
+
+	let mut pool = Stream::builder ( "945629,8984,8873".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_pool_receiver ( 2 )
+		.expect ( "Failed to create pool" );
+	assert_eq! ( pool.shards ( ), vec! [ vec! [ "945629", "8984" ], vec! [ "8873" ] ] );
+	assert_eq! ( pool.shard_of ( "8873" ), Some ( 1 ) );
+
+	// each shard delivers its own pair ids only
+	let mut pids = HashSet::new ( );
+	futures::executor::block_on ( async {
+		while pids.len ( ) < 3 {
+			pids.insert ( pool.next_snapshot ( ).await.unwrap ( ).pid );
+		}
+	} );
+	assert! ( server.connections ( ) >= 2 );
+	assert_eq! ( pool.states ( ).len ( ), 2 );
+
+	// rebalanced
+	let more: Vec<String> = [ "945629", "8984", "8873", "14958" ].iter ( ).map ( |pid| pid.to_string ( ) ).collect ( );
+	pool.set_pids ( &more ).unwrap ( );
+	assert_eq! ( pool.shard_of ( "14958" ), Some ( 1 ) );
+	pool.set_pids ( &more [ ..1 ] ).unwrap ( );
+	assert_eq! ( pool.shards ( ), vec! [ vec! [ "945629" ] ] );
+	assert_eq! ( pool.states ( ).len ( ), 1 );
+	assert! ( matches! ( pool.set_pids ( &[ ] ), Err ( Error::InvalidPairId ( _ ) ) ) );
+
+	pool.shutdown ( );
+	assert! ( !pool.is_running ( ) );
+}
+
+#[test]
+fn test_mock_pool_reshard ( ) {
+	// the connection stays open after the first frames
+	let server = MockServer::start_timed ( vec! [
+		( Duration::ZERO, frame ( "945629", 1597116058 ) ),
+		( Duration::ZERO, frame ( "8984", 1597116058 ) ),
+		( Duration::from_secs ( 60 ), frame ( "945629", 1597116059 ) ),
+	] );
+
+	let token = CancellationToken::new ( );
+	let mut pool = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.cancellation_token ( token.clone ( ) )
+		.build_pool_receiver ( 2 )
+		.expect ( "Failed to create pool" );
+	futures::executor::block_on ( async {
+		for _ in 0..2 {
+			pool.next_snapshot ( ).await.unwrap ( );
+		}
+	} );
+	assert_eq! ( server.connections ( ), 1 );
+
+	// no unsubscribe, so the shard reconnects without the lost pair id, before RESTART_DELAY
+	pool.set_pids ( &[ "945629".to_string ( ) ] ).unwrap ( );
+	let resubscribed = push::subscribe_message ( "945629", 8 );
+	for _ in 0..25 {
+		if server.received ( ).contains ( &resubscribed ) {
+			break;
+		}
+		thread::sleep ( Duration::from_millis ( 20 ) );
+	}
+	assert! ( server.received ( ).contains ( &resubscribed ) );
+	assert_eq! ( server.connections ( ), 2 );
+
+	// the token of the builder stops the pool
+	token.cancel ( );
+	for _ in 0..50 {
+		if !pool.is_running ( ) {
+			break;
+		}
+		thread::sleep ( Duration::from_millis ( 20 ) );
+	}
+	assert! ( !pool.is_running ( ) );
+
+	// the callbacks of Stream are rejected rather than dropped
+	let result = Stream::builder ( "945629".to_string ( ) )
+		.on_channel ( "pidTechSumm-", |_| { } )
+		.build_pool_receiver ( 2 );
+	assert! ( matches! ( result, Err ( Error::UnsupportedOption ( "on_channel" ) ) ) );
+}
+
+#[test]
+fn test_mock_into_tokio_stream ( ) {
+	use futures::StreamExt;
+
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_receiver ( )
+		.expect ( "Failed to create stream" );
+	let runtime = stream.runtime.clone ( );
+
+	let timestamps: Vec<_> = runtime.block_on ( stream.into_tokio_stream ( ).map ( |s| s.timestamp ).collect ( ) );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116059 ] );
+}
+
+#[test]
+fn test_mock_subscribe_replay ( ) {
+	use futures::StreamExt;
+
+	let (tx, rx) = tokio::sync::mpsc::channel ( 8 );
+	let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.snapshot_source ( rx )
+		.current_thread ( true )
+		.replay_depth ( 2 )
+		.build_without_handler ( )
+		.expect ( "Failed to create stream" );
+	let snapshot = |pid: &str, timestamp| Snapshot { pid: pid.to_string ( ), timestamp, ..Default::default ( ) };
+	for ( pid, timestamp ) in [ ( "945629", 1597116058 ), ( "945629", 1597116059 ), ( "8984", 1597116060 ), ( "945629", 1597116061 ) ] {
+		tx.try_send ( snapshot ( pid, timestamp ) ).unwrap ( );
+	}
+	// drive the stream until it took all four, none of them broadcast to a receiver yet
+	let runtime = stream.runtime.clone ( );
+	runtime.block_on ( async {
+		while stream.latest ( "945629" ).map ( |latest| latest.timestamp ) != Some ( 1597116061 ) {
+			tokio::task::yield_now ( ).await;
+		}
+	} );
+
+	// attaching late: the last two of each pid in the order received, then the live ones
+	let replay = stream.subscribe_replay ( );
+	tx.try_send ( snapshot ( "8984", 1597116062 ) ).unwrap ( );
+	drop ( tx );
+	let replayed: Vec<_> = runtime.block_on ( replay.map ( |s| ( s.pid.clone ( ), s.timestamp ) ).collect ( ) );
+	assert_eq! ( replayed, vec! [
+		( "945629".to_string ( ), 1597116059 ),
+		( "8984".to_string ( ), 1597116060 ),
+		( "945629".to_string ( ), 1597116061 ),
+		( "8984".to_string ( ), 1597116062 ),
+	] );
+	runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.config ( ).replay_depth, Some ( 2 ) );
+}
+
+#[test]
+fn test_mock_on_freeze ( ) {
+	let (tx, rx) = tokio::sync::mpsc::channel ( 8 );
+	let freezes = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let freezes_clone = freezes.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.snapshot_source ( rx )
+		.on_freeze ( Duration::from_millis ( 100 ), move |freeze| freezes_clone.lock ( ).unwrap ( ).push ( freeze ) )
+		.build_without_handler ( )
+		.expect ( "Failed to create stream" );
+
+	// the same price again and again, as a stalled feed does
+	let snapshot = |last_numeric| Snapshot { pid: "945629".to_string ( ), last_numeric, ..Default::default ( ) };
+	for last_numeric in [ 18951.2, 18951.2, 18951.2, 18951.2, 18952.0 ] {
+		tx.try_send ( snapshot ( last_numeric ) ).unwrap ( );
+		std::thread::sleep ( Duration::from_millis ( 60 ) );
+	}
+	drop ( tx );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let freezes = freezes.lock ( ).unwrap ( );
+	assert_eq! ( freezes.len ( ), 1 );
+	assert_eq! ( ( freezes [ 0 ].pid.as_str ( ), freezes [ 0 ].price ), ( "945629", 18951.2 ) );
+	assert! ( freezes [ 0 ].duration > Duration::from_millis ( 100 ) );
+}
+
+#[test]
+fn test_mock_on_freeze_without_snapshots ( ) {
+	let (tx, rx) = tokio::sync::mpsc::channel ( 8 );
+	let freezes = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let freezes_clone = freezes.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.snapshot_source ( rx )
+		.on_freeze ( Duration::from_millis ( 100 ), move |freeze| freezes_clone.lock ( ).unwrap ( ).push ( freeze ) )
+		.build_without_handler ( )
+		.expect ( "Failed to create stream" );
+
+	// one snapshot and then none, as a feed left with its heartbeats does
+	tx.try_send ( Snapshot { pid: "945629".to_string ( ), last_numeric: 18951.2, ..Default::default ( ) } ).unwrap ( );
+	std::thread::sleep ( Duration::from_millis ( 400 ) );
+	assert_eq! ( freezes.lock ( ).unwrap ( ).len ( ), 1 );
+	drop ( tx );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let freezes = freezes.lock ( ).unwrap ( );
+	assert_eq! ( ( freezes [ 0 ].pid.as_str ( ), freezes [ 0 ].price ), ( "945629", 18951.2 ) );
+	assert! ( freezes [ 0 ].duration > Duration::from_millis ( 100 ) );
+}
+
+#[test]
+fn test_mock_set_handler ( ) {
+	let (tx, rx) = tokio::sync::mpsc::channel ( 8 );
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.snapshot_source ( rx )
+		.on ( "8984", |_| Ok ( ( ) ) )
+		.build ( move |s| {
+			received_clone.lock ( ).unwrap ( ).push ( ( "first", s.timestamp ) );
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	let snapshot = |pid: &str, timestamp| Snapshot { pid: pid.to_string ( ), timestamp, ..Default::default ( ) };
+	let wait_for = |n: usize| while received.lock ( ).unwrap ( ).len ( ) < n {
+		std::thread::sleep ( Duration::from_millis ( 10 ) );
+	};
+
+	tx.try_send ( snapshot ( "945629", 1 ) ).unwrap ( );
+	wait_for ( 1 );
+	let received_clone = received.clone ( );
+	stream.set_handler ( move |s| {
+		received_clone.lock ( ).unwrap ( ).push ( ( "second", s.timestamp ) );
+		if s.timestamp < 3 { Ok ( ( ) ) } else { Err ( ( ) ) }
+	} );
+	// the handlers of on(..) keep their pids
+	for ( pid, timestamp ) in [ ( "8984", 2 ), ( "945629", 2 ), ( "945629", 3 ) ] {
+		tx.try_send ( snapshot ( pid, timestamp ) ).unwrap ( );
+	}
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ ( "first", 1 ), ( "second", 2 ), ( "second", 3 ) ] );
+}
+
+#[test]
+fn test_mock_windowed ( ) {
+	use futures::StreamExt;
+
+	let windows = |emit_empty: bool| {
+		let (stream, tx) = in_memory_stream ( "945629" ).unwrap ( );
+		let runtime = stream.runtime.clone ( );
+		let snapshot = |timestamp| Snapshot { pid: "945629".to_string ( ), timestamp, ..Default::default ( ) };
+		// 2 snapshots in the first window of 200ms, none in the second, 1 in the third
+		runtime.spawn ( async move {
+			tx.send ( snapshot ( 1 ) ).await.unwrap ( );
+			tx.send ( snapshot ( 2 ) ).await.unwrap ( );
+			tokio::time::sleep ( Duration::from_millis ( 500 ) ).await;
+			tx.send ( snapshot ( 3 ) ).await.unwrap ( );
+		} );
+		let windowed = stream.windowed ( Duration::from_millis ( 200 ), emit_empty );
+		let windows: Vec<Vec<u64>> = runtime.block_on ( windowed
+			.map ( |window| window.iter ( ).map ( |s| s.timestamp ).collect ( ) )
+			.collect ( ) );
+		windows
+	};
+
+	// the last window is cut short by the end of the stream
+	assert_eq! ( windows ( true ), vec! [ vec! [ 1, 2 ], vec! [ ], vec! [ 3 ] ] );
+	assert_eq! ( windows ( false ), vec! [ vec! [ 1, 2 ], vec! [ 3 ] ] );
+}
+
+#[test]
+fn test_mock_subscribe_broadcast ( ) {
+	use tokio::sync::broadcast::error::TryRecvError;
+
+	let frames = || vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	];
+	let server = MockServer::start ( frames ( ) );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.current_thread ( true )
+		.build_without_handler ( )
+		.expect ( "Failed to create stream" );
+	// the stream only runs once the runtime is driven, so the receivers miss none
+	let mut receivers = vec! [ stream.subscribe_broadcast ( ), stream.subscribe_broadcast ( ) ];
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	// one snapshot shared by all the receivers
+	let firsts: Vec<_> = receivers.iter_mut ( ).map ( |receiver| receiver.try_recv ( ).unwrap ( ) ).collect ( );
+	assert_eq! ( firsts [ 0 ].timestamp, 1597116058 );
+	assert! ( Arc::ptr_eq ( &firsts [ 0 ], &firsts [ 1 ] ) );
+	for receiver in &mut receivers {
+		assert_eq! ( receiver.try_recv ( ).unwrap ( ).timestamp, 1597116059 );
+		assert_eq! ( receiver.try_recv ( ).unwrap_err ( ), TryRecvError::Closed );
+	}
+
+	let server = MockServer::start ( frames ( ) );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.current_thread ( true )
+		.broadcast_capacity ( 1 )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	let mut receiver = stream.subscribe_broadcast ( );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	// the first snapshot was overwritten, the lagging receiver skips it
+	let snapshot = stream.runtime.block_on ( push::recv_broadcast ( &mut receiver ) ).unwrap ( );
+	assert_eq! ( snapshot.timestamp, 1597116059 );
+	assert! ( stream.runtime.block_on ( push::recv_broadcast ( &mut receiver ) ).is_none ( ) );
+}
+
+#[test]
+fn test_mock_latest ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let mut stream = Stream::builder ( "945629,8984,1".to_string ( ) )
+		.url ( &server.url ( ) )
+		.current_thread ( true )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	// the stream only runs once the runtime is driven, so the receiver misses none
+	let mut shared = stream.subscribe_broadcast ( );
+	let runtime = stream.runtime.clone ( );
+	let received = runtime.block_on ( async {
+		let mut received = 0;
+		while let Some ( snapshot ) = push::recv_broadcast ( &mut shared ).await {
+			// cached with the time it arrived before it is published
+			assert! ( stream.latest ( &snapshot.pid ).is_some_and ( |latest| latest.seq >= snapshot.seq ) );
+			assert! ( stream.last_update ( &snapshot.pid ).is_some ( ) );
+			received += 1;
+		}
+		received
+	} );
+	assert_eq! ( received, 3 );
+	runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	let latest = stream.latest_all ( );
+	assert_eq! ( latest.len ( ), 2 );
+	assert_eq! ( latest [ "945629" ].timestamp, 1597116060 );
+	assert_eq! ( latest [ "8984" ].timestamp, 1597116059 );
+	assert_eq! ( stream.latest ( "8984" ).map ( |snapshot| snapshot.timestamp ), Some ( 1597116059 ) );
+	assert! ( stream.latest ( "1" ).is_none ( ) );
+}
+
+#[test]
+fn test_mock_is_active ( ) {
+	let server = MockServer::start_timed ( vec! [
+		( Duration::ZERO, frame ( "945629", 1597116058 ) ),
+		( Duration::from_secs ( 5 ), frame ( "945629", 1597116059 ) ),
+	] );
+
+	let stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.active_window ( Duration::from_millis ( 200 ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let start = Instant::now ( );
+	while !stream.is_active ( "945629" ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 3 ), "no snapshot" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert! ( stream.last_update ( "945629" ).is_some ( ) );
+	assert! ( !stream.is_active ( "8984" ) );
+
+	assert! ( stream.runtime.block_on ( stream.healthy ( Duration::from_millis ( 200 ) ) ) );
+
+	// connected, but quiet for longer than the window
+	thread::sleep ( Duration::from_millis ( 300 ) );
+	assert! ( !stream.is_active ( "945629" ) );
+	assert! ( !stream.runtime.block_on ( stream.healthy ( Duration::from_millis ( 200 ) ) ) );
+	assert! ( stream.runtime.block_on ( stream.healthy ( Duration::from_secs ( 5 ) ) ) );
+	assert_eq! ( stream.state ( ), ConnectionState::Connected );
+}
+
+#[test]
+fn test_mock_clock ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let clock = Arc::new ( MockClock::new ( ) );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.clock ( clock.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let start = Instant::now ( );
+	while !stream.is_active ( "945629" ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 3 ), "no snapshot" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert_eq! ( stream.last_update ( "945629" ), Some ( clock.now ( ) ) );
+	assert_eq! ( stream.uptime ( ), Duration::ZERO );
+
+	clock.advance ( push::DEFAULT_ACTIVE_WINDOW + Duration::from_secs ( 1 ) );
+	assert! ( !stream.is_active ( "945629" ) );
+	assert_eq! ( stream.uptime ( ), push::DEFAULT_ACTIVE_WINDOW + Duration::from_secs ( 1 ) );
+	assert_eq! ( stream.state ( ), ConnectionState::Connected );
+}
+
+#[test]
+fn test_mock_instrument_class ( ) {
+	let server = MockServer::start ( vec! [ frame ( "8984", 1597116058 ) ] );
+	let clock = Arc::new ( MockClock::new ( ) );
+
+	let streams: Vec<_> = [ InstrumentClass::Continuous, InstrumentClass::Sessioned ].iter ( )
+		.map ( |class| Stream::builder ( "8984".to_string ( ) )
+			.url ( &server.url ( ) )
+			.clock ( clock.clone ( ) )
+			.instrument_class ( *class )
+			.build ( |_| Ok ( ( ) ) )
+			.expect ( "Failed to create stream" ) )
+		.collect ( );
+
+	let start = Instant::now ( );
+	while !streams.iter ( ).all ( |stream| stream.is_active ( "8984" ) ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 3 ), "no snapshot" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+
+	let healthy = || streams.iter ( )
+		.map ( |stream| stream.runtime.block_on ( stream.healthy ( Duration::from_secs ( 60 ) ) ) )
+		.collect::<Vec<_>> ( );
+	// quiet for minutes: a feed problem either way
+	clock.advance ( Duration::from_secs ( 600 ) );
+	assert_eq! ( healthy ( ), vec! [ false, false ] );
+
+	// quiet overnight: a feed problem around the clock, the market closed in sessions
+	clock.advance ( Duration::from_secs ( 16 * 3600 ) );
+	assert_eq! ( healthy ( ), vec! [ false, true ] );
+	assert! ( streams.iter ( ).all ( |stream| !stream.is_active ( "8984" ) ) );
+}
+
+#[test]
+fn test_mock_shutdown_timeout ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	while stream.state ( ) != ConnectionState::Connected {
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert! ( matches! ( stream.shutdown_timeout ( Duration::from_secs ( 3 ) ), push::ShutdownResult::Clean ( Ok ( ( ) ) ) ) );
+
+	// the handler blocks the task past the timeout, a second worker keeps the timer running
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let runtime = tokio::runtime::Builder::new_multi_thread ( ).worker_threads ( 2 ).enable_all ( ).build ( ).unwrap ( );
+	let (tx, rx) = std::sync::mpsc::channel ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.runtime ( Arc::new ( runtime ) )
+		.build ( move |_| {
+			tx.send ( ( ) ).unwrap ( );
+			thread::sleep ( Duration::from_millis ( 500 ) );
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	rx.recv ( ).unwrap ( );
+	let start = Instant::now ( );
+	assert! ( matches! ( stream.shutdown_timeout ( Duration::from_millis ( 100 ) ), push::ShutdownResult::Forced ) );
+	assert! ( start.elapsed ( ) < Duration::from_secs ( 2 ) );
+}
+
+#[test]
+fn test_mock_drop ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	while stream.state ( ) != ConnectionState::Connected {
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+
+	// the task stops on its own rather than with the runtime
+	let state = stream.state_receiver ( );
+	let start = Instant::now ( );
+	drop ( stream );
+	assert! ( start.elapsed ( ) < push::DROP_TIMEOUT );
+	assert_eq! ( *state.borrow ( ), ConnectionState::Closed );
+}
+
+#[test]
+fn test_mock_on_initial ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let initial = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let initial_clone = initial.clone ( );
+	let received = Arc::new ( Mutex::new ( 0 ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629,8984".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_initial ( move |snapshot| initial_clone.lock ( ).unwrap ( ).push ( ( snapshot.pid.clone ( ), snapshot.timestamp ) ) )
+		.build ( move |_| {
+			*received_clone.lock ( ).unwrap ( ) += 1;
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	assert_eq! ( *initial.lock ( ).unwrap ( ), vec! [ ( "945629".to_string ( ), 1597116058 ), ( "8984".to_string ( ), 1597116058 ) ] );
+	// the initial snapshots reach the handler as well
+	assert_eq! ( *received.lock ( ).unwrap ( ), 3 );
+}
+
+#[test]
+fn test_mock_filter ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+		frame ( "945629", 1597116061 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut last = 0;
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		// only the snapshots at least 2 seconds after the last delivered one
+		.filter ( move |snapshot| {
+			let deliver = snapshot.timestamp >= last + 2;
+			if deliver {
+				last = snapshot.timestamp;
+			}
+			deliver
+		} )
+		.build ( move |snapshot| {
+			received_clone.lock ( ).unwrap ( ).push ( snapshot.timestamp );
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ 1597116058, 1597116060 ] );
+}
+
+/// Writer keeping the lines written until flushed, and the lines flushed in a shared buffer
+#[derive(Default)]
+struct FlushedLines {
+	unflushed: Vec<u8>,
+	flushed: Arc<Mutex<Vec<String>>>,
+}
+
+impl Write for FlushedLines {
+	fn write ( &mut self, buf: &[u8] ) -> io::Result<usize> {
+		self.unflushed.write ( buf )
+	}
+
+	fn flush ( &mut self ) -> io::Result<()> {
+		let unflushed = String::from_utf8 ( std::mem::take ( &mut self.unflushed ) ).unwrap ( );
+		self.flushed.lock ( ).unwrap ( ).extend ( unflushed.lines ( ).map ( str::to_string ) );
+		Ok ( ( ) )
+	}
+}
+
+#[test]
+fn test_mock_emit_jsonl ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let writer = FlushedLines::default ( );
+	let flushed = writer.flushed.clone ( );
+	let flushed_clone = flushed.clone ( );
+	let flushed_in_handler = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let flushed_in_handler_clone = flushed_in_handler.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.emit_jsonl ( writer, JsonlFlush::Lines ( 2 ) )
+		.build ( move |_| {
+			flushed_in_handler_clone.lock ( ).unwrap ( ).push ( flushed_clone.lock ( ).unwrap ( ).len ( ) );
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap_err ( );
+
+	// flushed every 2 lines, and the last one when the stream ended
+	assert_eq! ( *flushed_in_handler.lock ( ).unwrap ( ), vec! [ 0, 2, 2 ] );
+	let timestamps: Vec<_> = flushed.lock ( ).unwrap ( ).iter ( )
+		.map ( |line| serde_json::from_str::<serde_json::Value> ( line ).unwrap ( ) [ "timestamp" ].as_u64 ( ).unwrap ( ) )
+		.collect ( );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116059, 1597116060 ] );
+}
+
+#[test]
+fn test_mock_parse_error ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ).replace ( "1597116059", "oops" ),
+		frame ( "945629", 1597116060 ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let parse_errors = Arc::new ( Mutex::new ( 0 ) );
+	let parse_errors_clone = parse_errors.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.on_parse_error ( move |e, _| {
+			assert! ( matches! ( e, Error::Parse ( _ ) ) );
+			*parse_errors_clone.lock ( ).unwrap ( ) += 1;
+		} )
+		.build ( move |s| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( s.timestamp );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ 1597116058, 1597116060 ] );
+	assert_eq! ( *parse_errors.lock ( ).unwrap ( ), 1 );
+	assert_eq! ( stream.stats ( ).parse_errors, 1 );
+}
+
+#[test]
+fn test_turnover_policy ( ) {
+	let frame = |turnover_numeric: &str| snapshot_frame ( &format! (
+		r#"{{"pid":"945629","last_numeric":18951.2,"turnover":"21.50K"{},"timestamp":1606850510}}"#,
+		turnover_numeric,
+	) );
+	let valid = [
+		frame ( r#","turnover_numeric":21503"# ),
+		frame ( r#","turnover_numeric":"21503""# ),
+		frame ( r#","volume_numeric":21503"# ),
+	];
+	let missing = frame ( "" );
+	let empty = frame ( r#","turnover_numeric":"""# );
+	let garbage = frame ( r#","turnover_numeric":"olia""# );
+	let parse = |frame: &str, policy| Snapshot::try_from_str_with ( frame, policy );
+
+	for frame in valid.iter ( ) {
+		let snapshot = parse ( frame, TurnoverPolicy::ZeroOnMissing ).unwrap ( );
+		assert_eq! ( ( snapshot.turnover_numeric, snapshot.turnover_numeric_opt ), ( 21503, None ) );
+		let snapshot = parse ( frame, TurnoverPolicy::NoneOnMissing ).unwrap ( );
+		assert_eq! ( ( snapshot.turnover_numeric, snapshot.turnover_numeric_opt ), ( 21503, Some ( 21503 ) ) );
+		assert_eq! ( snapshot.turnover, "21.50K" );
+		let snapshot = parse ( frame, TurnoverPolicy::Error ).unwrap ( );
+		assert_eq! ( snapshot.turnover_numeric, 21503 );
+	}
+	for frame in [ missing, empty, garbage ].iter ( ) {
+		let snapshot = parse ( frame, TurnoverPolicy::ZeroOnMissing ).unwrap ( );
+		assert_eq! ( ( snapshot.turnover_numeric, snapshot.turnover_numeric_opt ), ( 0, None ) );
+		let snapshot = parse ( frame, TurnoverPolicy::NoneOnMissing ).unwrap ( );
+		assert_eq! ( ( snapshot.turnover_numeric, snapshot.turnover_numeric_opt ), ( 0, None ) );
+		assert! ( parse ( frame, TurnoverPolicy::Error ).is_err ( ), "{}", frame );
+	}
+}
+
+#[test]
+fn test_mock_turnover_policy ( ) {
+	let with_turnover = |timestamp: u64, turnover_numeric: &str| {
+		frame ( "945629", timestamp ).replace ( r#"timestamp"#, &format! ( r#"turnover_numeric\\\":{},\\\"timestamp"#, turnover_numeric ) )
+	};
+	let server = MockServer::start ( vec! [
+		with_turnover ( 1597116058, "21503" ),
+		with_turnover ( 1597116059, r#"\\\"olia\\\""# ),
+		frame ( "945629", 1597116060 ),
+		with_turnover ( 1597116061, "3513" ),
+	] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.turnover_policy ( TurnoverPolicy::Error )
+		.build ( move |s| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( ( s.timestamp, s.turnover_numeric_opt ) );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	// the malformed and the missing turnover fail to parse
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ ( 1597116058, Some ( 21503 ) ), ( 1597116061, Some ( 3513 ) ) ] );
+	assert_eq! ( stream.stats ( ).parse_errors, 2 );
+}
+
+#[test]
+fn test_mock_channel_drop_oldest ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.channel ( ChannelPolicy::DropOldest, 1 )
+		.build_receiver ( )
+		.expect ( "Failed to create stream" );
+
+	// read only once the stream ended, so the queue overflows
+	let mut state = stream.state_receiver ( );
+	stream.runtime.block_on ( async {
+		while *state.borrow_and_update ( ) != ConnectionState::Closed {
+			state.changed ( ).await.unwrap ( );
+		}
+	} );
+
+	let snapshot = futures::executor::block_on ( stream.next_snapshot ( ) ).unwrap ( );
+	assert_eq! ( snapshot.timestamp, 1597116060 );
+	assert! ( futures::executor::block_on ( stream.next_snapshot ( ) ).is_none ( ) );
+	assert_eq! ( stream.stats ( ).dropped_snapshots, 2 );
+}
+
+#[test]
+fn test_mock_iter ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		r#"c[3000,"Go away!"]"#.to_string ( ),
+	] );
+
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_receiver ( )
+		.expect ( "Failed to create stream" );
+
+	let timestamps: Vec<_> = stream.iter ( ).map ( |snapshot| snapshot.timestamp ).collect ( );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116059 ] );
+
+	// the runtime of the stream is driven by the iterator
+	let mut current_thread = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.current_thread ( true )
+		.build_receiver ( )
+		.expect ( "Failed to create stream" );
+	let timestamps: Vec<_> = current_thread.iter ( ).map ( |snapshot| snapshot.timestamp ).collect ( );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116059 ] );
+
+	// blocking within an async context
+	let runtime = stream.runtime.clone ( );
+	let result = std::panic::catch_unwind ( std::panic::AssertUnwindSafe ( || {
+		runtime.block_on ( async { stream.iter ( ).next ( ) } )
+	} ) );
+	assert! ( result.is_err ( ) );
+}
+
+#[test]
+fn test_mock_fetch_snapshot ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "8984", 1597116058 ),
+		frame ( "945629", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+	] );
+	let runtime = tokio::runtime::Runtime::new ( ).unwrap ( );
+
+	let snapshot = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.fetch_snapshot ( Duration::from_secs ( 5 ) )
+	).unwrap ( );
+	assert_eq! ( ( snapshot.pid.as_str ( ), snapshot.timestamp ), ( "945629", 1597116059 ) );
+
+	let result = runtime.block_on (
+		Stream::builder ( "1".to_string ( ) )
+			.url ( &server.url ( ) )
+			.fetch_snapshot ( Duration::from_millis ( 100 ) )
+	);
+	assert! ( matches! ( result, Err ( Error::NoSnapshot ) ) );
+}
+
+#[test]
+fn test_mock_collect ( ) {
+	let server = MockServer::start ( vec! [
+		frame ( "945629", 1597116058 ),
+		frame ( "8984", 1597116059 ),
+		frame ( "945629", 1597116060 ),
+		frame ( "945629", 1597116061 ),
+	] );
+	let runtime = tokio::runtime::Runtime::new ( ).unwrap ( );
+
+	let snapshots = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.collect ( 2, Duration::from_secs ( 5 ) )
+	).unwrap ( );
+	let timestamps: Vec<_> = snapshots.iter ( ).map ( |snapshot| snapshot.timestamp ).collect ( );
+	assert_eq! ( timestamps, vec! [ 1597116058, 1597116060 ] );
+
+	let snapshots = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.collect ( 10, Duration::from_millis ( 500 ) )
+	).unwrap ( );
+	assert_eq! ( snapshots.len ( ), 3 );
+
+	let result = runtime.block_on (
+		Stream::builder ( "1".to_string ( ) )
+			.url ( &server.url ( ) )
+			.collect ( 2, Duration::from_millis ( 100 ) )
+	);
+	assert! ( matches! ( result, Err ( Error::NoSnapshot ) ) );
+}
+
+#[test]
+fn test_mock_close ( ) {
+	let server = MockServer::start ( vec! [ r#"c[3000,"Go away!"]"#.to_string ( ) ] );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let result = stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( );
+	assert! ( matches! ( result, Err ( Error::Closed { code: 3000, .. } ) ) );
+}
+
+#[test]
+fn test_mock_heartbeat ( ) {
+	let server = MockServer::start ( vec! [ ] );
+	let token = CancellationToken::new ( );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	// the first heartbeat follows the subscription
+	for _ in 0..100 {
+		if server.received ( ).len ( ) > 2 {
+			break;
+		}
+		thread::sleep ( Duration::from_millis ( 20 ) );
+	}
+	token.cancel ( );
+
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[2], push::heartbeat_message ( ) );
+}
+
+#[test]
+fn test_mock_sockjs_heartbeat ( ) {
+	let server = MockServer::start ( vec! [ "h".to_string ( ) ] );
+	let token = CancellationToken::new ( );
+
+	let received = Arc::new ( Mutex::new ( 0 ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( move |_| {
+			*received_clone.lock ( ).unwrap ( ) += 1;
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	let start = Instant::now ( );
+	while stream.last_activity ( ).is_none ( ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 3 ), "no activity" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert_eq! ( *received.lock ( ).unwrap ( ), 0 );
+	assert_eq! ( stream.stats ( ).ignored_frames, 0 );
+	assert_eq! ( stream.last_update ( "945629" ), None );
+
+	token.cancel ( );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+}
+
+#[test]
+fn test_mock_current_thread ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let token = CancellationToken::new ( );
+
+	let received = Arc::new ( Mutex::new ( 0 ) );
+	let received_clone = received.clone ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.cancellation_token ( token.clone ( ) )
+		.current_thread ( true )
+		.build ( move |_| {
+			*received_clone.lock ( ).unwrap ( ) += 1;
+			Ok ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+	assert_eq! ( stream.runtime.metrics ( ).num_workers ( ), 1 );
+
+	// both the receive loop and the heartbeat progress while the runtime is driven
+	stream.runtime.block_on ( async {
+		while *received.lock ( ).unwrap ( ) == 0 || server.received ( ).len ( ) < 3 {
+			tokio::time::sleep ( Duration::from_millis ( 10 ) ).await;
+		}
+	} );
+	assert_eq! ( server.received ( )[2], push::heartbeat_message ( ) );
+
+	token.cancel ( );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+}
+
+#[test]
+fn test_mock_from_config ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let config: StreamConfig = serde_json::from_str ( &format! (
+		r#"{{"pids":["945629"],"url":"{}","uid":42,"number_format":"CommaDecimal"}}"#,
+		server.url ( )
+	) ).unwrap ( );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::from_config ( &config, move |snapshot| {
+		received_clone.lock ( ).unwrap ( ).push ( snapshot.number_format );
+		Err ( ( ) )
+	} )
+	.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[1], push::uid_message ( 42 ) );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ NumberFormat::CommaDecimal ] );
+
+	let config = StreamConfig { pids: vec! [ ], ..config };
+	assert! ( matches! ( Stream::from_config ( &config, |_| Ok ( ( ) ) ), Err ( Error::InvalidPairId ( _ ) ) ) );
+}
+
+#[test]
+fn test_mock_config ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let config: StreamConfig = serde_json::from_str ( &format! (
+		r#"{{"pids":["945629"],"url":"{}","connect_timeout_ms":5000}}"#,
+		server.url ( )
+	) ).unwrap ( );
+
+	let mut stream = config.builder ( ).unwrap ( )
+		.max_connect_attempts ( 5 )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+
+	let effective = stream.config ( );
+	assert_eq! ( effective.connect_timeout_ms, Some ( 5000 ) );
+	assert_eq! ( effective.max_connect_attempts, Some ( 5 ) );
+	assert_eq! ( effective.domain.as_deref ( ), Some ( push::DEFAULT_DOMAIN ) );
+	assert_eq! ( effective.handler_concurrency, Some ( 1 ) );
+	assert_eq! ( effective.ping_interval_ms, None );
+
+	// the effective config builds the same stream
+	assert_eq! ( effective.builder ( ).unwrap ( ).build_without_handler ( ).unwrap ( ).config ( ), effective );
+}
+
+#[test]
+fn test_mock_fragmented ( ) {
+	let large = snapshot_frame ( &format! (
+		r#"{{"pid":"945629","last_numeric":24871.5,"padding":"{}","timestamp":1597116058}}"#,
+		"x".repeat ( 100_000 )
+	) );
+	let server = MockServer::start_fragmented ( vec! [ large, frame ( "945629", 1597116059 ) ], 4096 );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build_mut ( move |snapshot| {
+			let mut received = received_clone.lock ( ).unwrap ( );
+			received.push ( snapshot.timestamp );
+			if received.len ( ) < 2 { Ok ( ( ) ) } else { Err ( ( ) ) }
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ 1597116058, 1597116059 ] );
+	assert_eq! ( stream.stats ( ).ignored_frames, 0 );
+
+	// over the limit, the message fails the stream rather than being skipped
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.max_message_size ( Some ( 64 * 1024 ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let result = stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( );
+	assert! ( matches! ( result, Err ( Error::MessageTooLarge { max: 65536, .. } ) ), "{:?}", result );
+}
+
+#[test]
+fn test_mock_uid ( ) {
+	let server = MockServer::start ( vec! [
+		r#"a["{\"_event\":\"UID\",\"UID\":42}"]"#.to_string ( ),
+		frame ( "945629", 1597116058 ),
+	] );
+
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.uid ( 42 )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( server.received ( )[1], push::uid_message ( 42 ) );
+	assert_eq! ( stream.uid ( ), Some ( 42 ) );
+}
+
+#[test]
+fn test_mock_uid_with_quote ( ) {
+	let quote = frame ( "945629", 1597116058 );
+	let server = MockServer::start ( vec! [ format! ( r#"a["{{\"_event\":\"UID\",\"UID\":42}}",{}"#, &quote[2..] ) ] );
+
+	let received = Arc::new ( Mutex::new ( Vec::new ( ) ) );
+	let received_clone = received.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.uid ( 42 )
+		.build ( move |s| {
+			received_clone.lock ( ).unwrap ( ).push ( s.pid );
+			Err ( ( ) )
+		} )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.uid ( ), Some ( 42 ) );
+	assert_eq! ( *received.lock ( ).unwrap ( ), vec! [ "945629".to_string ( ) ] );
+}
+
+#[test]
+fn test_mock_probe ( ) {
+	let server = MockServer::start ( vec! [ r#"a["{\"_event\":\"UID\",\"UID\":42}"]"#.to_string ( ) ] );
+	let runtime = tokio::runtime::Runtime::new ( ).unwrap ( );
+
+	let report = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.probe ( false )
+	).unwrap ( );
+	assert_eq! ( ( report.url, report.server, report.attempts, report.uid ), ( server.url ( ), None, 1, None ) );
+
+	let report = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( &server.url ( ) )
+			.uid ( 42 )
+			.probe ( true )
+	).unwrap ( );
+	assert_eq! ( report.uid, Some ( 42 ) );
+	assert! ( report.latency < Duration::from_secs ( 5 ) );
+
+	let result = runtime.block_on (
+		Stream::builder ( "945629".to_string ( ) )
+			.url ( "ws://127.0.0.1:1/" )
+			.max_connect_attempts ( 1 )
+			.probe ( false )
+	);
+	assert! ( matches! ( result, Err ( Error::Connect ( _ ) ) ) );
+}
+
+#[test]
+fn test_mock_cancel_reconnecting ( ) {
+	let cancel_quickly = |mut stream: Stream, token: CancellationToken| {
+		let start = Instant::now ( );
+		token.cancel ( );
+		let task = &mut stream.stream_handle_spawn;
+		let result = stream.runtime.block_on ( async move { tokio::time::timeout ( Duration::from_secs ( 1 ), task ).await } );
+		assert! ( matches! ( result, Ok ( Ok ( Ok ( ( ) ) ) ) ) );
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 1 ) );
+	};
+
+	// sleeping in the backoff after a refused connect
+	let token = CancellationToken::new ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( "ws://127.0.0.1:1/" )
+		.max_connect_attempts ( 3 )
+		.retry_delay ( Duration::from_secs ( 60 ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	let mut state = stream.state_receiver ( );
+	stream.runtime.block_on ( state.wait_for ( |state| *state == ConnectionState::Reconnecting ) ).unwrap ( );
+	cancel_quickly ( stream, token );
+
+	// waiting for the open frame of a connect attempt
+	let server = MockServer::start_with_open ( vec! [ ], vec! [ ] );
+	let token = CancellationToken::new ( );
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.connect_timeout ( Duration::from_secs ( 60 ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	let start = Instant::now ( );
+	while server.connections ( ) == 0 {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 5 ), "Not connected" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert_eq! ( stream.state ( ), ConnectionState::Connecting );
+	cancel_quickly ( stream, token );
+}
+
+#[test]
+fn test_mock_open_frame ( ) {
+	let runtime = tokio::runtime::Runtime::new ( ).unwrap ( );
+	let probe = |open: &[&str]| {
+		let server = MockServer::start_with_open ( open.iter ( ).map ( |frame| frame.to_string ( ) ).collect ( ), vec! [ ] );
+		runtime.block_on (
+			Stream::builder ( "945629".to_string ( ) )
+				.url ( &server.url ( ) )
+				.connect_timeout ( Duration::from_millis ( 200 ) )
+				.max_connect_attempts ( 1 )
+				.probe ( false )
+		)
+	};
+
+	// heartbeats before the open frame are skipped
+	assert! ( probe ( &[ "h", "h", "o" ] ).is_ok ( ) );
+	assert! ( matches! ( probe ( &[ "h" ] ), Err ( Error::OpenFrameTimeout ) ) );
+	assert! ( matches! ( probe ( &[ "a[\"{}\"]", "o" ] ), Err ( Error::UnexpectedOpenFrame ( _ ) ) ) );
+}
+
+#[test]
+fn test_mock_url_provider ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	// an unreachable host first, then the server
+	let mut urls = vec! [ server.url ( ), "ws://127.0.0.1:1/echo/websocket".to_string ( ) ];
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url_provider ( move || urls.pop ( ).unwrap ( ) )
+		.max_connect_attempts ( 2 )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.connected_url ( ), Some ( server.url ( ) ) );
+	assert_eq! ( server.connections ( ), 1 );
+}
+
+#[test]
+fn test_mock_preferred_hosts ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let host = server.url ( ).replace ( "/echo/websocket", "" );
+
+	// an unreachable host first, then the server, before any generated URL
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.preferred_hosts ( vec! [ "ws://127.0.0.1:1".to_string ( ), host.clone ( ) ] )
+		.max_connect_attempts ( 2 )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	let url = stream.connected_url ( ).unwrap ( );
+	assert! ( url.starts_with ( &format! ( "{}/echo/", host ) ) && url.ends_with ( "/websocket" ), "{}", url );
+	assert_eq! ( stream.connected_server ( ), None );
+}
+
+#[test]
+fn test_mock_rejected_on_arrival ( ) {
+	let rejecting = MockServer::start_with_open ( vec! [ r#"c[2010,"Another connection still open"]"#.to_string ( ) ], vec! [ ] );
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	// another host after the retryable close
+	let mut urls = vec! [ server.url ( ), rejecting.url ( ) ];
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url_provider ( move || urls.pop ( ).unwrap ( ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.connected_url ( ), Some ( server.url ( ) ) );
+
+	// as after the close right after the open frame
+	let rejecting = MockServer::start_with_open ( vec! [ "o".to_string ( ), r#"c[2010,"Another connection still open"]"#.to_string ( ) ], vec! [ ] );
+	let mut urls = vec! [ server.url ( ), rejecting.url ( ) ];
+	let retries = Arc::new ( Mutex::new ( 0 ) );
+	let retries_clone = retries.clone ( );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url_provider ( move || urls.pop ( ).unwrap ( ) )
+		.on_reconnect ( move |event| if let push::ReconnectEvent::Retry { error: Error::Closed { code: 2010, .. }, .. } = event {
+			*retries_clone.lock ( ).unwrap ( ) += 1;
+		} )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert_eq! ( stream.connected_url ( ), Some ( server.url ( ) ) );
+	assert_eq! ( *retries.lock ( ).unwrap ( ), 1 );
+
+	// other codes end the stream
+	let going_away = MockServer::start_with_open ( vec! [ r#"c[3000,"Go away!"]"#.to_string ( ) ], vec! [ ] );
+	let mut stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &going_away.url ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+	let result = stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( );
+	assert! ( matches! ( result, Err ( Error::Closed { code: 3000, .. } ) ) );
+	assert_eq! ( going_away.connections ( ), 1 );
+}
+
+#[test]
+fn test_mock_subscriptions ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+
+	let mut stream = Stream::builder ( "945629,1".to_string ( ) )
+		.url ( &server.url ( ) )
+		.build ( |_| Err ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	let subscriptions = stream.subscriptions ( );
+	assert_eq! ( subscriptions [ "945629" ], SubscriptionStatus::Active );
+	assert_eq! ( subscriptions [ "1" ], SubscriptionStatus::Pending );
+	assert_eq! ( stream.subscribed_pids ( ), [ "945629", "1" ].iter ( ).map ( |pid| pid.to_string ( ) ).collect ( ) );
+}
+
+#[test]
+fn test_mock_first_data_timeout ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let token = CancellationToken::new ( );
+
+	let stream = Stream::builder ( "945629,1".to_string ( ) )
+		.url ( &server.url ( ) )
+		.first_data_timeout ( Some ( Duration::from_millis ( 100 ) ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let mut subscriptions = stream.subscriptions_receiver ( );
+	stream.runtime.block_on ( async {
+		while subscriptions.borrow_and_update ( ).get ( "1" ) != Some ( &SubscriptionStatus::NoData ) {
+			subscriptions.changed ( ).await.unwrap ( );
+		}
+	} );
+	token.cancel ( );
+
+	assert_eq! ( stream.subscriptions ( ) [ "945629" ], SubscriptionStatus::Active );
+	stream.runtime.block_on ( stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+}
+
+#[test]
+fn test_mock_subscribe_and_wait ( ) {
+	let server = MockServer::start_timed ( vec! [
+		( Duration::ZERO, frame ( "945629", 1597116058 ) ),
+		( Duration::from_millis ( 300 ), frame ( "8984", 1597116059 ) ),
+	] );
+
+	let stream = Stream::builder ( "945629".to_string ( ) )
+		.url ( &server.url ( ) )
+		.session_max_age ( Some ( Duration::from_millis ( 800 ) ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let snapshot = stream.runtime.block_on ( stream.subscribe_and_wait ( "8984", Duration::from_secs ( 5 ) ) ).unwrap ( );
+	assert_eq! ( snapshot.timestamp, 1597116059 );
+	assert_eq! ( stream.subscriptions ( ) [ "8984" ], SubscriptionStatus::Active );
+
+	let result = stream.runtime.block_on ( stream.subscribe_and_wait ( "1", Duration::from_millis ( 100 ) ) );
+	assert! ( matches! ( result, Err ( Error::NoSnapshot ) ) );
+	assert_eq! ( stream.subscriptions ( ) [ "1" ], SubscriptionStatus::Pending );
+	assert! ( matches! ( stream.subscribe ( "" ), Err ( Error::InvalidPairId ( _ ) ) ) );
+
+	// the reconnect subscribes to the added pair ids too
+	let resubscribed = push::subscribe_message ( "945629,1,8984", 8 );
+	let start = Instant::now ( );
+	while !server.received ( ).contains ( &resubscribed ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 5 ), "Not resubscribed: {:?}", server.received ( ) );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	assert! ( server.received ( ).contains ( &push::subscribe_message ( "1", 8 ) ) );
+
+	stream.shutdown_timeout ( Duration::from_secs ( 1 ) );
+}
+
+#[test]
+fn test_mock_resubscribe ( ) {
+	let server = MockServer::start ( vec! [ frame ( "945629", 1597116058 ) ] );
+	let token = CancellationToken::new ( );
+
+	let mut stream = Stream::builder ( "945629,1".to_string ( ) )
+		.url ( &server.url ( ) )
+		.first_data_timeout ( Some ( Duration::from_millis ( 100 ) ) )
+		.cancellation_token ( token.clone ( ) )
+		.build ( |_| Ok ( ( ) ) )
+		.expect ( "Failed to create stream" );
+
+	let mut subscriptions = stream.subscriptions_receiver ( );
+	stream.runtime.block_on ( async {
+		while subscriptions.borrow_and_update ( ).get ( "1" ) != Some ( &SubscriptionStatus::NoData ) {
+			subscriptions.changed ( ).await.unwrap ( );
+		}
+	} );
+
+	stream.resubscribe ( &[ "1".to_string ( ) ] ).unwrap ( );
+	let resubscribe = push::subscribe_message ( "1", 8 );
+	let start = Instant::now ( );
+	while !server.received ( ).contains ( &resubscribe ) {
+		assert! ( start.elapsed ( ) < Duration::from_secs ( 5 ), "Resubscribe not sent" );
+		thread::sleep ( Duration::from_millis ( 10 ) );
+	}
+	let subscriptions = stream.subscriptions ( );
+	assert_eq! ( subscriptions [ "1" ], SubscriptionStatus::Pending );
+	assert_eq! ( subscriptions [ "945629" ], SubscriptionStatus::Active );
+	assert_eq! ( server.connections ( ), 1 );
+
+	assert! ( matches! ( stream.resubscribe ( &[ "8984".to_string ( ) ] ), Err ( Error::InvalidPairId ( pids ) ) if pids == "8984" ) );
+
+	token.cancel ( );
+	stream.runtime.block_on ( &mut stream.stream_handle_spawn ).unwrap ( ).unwrap ( );
+	assert! ( matches! ( stream.resubscribe ( &[ "1".to_string ( ) ] ), Err ( Error::Ended ) ) );
+}
@@ -22,6 +22,7 @@ use tokio::{
     time,
 };
 use tokio_tungstenite;
+use forexpros_wss::push;
 use rand::Rng;
 use futures::prelude::*;
 use std::time::Duration;
@@ -65,11 +66,11 @@ fn test_access_to_server ( /*pid: 'static &str*/ ) {
 			} )
 			.and_then ( |(mut tx, mut rx)| async move {
 				// TODO: react to the server
-				tx.send ( format ! ( "[\"{{\\\"_event\\\":\\\"bulk-subscribe\\\",\\\"tzID\\\":\\\"8\\\",\\\"message\\\":\\\"pid-{}:\\\"}}\"]", &pair_id ).into ( ) )
+				tx.send ( push::subscribe_message ( pair_id, 8 ).into ( ) )
 					.await
 					.expect ( "Expect tx.send(bulk-subscribe, tzID, pid) to server" )
 					;
-				tx.send ( "[\"{\\\"_event\\\":\\\"UID\\\",\\\"UID\\\":0}\"]".into ( ) )
+				tx.send ( push::uid_message ( 0 ).into ( ) )
 					.await
 					.expect ( "Expect tx.send(UID=0) to server" )
 					;
@@ -78,7 +79,7 @@ fn test_access_to_server ( /*pid: 'static &str*/ ) {
 				rt_heartbeat
 					.spawn ( async move {
 						loop {
-							tx.send ( "[\"{\\\"_event\\\":\\\"heartbeat\\\",\\\"data\\\":\\\"h\\\"}\"]".into ( ) )
+							tx.send ( push::heartbeat_message ( ).into ( ) )
 								.await
 								.expect ( "Expect tx.send(heartbeat) to server" )
 								;